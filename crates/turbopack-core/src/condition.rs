@@ -1,9 +1,252 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Read,
+    path::PathBuf,
+    sync::{Arc, Mutex, OnceLock},
+};
+
 use anyhow::Result;
 use async_recursion::async_recursion;
 use futures::{stream, StreamExt};
+use regex::Regex;
+use semver::{Version, VersionReq};
 use serde::{Deserialize, Serialize};
-use turbo_tasks::{trace::TraceRawVcs, Vc};
-use turbo_tasks_fs::FileSystemPath;
+use serde_json::Value;
+use turbo_tasks::{trace::TraceRawVcs, ReadRef, Vc};
+use turbo_tasks_fs::{
+    glob::Glob, DirectoryContent, DirectoryEntry, FileContent, FileJsonContent, FileSystem,
+    FileSystemPath,
+};
+use turbo_tasks_hash::hash_xxh3_hash64;
+
+/// Workspace information that some conditions need in order to be evaluated.
+///
+/// Conditions that depend on it never match when evaluated with an empty
+/// context, e.g. via [`ContextCondition::matches`].
+#[derive(Debug, Clone, Default)]
+pub struct MatchContext {
+    /// The packages in the workspace.
+    pub packages: Vec<PackageInfo>,
+    /// The import specifiers of each module, keyed by the module's path
+    /// relative to the filesystem root.
+    pub imports: HashMap<String, Vec<String>>,
+    /// The names each module exports, keyed like `imports`.
+    pub exports: HashMap<String, Vec<String>>,
+    /// Named rulesets that [`ContextCondition::MatchesRuleset`] can refer to.
+    pub rulesets: HashMap<String, Ruleset>,
+    /// Named predicates that [`ContextCondition::NamedPredicate`] can refer
+    /// to, for embedders with matching logic of their own.
+    pub predicates: HashMap<String, Predicate>,
+    /// The files changed since each git base ref, relative to the filesystem
+    /// root, e.g. from `git diff --name-only <ref>`. Refs without an entry,
+    /// such as when the workspace isn't a git repository, have no known
+    /// changes.
+    pub changed_files: HashMap<String, Vec<String>>,
+    /// The workspace globs of the repository, from the root `package.json`'s
+    /// `workspaces` or `pnpm-workspace.yaml`'s `packages`, relative to the
+    /// filesystem root, e.g. `packages/*`. Globs starting with `!` exclude the
+    /// directories they match.
+    pub workspace_globs: Vec<String>,
+    /// The files the current build has registered as its outputs so far,
+    /// relative to the filesystem root. Unlike the `outputs` globs in
+    /// `turbo.json`, these are the files that were actually written.
+    pub build_outputs: HashSet<String>,
+    /// The application entrypoints from the framework's config, relative to
+    /// the filesystem root, e.g. `apps/web/pages/_app.tsx` for Next.js or an
+    /// explicit `entry`.
+    pub app_entrypoints: HashSet<String>,
+    /// How many paths each [`ContextCondition::Capped`] has matched so far,
+    /// keyed by the condition's `id`. Clones of the context share the counts,
+    /// so a session lasts for as long as a context and its clones are in
+    /// use.
+    pub capped_matches: Arc<Mutex<HashMap<String, usize>>>,
+    /// Whether the directories searched by [`ContextCondition::InTurboPackage`]
+    /// so far own a turbo config. Like `capped_matches`, clones of the context
+    /// share the cache.
+    pub turbo_packages: Arc<TurboPackageCache>,
+    /// The directory that [`ContextCondition::InTempDir`] treats as the temp
+    /// directory, instead of the platform's [`std::env::temp_dir`].
+    pub temp_dir: Option<PathBuf>,
+    /// The directory that [`ContextCondition::InOsCacheDir`] treats as the
+    /// cache directory, instead of the platform's.
+    pub cache_dir: Option<PathBuf>,
+    /// Globs of paths to ignore for this run only, relative to the filesystem
+    /// root, e.g. from an `--ignore` flag, that
+    /// [`ContextCondition::InRuntimeIgnore`] matches.
+    pub runtime_ignores: Vec<String>,
+    /// The globs above, parsed the first time a condition needs them rather
+    /// than for every path, so they mustn't change once matching has started.
+    pub parsed_globs: ParsedGlobs,
+}
+
+/// A predicate registered in the [`MatchContext`] under a name.
+pub type Predicate = fn(&FileSystemPath) -> bool;
+
+/// The globs of a [`MatchContext`], parsed on first use.
+#[derive(Debug, Clone, Default)]
+pub struct ParsedGlobs {
+    /// The including and the excluding `workspace_globs`.
+    workspace: OnceLock<(Vec<Glob>, Vec<Glob>)>,
+    runtime_ignores: OnceLock<Vec<Glob>>,
+}
+
+impl ParsedGlobs {
+    fn workspace(&self, workspace_globs: &[String]) -> Result<&(Vec<Glob>, Vec<Glob>)> {
+        if let Some(parsed) = self.workspace.get() {
+            return Ok(parsed);
+        }
+        #[cfg(test)]
+        tests::CONTEXT_GLOBS_PARSED.with(|parsed| parsed.set(parsed.get() + 1));
+
+        let mut include = Vec::new();
+        let mut exclude = Vec::new();
+        for glob in workspace_globs {
+            match glob.strip_prefix('!') {
+                Some(glob) => exclude.push(Glob::parse(glob)?),
+                None => include.push(Glob::parse(glob)?),
+            }
+        }
+        Ok(self.workspace.get_or_init(|| (include, exclude)))
+    }
+
+    fn runtime_ignores(&self, runtime_ignores: &[String]) -> Result<&[Glob]> {
+        if let Some(parsed) = self.runtime_ignores.get() {
+            return Ok(parsed);
+        }
+        #[cfg(test)]
+        tests::CONTEXT_GLOBS_PARSED.with(|parsed| parsed.set(parsed.get() + 1));
+
+        let parsed = runtime_ignores
+            .iter()
+            .map(|pattern| Glob::parse(pattern))
+            .collect::<Result<_>>()?;
+        Ok(self.runtime_ignores.get_or_init(|| parsed))
+    }
+}
+
+impl MatchContext {
+    /// Finds the innermost package containing the given path.
+    pub fn package_for(&self, path: &str) -> Option<&PackageInfo> {
+        self.packages
+            .iter()
+            .filter(|package| {
+                package.root.is_empty()
+                    || path == package.root
+                    || path
+                        .strip_prefix(&package.root)
+                        .is_some_and(|rest| rest.starts_with('/'))
+            })
+            .max_by_key(|package| package.root.len())
+    }
+
+    /// Counts the directories between the root of the package containing the
+    /// given path and the path itself.
+    pub fn package_relative_depth(&self, path: &str) -> Option<usize> {
+        let package = self.package_for(path)?;
+        let relative = if package.root.is_empty() {
+            path
+        } else {
+            path.strip_prefix(&package.root)?.trim_start_matches('/')
+        };
+        Some(relative.matches('/').count())
+    }
+}
+
+/// Metadata about a package in the workspace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageInfo {
+    /// The directory of the package, relative to the filesystem root.
+    pub root: String,
+    /// The entry module of the package (its `module` or `main` field),
+    /// relative to `root`.
+    pub entry: Option<String>,
+    /// The package's `type` field, `module` or `commonjs`.
+    pub module_type: Option<String>,
+    /// The package's `version` field, e.g. `1.2.3`.
+    pub version: Option<String>,
+    /// The files the package's `exports` map points to, under any condition,
+    /// relative to `root`, e.g. `./src/index.ts`. Subpath patterns keep their
+    /// `*`, e.g. `./src/components/*.tsx`. `None` if the package has no
+    /// `exports` field.
+    pub export_targets: Option<Vec<String>>,
+}
+
+/// A reusable bundle of conditions, e.g. one distributed by a plugin. It is
+/// registered in the [`MatchContext`] under a name.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Ruleset {
+    pub rules: Vec<Rule>,
+}
+
+/// A condition in a [`Ruleset`], identified so that matches can be traced
+/// back to it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rule {
+    pub id: String,
+    pub condition: ContextCondition,
+}
+
+impl Ruleset {
+    /// Returns the id of the first rule that matches the path.
+    pub async fn matching_rule(
+        &self,
+        path: &FileSystemPath,
+        ctx: &MatchContext,
+    ) -> Result<Option<&str>> {
+        for rule in &self.rules {
+            if rule.condition.matches_with_context(path, ctx).await? {
+                return Ok(Some(&rule.id));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl PackageInfo {
+    /// Returns the path of the package's entry module relative to the
+    /// filesystem root.
+    pub fn entry_path(&self) -> Option<String> {
+        Some(self.join(self.entry.as_deref()?))
+    }
+
+    /// Whether `path`, relative to the filesystem root, is reachable through
+    /// the package's `exports` map, or is its entry module if it has none. A
+    /// subpath pattern's `*` matches any part of a path, including `/`.
+    pub fn is_public_export(&self, path: &str) -> bool {
+        let Some(targets) = &self.export_targets else {
+            return self.entry_path().is_some_and(|entry| entry == path);
+        };
+        targets.iter().any(|target| {
+            let target = self.join(target);
+            match target.split_once('*') {
+                Some((prefix, suffix)) => {
+                    path.len() > prefix.len() + suffix.len()
+                        && path.starts_with(prefix)
+                        && path.ends_with(suffix)
+                }
+                None => target == path,
+            }
+        })
+    }
+
+    /// Resolves a path relative to the package's root.
+    fn join(&self, relative: &str) -> String {
+        let relative = relative.trim_start_matches("./");
+        if self.root.is_empty() {
+            relative.to_string()
+        } else {
+            format!("{}/{}", self.root, relative)
+        }
+    }
+}
+
+/// The module system a JavaScript or TypeScript file is loaded with, for
+/// [`ContextCondition::ModuleKind`].
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TraceRawVcs, PartialEq, Eq)]
+pub enum ModuleKind {
+    Esm,
+    Cjs,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, TraceRawVcs, PartialEq, Eq)]
 pub enum ContextCondition {
@@ -12,6 +255,293 @@ pub enum ContextCondition {
     Not(Box<ContextCondition>),
     InDirectory(String),
     InPath(Vc<FileSystemPath>),
+    /// Matches paths whose real path, with symlinks resolved, is below the
+    /// platform's temp directory, e.g. `/tmp` or `%TEMP%`, so that transient
+    /// files mounted into the workspace can be left alone. The temp
+    /// directory can be overridden in the [`MatchContext`]. Paths that aren't
+    /// on disk never match.
+    InTempDir,
+    /// Like `InTempDir`, but for the platform's cache directory, e.g.
+    /// `~/.cache`, `~/Library/Caches` or `%LOCALAPPDATA%`, where some tools
+    /// keep their caches. Nothing matches on platforms without one.
+    InOsCacheDir,
+    /// Matches when the context path differs from the given path only by
+    /// case, i.e. the two would collide on a case-insensitive filesystem.
+    /// Identical paths do not match.
+    CaseCollisionWith(Vc<FileSystemPath>),
+    /// Matches when the directory containing the context path has more than
+    /// the given number of entries. Directories that can't be read never
+    /// match.
+    ///
+    /// This reads the directory from the filesystem on every evaluation, so
+    /// place it after cheaper conditions in an `All` to let them short-circuit
+    /// first.
+    SiblingCountGreaterThan(usize),
+    /// Matches when the context path is a directory with no entries. Files
+    /// and paths that don't exist never match.
+    ///
+    /// Like `SiblingCountGreaterThan`, this reads from the filesystem, so
+    /// place it after cheaper conditions.
+    DirectoryIsEmpty,
+    /// Matches files with a sibling of the same name that has the given
+    /// extension instead of their own, e.g. `Button.tsx` with
+    /// `Button.module.css` or `Button.css` for the extension `css`. Names are
+    /// compared up to their first `.`, and only files, not directories, count
+    /// as siblings. Directories that can't be read never match.
+    ///
+    /// Like `SiblingCountGreaterThan`, this reads from the filesystem, so
+    /// place it after cheaper conditions.
+    HasSiblingExtension(String),
+    /// Matches paths inside a `.turbo` directory, where turbo keeps its own
+    /// cache and logs.
+    InTurboCache,
+    /// Matches paths with any non-ASCII character in them, e.g. an accent or
+    /// an emoji, which some toolchains don't handle. Only the path relative
+    /// to the filesystem root is checked.
+    HasNonAsciiPath,
+    /// Matches files that the current build has registered as its outputs in
+    /// the [`MatchContext`], so that a transform run during a watch rebuild
+    /// can skip what the build itself wrote instead of reacting to it.
+    IsBuildOutput,
+    /// Matches the application entrypoints configured in the
+    /// [`MatchContext`], so that a transform can run once at the app
+    /// boundary. Only exact paths match, and nothing does without any
+    /// configured entrypoints.
+    IsAppEntrypoint,
+    /// Matches the entry module of the package containing the context path.
+    /// Requires the packages to be provided in the [`MatchContext`].
+    IsPackageEntry,
+    /// Matches files that the package containing the context path makes
+    /// public through its `exports` map, including those matched by subpath
+    /// patterns. For a package without an `exports` field, this falls back to
+    /// its entry module, like [`ContextCondition::IsPackageEntry`]. Requires
+    /// the packages to be provided in the [`MatchContext`].
+    IsPublicExport,
+    /// Matches modules that import a specifier matching the given glob, e.g.
+    /// `@scope/*`. Requires the module's imports to be provided in the
+    /// [`MatchContext`]; modules without import information never match.
+    ImportsFrom(String),
+    /// Matches paths in packages with changes since the given git base ref,
+    /// e.g. `origin/main`, for building only affected packages. Requires the
+    /// packages and the files changed since the ref to be provided in the
+    /// [`MatchContext`]; without them, such as outside a git repository,
+    /// nothing matches. Paths outside every package never match.
+    InChangedWorkspace(String),
+    /// Matches paths in packages whose `version` satisfies the given semver
+    /// requirement, e.g. `^1.0.0` or `>=1.2, <2`, for migrations gated on a
+    /// package's version. Requirements use Cargo's syntax, so comparators are
+    /// separated by commas, and npm's `||` and hyphen ranges aren't supported.
+    /// Requires the packages to be provided in the [`MatchContext`];
+    /// paths outside every package, and packages without a valid version,
+    /// never match.
+    PackageVersionMatches(String),
+    /// Matches paths inside a directory matched by the workspace globs, i.e.
+    /// inside some workspace package rather than the repository's own
+    /// tooling. Requires the globs to be provided in the [`MatchContext`];
+    /// without them, nothing matches.
+    InWorkspaceGlobs,
+    /// Matches paths nested at most `max` directories deep inside the
+    /// package that contains them, so that a package's top-level files have a
+    /// depth of 0. Requires the packages to be provided in the
+    /// [`MatchContext`]; paths outside of any package never match.
+    PackageRelativeDepth {
+        max: usize,
+    },
+    /// Matches files that look binary: the start of the file contains a null
+    /// byte or isn't valid UTF-8. Files that can't be read never match.
+    ///
+    /// Like `SiblingCountGreaterThan`, this reads from the filesystem, so
+    /// place it after cheaper conditions.
+    IsBinary,
+    /// Matches files written by code generators, which mark them with a
+    /// comment such as `// @generated` or `DO NOT EDIT` near the top. Only
+    /// the start of the file is searched for [`GENERATED_MARKERS`] and the
+    /// `extra_markers`, which are compared case-sensitively. Files that can't
+    /// be read never match.
+    ///
+    /// Like `IsBinary`, this reads from the filesystem, so place it after
+    /// cheaper conditions.
+    IsGenerated {
+        extra_markers: Vec<String>,
+    },
+    /// Matches paths inside `base` whose path relative to `base` matches the
+    /// glob `pattern`, e.g. `src/**` within a package.
+    RelativeGlob {
+        base: Vc<FileSystemPath>,
+        pattern: String,
+    },
+    /// Like `RelativeGlob`, but for paths inside any of several `roots`, such
+    /// as the folders of a multi-root workspace. Roots are resolved
+    /// concurrently, and matching stops at the first root that matches.
+    GlobInRoots {
+        roots: Vec<Vc<FileSystemPath>>,
+        pattern: String,
+    },
+    /// Matches modules that export the given name, e.g. `default`. Requires
+    /// the module's exports to be provided in the [`MatchContext`]; modules
+    /// without export information never match.
+    HasExport(String),
+    /// Matches a deterministic sample of the paths matched by `inner`, e.g.
+    /// to canary a transform. Each path is included with a probability of
+    /// `rate_ppm` parts per million, so `1_000_000` includes every path,
+    /// based on a hash of the path and `seed`. The same path always gets the
+    /// same decision for a given seed.
+    Sampled {
+        inner: Box<ContextCondition>,
+        rate_ppm: u32,
+        seed: u64,
+    },
+    /// Matches the paths matched by `inner` until it has matched `max` of
+    /// them, and nothing after that, as a safety valve for rules that are
+    /// broader than intended. Matches are counted per [`MatchContext`], so
+    /// evaluating without one, e.g. via [`ContextCondition::matches`], never
+    /// reaches the cap.
+    ///
+    /// Rules with the same `id` share a count, so each rule that should be
+    /// capped on its own needs an `id` of its own.
+    Capped {
+        id: String,
+        inner: Box<ContextCondition>,
+        max: usize,
+    },
+    /// Matches files whose content hashes to shard `index` out of `shards`,
+    /// so that `shards` workers can each handle a disjoint, stable subset of
+    /// files. Only the start of each file is hashed. Files that can't be read
+    /// never match, and nothing matches if `shards` is 0.
+    ///
+    /// Like `IsBinary`, this reads from the filesystem, so place it after
+    /// cheaper conditions.
+    ContentHashShard {
+        shards: u32,
+        index: u32,
+    },
+    /// Matches if any rule in the named [`Ruleset`] matches. Requires the
+    /// ruleset to be registered in the [`MatchContext`]; unknown rulesets
+    /// never match. A ruleset must not refer to itself, directly or through
+    /// other rulesets.
+    MatchesRuleset(String),
+    /// Matches if the named [`Predicate`] returns true for the path. Like
+    /// `MatchesRuleset`, the predicate is looked up in the [`MatchContext`]
+    /// when matching, and unknown predicates never match.
+    NamedPredicate(String),
+    /// Matches paths covered by any of the runtime ignores in the
+    /// [`MatchContext`], so that a run can exclude paths ad hoc without
+    /// changing any config. Nothing matches without runtime ignores.
+    InRuntimeIgnore,
+    /// Matches paths below a directory that contains any of the given marker
+    /// files, e.g. `package.json`, `jsconfig.json` or `tsconfig.json`. Use
+    /// [`nearest_marker_dir`] to find that directory.
+    ///
+    /// Like `SiblingCountGreaterThan`, this reads from the filesystem, so
+    /// place it after cheaper conditions.
+    NearestMarkerAny(Vec<String>),
+    /// Matches paths listed in the given file, one path relative to the
+    /// filesystem root per line. Lines are compared exactly, without
+    /// interpreting patterns; blank lines are ignored. A missing allowlist
+    /// matches nothing.
+    ///
+    /// The file is read once when the condition is compiled, so a
+    /// [`CompiledCondition`] keeps using the list it was compiled with.
+    /// Uncompiled matching reads the file through turbo-tasks, which caches
+    /// it until it changes.
+    InAllowlist(Vc<FileSystemPath>),
+    /// Matches `inner`, but only evaluates it for paths that start with
+    /// `prefix`, so that an expensive condition can be guarded by a cheap
+    /// string comparison. Paths without the prefix never match.
+    Guarded {
+        prefix: String,
+        inner: Box<ContextCondition>,
+    },
+    /// Matches when the context path and the given path are the same file,
+    /// including through symlinks and, on Unix, hardlinks. On filesystems
+    /// that can't be read, such as a virtual one, the paths are compared as
+    /// they are.
+    ///
+    /// Like `SiblingCountGreaterThan`, this reads from the filesystem, so
+    /// place it after cheaper conditions.
+    SameFileAs(Vc<FileSystemPath>),
+    /// Matches paths listed in the given manifest whose position is in
+    /// `start..end`, so that build steps can partition work by a checked-in
+    /// ordering. The manifest is read like an `InAllowlist` file, and a path
+    /// listed more than once takes the position of its first listing. Paths
+    /// that aren't listed never match.
+    ///
+    /// Like `InAllowlist`, the manifest is read once when the condition is
+    /// compiled.
+    InManifestRange {
+        manifest: Vc<FileSystemPath>,
+        start: usize,
+        end: usize,
+    },
+    /// Matches files that the given alias in the `compilerOptions.paths` of
+    /// the nearest `tsconfig.json`, e.g. `@app/*`, resolves to. Targets are
+    /// relative to the tsconfig's `baseUrl`, or its directory without one,
+    /// and a target without a `*` also matches with any extension added. The
+    /// tsconfig's `extends` is not followed. Paths without a tsconfig, or
+    /// whose tsconfig doesn't define the alias, never match.
+    ///
+    /// Like `NearestMarkerAny`, this reads from the filesystem, so place it
+    /// after cheaper conditions.
+    MatchesTsconfigAlias(String),
+    /// Matches files covered by the given section of the nearest
+    /// `.editorconfig`, e.g. `*.{js,ts}`, using editorconfig's glob rules:
+    /// `*` doesn't cross directories but `**` does, `{a,b}` and `{1..3}` are
+    /// alternatives, and a glob without a `/` matches files by name in any
+    /// directory below the `.editorconfig`. Paths without an `.editorconfig`,
+    /// or whose `.editorconfig` has no such section, never match.
+    ///
+    /// Like `NearestMarkerAny`, this reads from the filesystem, so place it
+    /// after cheaper conditions.
+    MatchesEditorConfigSection(String),
+    /// Matches test files by the common conventions:
+    /// - file names with a `.test.` or `.spec.` part, e.g. `button.test.tsx`;
+    /// - files anywhere below a `__tests__`, `test` or `tests` directory.
+    ///
+    /// Paths matching any of the `extra_patterns` globs, relative to the
+    /// filesystem root, are test files as well, e.g. `**/*.e2e.ts`.
+    IsTestFile {
+        extra_patterns: Vec<String>,
+    },
+    /// Matches files whose extension, without the leading `.`, is one of
+    /// `extensions`. Only the last extension counts, so `a.d.ts` has the
+    /// extension `ts`. Use [`ContextCondition::is_source_file`] for the
+    /// [`SOURCE_EXTENSIONS`] that are compiled by default.
+    IsSourceFile {
+        extensions: Vec<String>,
+    },
+    /// Matches package manager lockfiles by their name: the
+    /// [`LOCKFILE_NAMES`] and any of the `extra_names`, e.g.
+    /// `deno.lock`.
+    IsLockfile {
+        extra_names: Vec<String>,
+    },
+    /// Matches paths inside a directory of third-party code: one named like
+    /// the [`VENDORED_DIRS`] or any of the `extra_dirs`, e.g. `deps`. Only
+    /// whole path components count, so `vendored_docs` is not vendored.
+    IsVendored {
+        extra_dirs: Vec<String>,
+    },
+    /// Matches the config files of known tools by their name: the
+    /// [`TOOL_CONFIG_PATTERNS`] and any of the `extra_patterns`, e.g.
+    /// `knip.json`, so that transforms can target configs declaratively.
+    IsToolConfig {
+        extra_patterns: Vec<String>,
+    },
+    /// Matches JavaScript and TypeScript files loaded with the given module
+    /// system, the way Node decides it: `.mjs` and `.mts` files are ES
+    /// modules, `.cjs` and `.cts` files are CommonJS, and for other
+    /// extensions the `type` field of the package containing the file in the
+    /// [`MatchContext`] decides. Files outside any known package, or in one
+    /// without a `type` field, are CommonJS.
+    ModuleKind(ModuleKind),
+    /// Matches paths below a directory that owns a turbo config: one with a
+    /// `turbo.json`, or a `package.json` with a `turbo` key. Directories are
+    /// searched up to and including the filesystem root.
+    ///
+    /// The answer for each directory searched is remembered in the
+    /// [`MatchContext`], so paths that share directories are only looked up
+    /// once per context.
+    InTurboPackage,
 }
 
 impl ContextCondition {
@@ -31,16 +561,139 @@ impl ContextCondition {
         ContextCondition::Not(Box::new(condition))
     }
 
-    #[async_recursion]
+    /// Creates a condition that matches source files with any of the
+    /// [`SOURCE_EXTENSIONS`].
+    pub fn is_source_file() -> ContextCondition {
+        ContextCondition::IsSourceFile {
+            extensions: SOURCE_EXTENSIONS
+                .iter()
+                .map(|ext| ext.to_string())
+                .collect(),
+        }
+    }
+
+    /// Estimates how specific this condition is, so that when several
+    /// condition-gated rules could apply, the most specific one can be
+    /// preferred. Higher scores mean the condition matches fewer paths.
+    ///
+    /// This is a heuristic and never consults the filesystem:
+    /// - `CaseCollisionWith` and `SameFileAs` match (aliases of) a single path;
+    /// - `InAllowlist` and `InManifestRange` match a handful of chosen files;
+    /// - `IsPackageEntry` and `IsAppEntrypoint` match a single file per package
+    ///   or app;
+    /// - `IsLockfile`, `IsToolConfig` and `IsPublicExport` match a handful of
+    ///   files per package;
+    /// - `IsBuildOutput` matches the files a build has written;
+    /// - `RelativeGlob` matches part of a single subtree;
+    /// - `GlobInRoots` matches part of a few subtrees;
+    /// - `InPath`, `InTempDir` and `InOsCacheDir` match a single subtree;
+    /// - `MatchesTsconfigAlias` matches what an alias resolves to, usually a
+    ///   subtree;
+    /// - `HasExport` matches the modules that export a given name;
+    /// - `ImportsFrom` matches the dependents of some modules;
+    /// - `InChangedWorkspace` and `PackageVersionMatches` match the packages
+    ///   with changes or at some versions;
+    /// - `IsBinary`, `IsGenerated`, `IsTestFile`, `IsSourceFile` and
+    ///   `ModuleKind` match a kind of file;
+    /// - `HasSiblingExtension` matches files that are paired with another;
+    /// - `MatchesEditorConfigSection` matches what a section covers, usually a
+    ///   kind of file;
+    /// - `ContentHashShard` matches a fraction of all files;
+    /// - `NearestMarkerAny` and `InTurboPackage` match anything below common
+    ///   project files;
+    /// - `MatchesRuleset`, `NamedPredicate` and `InRuntimeIgnore` match
+    ///   whatever their rules, predicates or ignores do, which can't be known
+    ///   without a context;
+    /// - `InDirectory`, `InTurboCache` and `IsVendored` match any directory
+    ///   with a given name;
+    /// - `SiblingCountGreaterThan` and `PackageRelativeDepth` match anything in
+    ///   large enough directories or shallow enough packages;
+    /// - `DirectoryIsEmpty` only matches directories, and few of them;
+    /// - `HasNonAsciiPath` matches the few paths with unusual names;
+    /// - `All` adds up the specificity of its conditions, since each one
+    ///   narrows the match further (an empty `All` matches everything);
+    /// - `Any` is only as specific as its least specific condition;
+    /// - `InWorkspaceGlobs` matches anything inside a workspace package;
+    /// - `Not` matches nearly everything its inner condition doesn't;
+    /// - `Sampled` is slightly more specific than its inner condition;
+    /// - `Capped` is as specific as its inner condition, since the cap only
+    ///   limits how many paths it matches in total;
+    /// - `Guarded` narrows its inner condition to a subtree, like `InPath`.
+    pub fn specificity(&self) -> u32 {
+        match self {
+            ContextCondition::All(conditions) => conditions
+                .iter()
+                .fold(0, |acc, c| acc.saturating_add(c.specificity())),
+            ContextCondition::Any(conditions) => conditions
+                .iter()
+                .map(|c| c.specificity())
+                .min()
+                .unwrap_or(0),
+            ContextCondition::Not(_) => 1,
+            ContextCondition::InDirectory(_)
+            | ContextCondition::InTurboCache
+            | ContextCondition::IsVendored { .. } => 25,
+            ContextCondition::InPath(_)
+            | ContextCondition::InTempDir
+            | ContextCondition::InOsCacheDir => 50,
+            ContextCondition::MatchesTsconfigAlias(_) => 55,
+            ContextCondition::CaseCollisionWith(_) | ContextCondition::SameFileAs(_) => 100,
+            ContextCondition::IsPackageEntry | ContextCondition::IsAppEntrypoint => 75,
+            ContextCondition::ImportsFrom(_) => 40,
+            ContextCondition::InChangedWorkspace(_)
+            | ContextCondition::PackageVersionMatches(_) => 35,
+            ContextCondition::InWorkspaceGlobs => 5,
+            ContextCondition::HasExport(_) => 45,
+            ContextCondition::Sampled { inner, .. } => inner.specificity().saturating_add(1),
+            ContextCondition::Capped { inner, .. } => inner.specificity(),
+            ContextCondition::PackageRelativeDepth { .. } => 10,
+            ContextCondition::IsBinary
+            | ContextCondition::IsGenerated { .. }
+            | ContextCondition::IsTestFile { .. }
+            | ContextCondition::IsSourceFile { .. }
+            | ContextCondition::ModuleKind(_) => 20,
+            ContextCondition::HasSiblingExtension(_)
+            | ContextCondition::MatchesEditorConfigSection(_) => 20,
+            ContextCondition::IsLockfile { .. }
+            | ContextCondition::IsToolConfig { .. }
+            | ContextCondition::IsPublicExport => 70,
+            ContextCondition::ContentHashShard { .. } => 15,
+            ContextCondition::MatchesRuleset(_)
+            | ContextCondition::NamedPredicate(_)
+            | ContextCondition::InRuntimeIgnore => 30,
+            ContextCondition::NearestMarkerAny(_) | ContextCondition::InTurboPackage => 15,
+            ContextCondition::InAllowlist(_) | ContextCondition::InManifestRange { .. } => 90,
+            ContextCondition::Guarded { inner, .. } => inner.specificity().saturating_add(50),
+            ContextCondition::RelativeGlob { .. } => 60,
+            ContextCondition::IsBuildOutput => 65,
+            ContextCondition::GlobInRoots { .. } => 55,
+            ContextCondition::SiblingCountGreaterThan(_) => 10,
+            ContextCondition::DirectoryIsEmpty => 80,
+            ContextCondition::HasNonAsciiPath => 85,
+        }
+    }
+
     /// Returns true if the condition matches the context.
     pub async fn matches(&self, path: &FileSystemPath) -> Result<bool> {
+        self.matches_with_context(path, &MatchContext::default())
+            .await
+    }
+
+    #[async_recursion]
+    /// Returns true if the condition matches the context, using `ctx` for
+    /// conditions that need to know about the workspace.
+    pub async fn matches_with_context(
+        &self,
+        path: &FileSystemPath,
+        ctx: &MatchContext,
+    ) -> Result<bool> {
         match self {
             ContextCondition::All(conditions) => {
                 // False positive.
                 #[allow(clippy::manual_try_fold)]
                 stream::iter(conditions)
                     .fold(Ok(true), |acc, c| async move {
-                        Ok(acc? && c.matches(path).await?)
+                        Ok(acc? && c.matches_with_context(path, ctx).await?)
                     })
                     .await
             }
@@ -49,18 +702,3358 @@ impl ContextCondition {
                 #[allow(clippy::manual_try_fold)]
                 stream::iter(conditions)
                     .fold(Ok(false), |acc, c| async move {
-                        Ok(acc? || c.matches(path).await?)
+                        Ok(acc? || c.matches_with_context(path, ctx).await?)
                     })
                     .await
             }
-            ContextCondition::Not(condition) => condition.matches(path).await.map(|b| !b),
+            ContextCondition::Not(condition) => {
+                condition.matches_with_context(path, ctx).await.map(|b| !b)
+            }
             ContextCondition::InPath(other_path) => {
                 Ok(path.is_inside_or_equal_ref(&*other_path.await?))
             }
-            ContextCondition::InDirectory(dir) => Ok(path.path.starts_with(&format!("{dir}/"))
-                || path.path.contains(&format!("/{dir}/"))
-                || path.path.ends_with(&format!("/{dir}"))
-                || path.path == *dir),
+            ContextCondition::CaseCollisionWith(other_path) => {
+                let other_path = &*other_path.await?;
+                Ok(path.fs == other_path.fs
+                    && path.path != other_path.path
+                    && path.path.to_lowercase() == other_path.path.to_lowercase())
+            }
+            ContextCondition::SameFileAs(other_path) => same_file(path, *other_path).await,
+            ContextCondition::InTempDir => in_temp_dir(path, ctx).await,
+            ContextCondition::InOsCacheDir => in_os_cache_dir(path, ctx).await,
+            ContextCondition::SiblingCountGreaterThan(threshold) => {
+                let parent = path.clone().cell().parent();
+                Ok(match parent.read_dir().await {
+                    Ok(content) => match &*content {
+                        DirectoryContent::Entries(entries) => entries.len() > *threshold,
+                        DirectoryContent::NotFound => false,
+                    },
+                    Err(_) => false,
+                })
+            }
+            ContextCondition::DirectoryIsEmpty => {
+                // reading a file as a directory finds nothing, like a missing path
+                Ok(match path.clone().cell().read_dir().await {
+                    Ok(content) => match &*content {
+                        DirectoryContent::Entries(entries) => entries.is_empty(),
+                        DirectoryContent::NotFound => false,
+                    },
+                    Err(_) => false,
+                })
+            }
+            ContextCondition::HasSiblingExtension(extension) => {
+                has_sibling_extension(path, extension).await
+            }
+            ContextCondition::InTurboCache => {
+                Ok(path.path.split('/').any(|segment| segment == ".turbo"))
+            }
+            ContextCondition::HasNonAsciiPath => Ok(!path.path.is_ascii()),
+            ContextCondition::IsTestFile { extra_patterns } => {
+                if is_conventional_test_file(&path.path) {
+                    return Ok(true);
+                }
+                for pattern in extra_patterns {
+                    if Glob::parse(pattern)?.execute(&path.path) {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            ContextCondition::IsLockfile { extra_names } => {
+                let name = path.file_name();
+                Ok(LOCKFILE_NAMES.contains(&name) || extra_names.iter().any(|extra| extra == name))
+            }
+            ContextCondition::IsToolConfig { extra_patterns } => {
+                Ok(tool_config_globs(extra_patterns)?
+                    .iter()
+                    .any(|glob| glob.execute(path.file_name())))
+            }
+            ContextCondition::IsVendored { extra_dirs } => {
+                Ok(path.path.split('/').any(|segment| {
+                    VENDORED_DIRS.contains(&segment)
+                        || extra_dirs.iter().any(|extra| extra == segment)
+                }))
+            }
+            ContextCondition::IsSourceFile { extensions } => Ok(path
+                .extension_ref()
+                .is_some_and(|ext| extensions.iter().any(|source| source == ext))),
+            ContextCondition::IsPackageEntry => Ok(ctx
+                .package_for(&path.path)
+                .and_then(PackageInfo::entry_path)
+                .is_some_and(|entry| entry == path.path)),
+            ContextCondition::IsBuildOutput => Ok(ctx.build_outputs.contains(&path.path)),
+            ContextCondition::IsAppEntrypoint => Ok(ctx.app_entrypoints.contains(&path.path)),
+            ContextCondition::IsPublicExport => Ok(ctx
+                .package_for(&path.path)
+                .is_some_and(|package| package.is_public_export(&path.path))),
+            ContextCondition::ModuleKind(kind) => {
+                Ok(module_kind(path, ctx).is_some_and(|actual| actual == *kind))
+            }
+            ContextCondition::InChangedWorkspace(base) => {
+                Ok(in_changed_workspace(&path.path, base, ctx))
+            }
+            ContextCondition::PackageVersionMatches(range) => {
+                package_version_matches(&path.path, &VersionReq::parse(range)?, ctx)
+            }
+            ContextCondition::InWorkspaceGlobs => in_workspace_globs(&path.path, ctx),
+            ContextCondition::ImportsFrom(specifier) => {
+                imports_from(&Glob::parse(specifier)?, path, ctx)
+            }
+            ContextCondition::RelativeGlob { base, pattern } => {
+                Ok(relative_glob(&*base.await?, &Glob::parse(pattern)?, path))
+            }
+            ContextCondition::GlobInRoots { roots, pattern } => {
+                glob_in_roots(roots, &Glob::parse(pattern)?, path).await
+            }
+            ContextCondition::IsBinary => Ok(match path.clone().cell().read().await {
+                Ok(content) => match &*content {
+                    FileContent::Content(file) => looks_binary(file.content().read())?,
+                    FileContent::NotFound => false,
+                },
+                Err(_) => false,
+            }),
+            ContextCondition::IsGenerated { extra_markers } => {
+                Ok(match path.clone().cell().read().await {
+                    Ok(content) => match &*content {
+                        FileContent::Content(file) => {
+                            has_generated_marker(file.content().read(), extra_markers)?
+                        }
+                        FileContent::NotFound => false,
+                    },
+                    Err(_) => false,
+                })
+            }
+            ContextCondition::ContentHashShard { shards, index } => {
+                if *shards == 0 {
+                    return Ok(false);
+                }
+                Ok(match path.clone().cell().read().await {
+                    Ok(content) => match &*content {
+                        FileContent::Content(file) => {
+                            content_hash(file.content().read())? % u64::from(*shards)
+                                == u64::from(*index)
+                        }
+                        FileContent::NotFound => false,
+                    },
+                    Err(_) => false,
+                })
+            }
+            ContextCondition::PackageRelativeDepth { max } => Ok(ctx
+                .package_relative_depth(&path.path)
+                .is_some_and(|depth| depth <= *max)),
+            ContextCondition::InAllowlist(allowlist) => {
+                Ok(load_allowlist(*allowlist).await?.contains(&path.path))
+            }
+            ContextCondition::InManifestRange {
+                manifest,
+                start,
+                end,
+            } => Ok(load_manifest_range(*manifest, *start, *end)
+                .await?
+                .contains(&path.path)),
+            ContextCondition::Guarded { prefix, inner } => {
+                Ok(path.path.starts_with(prefix) && inner.matches_with_context(path, ctx).await?)
+            }
+            ContextCondition::NearestMarkerAny(markers) => {
+                Ok(nearest_marker_dir(path, markers).await?.is_some())
+            }
+            ContextCondition::MatchesEditorConfigSection(section) => {
+                matches_editorconfig_section(path, section, &editorconfig_regex(section)?).await
+            }
+            ContextCondition::MatchesTsconfigAlias(alias) => {
+                matches_tsconfig_alias(path, alias).await
+            }
+            ContextCondition::InTurboPackage => in_turbo_package(path, &ctx.turbo_packages).await,
+            ContextCondition::MatchesRuleset(name) => Ok(match ctx.rulesets.get(name) {
+                Some(ruleset) => ruleset.matching_rule(path, ctx).await?.is_some(),
+                None => false,
+            }),
+            ContextCondition::NamedPredicate(name) => Ok(ctx
+                .predicates
+                .get(name)
+                .is_some_and(|predicate| predicate(path))),
+            ContextCondition::InRuntimeIgnore => in_runtime_ignore(&path.path, ctx),
+            ContextCondition::HasExport(name) => Ok(ctx
+                .exports
+                .get(&path.path)
+                .is_some_and(|exports| exports.contains(name))),
+            ContextCondition::Sampled {
+                inner,
+                rate_ppm,
+                seed,
+            } => {
+                Ok(inner.matches_with_context(path, ctx).await? && sampled(path, *rate_ppm, *seed))
+            }
+            ContextCondition::Capped { id, inner, max } => {
+                Ok(inner.matches_with_context(path, ctx).await? && below_cap(id, *max, ctx))
+            }
+            ContextCondition::InDirectory(dir) => {
+                Ok(DirectoryPatterns::new(dir).matches(&path.path))
+            }
+        }
+    }
+
+    /// Prepares this condition for matching many paths, doing the work that
+    /// doesn't depend on the path once up front.
+    #[async_recursion]
+    pub async fn compile(&self) -> Result<CompiledCondition> {
+        Ok(match self {
+            ContextCondition::All(conditions) => {
+                CompiledCondition::All(compile_all(conditions).await?)
+            }
+            ContextCondition::Any(conditions) => {
+                CompiledCondition::Any(compile_all(conditions).await?)
+            }
+            ContextCondition::Not(condition) => {
+                CompiledCondition::Not(Box::new(condition.compile().await?))
+            }
+            ContextCondition::InDirectory(dir) => {
+                CompiledCondition::InDirectory(DirectoryPatterns::new(dir))
+            }
+            ContextCondition::InPath(other_path) => CompiledCondition::InPath(other_path.await?),
+            ContextCondition::InAllowlist(allowlist) => {
+                CompiledCondition::InAllowlist(load_allowlist(*allowlist).await?)
+            }
+            ContextCondition::InManifestRange {
+                manifest,
+                start,
+                end,
+            } => CompiledCondition::InManifestRange(
+                load_manifest_range(*manifest, *start, *end).await?,
+            ),
+            ContextCondition::Guarded { prefix, inner } => {
+                CompiledCondition::Guarded(prefix.clone(), Box::new(inner.compile().await?))
+            }
+            ContextCondition::CaseCollisionWith(other_path) => {
+                let other_path = other_path.await?;
+                let lowercase = other_path.path.to_lowercase();
+                CompiledCondition::CaseCollisionWith(other_path, lowercase)
+            }
+            ContextCondition::InTurboCache => CompiledCondition::InTurboCache,
+            ContextCondition::HasNonAsciiPath => CompiledCondition::HasNonAsciiPath,
+            ContextCondition::InTurboPackage => CompiledCondition::InTurboPackage,
+            ContextCondition::InWorkspaceGlobs => CompiledCondition::InWorkspaceGlobs,
+            ContextCondition::InRuntimeIgnore => CompiledCondition::InRuntimeIgnore,
+            ContextCondition::IsTestFile { extra_patterns } => CompiledCondition::IsTestFile(
+                extra_patterns
+                    .iter()
+                    .map(|pattern| Glob::parse(pattern))
+                    .collect::<Result<_>>()?,
+            ),
+            ContextCondition::IsSourceFile { extensions } => {
+                CompiledCondition::IsSourceFile(extensions.iter().cloned().collect())
+            }
+            ContextCondition::MatchesEditorConfigSection(section) => {
+                CompiledCondition::MatchesEditorConfigSection(
+                    section.clone(),
+                    editorconfig_regex(section)?,
+                )
+            }
+            ContextCondition::IsLockfile { extra_names } => CompiledCondition::IsLockfile(
+                LOCKFILE_NAMES
+                    .iter()
+                    .map(|name| name.to_string())
+                    .chain(extra_names.iter().cloned())
+                    .collect(),
+            ),
+            ContextCondition::IsVendored { extra_dirs } => CompiledCondition::IsVendored(
+                VENDORED_DIRS
+                    .iter()
+                    .map(|name| name.to_string())
+                    .chain(extra_dirs.iter().cloned())
+                    .collect(),
+            ),
+            ContextCondition::IsToolConfig { extra_patterns } => {
+                CompiledCondition::IsToolConfig(tool_config_globs(extra_patterns)?)
+            }
+            ContextCondition::RelativeGlob { base, pattern } => {
+                CompiledCondition::RelativeGlob(base.await?, Glob::parse(pattern)?)
+            }
+            ContextCondition::GlobInRoots { roots, pattern } => CompiledCondition::GlobInRoots(
+                futures::future::try_join_all(roots.iter().map(|root| async move { root.await }))
+                    .await?,
+                Glob::parse(pattern)?,
+            ),
+            ContextCondition::ImportsFrom(specifier) => {
+                CompiledCondition::ImportsFrom(Glob::parse(specifier)?)
+            }
+            ContextCondition::Sampled {
+                inner,
+                rate_ppm,
+                seed,
+            } => CompiledCondition::Sampled(Box::new(inner.compile().await?), *rate_ppm, *seed),
+            ContextCondition::Capped { id, inner, max } => {
+                CompiledCondition::Capped(Box::new(inner.compile().await?), *max, id.clone())
+            }
+            ContextCondition::PackageVersionMatches(range) => {
+                CompiledCondition::PackageVersionMatches(VersionReq::parse(range)?)
+            }
+            ContextCondition::SiblingCountGreaterThan(_)
+            | ContextCondition::DirectoryIsEmpty
+            | ContextCondition::HasSiblingExtension(_)
+            | ContextCondition::IsPackageEntry
+            | ContextCondition::IsPublicExport
+            | ContextCondition::IsBuildOutput
+            | ContextCondition::IsAppEntrypoint
+            | ContextCondition::ModuleKind(_)
+            | ContextCondition::PackageRelativeDepth { .. }
+            | ContextCondition::IsBinary
+            | ContextCondition::IsGenerated { .. }
+            | ContextCondition::ContentHashShard { .. }
+            | ContextCondition::MatchesRuleset(_)
+            | ContextCondition::NamedPredicate(_)
+            | ContextCondition::InChangedWorkspace(_)
+            | ContextCondition::NearestMarkerAny(_)
+            | ContextCondition::SameFileAs(_)
+            | ContextCondition::InTempDir
+            | ContextCondition::InOsCacheDir
+            | ContextCondition::MatchesTsconfigAlias(_)
+            | ContextCondition::HasExport(_) => CompiledCondition::Dynamic(self.clone()),
+        })
+    }
+}
+
+/// Finds the nearest directory above `path` that contains any of the given
+/// marker files, up to and including the filesystem root. Directories that
+/// can't be read are skipped.
+pub async fn nearest_marker_dir(
+    path: &FileSystemPath,
+    markers: &[String],
+) -> Result<Option<Vc<FileSystemPath>>> {
+    let mut dir = path.clone().cell().parent();
+    loop {
+        if let Ok(content) = dir.read_dir().await {
+            if let DirectoryContent::Entries(entries) = &*content {
+                if markers.iter().any(|marker| entries.contains_key(marker)) {
+                    return Ok(Some(dir));
+                }
+            }
+        }
+        if dir.await?.path.is_empty() {
+            return Ok(None);
+        }
+        dir = dir.parent();
+    }
+}
+
+/// Whether the directory containing `path` has a file with the same name up to
+/// the first `.` and the given extension, for
+/// [`ContextCondition::HasSiblingExtension`].
+async fn has_sibling_extension(path: &FileSystemPath, extension: &str) -> Result<bool> {
+    let name = path.file_name();
+    let base = name.split_once('.').map_or(name, |(base, _)| base);
+    let Ok(content) = path.clone().cell().parent().read_dir().await else {
+        return Ok(false);
+    };
+    let DirectoryContent::Entries(entries) = &*content else {
+        return Ok(false);
+    };
+    Ok(entries.iter().any(|(sibling, entry)| {
+        sibling != name
+            && matches!(entry, DirectoryEntry::File(_) | DirectoryEntry::Symlink(_))
+            && sibling
+                .strip_prefix(base)
+                .and_then(|rest| rest.strip_prefix('.'))
+                .is_some_and(|rest| {
+                    rest == extension
+                        || rest
+                            .strip_suffix(extension)
+                            .is_some_and(|rest| rest.ends_with('.'))
+                })
+    }))
+}
+
+/// Counts a match of the [`ContextCondition::Capped`] conditions with the
+/// given `id`, returning whether it was within the first `max` matches.
+fn below_cap(id: &str, max: usize, ctx: &MatchContext) -> bool {
+    let mut counts = ctx
+        .capped_matches
+        .lock()
+        .expect("capped matches lock poisoned");
+    let count = counts.entry(id.to_string()).or_default();
+    if *count >= max {
+        return false;
+    }
+    *count += 1;
+    true
+}
+
+/// Whether a directory above `path` is matched by the workspace globs, for
+/// [`ContextCondition::InWorkspaceGlobs`].
+fn in_workspace_globs(path: &str, ctx: &MatchContext) -> Result<bool> {
+    let (include, exclude) = ctx.parsed_globs.workspace(&ctx.workspace_globs)?;
+    // a package's own directory is inside the package, but not the path itself
+    Ok(path.match_indices('/').any(|(end, _)| {
+        let dir = &path[..end];
+        include.iter().any(|glob| glob.execute(dir))
+            && !exclude.iter().any(|glob| glob.execute(dir))
+    }))
+}
+
+/// Whether `path` is matched by the runtime ignores, for
+/// [`ContextCondition::InRuntimeIgnore`].
+fn in_runtime_ignore(path: &str, ctx: &MatchContext) -> Result<bool> {
+    Ok(ctx
+        .parsed_globs
+        .runtime_ignores(&ctx.runtime_ignores)?
+        .iter()
+        .any(|glob| glob.execute(path)))
+}
+
+/// Whether the package containing `path` has files changed since `base`, for
+/// [`ContextCondition::InChangedWorkspace`].
+fn in_changed_workspace(path: &str, base: &str, ctx: &MatchContext) -> bool {
+    let (Some(changed_files), Some(package)) = (ctx.changed_files.get(base), ctx.package_for(path))
+    else {
+        return false;
+    };
+    changed_files.iter().any(|file| {
+        ctx.package_for(file)
+            .is_some_and(|changed| changed.root == package.root)
+    })
+}
+
+/// Whether directories are in a turbo package, keyed by their filesystem and
+/// path, for [`ContextCondition::InTurboPackage`].
+pub type TurboPackageCache = Mutex<HashMap<(Vc<Box<dyn FileSystem>>, String), bool>>;
+
+/// Whether any directory above `path` owns a turbo config. Every directory
+/// searched is recorded in `cache`, and the search stops at the first one
+/// already in it.
+async fn in_turbo_package(path: &FileSystemPath, cache: &TurboPackageCache) -> Result<bool> {
+    let mut dir = path.clone().cell().parent();
+    let mut searched = Vec::new();
+    let found = loop {
+        let dir_path = dir.await?;
+        let key = (dir_path.fs, dir_path.path.clone());
+        if let Some(&found) = cache
+            .lock()
+            .expect("turbo package cache lock poisoned")
+            .get(&key)
+        {
+            break found;
+        }
+        searched.push(key);
+        if has_turbo_config(dir).await? {
+            break true;
+        }
+        if dir_path.path.is_empty() {
+            break false;
+        }
+        dir = dir.parent();
+    };
+
+    let mut cache = cache.lock().expect("turbo package cache lock poisoned");
+    for key in searched {
+        cache.insert(key, found);
+    }
+    Ok(found)
+}
+
+/// Whether `dir` contains a `turbo.json`, or a `package.json` with a `turbo`
+/// key. Directories that can't be read have neither.
+async fn has_turbo_config(dir: Vc<FileSystemPath>) -> Result<bool> {
+    let Ok(content) = dir.read_dir().await else {
+        return Ok(false);
+    };
+    let DirectoryContent::Entries(entries) = &*content else {
+        return Ok(false);
+    };
+    if entries.contains_key(&"turbo.json".to_string()) {
+        return Ok(true);
+    }
+    if !entries.contains_key(&"package.json".to_string()) {
+        return Ok(false);
+    }
+    let package_json = dir
+        .join("package.json".to_string())
+        .read()
+        .parse_json()
+        .await?;
+    Ok(matches!(&*package_json, FileJsonContent::Content(json) if json.get("turbo").is_some()))
+}
+
+/// Whether `path` is a target of `alias` in the nearest tsconfig, for
+/// [`ContextCondition::MatchesTsconfigAlias`].
+async fn matches_tsconfig_alias(path: &FileSystemPath, alias: &str) -> Result<bool> {
+    let Some(dir) = nearest_marker_dir(path, &["tsconfig.json".to_string()]).await? else {
+        return Ok(false);
+    };
+    let tsconfig = dir
+        .join("tsconfig.json".to_string())
+        .read()
+        .parse_json_with_comments()
+        .await?;
+    let FileJsonContent::Content(tsconfig) = &*tsconfig else {
+        return Ok(false);
+    };
+    let compiler_options = &tsconfig["compilerOptions"];
+    let Some(targets) = compiler_options["paths"][alias].as_array() else {
+        return Ok(false);
+    };
+    let base = match compiler_options["baseUrl"].as_str() {
+        Some(base_url) => dir.join(base_url.to_string()).await?,
+        None => dir.await?,
+    };
+    let Some(relative) = base.get_path_to(path) else {
+        return Ok(false);
+    };
+    Ok(targets
+        .iter()
+        .filter_map(Value::as_str)
+        .any(|target| alias_target_matches(target, relative)))
+}
+
+/// Whether `path` is covered by `section` of the nearest `.editorconfig`, for
+/// [`ContextCondition::MatchesEditorConfigSection`]. `regex` is the section's
+/// glob, from [`editorconfig_regex`].
+async fn matches_editorconfig_section(
+    path: &FileSystemPath,
+    section: &str,
+    regex: &Regex,
+) -> Result<bool> {
+    let Some(dir) = nearest_marker_dir(path, &[".editorconfig".to_string()]).await? else {
+        return Ok(false);
+    };
+    let content = dir.join(".editorconfig".to_string()).read().await?;
+    let FileContent::Content(file) = &*content else {
+        return Ok(false);
+    };
+    let mut editorconfig = String::new();
+    file.content().read().read_to_string(&mut editorconfig)?;
+    let has_section = editorconfig.lines().any(|line| {
+        line.trim()
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+            == Some(section)
+    });
+    if !has_section {
+        return Ok(false);
+    }
+    let dir = dir.await?;
+    Ok(dir
+        .get_path_to(path)
+        .is_some_and(|relative| regex.is_match(relative)))
+}
+
+/// The most numbers an editorconfig `{num1..num2}` range can cover.
+const MAX_EDITORCONFIG_RANGE: i64 = 10_000;
+
+/// Translates an editorconfig section glob to a regex matching paths relative
+/// to the `.editorconfig`'s directory.
+fn editorconfig_regex(glob: &str) -> Result<Regex> {
+    // a glob with a `/` is relative to the `.editorconfig`, one without it
+    // matches in any directory
+    let (glob, anchored) = match glob.strip_prefix('/') {
+        Some(glob) => (glob, true),
+        None => (glob, glob.contains('/')),
+    };
+    let mut regex = String::from(if anchored { "^" } else { "^(?:.*/)?" });
+    push_editorconfig_glob(glob, &mut regex)?;
+    regex.push('$');
+    Ok(Regex::new(&regex)?)
+}
+
+fn push_editorconfig_glob(glob: &str, regex: &mut String) -> Result<()> {
+    let mut chars = glob.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some((_, escaped)) = chars.next() {
+                    regex.push_str(&regex::escape(&escaped.to_string()));
+                }
+            }
+            '*' if chars.next_if(|&(_, c)| c == '*').is_some() => regex.push_str(".*"),
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                let Some(len) = glob[i + 1..].find(']') else {
+                    regex.push_str(r"\[");
+                    continue;
+                };
+                let class = &glob[i + 1..i + 1 + len];
+                let (negated, class) = match class.strip_prefix('!') {
+                    Some(class) => (true, class),
+                    None => (false, class),
+                };
+                regex.push_str(if negated { "[^" } else { "[" });
+                for c in class.chars() {
+                    // keep ranges, such as `a-z`, but nothing else special
+                    if c == '-' {
+                        regex.push('-');
+                    } else {
+                        regex.push_str(&regex::escape(&c.to_string()));
+                    }
+                }
+                regex.push(']');
+                while chars.next_if(|&(j, _)| j <= i + 1 + len).is_some() {}
+            }
+            '{' => {
+                let Some(end) = matching_brace(&glob[i..]) else {
+                    regex.push_str(r"\{");
+                    continue;
+                };
+                let inner = &glob[i + 1..i + end];
+                let alternatives = split_alternatives(inner);
+                if let Some((start, end)) = inner.split_once("..").and_then(|(start, end)| {
+                    Some((start.parse::<i64>().ok()?, end.parse::<i64>().ok()?))
+                }) {
+                    let (start, end) = (start.min(end), start.max(end));
+                    if end - start > MAX_EDITORCONFIG_RANGE {
+                        anyhow::bail!("editorconfig range {{{inner}}} is too large");
+                    }
+                    let numbers = (start..=end).map(|n| n.to_string()).collect::<Vec<_>>();
+                    regex.push_str(&format!("(?:{})", numbers.join("|")));
+                } else if alternatives.len() > 1 {
+                    regex.push_str("(?:");
+                    for (n, alternative) in alternatives.into_iter().enumerate() {
+                        if n > 0 {
+                            regex.push('|');
+                        }
+                        push_editorconfig_glob(alternative, regex)?;
+                    }
+                    regex.push(')');
+                } else {
+                    // a single word in braces is matched literally
+                    regex.push_str(r"\{");
+                    push_editorconfig_glob(inner, regex)?;
+                    regex.push_str(r"\}");
+                }
+                while chars.next_if(|&(j, _)| j <= i + end).is_some() {}
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    Ok(())
+}
+
+/// The offset of the `}` closing the `{` that `glob` starts with.
+fn matching_brace(glob: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut escaped = false;
+    for (i, c) in glob.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Splits the inside of a `{...}` at the commas that aren't nested in braces.
+fn split_alternatives(inner: &str) -> Vec<&str> {
+    let mut alternatives = Vec::new();
+    let mut depth = 0;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in inner.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                alternatives.push(&inner[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    alternatives.push(&inner[start..]);
+    alternatives
+}
+
+fn alias_target_matches(target: &str, path: &str) -> bool {
+    let target = target.trim_start_matches("./");
+    match target.split_once('*') {
+        Some((prefix, suffix)) => {
+            path.len() >= prefix.len() + suffix.len()
+                && path.starts_with(prefix)
+                && path.ends_with(suffix)
+        }
+        None => path.strip_prefix(target).is_some_and(|extension| {
+            extension.is_empty() || (extension.starts_with('.') && !extension.contains('/'))
+        }),
+    }
+}
+
+/// Resolves the symlinks in `path`, falling back to the path itself on
+/// filesystems that can't be read.
+async fn realpath_or_self(path: Vc<FileSystemPath>) -> Result<ReadRef<FileSystemPath>> {
+    match path.realpath().await {
+        Ok(real) => Ok(real),
+        Err(_) => path.await,
+    }
+}
+
+/// Whether the real path of `path` is below the temp directory, for
+/// [`ContextCondition::InTempDir`].
+async fn in_temp_dir(path: &FileSystemPath, ctx: &MatchContext) -> Result<bool> {
+    let temp_dir = ctx.temp_dir.clone().unwrap_or_else(std::env::temp_dir);
+    is_really_below(path, temp_dir).await
+}
+
+/// Whether the real path of `path` is below the cache directory, for
+/// [`ContextCondition::InOsCacheDir`].
+async fn in_os_cache_dir(path: &FileSystemPath, ctx: &MatchContext) -> Result<bool> {
+    let Some(cache_dir) = ctx.cache_dir.clone().or_else(dirs_next::cache_dir) else {
+        return Ok(false);
+    };
+    is_really_below(path, cache_dir).await
+}
+
+/// Whether `path` is on disk below `dir`, with symlinks resolved.
+async fn is_really_below(path: &FileSystemPath, dir: PathBuf) -> Result<bool> {
+    let Some(sys_path) = turbo_tasks_fs::to_sys_path(path.clone().cell()).await? else {
+        return Ok(false);
+    };
+    // resolve symlinks on both sides, e.g. macOS's temp directory is below
+    // `/var`, which links to `/private/var`
+    let real = |path: PathBuf| std::fs::canonicalize(&path).unwrap_or(path);
+    Ok(real(sys_path).starts_with(real(dir)))
+}
+
+/// Whether two paths are the same file for [`ContextCondition::SameFileAs`].
+async fn same_file(path: &FileSystemPath, other: Vc<FileSystemPath>) -> Result<bool> {
+    let path = path.clone().cell();
+    let real = realpath_or_self(path).await?;
+    let other_real = realpath_or_self(other).await?;
+    if real.fs == other_real.fs && real.path == other_real.path {
+        return Ok(true);
+    }
+    // hardlinks can only be told apart by the inode they refer to
+    #[cfg(unix)]
+    if let (Some(sys_path), Some(other_sys_path)) = (
+        turbo_tasks_fs::to_sys_path(path).await?,
+        turbo_tasks_fs::to_sys_path(other).await?,
+    ) {
+        use std::os::unix::fs::MetadataExt;
+
+        if let (Ok(metadata), Ok(other_metadata)) = (
+            std::fs::metadata(sys_path),
+            std::fs::metadata(other_sys_path),
+        ) {
+            return Ok(
+                metadata.dev() == other_metadata.dev() && metadata.ino() == other_metadata.ino()
+            );
+        }
+    }
+    Ok(false)
+}
+
+/// Reads the paths listed in a file, one per line, in order. A missing file
+/// lists nothing.
+async fn load_path_list(list: Vc<FileSystemPath>) -> Result<Vec<String>> {
+    let content = list.read().await?;
+    let FileContent::Content(file) = &*content else {
+        return Ok(Vec::new());
+    };
+    let mut list = String::new();
+    file.content().read().read_to_string(&mut list)?;
+    Ok(list
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Reads the paths listed in an allowlist for
+/// [`ContextCondition::InAllowlist`].
+async fn load_allowlist(allowlist: Vc<FileSystemPath>) -> Result<HashSet<String>> {
+    Ok(load_path_list(allowlist).await?.into_iter().collect())
+}
+
+/// Reads the paths at positions `start..end` of a manifest for
+/// [`ContextCondition::InManifestRange`].
+async fn load_manifest_range(
+    manifest: Vc<FileSystemPath>,
+    start: usize,
+    end: usize,
+) -> Result<HashSet<String>> {
+    let mut listed = HashSet::new();
+    let mut in_range = HashSet::new();
+    for (position, path) in load_path_list(manifest).await?.into_iter().enumerate() {
+        // only the first listing of a path counts
+        if listed.insert(path.clone()) && (start..end).contains(&position) {
+            in_range.insert(path);
+        }
+    }
+    Ok(in_range)
+}
+
+/// How much of a file [`ContextCondition::IsBinary`] looks at.
+const BINARY_DETECTION_PREFIX: u64 = 8000;
+
+fn looks_binary(content: impl Read) -> Result<bool> {
+    let mut prefix = Vec::new();
+    content
+        .take(BINARY_DETECTION_PREFIX)
+        .read_to_end(&mut prefix)?;
+    if prefix.contains(&0) {
+        return Ok(true);
+    }
+    // a character cut off by the end of the prefix is not a sign of binary data
+    Ok(std::str::from_utf8(&prefix).is_err_and(|e| e.error_len().is_some()))
+}
+
+/// The markers that [`ContextCondition::IsGenerated`] looks for.
+pub const GENERATED_MARKERS: &[&str] = &[
+    "@generated",
+    "DO NOT EDIT",
+    "Code generated by",
+    "This file is automatically generated",
+];
+
+/// How much of a file [`ContextCondition::IsGenerated`] searches for markers.
+const GENERATED_MARKER_PREFIX: u64 = 2048;
+
+fn has_generated_marker(content: impl Read, extra_markers: &[String]) -> Result<bool> {
+    let mut prefix = Vec::new();
+    content
+        .take(GENERATED_MARKER_PREFIX)
+        .read_to_end(&mut prefix)?;
+    let prefix = String::from_utf8_lossy(&prefix);
+    Ok(GENERATED_MARKERS
+        .iter()
+        .copied()
+        .chain(extra_markers.iter().map(String::as_str))
+        .any(|marker| prefix.contains(marker)))
+}
+
+/// How much of a file [`ContextCondition::ContentHashShard`] hashes.
+const CONTENT_HASH_PREFIX: u64 = 64 * 1024;
+
+fn content_hash(content: impl Read) -> Result<u64> {
+    let mut prefix = Vec::new();
+    content.take(CONTENT_HASH_PREFIX).read_to_end(&mut prefix)?;
+    Ok(hash_xxh3_hash64(prefix.as_slice()))
+}
+
+/// The lockfiles that [`ContextCondition::IsLockfile`] matches without extra
+/// names.
+pub const LOCKFILE_NAMES: &[&str] = &[
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "pnpm-lock.yaml",
+    "yarn.lock",
+    "bun.lockb",
+];
+
+/// The directories of third-party code that [`ContextCondition::IsVendored`]
+/// matches without extra names.
+pub const VENDORED_DIRS: &[&str] = &[
+    "node_modules",
+    "bower_components",
+    "vendor",
+    "third_party",
+    "third-party",
+    "external",
+];
+
+/// The file names of tool configs that [`ContextCondition::IsToolConfig`]
+/// matches without extra patterns.
+pub const TOOL_CONFIG_PATTERNS: &[&str] = &[
+    ".eslintrc",
+    ".eslintrc.*",
+    "eslint.config.*",
+    "tsconfig.json",
+    "tsconfig.*.json",
+    "jsconfig.json",
+    ".babelrc",
+    ".babelrc.*",
+    "babel.config.*",
+    ".prettierrc",
+    ".prettierrc.*",
+    "prettier.config.*",
+    "vite.config.*",
+    "vitest.config.*",
+    "jest.config.*",
+    "webpack.config.*",
+    "rollup.config.*",
+    "postcss.config.*",
+    "tailwind.config.*",
+];
+
+fn tool_config_globs(extra_patterns: &[String]) -> Result<Vec<Glob>> {
+    TOOL_CONFIG_PATTERNS
+        .iter()
+        .copied()
+        .chain(extra_patterns.iter().map(String::as_str))
+        .map(Glob::parse)
+        .collect()
+}
+
+/// The extensions that [`ContextCondition::is_source_file`] matches.
+pub const SOURCE_EXTENSIONS: &[&str] = &["ts", "tsx", "js", "jsx", "mjs", "cjs"];
+
+/// Whether the package containing `path` has a version in `range`, for
+/// [`ContextCondition::PackageVersionMatches`].
+fn package_version_matches(path: &str, range: &VersionReq, ctx: &MatchContext) -> Result<bool> {
+    let Some(version) = ctx
+        .package_for(path)
+        .and_then(|package| package.version.as_deref())
+    else {
+        return Ok(false);
+    };
+    Ok(Version::parse(version).is_ok_and(|version| range.matches(&version)))
+}
+
+/// The module system `path` is loaded with, or `None` if it isn't a
+/// JavaScript or TypeScript file, for [`ContextCondition::ModuleKind`].
+fn module_kind(path: &FileSystemPath, ctx: &MatchContext) -> Option<ModuleKind> {
+    match path.extension_ref()? {
+        "mjs" | "mts" => Some(ModuleKind::Esm),
+        "cjs" | "cts" => Some(ModuleKind::Cjs),
+        "js" | "jsx" | "ts" | "tsx" => {
+            let module_type = ctx
+                .package_for(&path.path)
+                .and_then(|package| package.module_type.as_deref());
+            Some(if module_type == Some("module") {
+                ModuleKind::Esm
+            } else {
+                ModuleKind::Cjs
+            })
+        }
+        _ => None,
+    }
+}
+
+/// The conventions [`ContextCondition::IsTestFile`] matches without extra
+/// patterns.
+fn is_conventional_test_file(path: &str) -> bool {
+    let (dirs, name) = path.rsplit_once('/').unwrap_or(("", path));
+    name.contains(".test.")
+        || name.contains(".spec.")
+        || dirs
+            .split('/')
+            .any(|dir| matches!(dir, "__tests__" | "test" | "tests"))
+}
+
+/// Whether `path` matches `pattern` relative to any of `roots`, resolving the
+/// roots concurrently, for [`ContextCondition::GlobInRoots`].
+async fn glob_in_roots(
+    roots: &[Vc<FileSystemPath>],
+    pattern: &Glob,
+    path: &FileSystemPath,
+) -> Result<bool> {
+    let mut matches =
+        stream::iter(roots)
+            .map(|root| async move {
+                Ok::<_, anyhow::Error>(relative_glob(&*root.await?, pattern, path))
+            })
+            .buffer_unordered(roots.len().max(1));
+    while let Some(matched) = matches.next().await {
+        if matched? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn relative_glob(base: &FileSystemPath, pattern: &Glob, path: &FileSystemPath) -> bool {
+    base.get_path_to(path)
+        .is_some_and(|relative| pattern.execute(relative))
+}
+
+fn imports_from(specifier: &Glob, path: &FileSystemPath, ctx: &MatchContext) -> Result<bool> {
+    Ok(ctx
+        .imports
+        .get(&path.path)
+        .is_some_and(|imports| imports.iter().any(|import| specifier.execute(import))))
+}
+
+/// Deterministically decides whether `path` is part of a sample of the given
+/// rate, in parts per million.
+fn sampled(path: &FileSystemPath, rate_ppm: u32, seed: u64) -> bool {
+    let hash = hash_xxh3_hash64((seed, path.path.as_str()));
+    hash % 1_000_000 < u64::from(rate_ppm)
+}
+
+async fn compile_all(conditions: &[ContextCondition]) -> Result<Vec<CompiledCondition>> {
+    let mut compiled = Vec::with_capacity(conditions.len());
+    for condition in conditions {
+        compiled.push(condition.compile().await?);
+    }
+    Ok(compiled)
+}
+
+/// A [`ContextCondition`] prepared by [`ContextCondition::compile`]. Paths
+/// it refers to are resolved and its patterns are built, so matching doesn't
+/// need to allocate for most conditions.
+#[derive(Debug)]
+pub enum CompiledCondition {
+    All(Vec<CompiledCondition>),
+    Any(Vec<CompiledCondition>),
+    Not(Box<CompiledCondition>),
+    InDirectory(DirectoryPatterns),
+    InPath(ReadRef<FileSystemPath>),
+    CaseCollisionWith(ReadRef<FileSystemPath>, String),
+    InTurboCache,
+    HasNonAsciiPath,
+    InTurboPackage,
+    InWorkspaceGlobs,
+    InRuntimeIgnore,
+    IsTestFile(Vec<Glob>),
+    IsSourceFile(HashSet<String>),
+    IsLockfile(HashSet<String>),
+    IsVendored(HashSet<String>),
+    IsToolConfig(Vec<Glob>),
+    MatchesEditorConfigSection(String, Regex),
+    ImportsFrom(Glob),
+    RelativeGlob(ReadRef<FileSystemPath>, Glob),
+    GlobInRoots(Vec<ReadRef<FileSystemPath>>, Glob),
+    Sampled(Box<CompiledCondition>, u32, u64),
+    Capped(Box<CompiledCondition>, usize, String),
+    PackageVersionMatches(VersionReq),
+    InAllowlist(HashSet<String>),
+    InManifestRange(HashSet<String>),
+    Guarded(String, Box<CompiledCondition>),
+    /// Conditions that depend on the filesystem or the [`MatchContext`] and
+    /// can't be prepared ahead of time.
+    Dynamic(ContextCondition),
+}
+
+impl CompiledCondition {
+    /// Returns true if the condition matches the context.
+    pub async fn matches(&self, path: &FileSystemPath) -> Result<bool> {
+        self.matches_with_context(path, &MatchContext::default())
+            .await
+    }
+
+    #[async_recursion]
+    /// Returns true if the condition matches the context, using `ctx` for
+    /// conditions that need to know about the workspace.
+    pub async fn matches_with_context(
+        &self,
+        path: &FileSystemPath,
+        ctx: &MatchContext,
+    ) -> Result<bool> {
+        match self {
+            CompiledCondition::All(conditions) => {
+                for condition in conditions {
+                    if !condition.matches_with_context(path, ctx).await? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            CompiledCondition::Any(conditions) => {
+                for condition in conditions {
+                    if condition.matches_with_context(path, ctx).await? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            CompiledCondition::Not(condition) => {
+                condition.matches_with_context(path, ctx).await.map(|b| !b)
+            }
+            CompiledCondition::InDirectory(patterns) => Ok(patterns.matches(&path.path)),
+            CompiledCondition::InPath(other_path) => Ok(path.is_inside_or_equal_ref(other_path)),
+            CompiledCondition::CaseCollisionWith(other_path, lowercase) => Ok(path.fs
+                == other_path.fs
+                && path.path != other_path.path
+                && path.path.to_lowercase() == *lowercase),
+            CompiledCondition::InTurboCache => {
+                Ok(path.path.split('/').any(|segment| segment == ".turbo"))
+            }
+            CompiledCondition::HasNonAsciiPath => Ok(!path.path.is_ascii()),
+            CompiledCondition::InTurboPackage => in_turbo_package(path, &ctx.turbo_packages).await,
+            CompiledCondition::InWorkspaceGlobs => in_workspace_globs(&path.path, ctx),
+            CompiledCondition::InRuntimeIgnore => in_runtime_ignore(&path.path, ctx),
+            CompiledCondition::IsTestFile(extra_patterns) => {
+                Ok(is_conventional_test_file(&path.path)
+                    || extra_patterns
+                        .iter()
+                        .any(|pattern| pattern.execute(&path.path)))
+            }
+            CompiledCondition::IsLockfile(names) => Ok(names.contains(path.file_name())),
+            CompiledCondition::IsVendored(names) => {
+                Ok(path.path.split('/').any(|segment| names.contains(segment)))
+            }
+            CompiledCondition::IsToolConfig(globs) => {
+                Ok(globs.iter().any(|glob| glob.execute(path.file_name())))
+            }
+            CompiledCondition::MatchesEditorConfigSection(section, regex) => {
+                matches_editorconfig_section(path, section, regex).await
+            }
+            CompiledCondition::IsSourceFile(extensions) => Ok(path
+                .extension_ref()
+                .is_some_and(|ext| extensions.contains(ext))),
+            CompiledCondition::ImportsFrom(specifier) => imports_from(specifier, path, ctx),
+            CompiledCondition::GlobInRoots(roots, pattern) => {
+                Ok(roots.iter().any(|root| relative_glob(root, pattern, path)))
+            }
+            CompiledCondition::RelativeGlob(base, pattern) => {
+                Ok(relative_glob(base, pattern, path))
+            }
+            CompiledCondition::InAllowlist(paths) | CompiledCondition::InManifestRange(paths) => {
+                Ok(paths.contains(&path.path))
+            }
+            CompiledCondition::Guarded(prefix, inner) => Ok(path.path.starts_with(prefix.as_str())
+                && inner.matches_with_context(path, ctx).await?),
+            CompiledCondition::Sampled(inner, rate_ppm, seed) => {
+                Ok(inner.matches_with_context(path, ctx).await? && sampled(path, *rate_ppm, *seed))
+            }
+            CompiledCondition::Capped(inner, max, id) => {
+                Ok(inner.matches_with_context(path, ctx).await? && below_cap(id, *max, ctx))
+            }
+            CompiledCondition::PackageVersionMatches(range) => {
+                package_version_matches(&path.path, range, ctx)
+            }
+            CompiledCondition::Dynamic(condition) => {
+                condition.matches_with_context(path, ctx).await
+            }
+        }
+    }
+}
+
+/// The patterns a path is checked against for
+/// [`ContextCondition::InDirectory`].
+#[derive(Debug)]
+pub struct DirectoryPatterns {
+    dir: String,
+    prefix: String,
+    infix: String,
+    suffix: String,
+}
+
+impl DirectoryPatterns {
+    fn new(dir: &str) -> Self {
+        #[cfg(test)]
+        tests::PATTERNS_BUILT.with(|built| built.set(built.get() + 1));
+
+        DirectoryPatterns {
+            dir: dir.to_string(),
+            prefix: format!("{dir}/"),
+            infix: format!("/{dir}/"),
+            suffix: format!("/{dir}"),
         }
     }
+
+    fn matches(&self, path: &str) -> bool {
+        path.starts_with(&self.prefix)
+            || path.contains(&self.infix)
+            || path.ends_with(&self.suffix)
+            || path == self.dir
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use turbo_tasks::{TurboTasks, Vc};
+    use turbo_tasks_fs::{DiskFileSystem, FileSystem, FileSystemPath, VirtualFileSystem};
+    use turbo_tasks_memory::MemoryBackend;
+
+    use super::{
+        editorconfig_regex, nearest_marker_dir, CompiledCondition, ContextCondition, MatchContext,
+        ModuleKind, PackageInfo, Predicate, Rule, Ruleset, LOCKFILE_NAMES, VENDORED_DIRS,
+    };
+
+    thread_local! {
+        /// How many times `InDirectory` patterns have been built on this thread.
+        pub(super) static PATTERNS_BUILT: Cell<usize> = const { Cell::new(0) };
+        /// How many times a `MatchContext`'s globs have been parsed on this
+        /// thread.
+        pub(super) static CONTEXT_GLOBS_PARSED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    #[tokio::test]
+    async fn case_collision_with() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let condition = ContextCondition::CaseCollisionWith(FileSystemPath::new_normalized(
+                fs,
+                "src/Foo.ts".into(),
+            ));
+
+            let path = FileSystemPath::new_normalized(fs, "src/foo.ts".into()).await?;
+            assert!(condition.matches(&path).await?);
+
+            let path = FileSystemPath::new_normalized(fs, "src/Foo.ts".into()).await?;
+            assert!(
+                !condition.matches(&path).await?,
+                "identical paths do not collide"
+            );
+
+            let path = FileSystemPath::new_normalized(fs, "src/Bar.ts".into()).await?;
+            assert!(!condition.matches(&path).await?);
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn specificity_ordering() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let path = FileSystemPath::new_normalized(fs, "src/index.ts".into());
+
+            let case_collision = ContextCondition::CaseCollisionWith(path);
+            let in_path = ContextCondition::InPath(path);
+            let in_directory = ContextCondition::InDirectory("node_modules".to_string());
+            let always = ContextCondition::all(vec![]);
+
+            assert!(case_collision.specificity() > in_path.specificity());
+            assert!(in_path.specificity() > in_directory.specificity());
+            assert!(in_directory.specificity() > always.specificity());
+
+            let both = ContextCondition::all(vec![in_path.clone(), in_directory.clone()]);
+            assert!(both.specificity() > in_path.specificity());
+
+            let either = ContextCondition::any(vec![in_path.clone(), in_directory.clone()]);
+            assert_eq!(either.specificity(), in_directory.specificity());
+
+            let negated = ContextCondition::not(in_path);
+            assert!(negated.specificity() < in_directory.specificity());
+            assert!(negated.specificity() > always.specificity());
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn sibling_count_greater_than() {
+        crate::register();
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap().to_string();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        for name in ["a.ts", "b.ts", "c.ts"] {
+            std::fs::write(dir.path().join("src").join(name), "").unwrap();
+        }
+
+        let tt = TurboTasks::new(MemoryBackend::default());
+        tt.run_once(async move {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(DiskFileSystem::new(
+                "test".to_string(),
+                root,
+                vec![],
+            ));
+            let path = FileSystemPath::new_normalized(fs, "src/a.ts".into()).await?;
+
+            assert!(
+                ContextCondition::SiblingCountGreaterThan(2)
+                    .matches(&path)
+                    .await?
+            );
+            assert!(
+                !ContextCondition::SiblingCountGreaterThan(3)
+                    .matches(&path)
+                    .await?,
+                "the count must exceed the threshold"
+            );
+
+            let missing = FileSystemPath::new_normalized(fs, "missing/a.ts".into()).await?;
+            assert!(
+                !ContextCondition::SiblingCountGreaterThan(0)
+                    .matches(&missing)
+                    .await?,
+                "unreadable directories never match"
+            );
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn directory_is_empty() {
+        crate::register();
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap().to_string();
+        std::fs::create_dir_all(dir.path().join("src/empty")).unwrap();
+        std::fs::write(dir.path().join("src/a.ts"), "").unwrap();
+
+        let tt = TurboTasks::new(MemoryBackend::default());
+        tt.run_once(async move {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(DiskFileSystem::new(
+                "test".to_string(),
+                root,
+                vec![],
+            ));
+            let condition = ContextCondition::DirectoryIsEmpty;
+            let compiled = condition.compile().await?;
+
+            for (path, expected) in [
+                ("src/empty", true),
+                ("src", false),
+                // files and missing paths aren't empty directories
+                ("src/a.ts", false),
+                ("missing", false),
+            ] {
+                let path = FileSystemPath::new_normalized(fs, path.into()).await?;
+                assert_eq!(condition.matches(&path).await?, expected, "{}", path.path);
+                assert_eq!(compiled.matches(&path).await?, expected, "{}", path.path);
+            }
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn has_sibling_extension() {
+        crate::register();
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap().to_string();
+        std::fs::create_dir_all(dir.path().join("src/Card.css")).unwrap();
+        for name in [
+            "Button.tsx",
+            "Button.module.css",
+            "Card.tsx",
+            "Link.tsx",
+            "Link.scss",
+        ] {
+            std::fs::write(dir.path().join("src").join(name), "").unwrap();
+        }
+
+        let tt = TurboTasks::new(MemoryBackend::default());
+        tt.run_once(async move {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(DiskFileSystem::new(
+                "test".to_string(),
+                root,
+                vec![],
+            ));
+            let css = ContextCondition::HasSiblingExtension("css".to_string());
+
+            for (path, expected) in [
+                ("src/Button.tsx", true),
+                // a file isn't its own sibling
+                ("src/Button.module.css", false),
+                // directories aren't siblings
+                ("src/Card.tsx", false),
+                // `scss` is not `css`
+                ("src/Link.tsx", false),
+                ("missing/Button.tsx", false),
+            ] {
+                let path = FileSystemPath::new_normalized(fs, path.into()).await?;
+                assert_eq!(css.matches(&path).await?, expected, "{}", path.path);
+                assert_eq!(
+                    css.compile().await?.matches(&path).await?,
+                    expected,
+                    "{}",
+                    path.path
+                );
+            }
+
+            let module_css = ContextCondition::HasSiblingExtension("module.css".to_string());
+            let button = FileSystemPath::new_normalized(fs, "src/Button.tsx".into()).await?;
+            assert!(module_css.matches(&button).await?);
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn in_turbo_cache() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let condition = ContextCondition::InTurboCache;
+
+            let path =
+                FileSystemPath::new_normalized(fs, "packages/a/.turbo/turbo-build.log".into())
+                    .await?;
+            assert!(condition.matches(&path).await?);
+
+            let path = FileSystemPath::new_normalized(fs, ".turbo/cookies/1.cookie".into()).await?;
+            assert!(condition.matches(&path).await?);
+
+            let path = FileSystemPath::new_normalized(fs, "packages/turbo/index.ts".into()).await?;
+            assert!(!condition.matches(&path).await?);
+
+            let path = FileSystemPath::new_normalized(fs, "packages/a/.turbo.ts".into()).await?;
+            assert!(!condition.matches(&path).await?);
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn is_test_file() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let condition = ContextCondition::IsTestFile {
+                extra_patterns: vec!["**/*.e2e.ts".to_string()],
+            };
+            let compiled = condition.compile().await?;
+
+            for (path, expected) in [
+                ("src/button.test.tsx", true),
+                ("src/button.spec.js", true),
+                ("src/__tests__/a.ts", true),
+                ("tests/unit/x.ts", true),
+                ("packages/a/test/x.ts", true),
+                ("e2e/login.e2e.ts", true),
+                ("src/button.tsx", false),
+                ("src/testing/x.ts", false),
+                ("src/test.ts", false),
+                ("src/latest.test", false),
+            ] {
+                let path = FileSystemPath::new_normalized(fs, path.into()).await?;
+                assert_eq!(condition.matches(&path).await?, expected, "{}", path.path);
+                assert_eq!(compiled.matches(&path).await?, expected, "{}", path.path);
+            }
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn is_source_file() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let defaults = ContextCondition::is_source_file();
+            let ContextCondition::IsSourceFile { mut extensions } = defaults.clone() else {
+                unreachable!()
+            };
+            extensions.push("vue".to_string());
+            let with_vue = ContextCondition::IsSourceFile { extensions };
+
+            for (path, expected, expected_with_vue) in [
+                ("src/index.ts", true, true),
+                ("src/button.jsx", true, true),
+                ("src/types.d.ts", true, true),
+                ("src/App.vue", false, true),
+                ("src/styles.css", false, false),
+                ("src/ts", false, false),
+                ("src/.tsx", false, false),
+                ("src/index.ts.map", false, false),
+            ] {
+                let path = FileSystemPath::new_normalized(fs, path.into()).await?;
+                assert_eq!(defaults.matches(&path).await?, expected, "{}", path.path);
+                assert_eq!(
+                    defaults.compile().await?.matches(&path).await?,
+                    expected,
+                    "{}",
+                    path.path
+                );
+                assert_eq!(
+                    with_vue.matches(&path).await?,
+                    expected_with_vue,
+                    "{}",
+                    path.path
+                );
+                assert_eq!(
+                    with_vue.compile().await?.matches(&path).await?,
+                    expected_with_vue,
+                    "{}",
+                    path.path
+                );
+            }
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn is_vendored() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let condition = ContextCondition::IsVendored {
+                extra_dirs: vec!["deps".to_string()],
+            };
+            let compiled = condition.compile().await?;
+
+            for name in VENDORED_DIRS {
+                let path =
+                    FileSystemPath::new_normalized(fs, format!("apps/web/{name}/x.js")).await?;
+                assert!(condition.matches(&path).await?, "{}", path.path);
+                assert!(compiled.matches(&path).await?, "{}", path.path);
+            }
+            for (path, expected) in [
+                ("vendor/lib/x.ts", true),
+                ("third_party/a/y.ts", true),
+                ("deps/zlib/zlib.h", true),
+                // only whole components count
+                ("vendored_docs/x.md", false),
+                ("src/external.ts", false),
+                ("src/index.ts", false),
+            ] {
+                let path = FileSystemPath::new_normalized(fs, path.into()).await?;
+                assert_eq!(condition.matches(&path).await?, expected, "{}", path.path);
+                assert_eq!(compiled.matches(&path).await?, expected, "{}", path.path);
+            }
+
+            let default = ContextCondition::IsVendored { extra_dirs: vec![] };
+            let path = FileSystemPath::new_normalized(fs, "deps/zlib/zlib.h".into()).await?;
+            assert!(!default.matches(&path).await?);
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn has_non_ascii_path() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let condition = ContextCondition::HasNonAsciiPath;
+            let compiled = condition.compile().await?;
+
+            for (path, expected) in [
+                ("packages/ui/src/button.tsx", false),
+                ("docs/résumé.md", true),
+                ("assets/\u{1F680}-launch.png", true),
+            ] {
+                let path = FileSystemPath::new_normalized(fs, path.into()).await?;
+                assert_eq!(condition.matches(&path).await?, expected, "{}", path.path);
+                assert_eq!(compiled.matches(&path).await?, expected, "{}", path.path);
+            }
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn is_lockfile() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let condition = ContextCondition::IsLockfile {
+                extra_names: vec!["deno.lock".to_string()],
+            };
+            let compiled = condition.compile().await?;
+
+            for name in LOCKFILE_NAMES {
+                let path = FileSystemPath::new_normalized(fs, format!("apps/web/{name}")).await?;
+                assert!(condition.matches(&path).await?, "{}", path.path);
+                assert!(compiled.matches(&path).await?, "{}", path.path);
+            }
+            for (path, expected) in [
+                ("deno.lock", true),
+                ("package.json", false),
+                ("apps/web/package.json", false),
+                ("yarn.lock/index.js", false),
+                ("old-yarn.lock", false),
+            ] {
+                let path = FileSystemPath::new_normalized(fs, path.into()).await?;
+                assert_eq!(condition.matches(&path).await?, expected, "{}", path.path);
+                assert_eq!(compiled.matches(&path).await?, expected, "{}", path.path);
+            }
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn is_tool_config() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let default = ContextCondition::IsToolConfig {
+                extra_patterns: vec![],
+            };
+            let extended = ContextCondition::IsToolConfig {
+                extra_patterns: vec!["knip.json".to_string()],
+            };
+            let (compiled_default, compiled_extended) =
+                (default.compile().await?, extended.compile().await?);
+
+            for (path, expected_default, expected_extended) in [
+                (".eslintrc.json", true, true),
+                ("packages/ui/tsconfig.base.json", true, true),
+                ("apps/web/vite.config.ts", true, true),
+                ("apps/web/src/index.ts", false, false),
+                ("knip.json", false, true),
+            ] {
+                let path = FileSystemPath::new_normalized(fs, path.into()).await?;
+                assert_eq!(
+                    default.matches(&path).await?,
+                    expected_default,
+                    "{}",
+                    path.path
+                );
+                assert_eq!(
+                    compiled_default.matches(&path).await?,
+                    expected_default,
+                    "{}",
+                    path.path
+                );
+                assert_eq!(
+                    extended.matches(&path).await?,
+                    expected_extended,
+                    "{}",
+                    path.path
+                );
+                assert_eq!(
+                    compiled_extended.matches(&path).await?,
+                    expected_extended,
+                    "{}",
+                    path.path
+                );
+            }
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn in_workspace_globs() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let ctx = MatchContext {
+                workspace_globs: vec![
+                    "packages/*".to_string(),
+                    "apps/*".to_string(),
+                    "!apps/legacy".to_string(),
+                ],
+                ..Default::default()
+            };
+            let condition = ContextCondition::InWorkspaceGlobs;
+            let compiled = condition.compile().await?;
+            assert!(matches!(compiled, CompiledCondition::InWorkspaceGlobs));
+
+            let parsed = CONTEXT_GLOBS_PARSED.with(Cell::get);
+            for (path, expected) in [
+                ("packages/ui/src/button.tsx", true),
+                ("packages/ui/package.json", true),
+                ("apps/web/next.config.js", true),
+                ("apps/legacy/index.js", false),
+                ("apps/README.md", false),
+                ("packages/ui", false),
+                ("scripts/x.ts", false),
+                ("package.json", false),
+            ] {
+                let path = FileSystemPath::new_normalized(fs, path.into()).await?;
+                assert_eq!(
+                    condition.matches_with_context(&path, &ctx).await?,
+                    expected,
+                    "{}",
+                    path.path
+                );
+                assert_eq!(
+                    compiled.matches_with_context(&path, &ctx).await?,
+                    expected,
+                    "{}",
+                    path.path
+                );
+            }
+            assert_eq!(
+                CONTEXT_GLOBS_PARSED.with(Cell::get) - parsed,
+                1,
+                "the workspace globs are parsed once per context"
+            );
+
+            let packages = FileSystemPath::new_normalized(fs, "packages/ui/a.ts".into()).await?;
+            assert!(
+                !condition.matches(&packages).await?,
+                "nothing matches without workspace globs"
+            );
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn in_changed_workspace() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let package = |root: &str| PackageInfo {
+                root: root.to_string(),
+                entry: None,
+                module_type: None,
+                version: None,
+                export_targets: None,
+            };
+            let ctx = MatchContext {
+                packages: vec![
+                    package("apps/web"),
+                    package("apps/docs"),
+                    package("packages/ui"),
+                ],
+                changed_files: [(
+                    "origin/main".to_string(),
+                    vec![
+                        "apps/web/src/pages/index.tsx".to_string(),
+                        "README.md".to_string(),
+                    ],
+                )]
+                .into_iter()
+                .collect(),
+                ..Default::default()
+            };
+            let condition = ContextCondition::InChangedWorkspace("origin/main".to_string());
+
+            for (path, expected) in [
+                ("apps/web/src/pages/index.tsx", true),
+                ("apps/web/next.config.js", true),
+                ("apps/docs/src/index.tsx", false),
+                ("packages/ui/src/button.tsx", false),
+                // changed, but not in a package
+                ("README.md", false),
+            ] {
+                let path = FileSystemPath::new_normalized(fs, path.into()).await?;
+                assert_eq!(
+                    condition.matches_with_context(&path, &ctx).await?,
+                    expected,
+                    "{}",
+                    path.path
+                );
+            }
+
+            let changed =
+                FileSystemPath::new_normalized(fs, "apps/web/next.config.js".into()).await?;
+            assert!(
+                !condition.matches(&changed).await?,
+                "nothing has changed without git information"
+            );
+            let other_ref = ContextCondition::InChangedWorkspace("v1.0.0".to_string());
+            assert!(!other_ref.matches_with_context(&changed, &ctx).await?);
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn package_version_matches() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let package = |root: &str, version: Option<&str>| PackageInfo {
+                root: root.to_string(),
+                entry: None,
+                module_type: None,
+                version: version.map(|v| v.to_string()),
+                export_targets: None,
+            };
+            let ctx = MatchContext {
+                packages: vec![
+                    package("packages/ui", Some("1.2.3")),
+                    package("packages/private", None),
+                    package("packages/tagged", Some("latest")),
+                ],
+                ..Default::default()
+            };
+            let v1 = ContextCondition::PackageVersionMatches("^1.0.0".to_string());
+            let v2 = ContextCondition::PackageVersionMatches(">=2.0.0".to_string());
+            // the range is parsed once, when compiling
+            let compiled_v1 = v1.compile().await?;
+            assert!(matches!(
+                compiled_v1,
+                CompiledCondition::PackageVersionMatches(_)
+            ));
+            let compiled_v2 = v2.compile().await?;
+            assert!(
+                ContextCondition::PackageVersionMatches("not a range".to_string())
+                    .compile()
+                    .await
+                    .is_err()
+            );
+
+            for (path, expected) in [
+                ("packages/ui/src/index.ts", true),
+                // without a valid version
+                ("packages/private/index.ts", false),
+                ("packages/tagged/index.ts", false),
+                ("scripts/build.js", false),
+            ] {
+                let path = FileSystemPath::new_normalized(fs, path.into()).await?;
+                assert_eq!(
+                    v1.matches_with_context(&path, &ctx).await?,
+                    expected,
+                    "{}",
+                    path.path
+                );
+                assert_eq!(
+                    compiled_v1.matches_with_context(&path, &ctx).await?,
+                    expected,
+                    "{}",
+                    path.path
+                );
+                assert!(
+                    !v2.matches_with_context(&path, &ctx).await?,
+                    "{}",
+                    path.path
+                );
+                assert!(
+                    !compiled_v2.matches_with_context(&path, &ctx).await?,
+                    "{}",
+                    path.path
+                );
+            }
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn module_kind() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let package = |root: &str, module_type: Option<&str>| PackageInfo {
+                root: root.to_string(),
+                entry: None,
+                module_type: module_type.map(|t| t.to_string()),
+                version: None,
+                export_targets: None,
+            };
+            let ctx = MatchContext {
+                packages: vec![
+                    package("packages/esm", Some("module")),
+                    package("packages/cjs", Some("commonjs")),
+                    package("packages/untyped", None),
+                ],
+                ..Default::default()
+            };
+            let esm = ContextCondition::ModuleKind(ModuleKind::Esm);
+            let cjs = ContextCondition::ModuleKind(ModuleKind::Cjs);
+            let compiled_esm = esm.compile().await?;
+
+            for (path, expected) in [
+                ("packages/cjs/index.mjs", Some(ModuleKind::Esm)),
+                ("packages/esm/index.cjs", Some(ModuleKind::Cjs)),
+                ("packages/esm/index.js", Some(ModuleKind::Esm)),
+                ("packages/esm/src/button.tsx", Some(ModuleKind::Esm)),
+                ("packages/cjs/index.js", Some(ModuleKind::Cjs)),
+                ("packages/untyped/index.js", Some(ModuleKind::Cjs)),
+                ("scripts/build.js", Some(ModuleKind::Cjs)),
+                ("packages/esm/styles.css", None),
+            ] {
+                let path = FileSystemPath::new_normalized(fs, path.into()).await?;
+                assert_eq!(
+                    esm.matches_with_context(&path, &ctx).await?,
+                    expected == Some(ModuleKind::Esm),
+                    "{}",
+                    path.path
+                );
+                assert_eq!(
+                    cjs.matches_with_context(&path, &ctx).await?,
+                    expected == Some(ModuleKind::Cjs),
+                    "{}",
+                    path.path
+                );
+                assert_eq!(
+                    compiled_esm.matches_with_context(&path, &ctx).await?,
+                    expected == Some(ModuleKind::Esm),
+                    "{}",
+                    path.path
+                );
+            }
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn is_app_entrypoint() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let ctx = MatchContext {
+                app_entrypoints: [
+                    "apps/web/pages/_app.tsx".to_string(),
+                    "apps/docs/src/main.ts".to_string(),
+                ]
+                .into_iter()
+                .collect(),
+                ..Default::default()
+            };
+            let condition = ContextCondition::IsAppEntrypoint;
+            let compiled = condition.compile().await?;
+
+            for (path, expected) in [
+                ("apps/web/pages/_app.tsx", true),
+                ("apps/docs/src/main.ts", true),
+                ("apps/web/pages/index.tsx", false),
+                ("apps/web/pages/_app.test.tsx", false),
+                ("apps/docs/src", false),
+            ] {
+                let path = FileSystemPath::new_normalized(fs, path.into()).await?;
+                assert_eq!(
+                    condition.matches_with_context(&path, &ctx).await?,
+                    expected,
+                    "{}",
+                    path.path
+                );
+                assert_eq!(
+                    compiled.matches_with_context(&path, &ctx).await?,
+                    expected,
+                    "{}",
+                    path.path
+                );
+            }
+
+            let path = FileSystemPath::new_normalized(fs, "apps/web/pages/_app.tsx".into()).await?;
+            assert!(
+                !condition.matches(&path).await?,
+                "no entrypoints are configured"
+            );
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn is_build_output() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let ctx = MatchContext {
+                build_outputs: [
+                    "packages/ui/dist/index.js".to_string(),
+                    "packages/ui/dist/index.d.ts".to_string(),
+                ]
+                .into_iter()
+                .collect(),
+                ..Default::default()
+            };
+            let condition = ContextCondition::IsBuildOutput;
+            let compiled = condition.compile().await?;
+
+            for (path, expected) in [
+                ("packages/ui/dist/index.js", true),
+                ("packages/ui/dist/index.d.ts", true),
+                ("packages/ui/dist/other.js", false),
+                ("packages/ui/src/index.ts", false),
+            ] {
+                let path = FileSystemPath::new_normalized(fs, path.into()).await?;
+                assert_eq!(
+                    condition.matches_with_context(&path, &ctx).await?,
+                    expected,
+                    "{}",
+                    path.path
+                );
+                assert_eq!(
+                    compiled.matches_with_context(&path, &ctx).await?,
+                    expected,
+                    "{}",
+                    path.path
+                );
+            }
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn is_public_export() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let ctx = MatchContext {
+                packages: vec![
+                    PackageInfo {
+                        root: "packages/ui".to_string(),
+                        entry: Some("./dist/index.js".to_string()),
+                        module_type: None,
+                        version: None,
+                        export_targets: Some(vec![
+                            "./src/index.ts".to_string(),
+                            "./src/components/*.tsx".to_string(),
+                        ]),
+                    },
+                    PackageInfo {
+                        root: "packages/legacy".to_string(),
+                        entry: Some("./lib/main.js".to_string()),
+                        module_type: None,
+                        version: None,
+                        export_targets: None,
+                    },
+                ],
+                ..Default::default()
+            };
+            let condition = ContextCondition::IsPublicExport;
+            let compiled = condition.compile().await?;
+
+            for (path, expected) in [
+                ("packages/ui/src/index.ts", true),
+                ("packages/ui/src/components/button.tsx", true),
+                ("packages/ui/src/components/forms/input.tsx", true),
+                ("packages/ui/src/util.ts", false),
+                ("packages/ui/dist/index.js", false),
+                ("packages/legacy/lib/main.js", true),
+                ("packages/legacy/lib/util.js", false),
+                ("apps/web/src/index.ts", false),
+            ] {
+                let path = FileSystemPath::new_normalized(fs, path.into()).await?;
+                assert_eq!(
+                    condition.matches_with_context(&path, &ctx).await?,
+                    expected,
+                    "{}",
+                    path.path
+                );
+                assert_eq!(
+                    compiled.matches_with_context(&path, &ctx).await?,
+                    expected,
+                    "{}",
+                    path.path
+                );
+            }
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn is_package_entry() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let ctx = MatchContext {
+                packages: vec![
+                    PackageInfo {
+                        root: "packages/a".to_string(),
+                        entry: Some("./src/index.ts".to_string()),
+                        module_type: None,
+                        version: None,
+                        export_targets: None,
+                    },
+                    PackageInfo {
+                        root: "packages/a/fixtures/b".to_string(),
+                        entry: None,
+                        module_type: None,
+                        version: None,
+                        export_targets: None,
+                    },
+                ],
+                ..Default::default()
+            };
+            let condition = ContextCondition::IsPackageEntry;
+
+            let entry =
+                FileSystemPath::new_normalized(fs, "packages/a/src/index.ts".into()).await?;
+            assert!(condition.matches_with_context(&entry, &ctx).await?);
+            assert!(
+                !condition.matches(&entry).await?,
+                "nothing is an entry without package metadata"
+            );
+
+            let sibling =
+                FileSystemPath::new_normalized(fs, "packages/a/src/utils.ts".into()).await?;
+            assert!(!condition.matches_with_context(&sibling, &ctx).await?);
+
+            let nested =
+                FileSystemPath::new_normalized(fs, "packages/a/fixtures/b/src/index.ts".into())
+                    .await?;
+            assert!(
+                !condition.matches_with_context(&nested, &ctx).await?,
+                "the innermost package decides the entry"
+            );
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn compiled_condition() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let condition = ContextCondition::all(vec![
+                ContextCondition::InPath(FileSystemPath::new_normalized(fs, "packages".into())),
+                ContextCondition::not(ContextCondition::InDirectory("node_modules".to_string())),
+                ContextCondition::not(ContextCondition::InTurboCache),
+            ]);
+            let paths = (0..100)
+                .flat_map(|i| {
+                    [
+                        format!("packages/{i}/src/index.ts"),
+                        format!("packages/{i}/node_modules/dep/index.js"),
+                        format!("packages/{i}/.turbo/turbo-build.log"),
+                        format!("apps/{i}/src/index.ts"),
+                    ]
+                })
+                .collect::<Vec<_>>();
+
+            let built = PATTERNS_BUILT.with(Cell::get);
+            let compiled = condition.compile().await?;
+            assert_eq!(PATTERNS_BUILT.with(Cell::get) - built, 1);
+
+            let built = PATTERNS_BUILT.with(Cell::get);
+            for path in &paths {
+                let path = FileSystemPath::new_normalized(fs, path.clone()).await?;
+                assert_eq!(
+                    compiled.matches(&path).await?,
+                    condition.matches(&path).await?,
+                    "{}",
+                    path.path
+                );
+            }
+            // only the uncompiled condition builds patterns, and it only does
+            // so for paths inside `packages`
+            assert_eq!(PATTERNS_BUILT.with(Cell::get) - built, 300);
+
+            let matching = FileSystemPath::new_normalized(fs, paths[0].clone()).await?;
+            assert!(compiled.matches(&matching).await?);
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn imports_from() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let ctx = MatchContext {
+                imports: [(
+                    "src/index.ts".to_string(),
+                    vec!["react".to_string(), "@scope/legacy".to_string()],
+                )]
+                .into_iter()
+                .collect(),
+                ..Default::default()
+            };
+
+            let path = FileSystemPath::new_normalized(fs, "src/index.ts".into()).await?;
+            for specifier in ["@scope/legacy", "@scope/*", "react"] {
+                let condition = ContextCondition::ImportsFrom(specifier.to_string());
+                assert!(
+                    condition.matches_with_context(&path, &ctx).await?,
+                    "{specifier}"
+                );
+                assert!(
+                    condition
+                        .compile()
+                        .await?
+                        .matches_with_context(&path, &ctx)
+                        .await?,
+                    "{specifier}"
+                );
+                assert!(
+                    !condition.matches(&path).await?,
+                    "without import information, {specifier} doesn't match"
+                );
+            }
+
+            for specifier in ["@scope/modern", "@other/*", "react-dom"] {
+                let condition = ContextCondition::ImportsFrom(specifier.to_string());
+                assert!(
+                    !condition.matches_with_context(&path, &ctx).await?,
+                    "{specifier}"
+                );
+            }
+
+            let other = FileSystemPath::new_normalized(fs, "src/other.ts".into()).await?;
+            let condition = ContextCondition::ImportsFrom("react".to_string());
+            assert!(!condition.matches_with_context(&other, &ctx).await?);
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn has_export() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let ctx = MatchContext {
+                exports: [(
+                    "src/button.ts".to_string(),
+                    vec!["default".to_string(), "Button".to_string()],
+                )]
+                .into_iter()
+                .collect(),
+                ..Default::default()
+            };
+
+            let path = FileSystemPath::new_normalized(fs, "src/button.ts".into()).await?;
+            let condition = ContextCondition::HasExport("Button".to_string());
+            assert!(condition.matches_with_context(&path, &ctx).await?);
+            assert!(
+                condition
+                    .compile()
+                    .await?
+                    .matches_with_context(&path, &ctx)
+                    .await?
+            );
+            assert!(
+                !condition.matches(&path).await?,
+                "without export information, nothing matches"
+            );
+
+            let condition = ContextCondition::HasExport("Link".to_string());
+            assert!(!condition.matches_with_context(&path, &ctx).await?);
+
+            let other = FileSystemPath::new_normalized(fs, "src/link.ts".into()).await?;
+            let condition = ContextCondition::HasExport("default".to_string());
+            assert!(!condition.matches_with_context(&other, &ctx).await?);
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn sampled() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let condition = ContextCondition::Sampled {
+                inner: Box::new(ContextCondition::InDirectory("src".to_string())),
+                rate_ppm: 250_000,
+                seed: 42,
+            };
+            let compiled = condition.compile().await?;
+            let reseeded = ContextCondition::Sampled {
+                inner: Box::new(ContextCondition::InDirectory("src".to_string())),
+                rate_ppm: 250_000,
+                seed: 7,
+            };
+
+            let mut matched = 0;
+            let mut differs_by_seed = false;
+            for i in 0..1000 {
+                let path =
+                    FileSystemPath::new_normalized(fs, format!("src/file{i}.js").into()).await?;
+                let decision = condition.matches(&path).await?;
+                assert_eq!(decision, condition.matches(&path).await?, "{}", path.path);
+                assert_eq!(decision, compiled.matches(&path).await?, "{}", path.path);
+                differs_by_seed |= decision != reseeded.matches(&path).await?;
+                if decision {
+                    matched += 1;
+                }
+            }
+            assert!((200..300).contains(&matched), "{matched} of 1000 matched");
+            assert!(differs_by_seed, "the seed changes the sample");
+
+            let outside = FileSystemPath::new_normalized(fs, "lib/file.js".into()).await?;
+            let everything = ContextCondition::Sampled {
+                inner: Box::new(ContextCondition::InDirectory("src".to_string())),
+                rate_ppm: 1_000_000,
+                seed: 42,
+            };
+            assert!(
+                !everything.matches(&outside).await?,
+                "only paths matching the inner condition are sampled"
+            );
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn capped() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let condition = ContextCondition::Capped {
+                id: "src".to_string(),
+                inner: Box::new(ContextCondition::InDirectory("src".to_string())),
+                max: 5,
+            };
+            let compiled = condition.compile().await?;
+
+            let ctx = MatchContext::default();
+            let outside = FileSystemPath::new_normalized(fs, "lib/file.js".into()).await?;
+            assert!(!condition.matches_with_context(&outside, &ctx).await?);
+            let mut matched = Vec::new();
+            for i in 0..20 {
+                let path =
+                    FileSystemPath::new_normalized(fs, format!("src/file{i}.js").into()).await?;
+                // the compiled condition counts towards the same cap
+                let matches = if i % 2 == 0 {
+                    condition.matches_with_context(&path, &ctx).await?
+                } else {
+                    compiled.matches_with_context(&path, &ctx).await?
+                };
+                if matches {
+                    matched.push(i);
+                }
+            }
+            assert_eq!(matched, [0, 1, 2, 3, 4], "only the first 5 matches count");
+
+            let path = FileSystemPath::new_normalized(fs, "src/file0.js".into()).await?;
+            assert!(
+                !condition.matches_with_context(&path, &ctx.clone()).await?,
+                "clones of the context share the cap"
+            );
+            assert!(
+                condition
+                    .matches_with_context(&path, &MatchContext::default())
+                    .await?,
+                "a new context starts a new session"
+            );
+
+            let other = ContextCondition::Capped {
+                id: "other".to_string(),
+                inner: Box::new(ContextCondition::InDirectory("src".to_string())),
+                max: 5,
+            };
+            assert!(
+                other.matches_with_context(&path, &ctx).await?,
+                "a rule with another id has a cap of its own"
+            );
+            let same_id = ContextCondition::Capped {
+                id: "src".to_string(),
+                inner: Box::new(ContextCondition::InDirectory("src".to_string())),
+                max: 5,
+            };
+            assert!(
+                !same_id.matches_with_context(&path, &ctx).await?,
+                "rules with the same id share a count"
+            );
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn named_predicate() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let ctx = MatchContext {
+                predicates: [(
+                    "is_story".to_string(),
+                    (|path: &FileSystemPath| path.path.ends_with(".stories.tsx")) as Predicate,
+                )]
+                .into_iter()
+                .collect(),
+                ..Default::default()
+            };
+            let condition = ContextCondition::all(vec![
+                ContextCondition::NamedPredicate("is_story".to_string()),
+                ContextCondition::not(ContextCondition::InDirectory("legacy".to_string())),
+            ]);
+            let compiled = condition.compile().await?;
+
+            for (path, expected) in [
+                ("src/button.stories.tsx", true),
+                ("src/legacy/button.stories.tsx", false),
+                ("src/button.tsx", false),
+            ] {
+                let path = FileSystemPath::new_normalized(fs, path.into()).await?;
+                assert_eq!(
+                    condition.matches_with_context(&path, &ctx).await?,
+                    expected,
+                    "{}",
+                    path.path
+                );
+                assert_eq!(
+                    compiled.matches_with_context(&path, &ctx).await?,
+                    expected,
+                    "{}",
+                    path.path
+                );
+            }
+
+            let story = FileSystemPath::new_normalized(fs, "src/button.stories.tsx".into()).await?;
+            assert!(
+                !condition.matches(&story).await?,
+                "without the predicate, nothing matches"
+            );
+            let unknown = ContextCondition::NamedPredicate("other".to_string());
+            assert!(!unknown.matches_with_context(&story, &ctx).await?);
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn in_runtime_ignore() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let ctx = MatchContext {
+                runtime_ignores: vec!["generated/**".to_string()],
+                ..Default::default()
+            };
+            let condition = ContextCondition::InRuntimeIgnore;
+            let compiled = condition.compile().await?;
+            assert!(matches!(compiled, CompiledCondition::InRuntimeIgnore));
+
+            let parsed = CONTEXT_GLOBS_PARSED.with(Cell::get);
+            for (path, expected) in [
+                ("generated/schema.ts", true),
+                ("generated/api/client.ts", true),
+                ("src/generated.ts", false),
+                ("src/index.ts", false),
+            ] {
+                let path = FileSystemPath::new_normalized(fs, path.into()).await?;
+                assert_eq!(
+                    condition.matches_with_context(&path, &ctx).await?,
+                    expected,
+                    "{}",
+                    path.path
+                );
+                assert_eq!(
+                    compiled.matches_with_context(&path, &ctx).await?,
+                    expected,
+                    "{}",
+                    path.path
+                );
+            }
+            assert_eq!(
+                CONTEXT_GLOBS_PARSED.with(Cell::get) - parsed,
+                1,
+                "the runtime ignores are parsed once per context"
+            );
+
+            let path = FileSystemPath::new_normalized(fs, "generated/schema.ts".into()).await?;
+            assert!(
+                !condition.matches(&path).await?,
+                "without runtime ignores, nothing matches"
+            );
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn matches_ruleset() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let ruleset = Ruleset {
+                rules: vec![
+                    Rule {
+                        id: "generated".to_string(),
+                        condition: ContextCondition::InDirectory("__generated__".to_string()),
+                    },
+                    Rule {
+                        id: "cache".to_string(),
+                        condition: ContextCondition::InTurboCache,
+                    },
+                ],
+            };
+            let ctx = MatchContext {
+                rulesets: [("plugin".to_string(), ruleset.clone())]
+                    .into_iter()
+                    .collect(),
+                ..Default::default()
+            };
+            let condition = ContextCondition::MatchesRuleset("plugin".to_string());
+
+            let covered = FileSystemPath::new_normalized(fs, "a/.turbo/log".into()).await?;
+            assert!(condition.matches_with_context(&covered, &ctx).await?);
+            assert!(
+                condition
+                    .compile()
+                    .await?
+                    .matches_with_context(&covered, &ctx)
+                    .await?
+            );
+            assert_eq!(ruleset.matching_rule(&covered, &ctx).await?, Some("cache"));
+            assert!(
+                !condition.matches(&covered).await?,
+                "without the ruleset, nothing matches"
+            );
+
+            let uncovered = FileSystemPath::new_normalized(fs, "a/src/index.js".into()).await?;
+            assert!(!condition.matches_with_context(&uncovered, &ctx).await?);
+
+            let unknown = ContextCondition::MatchesRuleset("other".to_string());
+            assert!(!unknown.matches_with_context(&covered, &ctx).await?);
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn guarded() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let condition = ContextCondition::Guarded {
+                prefix: "packages/".to_string(),
+                inner: Box::new(ContextCondition::InDirectory("src".to_string())),
+            };
+
+            let inside = FileSystemPath::new_normalized(fs, "packages/a/src/x.js".into()).await?;
+            let built = PATTERNS_BUILT.with(Cell::get);
+            assert!(condition.matches(&inside).await?);
+            assert_eq!(
+                PATTERNS_BUILT.with(Cell::get) - built,
+                1,
+                "inner is evaluated when the prefix matches"
+            );
+
+            let outside = FileSystemPath::new_normalized(fs, "apps/web/src/x.js".into()).await?;
+            let built = PATTERNS_BUILT.with(Cell::get);
+            assert!(!condition.matches(&outside).await?);
+            assert_eq!(
+                PATTERNS_BUILT.with(Cell::get),
+                built,
+                "inner is not evaluated when the prefix doesn't match"
+            );
+
+            let compiled = condition.compile().await?;
+            assert!(compiled.matches(&inside).await?);
+            assert!(!compiled.matches(&outside).await?);
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn package_relative_depth() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let ctx = MatchContext {
+                packages: vec![PackageInfo {
+                    root: "packages/a".to_string(),
+                    entry: None,
+                    module_type: None,
+                    version: None,
+                    export_targets: None,
+                }],
+                ..Default::default()
+            };
+            assert_eq!(ctx.package_relative_depth("packages/a/index.ts"), Some(0));
+            assert_eq!(
+                ctx.package_relative_depth("packages/a/src/deep/x.ts"),
+                Some(2)
+            );
+            assert_eq!(ctx.package_relative_depth("packages/b/index.ts"), None);
+
+            let top_level =
+                FileSystemPath::new_normalized(fs, "packages/a/index.ts".into()).await?;
+            let nested =
+                FileSystemPath::new_normalized(fs, "packages/a/src/deep/x.ts".into()).await?;
+
+            let shallow = ContextCondition::PackageRelativeDepth { max: 0 };
+            assert!(shallow.matches_with_context(&top_level, &ctx).await?);
+            assert!(!shallow.matches_with_context(&nested, &ctx).await?);
+
+            let deep = ContextCondition::PackageRelativeDepth { max: 2 };
+            assert!(deep.matches_with_context(&nested, &ctx).await?);
+            assert!(
+                !deep.matches(&top_level).await?,
+                "nothing is in a package without package metadata"
+            );
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn is_binary() {
+        crate::register();
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap().to_string();
+        std::fs::write(dir.path().join("text.txt"), "hello, wörld\n").unwrap();
+        std::fs::write(dir.path().join("nulls.bin"), b"PK\x03\x04\x00\x00hello").unwrap();
+        std::fs::write(dir.path().join("latin1.txt"), b"caf\xe9 au lait").unwrap();
+
+        let tt = TurboTasks::new(MemoryBackend::default());
+        tt.run_once(async move {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(DiskFileSystem::new(
+                "test".to_string(),
+                root,
+                vec![],
+            ));
+            let condition = ContextCondition::IsBinary;
+
+            let text = FileSystemPath::new_normalized(fs, "text.txt".into()).await?;
+            assert!(!condition.matches(&text).await?);
+
+            let nulls = FileSystemPath::new_normalized(fs, "nulls.bin".into()).await?;
+            assert!(condition.matches(&nulls).await?);
+
+            let latin1 = FileSystemPath::new_normalized(fs, "latin1.txt".into()).await?;
+            assert!(condition.matches(&latin1).await?, "invalid UTF-8 is binary");
+
+            let missing = FileSystemPath::new_normalized(fs, "missing.bin".into()).await?;
+            assert!(!condition.matches(&missing).await?);
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn is_generated() {
+        crate::register();
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap().to_string();
+        std::fs::write(
+            dir.path().join("schema.ts"),
+            "/**\n * This file was written by a tool.\n * @generated\n */\nexport type Query = \
+             {};\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("button.tsx"),
+            "// a button\nexport const Button = () => null;\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("api.js"),
+            "// Produced by our-codegen, edits will be lost\nmodule.exports = {};\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("late.js"),
+            format!("{}// @generated\n", "// filler\n".repeat(500)),
+        )
+        .unwrap();
+
+        let tt = TurboTasks::new(MemoryBackend::default());
+        tt.run_once(async move {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(DiskFileSystem::new(
+                "test".to_string(),
+                root,
+                vec![],
+            ));
+            let condition = ContextCondition::IsGenerated {
+                extra_markers: vec![],
+            };
+            let with_extra = ContextCondition::IsGenerated {
+                extra_markers: vec!["Produced by our-codegen".to_string()],
+            };
+
+            let schema = FileSystemPath::new_normalized(fs, "schema.ts".into()).await?;
+            assert!(condition.matches(&schema).await?);
+
+            let button = FileSystemPath::new_normalized(fs, "button.tsx".into()).await?;
+            assert!(!condition.matches(&button).await?);
+            assert!(!with_extra.matches(&button).await?);
+
+            let api = FileSystemPath::new_normalized(fs, "api.js".into()).await?;
+            assert!(!condition.matches(&api).await?);
+            assert!(with_extra.matches(&api).await?);
+
+            let late = FileSystemPath::new_normalized(fs, "late.js".into()).await?;
+            assert!(
+                !condition.matches(&late).await?,
+                "markers past the start of the file are ignored"
+            );
+
+            let missing = FileSystemPath::new_normalized(fs, "missing.ts".into()).await?;
+            assert!(!condition.matches(&missing).await?);
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn content_hash_shard() {
+        crate::register();
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap().to_string();
+        for i in 0..20 {
+            std::fs::write(dir.path().join(format!("{i}.js")), format!("export = {i};")).unwrap();
+        }
+        std::fs::write(dir.path().join("copy.js"), "export = 0;").unwrap();
+
+        let tt = TurboTasks::new(MemoryBackend::default());
+        tt.run_once(async move {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(DiskFileSystem::new(
+                "test".to_string(),
+                root,
+                vec![],
+            ));
+            async fn shards_of(path: &FileSystemPath) -> anyhow::Result<Vec<u32>> {
+                let mut matching = Vec::new();
+                for index in 0..3 {
+                    let shard = ContextCondition::ContentHashShard { shards: 3, index };
+                    if shard.matches(path).await? {
+                        matching.push(index);
+                    }
+                }
+                Ok(matching)
+            }
+
+            for i in 0..20 {
+                let path = FileSystemPath::new_normalized(fs, format!("{i}.js").into()).await?;
+                assert_eq!(
+                    shards_of(&path).await?.len(),
+                    1,
+                    "{i}.js is in exactly one shard"
+                );
+            }
+
+            let original = FileSystemPath::new_normalized(fs, "0.js".into()).await?;
+            let copy = FileSystemPath::new_normalized(fs, "copy.js".into()).await?;
+            assert_eq!(
+                shards_of(&original).await?,
+                shards_of(&copy).await?,
+                "the shard only depends on the content"
+            );
+            assert_eq!(shards_of(&original).await?, shards_of(&original).await?);
+
+            let missing = FileSystemPath::new_normalized(fs, "missing.js".into()).await?;
+            assert!(shards_of(&missing).await?.is_empty());
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn nearest_marker_any() {
+        crate::register();
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap().to_string();
+        std::fs::create_dir_all(dir.path().join("apps/web/src/pages")).unwrap();
+        std::fs::write(dir.path().join("apps/web/jsconfig.json"), "{}").unwrap();
+        std::fs::write(dir.path().join("apps/web/src/pages/index.js"), "").unwrap();
+        std::fs::create_dir_all(dir.path().join("scripts")).unwrap();
+        std::fs::write(dir.path().join("scripts/build.js"), "").unwrap();
+
+        let tt = TurboTasks::new(MemoryBackend::default());
+        tt.run_once(async move {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(DiskFileSystem::new(
+                "test".to_string(),
+                root,
+                vec![],
+            ));
+            let markers = ["package.json", "jsconfig.json", "tsconfig.json"]
+                .map(String::from)
+                .to_vec();
+            let condition = ContextCondition::NearestMarkerAny(markers.clone());
+
+            let page =
+                FileSystemPath::new_normalized(fs, "apps/web/src/pages/index.js".into()).await?;
+            assert!(condition.matches(&page).await?);
+            let boundary = nearest_marker_dir(&page, &markers)
+                .await?
+                .expect("the app directory has a marker");
+            assert_eq!(boundary.await?.path, "apps/web");
+
+            let script = FileSystemPath::new_normalized(fs, "scripts/build.js".into()).await?;
+            assert!(
+                !condition.matches(&script).await?,
+                "no marker up to the root"
+            );
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn in_turbo_package() {
+        crate::register();
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap().to_string();
+        for subdir in ["apps/web/src/lib", "apps/docs/src", "scripts"] {
+            std::fs::create_dir_all(dir.path().join(subdir)).unwrap();
+        }
+        std::fs::write(dir.path().join("apps/web/turbo.json"), "{}").unwrap();
+        std::fs::write(
+            dir.path().join("apps/docs/package.json"),
+            r#"{ "name": "docs", "turbo": { "pipeline": {} } }"#,
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("package.json"), r#"{ "name": "root" }"#).unwrap();
+
+        let tt = TurboTasks::new(MemoryBackend::default());
+        tt.run_once(async move {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(DiskFileSystem::new(
+                "test".to_string(),
+                root,
+                vec![],
+            ));
+            let condition = ContextCondition::InTurboPackage;
+            let compiled = condition.compile().await?;
+            let ctx = MatchContext::default();
+
+            for (path, expected) in [
+                // turbo.json two levels up
+                ("apps/web/src/lib/util.ts", true),
+                ("apps/web/src/index.ts", true),
+                ("apps/docs/src/index.ts", true),
+                // the root package.json has no turbo config
+                ("scripts/build.js", false),
+            ] {
+                let path = FileSystemPath::new_normalized(fs, path.into()).await?;
+                assert_eq!(
+                    condition.matches_with_context(&path, &ctx).await?,
+                    expected,
+                    "{}",
+                    path.path
+                );
+                let parent = path.clone().cell().parent().await?;
+                assert_eq!(
+                    ctx.turbo_packages
+                        .lock()
+                        .unwrap()
+                        .get(&(parent.fs, parent.path.clone())),
+                    Some(&expected),
+                    "the uncompiled condition caches {} in the context",
+                    parent.path
+                );
+                // answered from the directories cached by the first match
+                assert_eq!(
+                    compiled.matches_with_context(&path, &ctx).await?,
+                    expected,
+                    "{}",
+                    path.path
+                );
+                assert_eq!(compiled.matches(&path).await?, expected, "{}", path.path);
+            }
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn matches_editorconfig_section() {
+        crate::register();
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap().to_string();
+        for subdir in ["web/src/lib", "scripts"] {
+            std::fs::create_dir_all(dir.path().join(subdir)).unwrap();
+        }
+        std::fs::write(
+            dir.path().join("web/.editorconfig"),
+            "root = true\n\n[*.{js,ts}]\nindent_size = 2\n",
+        )
+        .unwrap();
+
+        let tt = TurboTasks::new(MemoryBackend::default());
+        tt.run_once(async move {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(DiskFileSystem::new(
+                "test".to_string(),
+                root,
+                vec![],
+            ));
+            let condition = ContextCondition::MatchesEditorConfigSection("*.{js,ts}".to_string());
+            let compiled = condition.compile().await?;
+            let missing = ContextCondition::MatchesEditorConfigSection("*.css".to_string());
+
+            for (path, expected) in [
+                ("web/src/lib/util.ts", true),
+                ("web/src/index.js", true),
+                ("web/src/index.css", false),
+                ("web/src/index.tsx", false),
+                // no .editorconfig above it
+                ("scripts/build.js", false),
+            ] {
+                let path = FileSystemPath::new_normalized(fs, path.into()).await?;
+                assert_eq!(condition.matches(&path).await?, expected, "{}", path.path);
+                assert_eq!(compiled.matches(&path).await?, expected, "{}", path.path);
+                // the .editorconfig has no such section
+                assert!(!missing.matches(&path).await?, "{}", path.path);
+            }
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[test]
+    fn editorconfig_globs() {
+        for (glob, path, expected) in [
+            ("*", "src/index.ts", true),
+            ("*.ts", "src/index.ts", true),
+            ("src/*.ts", "src/index.ts", true),
+            ("src/*.ts", "src/lib/index.ts", false),
+            ("src/**.ts", "src/lib/index.ts", true),
+            ("/index.ts", "src/index.ts", false),
+            ("index.?s", "index.js", true),
+            ("[!a]*.js", "b.js", true),
+            ("[!a]*.js", "a.js", false),
+            ("file{1..3}.md", "file2.md", true),
+            ("file{1..3}.md", "file4.md", false),
+            ("*.{json,{yml,yaml}}", "ci.yaml", true),
+            ("{single}.md", "{single}.md", true),
+        ] {
+            let regex = editorconfig_regex(glob).unwrap();
+            assert_eq!(regex.is_match(path), expected, "{glob} {path}");
+        }
+        assert!(editorconfig_regex("{0..99999999}").is_err());
+    }
+
+    #[tokio::test]
+    async fn matches_tsconfig_alias() {
+        crate::register();
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap().to_string();
+        for subdir in [
+            "app/src/nested",
+            "app/lib",
+            "app/config",
+            "web/src",
+            "other/src",
+        ] {
+            std::fs::create_dir_all(dir.path().join(subdir)).unwrap();
+        }
+        std::fs::write(
+            dir.path().join("app/tsconfig.json"),
+            r#"{
+                // comments are allowed in a tsconfig
+                "compilerOptions": {
+                    "paths": {
+                        "@app/*": ["src/*"],
+                        "@config": ["./config/index"],
+                    },
+                },
+            }"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("web/tsconfig.json"),
+            r#"{ "compilerOptions": { "baseUrl": "src", "paths": { "@/*": ["*"] } } }"#,
+        )
+        .unwrap();
+
+        let tt = TurboTasks::new(MemoryBackend::default());
+        tt.run_once(async move {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(DiskFileSystem::new(
+                "test".to_string(),
+                root,
+                vec![],
+            ));
+
+            for (alias, path, expected) in [
+                ("@app/*", "app/src/a.ts", true),
+                ("@app/*", "app/src/nested/b.tsx", true),
+                ("@app/*", "app/lib/c.ts", false),
+                ("@config", "app/config/index.ts", true),
+                ("@config", "app/config/other.ts", false),
+                ("@lib/*", "app/lib/c.ts", false),
+                ("@/*", "web/src/d.ts", true),
+                ("@/*", "web/e.ts", false),
+                ("@app/*", "other/src/a.ts", false),
+            ] {
+                let condition = ContextCondition::MatchesTsconfigAlias(alias.to_string());
+                let path = FileSystemPath::new_normalized(fs, path.into()).await?;
+                assert_eq!(
+                    condition.matches(&path).await?,
+                    expected,
+                    "{alias} {}",
+                    path.path
+                );
+            }
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn in_allowlist() {
+        crate::register();
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap().to_string();
+        std::fs::write(
+            dir.path().join("allowlist.txt"),
+            "src/a.js\n\npackages/b/index.ts\n",
+        )
+        .unwrap();
+
+        let tt = TurboTasks::new(MemoryBackend::default());
+        tt.run_once(async move {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(DiskFileSystem::new(
+                "test".to_string(),
+                root,
+                vec![],
+            ));
+            let condition = ContextCondition::InAllowlist(FileSystemPath::new_normalized(
+                fs,
+                "allowlist.txt".into(),
+            ));
+            let compiled = condition.compile().await?;
+
+            for (path, expected) in [
+                ("src/a.js", true),
+                ("packages/b/index.ts", true),
+                ("src/a.jsx", false),
+                ("src", false),
+                ("", false),
+            ] {
+                let path = FileSystemPath::new_normalized(fs, path.into()).await?;
+                assert_eq!(condition.matches(&path).await?, expected, "{}", path.path);
+                assert_eq!(compiled.matches(&path).await?, expected, "{}", path.path);
+            }
+
+            let missing = ContextCondition::InAllowlist(FileSystemPath::new_normalized(
+                fs,
+                "missing.txt".into(),
+            ));
+            let path = FileSystemPath::new_normalized(fs, "src/a.js".into()).await?;
+            assert!(!missing.matches(&path).await?);
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn same_file_as_without_links() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let condition =
+                ContextCondition::SameFileAs(FileSystemPath::new_normalized(fs, "src/a.ts".into()));
+
+            let same = FileSystemPath::new_normalized(fs, "src/a.ts".into()).await?;
+            assert!(condition.matches(&same).await?);
+
+            let other = FileSystemPath::new_normalized(fs, "src/b.ts".into()).await?;
+            assert!(!condition.matches(&other).await?);
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn in_temp_dir() {
+        crate::register();
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap().to_string();
+        std::fs::create_dir_all(dir.path().join("tmp")).unwrap();
+        std::fs::create_dir_all(dir.path().join("repo/src")).unwrap();
+        std::fs::write(dir.path().join("tmp/scratch.js"), "").unwrap();
+        std::fs::write(dir.path().join("repo/src/index.js"), "").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(
+            "../../tmp/scratch.js",
+            dir.path().join("repo/src/linked.js"),
+        )
+        .unwrap();
+        let ctx = MatchContext {
+            temp_dir: Some(dir.path().join("tmp")),
+            ..Default::default()
+        };
+
+        let tt = TurboTasks::new(MemoryBackend::default());
+        tt.run_once(async move {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(DiskFileSystem::new(
+                "test".to_string(),
+                root,
+                vec![],
+            ));
+            let condition = ContextCondition::InTempDir;
+
+            let scratch = FileSystemPath::new_normalized(fs, "tmp/scratch.js".into()).await?;
+            assert!(condition.matches_with_context(&scratch, &ctx).await?);
+
+            let index = FileSystemPath::new_normalized(fs, "repo/src/index.js".into()).await?;
+            assert!(!condition.matches_with_context(&index, &ctx).await?);
+            assert!(
+                condition.matches(&index).await?,
+                "the test workspace is itself in the platform temp directory"
+            );
+
+            #[cfg(unix)]
+            {
+                let linked =
+                    FileSystemPath::new_normalized(fs, "repo/src/linked.js".into()).await?;
+                assert!(
+                    condition.matches_with_context(&linked, &ctx).await?,
+                    "links are resolved to their target"
+                );
+            }
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn in_os_cache_dir() {
+        crate::register();
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap().to_string();
+        std::fs::create_dir_all(dir.path().join("cache/eslint")).unwrap();
+        std::fs::create_dir_all(dir.path().join("repo/src")).unwrap();
+        std::fs::write(dir.path().join("cache/eslint/results.json"), "{}").unwrap();
+        std::fs::write(dir.path().join("repo/src/index.js"), "").unwrap();
+        let ctx = MatchContext {
+            cache_dir: Some(dir.path().join("cache")),
+            ..Default::default()
+        };
+
+        let tt = TurboTasks::new(MemoryBackend::default());
+        tt.run_once(async move {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(DiskFileSystem::new(
+                "test".to_string(),
+                root,
+                vec![],
+            ));
+            let condition = ContextCondition::InOsCacheDir;
+
+            let cached =
+                FileSystemPath::new_normalized(fs, "cache/eslint/results.json".into()).await?;
+            assert!(condition.matches_with_context(&cached, &ctx).await?);
+
+            let index = FileSystemPath::new_normalized(fs, "repo/src/index.js".into()).await?;
+            assert!(!condition.matches_with_context(&index, &ctx).await?);
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn same_file_as_links() {
+        crate::register();
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap().to_string();
+        std::fs::write(dir.path().join("a.ts"), "export {}").unwrap();
+        std::fs::write(dir.path().join("b.ts"), "export {}").unwrap();
+        std::fs::hard_link(dir.path().join("a.ts"), dir.path().join("hardlink.ts")).unwrap();
+        std::os::unix::fs::symlink("a.ts", dir.path().join("symlink.ts")).unwrap();
+
+        let tt = TurboTasks::new(MemoryBackend::default());
+        tt.run_once(async move {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(DiskFileSystem::new(
+                "test".to_string(),
+                root,
+                vec![],
+            ));
+            let condition =
+                ContextCondition::SameFileAs(FileSystemPath::new_normalized(fs, "a.ts".into()));
+
+            for (path, expected) in [
+                ("a.ts", true),
+                ("hardlink.ts", true),
+                ("symlink.ts", true),
+                ("b.ts", false),
+                ("missing.ts", false),
+            ] {
+                let path = FileSystemPath::new_normalized(fs, path.into()).await?;
+                assert_eq!(condition.matches(&path).await?, expected, "{}", path.path);
+            }
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn in_manifest_range() {
+        crate::register();
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap().to_string();
+        std::fs::write(
+            dir.path().join("manifest.txt"),
+            "src/a.js\nsrc/b.js\nsrc/c.js\nsrc/d.js\nsrc/e.js\nsrc/a.js\n",
+        )
+        .unwrap();
+
+        let tt = TurboTasks::new(MemoryBackend::default());
+        tt.run_once(async move {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(DiskFileSystem::new(
+                "test".to_string(),
+                root,
+                vec![],
+            ));
+            let condition = ContextCondition::InManifestRange {
+                manifest: FileSystemPath::new_normalized(fs, "manifest.txt".into()),
+                start: 1,
+                end: 3,
+            };
+            let compiled = condition.compile().await?;
+
+            for (path, expected) in [
+                ("src/a.js", false),
+                ("src/b.js", true),
+                ("src/c.js", true),
+                ("src/d.js", false),
+                ("src/e.js", false),
+                ("src/f.js", false),
+            ] {
+                let path = FileSystemPath::new_normalized(fs, path.into()).await?;
+                assert_eq!(condition.matches(&path).await?, expected, "{}", path.path);
+                assert_eq!(compiled.matches(&path).await?, expected, "{}", path.path);
+            }
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn glob_in_roots() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let condition = ContextCondition::GlobInRoots {
+                roots: vec![
+                    FileSystemPath::new_normalized(fs, "frontend".into()),
+                    FileSystemPath::new_normalized(fs, "backend/api".into()),
+                ],
+                pattern: "src/**".to_string(),
+            };
+            let compiled = condition.compile().await?;
+
+            for (path, expected) in [
+                ("frontend/src/index.ts", true),
+                ("backend/api/src/routes/users.ts", true),
+                ("frontend/test/index.test.ts", false),
+                ("backend/api/test/users.test.ts", false),
+                ("backend/src/index.ts", false),
+                ("docs/src/index.md", false),
+            ] {
+                let path = FileSystemPath::new_normalized(fs, path.into()).await?;
+                assert_eq!(condition.matches(&path).await?, expected, "{}", path.path);
+                assert_eq!(compiled.matches(&path).await?, expected, "{}", path.path);
+            }
+
+            let no_roots = ContextCondition::GlobInRoots {
+                roots: vec![],
+                pattern: "**".to_string(),
+            };
+            let path = FileSystemPath::new_normalized(fs, "frontend/src/index.ts".into()).await?;
+            assert!(!no_roots.matches(&path).await?);
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn relative_glob() {
+        crate::register();
+
+        turbo_tasks_testing::VcStorage::with(async {
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(VirtualFileSystem::new());
+            let condition = ContextCondition::RelativeGlob {
+                base: FileSystemPath::new_normalized(fs, "packages/a".into()),
+                pattern: "src/**".to_string(),
+            };
+            let compiled = condition.compile().await?;
+
+            for (path, expected) in [
+                ("packages/a/src/x", true),
+                ("packages/a/src/nested/x.ts", true),
+                ("packages/a/test/x", false),
+                ("packages/b/src/x", false),
+                ("packages/ab/src/x", false),
+            ] {
+                let path = FileSystemPath::new_normalized(fs, path.into()).await?;
+                assert_eq!(condition.matches(&path).await?, expected, "{}", path.path);
+                assert_eq!(compiled.matches(&path).await?, expected, "{}", path.path);
+            }
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
 }
@@ -20,6 +20,15 @@ use crate::{
 
 type Hash = String;
 
+/// A glob that the [`GlobWatcher`] is watching, along with the exclusions of
+/// the glob sets that still watch it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchedGlob {
+    pub glob: String,
+    /// Sorted, without duplicates.
+    pub exclude: Vec<String>,
+}
+
 #[derive(Clone)]
 pub struct GlobSet {
     include: HashMap<String, wax::Glob<'static>>,
@@ -183,6 +192,9 @@ pub enum Query {
         candidates: HashSet<String>,
         resp: oneshot::Sender<Result<HashSet<String>, Error>>,
     },
+    WatchedGlobs {
+        resp: oneshot::Sender<Result<Vec<WatchedGlob>, Error>>,
+    },
 }
 
 struct GlobTracker {
@@ -280,6 +292,19 @@ impl GlobWatcher {
         tokio::time::timeout(timeout, rx).await??
     }
 
+    /// Get the globs that are currently watched for any hash, in sorted
+    /// order. Globs that have changed for every hash are no longer watched.
+    ///
+    /// This function will return `Error::Unavailable` if the globwatcher is not
+    /// yet available.
+    pub async fn watched_globs(&self, timeout: Duration) -> Result<Vec<WatchedGlob>, Error> {
+        let (tx, rx) = oneshot::channel();
+        let req = Query::WatchedGlobs { resp: tx };
+
+        self.send_request(req).await?;
+        tokio::time::timeout(timeout, rx).await??
+    }
+
     async fn send_request(&self, req: Query) -> Result<(), Error> {
         let cookied_request = self.cookie_writer.cookie_request(req).await?;
         let mut query_ch = self.query_ch_lazy.clone();
@@ -369,6 +394,25 @@ impl GlobTracker {
                 // If the client has gone away, we don't care about the error
                 let _ = resp.send(Ok(candidates));
             }
+            Query::WatchedGlobs { resp } => {
+                let mut globs = self
+                    .glob_statuses
+                    .iter()
+                    .map(|(glob, (_, hashes))| {
+                        let exclude = hashes
+                            .iter()
+                            .filter_map(|hash| self.hash_globs.get(hash))
+                            .flat_map(|glob_set| glob_set.exclude_raw.iter().cloned())
+                            .collect::<BTreeSet<_>>();
+                        WatchedGlob {
+                            glob: glob.clone(),
+                            exclude: exclude.into_iter().collect(),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                globs.sort_by(|a, b| a.glob.cmp(&b.glob));
+                let _ = resp.send(Ok(globs));
+            }
         }
     }
 
@@ -478,7 +522,7 @@ mod test {
 
     use crate::{
         cookies::CookieWriter,
-        globwatcher::{GlobSet, GlobWatcher},
+        globwatcher::{GlobSet, GlobWatcher, WatchedGlob},
         FileSystemWatcher,
     };
 
@@ -626,6 +670,51 @@ mod test {
         assert_eq!(results, expected);
     }
 
+    #[tokio::test]
+    async fn test_watched_globs() {
+        let timeout = Duration::from_secs(2);
+        let (repo_root, _tmp_dir) = temp_dir();
+        setup(&repo_root);
+        let cookie_dir = repo_root.join_component(".git");
+
+        let watcher = FileSystemWatcher::new_with_default_cookie_dir(&repo_root).unwrap();
+        let recv = watcher.watch();
+        let cookie_writer = CookieWriter::new(&cookie_dir, Duration::from_secs(2), recv.clone());
+        let glob_watcher = GlobWatcher::new(repo_root.clone(), cookie_writer, recv);
+
+        let raw_includes = &["my-pkg/dist/**", "my-pkg/.next/**"];
+        let raw_excludes = ["my-pkg/.next/cache/**"];
+        let globs = GlobSet {
+            include: make_includes(raw_includes),
+            exclude: any(raw_excludes).unwrap().to_owned(),
+            exclude_raw: raw_excludes.iter().map(|s| s.to_string()).collect(),
+        };
+        let hash = "the-hash".to_string();
+        glob_watcher
+            .watch_globs(hash.clone(), globs, timeout)
+            .await
+            .unwrap();
+
+        let watched = |glob: &str| WatchedGlob {
+            glob: glob.to_string(),
+            exclude: vec!["my-pkg/.next/cache/**".to_string()],
+        };
+        assert_eq!(
+            glob_watcher.watched_globs(timeout).await.unwrap(),
+            [watched("my-pkg/.next/**"), watched("my-pkg/dist/**")]
+        );
+
+        // a changed glob is no longer watched
+        repo_root
+            .join_components(&["my-pkg", "dist", "foo"])
+            .create_with_contents("some bytes")
+            .unwrap();
+        assert_eq!(
+            glob_watcher.watched_globs(timeout).await.unwrap(),
+            [watched("my-pkg/.next/**")]
+        );
+    }
+
     #[tokio::test]
     async fn test_track_multiple_hashes() {
         let timeout = Duration::from_secs(2);
@@ -422,10 +422,10 @@ impl Subscriber {
         let mut changed_packages: HashSet<AnchoredSystemPathBuf> = HashSet::new();
         for path in event.paths {
             let path = AbsoluteSystemPathBuf::try_from(path).expect("event path is a valid path");
-            let repo_relative_change_path = self
-                .repo_root
-                .anchor(&path)
-                .expect("event path is in the repository");
+            // cookies may be written outside of the repository
+            let Ok(repo_relative_change_path) = self.repo_root.anchor(&path) else {
+                continue;
+            };
             // If this change is not relevant to a package, ignore it
             trace!("file change at {:?}", repo_relative_change_path);
             if let Some(package_path) = hashes.get_package_path(&repo_relative_change_path) {
@@ -5,7 +5,7 @@ use std::{
     fmt::{Debug, Display},
     future::IntoFuture,
     path::Path,
-    sync::Arc,
+    sync::{Arc, OnceLock},
     time::Duration,
 };
 
@@ -18,7 +18,7 @@ use fsevent::FsEventWatcher;
 use notify::event::EventKind;
 #[cfg(not(target_os = "macos"))]
 use notify::{Config, RecommendedWatcher};
-use notify::{Event, EventHandler, RecursiveMode, Watcher};
+use notify::{Event, EventHandler, PollWatcher, RecursiveMode, Watcher};
 use thiserror::Error;
 use tokio::sync::{broadcast, mpsc, watch::error::RecvError};
 use tracing::{debug, warn};
@@ -51,6 +51,39 @@ type Backend = FsEventWatcher;
 
 type EventResult = Result<Event, notify::Error>;
 
+/// A watcher that has been set up with either backend.
+type AnyWatcher = Box<dyn Watcher + Send>;
+
+/// Which backend is delivering file events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatcherBackend {
+    /// The platform's file watching API.
+    Native,
+    /// Scanning the repository for changes on an interval.
+    Polling,
+}
+
+impl Display for WatcherBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            WatcherBackend::Native => "native",
+            WatcherBackend::Polling => "polling",
+        })
+    }
+}
+
+/// Options for how a [`FileSystemWatcher`] sets up its backend.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WatchOptions {
+    /// If the native backend fails to start, e.g. on network mounts or
+    /// where its watch limit is exhausted, poll for changes on this interval
+    /// instead of leaving file watching unavailable.
+    pub poll_fallback: Option<Duration>,
+    /// Don't try the native backend at all, as if it had failed. This is for
+    /// mounts where it is known to miss events.
+    pub disable_native: bool,
+}
+
 #[derive(Debug, Error)]
 pub enum WatchError {
     #[error("filewatching backend error: {0}")]
@@ -89,50 +122,84 @@ pub struct FileSystemWatcher {
     // to be notified of a close.
     _exit_ch: tokio::sync::oneshot::Sender<()>,
     cookie_dir: AbsoluteSystemPathBuf,
+    backend: Arc<OnceLock<WatcherBackend>>,
 }
 
 impl FileSystemWatcher {
     pub fn new_with_default_cookie_dir(root: &AbsoluteSystemPath) -> Result<Self, WatchError> {
-        // We already store logs in .turbo and recommend it be gitignore'd.
-        // Watchman uses .git, but we can't guarantee that git is present _or_
-        // that the turbo root is the same as the git root.
-        Self::new(root, root.join_components(&[".turbo", "cookies"]))
+        Self::new_with_options(root, default_cookie_dir(root), WatchOptions::default())
     }
 
     pub fn new(
         root: &AbsoluteSystemPath,
         cookie_dir: AbsoluteSystemPathBuf,
+    ) -> Result<Self, WatchError> {
+        Self::new_with_options(root, cookie_dir, WatchOptions::default())
+    }
+
+    /// Like [`FileSystemWatcher::new_with_default_cookie_dir`], with the given
+    /// options for setting up the backend.
+    pub fn new_with_default_cookie_dir_and_options(
+        root: &AbsoluteSystemPath,
+        options: WatchOptions,
+    ) -> Result<Self, WatchError> {
+        Self::new_with_options(root, default_cookie_dir(root), options)
+    }
+
+    /// Watches `root`, writing cookies to `cookie_dir`. A cookie dir outside
+    /// of `root` is watched alongside it, so that nothing is written to
+    /// `root`. Cookies then only order events in `root` on backends that
+    /// deliver the events of every watch in order, as inotify and FSEvents
+    /// do.
+    pub fn new_with_options(
+        root: &AbsoluteSystemPath,
+        cookie_dir: AbsoluteSystemPathBuf,
+        options: WatchOptions,
     ) -> Result<Self, WatchError> {
         tracing::debug!("initing file-system watcher");
 
-        if root.relation_to_path(&cookie_dir) != PathRelation::Parent {
-            return Err(WatchError::Setup(format!(
-                "Invalid cookie directory: {} does not contain {}",
-                root, cookie_dir
-            )));
-        }
+        // the cookie dir is cleared out on startup, so it must not contain the
+        // root
+        let external_cookie_dir = match root.relation_to_path(&cookie_dir) {
+            PathRelation::Parent => None,
+            PathRelation::Divergent => Some(cookie_dir.clone()),
+            PathRelation::Child => {
+                return Err(WatchError::Setup(format!(
+                    "Invalid cookie directory: {} contains {}",
+                    cookie_dir, root
+                )));
+            }
+        };
 
         let (file_events_receiver_tx, file_events_receiver_lazy) = OptionalWatch::new();
         let (send_file_events, mut recv_file_events) = mpsc::channel(1024);
         let (exit_ch, exit_signal) = tokio::sync::oneshot::channel();
+        let backend = Arc::new(OnceLock::new());
 
         tokio::task::spawn({
             let cookie_dir = cookie_dir.clone();
             let watch_root = root.to_owned();
+            let backend = backend.clone();
             async move {
                 // this task never yields, so run it in the blocking threadpool
                 let watch_root_task = watch_root.clone();
                 let cookie_dir_task = cookie_dir.clone();
                 let task = tokio::task::spawn_blocking(move || {
                     setup_cookie_dir(&cookie_dir_task)?;
-                    run_watcher(&watch_root_task, send_file_events)
+                    run_watcher(
+                        &watch_root_task,
+                        external_cookie_dir.as_deref(),
+                        send_file_events,
+                        options,
+                    )
                 });
 
-                let Ok(Ok(watcher)) = task.await else {
+                let Ok(Ok((watcher, kind))) = task.await else {
                     // if the watcher fails, just return. we don't set the event sender, and other
                     // services will never start
                     return;
                 };
+                let _ = backend.set(kind);
 
                 // Ensure we are ready to receive new events, not events for existing state
                 debug!("waiting for initial filesystem cookie");
@@ -154,7 +221,15 @@ impl FileSystemWatcher {
                     return;
                 }
 
-                watch_events(watcher, watch_root, recv_file_events, exit_signal, sender).await;
+                watch_events(
+                    watcher,
+                    watch_root,
+                    cookie_dir,
+                    recv_file_events,
+                    exit_signal,
+                    sender,
+                )
+                .await;
             }
         });
 
@@ -162,6 +237,7 @@ impl FileSystemWatcher {
             receiver: file_events_receiver_lazy,
             _exit_ch: exit_ch,
             cookie_dir,
+            backend,
         })
     }
 
@@ -181,6 +257,18 @@ impl FileSystemWatcher {
     pub fn cookie_dir(&self) -> &AbsoluteSystemPath {
         &self.cookie_dir
     }
+
+    /// The backend delivering events, once one has started.
+    pub fn backend(&self) -> Option<WatcherBackend> {
+        self.backend.get().copied()
+    }
+}
+
+fn default_cookie_dir(root: &AbsoluteSystemPath) -> AbsoluteSystemPathBuf {
+    // We already store logs in .turbo and recommend it be gitignore'd.
+    // Watchman uses .git, but we can't guarantee that git is present _or_
+    // that the turbo root is the same as the git root.
+    root.join_components(&[".turbo", "cookies"])
 }
 
 fn setup_cookie_dir(cookie_dir: &AbsoluteSystemPath) -> Result<(), WatchError> {
@@ -201,8 +289,9 @@ fn setup_cookie_dir(cookie_dir: &AbsoluteSystemPath) -> Result<(), WatchError> {
 
 #[cfg(not(any(feature = "watch_ancestors", feature = "manual_recursive_watch")))]
 async fn watch_events(
-    _watcher: Backend,
+    _watcher: AnyWatcher,
     _watch_root: AbsoluteSystemPathBuf,
+    _cookie_dir: AbsoluteSystemPathBuf,
     mut recv_file_events: mpsc::Receiver<EventResult>,
     exit_signal: tokio::sync::oneshot::Receiver<()>,
     broadcast_sender: broadcast::Sender<Result<Event, NotifyError>>,
@@ -221,9 +310,11 @@ async fn watch_events(
 
 #[cfg(any(feature = "watch_ancestors", feature = "manual_recursive_watch"))]
 async fn watch_events(
-    #[cfg(feature = "manual_recursive_watch")] mut watcher: Backend,
-    #[cfg(not(feature = "manual_recursive_watch"))] _watcher: Backend,
+    #[cfg(feature = "manual_recursive_watch")] mut watcher: AnyWatcher,
+    #[cfg(not(feature = "manual_recursive_watch"))] _watcher: AnyWatcher,
     watch_root: AbsoluteSystemPathBuf,
+    #[cfg(feature = "watch_ancestors")] cookie_dir: AbsoluteSystemPathBuf,
+    #[cfg(not(feature = "watch_ancestors"))] _cookie_dir: AbsoluteSystemPathBuf,
     mut recv_file_events: mpsc::Receiver<EventResult>,
     exit_signal: tokio::sync::oneshot::Receiver<()>,
     broadcast_sender: broadcast::Sender<Result<Event, NotifyError>>,
@@ -240,13 +331,13 @@ async fn watch_events(
                         // we don't try to add watches to siblings of the
                         // directories on our path to the root.
                         #[cfg(feature = "watch_ancestors")]
-                        filter_relevant(&watch_root, &mut event);
+                        filter_relevant(&watch_root, &cookie_dir, &mut event);
 
                         #[cfg(feature = "manual_recursive_watch")]
                         {
                             if event.kind == EventKind::Create(CreateKind::Folder) {
                                 for new_path in &event.paths {
-                                    if let Err(err) = manually_add_recursive_watches(new_path, &mut watcher, Some(&broadcast_sender)) {
+                                    if let Err(err) = manually_add_recursive_watches(new_path, watcher.as_mut(), Some(&broadcast_sender)) {
                                         warn!("encountered error watching filesystem {}", err);
                                         break 'outer;
                                     }
@@ -270,7 +361,7 @@ async fn watch_events(
 // to handle both getting irrelevant events and getting ancestor
 // events that translate to events at the root.
 #[cfg(feature = "watch_ancestors")]
-fn filter_relevant(root: &AbsoluteSystemPath, event: &mut Event) {
+fn filter_relevant(root: &AbsoluteSystemPath, cookie_dir: &AbsoluteSystemPath, event: &mut Event) {
     // If path contains root && event type is modify, synthesize modify at root
     let is_modify_existing = matches!(event.kind, EventKind::Remove(_) | EventKind::Modify(_));
 
@@ -279,6 +370,10 @@ fn filter_relevant(root: &AbsoluteSystemPath, event: &mut Event) {
             .as_path()
             .try_into()
             .expect("Non-absolute path from filewatching");
+        // cookies may be written outside of the root
+        if cookie_dir.relation_to_path(abs_path) == PathRelation::Parent {
+            return true;
+        }
         match root.relation_to_path(abs_path) {
             // An irrelevant path, probably from a non-recursive watch of a parent directory
             PathRelation::Divergent => false,
@@ -310,7 +405,7 @@ fn is_permission_denied(result: &Result<(), notify::Error>) -> bool {
 }
 
 #[cfg(feature = "watch_ancestors")]
-fn watch_parents(root: &AbsoluteSystemPath, watcher: &mut Backend) -> Result<(), WatchError> {
+fn watch_parents(root: &AbsoluteSystemPath, watcher: &mut dyn Watcher) -> Result<(), WatchError> {
     let mut current = root;
     while let Some(parent) = current.parent() {
         current = parent;
@@ -327,7 +422,10 @@ fn watch_parents(root: &AbsoluteSystemPath, watcher: &mut Backend) -> Result<(),
 }
 
 #[cfg(not(feature = "manual_recursive_watch"))]
-fn watch_recursively(root: &AbsoluteSystemPath, watcher: &mut Backend) -> Result<(), WatchError> {
+fn watch_recursively(
+    root: &AbsoluteSystemPath,
+    watcher: &mut dyn Watcher,
+) -> Result<(), WatchError> {
     watcher.watch(root.as_std_path(), RecursiveMode::Recursive)?;
     Ok(())
 }
@@ -342,7 +440,10 @@ fn is_not_found(err: &notify::Error) -> bool {
 }
 
 #[cfg(feature = "manual_recursive_watch")]
-fn watch_recursively(root: &AbsoluteSystemPath, watcher: &mut Backend) -> Result<(), WatchError> {
+fn watch_recursively(
+    root: &AbsoluteSystemPath,
+    watcher: &mut dyn Watcher,
+) -> Result<(), WatchError> {
     // Don't synthesize initial events
     manually_add_recursive_watches(root.as_std_path(), watcher, None)
 }
@@ -350,7 +451,7 @@ fn watch_recursively(root: &AbsoluteSystemPath, watcher: &mut Backend) -> Result
 #[cfg(feature = "manual_recursive_watch")]
 fn manually_add_recursive_watches(
     root: &Path,
-    watcher: &mut Backend,
+    watcher: &mut dyn Watcher,
     sender: Option<&broadcast::Sender<Result<Event, NotifyError>>>,
 ) -> Result<(), WatchError> {
     // Note that WalkDir yields the root as well as doing the walk.
@@ -386,16 +487,56 @@ fn manually_add_recursive_watches(
 
 fn run_watcher(
     root: &AbsoluteSystemPath,
+    cookie_dir: Option<&AbsoluteSystemPath>,
     sender: mpsc::Sender<EventResult>,
-) -> Result<Backend, WatchError> {
-    let mut watcher = make_watcher(move |res| {
-        let _ = sender.blocking_send(res);
-    })?;
+    options: WatchOptions,
+) -> Result<(AnyWatcher, WatcherBackend), WatchError> {
+    let native = if options.disable_native {
+        Err(WatchError::Setup(
+            "native file watching is disabled".to_string(),
+        ))
+    } else {
+        let sender = sender.clone();
+        make_watcher(move |res| {
+            let _ = sender.blocking_send(res);
+        })
+        .map_err(WatchError::from)
+        .and_then(|watcher| start_watching(root, cookie_dir, Box::new(watcher)))
+    };
+    let error = match native {
+        Ok(watcher) => return Ok((watcher, WatcherBackend::Native)),
+        Err(error) => error,
+    };
+    let Some(interval) = options.poll_fallback else {
+        return Err(error);
+    };
+
+    warn!(
+        "native file watching failed, polling every {:?} instead: {}",
+        interval, error
+    );
+    let watcher = PollWatcher::new(
+        move |res| {
+            let _ = sender.blocking_send(res);
+        },
+        notify::Config::default().with_poll_interval(interval),
+    )?;
+    let watcher = start_watching(root, cookie_dir, Box::new(watcher))?;
+    Ok((watcher, WatcherBackend::Polling))
+}
 
-    watch_recursively(root, &mut watcher)?;
+fn start_watching(
+    root: &AbsoluteSystemPath,
+    cookie_dir: Option<&AbsoluteSystemPath>,
+    mut watcher: AnyWatcher,
+) -> Result<AnyWatcher, WatchError> {
+    watch_recursively(root, watcher.as_mut())?;
+    if let Some(cookie_dir) = cookie_dir {
+        watcher.watch(cookie_dir.as_std_path(), RecursiveMode::NonRecursive)?;
+    }
 
     #[cfg(feature = "watch_ancestors")]
-    watch_parents(root, &mut watcher)?;
+    watch_parents(root, watcher.as_mut())?;
     Ok(watcher)
 }
 
@@ -459,7 +600,7 @@ mod test {
     use tokio::sync::broadcast;
     use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf};
 
-    use crate::{FileSystemWatcher, NotifyError};
+    use crate::{FileSystemWatcher, NotifyError, WatchOptions, WatcherBackend};
 
     fn temp_dir() -> (AbsoluteSystemPathBuf, tempfile::TempDir) {
         let tmp = tempfile::tempdir().unwrap();
@@ -915,4 +1056,58 @@ mod test {
         .await
         .unwrap();
     }
+
+    #[tokio::test]
+    async fn test_poll_fallback() {
+        let (repo_root, _tmp_repo_root) = temp_dir();
+        let repo_root = repo_root.to_realpath().unwrap();
+        let parent_path = repo_root.join_component("parent");
+        parent_path.create_dir_all().unwrap();
+
+        let options = WatchOptions {
+            poll_fallback: Some(Duration::from_millis(50)),
+            disable_native: true,
+        };
+        let watcher =
+            FileSystemWatcher::new_with_default_cookie_dir_and_options(&repo_root, options)
+                .unwrap();
+        let mut recv = watcher.subscribe().await.unwrap();
+        assert_eq!(watcher.backend(), Some(WatcherBackend::Polling));
+
+        let foo_path = parent_path.join_component("foo");
+        foo_path.create_with_contents("hello").unwrap();
+        expect_filesystem_event!(recv, foo_path, EventKind::Create(_));
+    }
+
+    #[tokio::test]
+    async fn test_external_cookie_dir() {
+        let (repo_root, _tmp_repo_root) = temp_dir();
+        let repo_root = repo_root.to_realpath().unwrap();
+        let (cookie_root, _tmp_cookie_root) = temp_dir();
+        let cookie_dir = cookie_root.to_realpath().unwrap().join_component("cookies");
+
+        // subscribing waits for the initial cookie, so the cookie dir is watched
+        let watcher = FileSystemWatcher::new(&repo_root, cookie_dir.clone()).unwrap();
+        let mut recv = watcher.subscribe().await.unwrap();
+
+        let cookie_path = cookie_dir.join_component("1.cookie");
+        cookie_path.create_with_contents("cookie").unwrap();
+        expect_filesystem_event!(recv, cookie_path, EventKind::Create(_));
+        expect_watching(&mut recv, &[&repo_root]).await;
+
+        let written = std::fs::read_dir(&repo_root)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .filter(|name| !name.to_string_lossy().starts_with("test-"))
+            .collect::<Vec<_>>();
+        assert!(written.is_empty(), "wrote {:?} to the root", written);
+    }
+
+    #[test]
+    fn test_cookie_dir_containing_root() {
+        let (parent, _tmp_parent) = temp_dir();
+        let repo_root = parent.join_component("repo");
+        repo_root.create_dir_all().unwrap();
+        assert!(FileSystemWatcher::new(&repo_root, parent).is_err());
+    }
 }
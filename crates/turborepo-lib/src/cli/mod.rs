@@ -120,6 +120,37 @@ impl Display for DryRunMode {
     }
 }
 
+/// How daemon introspection commands render their output
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, ValueEnum)]
+pub enum OutputFormat {
+    /// Aligned columns, for humans
+    #[default]
+    Table,
+    /// JSON, for machines
+    Json,
+    /// Uncolored `key=value` lines, for scripting
+    Plain,
+}
+
+impl Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OutputFormat::Table => "table",
+            OutputFormat::Json => "json",
+            OutputFormat::Plain => "plain",
+        })
+    }
+}
+
+/// The daemon health at which `turbo daemon status --exit-on` fails
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, ValueEnum)]
+pub enum HealthThreshold {
+    /// Fail if the daemon is degraded or not running
+    Degraded,
+    /// Fail only if the daemon is not running
+    Unhealthy,
+}
+
 #[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, ValueEnum)]
 pub enum EnvMode {
     #[default]
@@ -245,7 +276,7 @@ impl From<Verbosity> for u8 {
     }
 }
 
-#[derive(Subcommand, Copy, Clone, Debug, Serialize, PartialEq)]
+#[derive(Subcommand, Clone, Debug, Serialize, PartialEq)]
 #[serde(tag = "command")]
 pub enum DaemonCommand {
     /// Restarts the turbo daemon
@@ -254,12 +285,109 @@ pub enum DaemonCommand {
     Start,
     /// Reports the status of the turbo daemon
     Status {
-        /// Pass --json to report status in JSON format
-        #[clap(long)]
+        /// Pass --json to report status in JSON format. Equivalent to
+        /// `--format json`
+        #[clap(long, conflicts_with = "format")]
         json: bool,
+        /// The format to report status in
+        #[clap(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+        /// Exit with a non-zero code if the daemon's health is at or below
+        /// the given threshold
+        #[clap(long, value_enum)]
+        exit_on: Option<HealthThreshold>,
+        /// Keep watching the daemon after reporting its status, counting down
+        /// to its idle shutdown every second. Watching doesn't extend the
+        /// daemon's idle timeout
+        #[clap(long, conflicts_with_all = ["json", "exit_on"])]
+        watch: bool,
+    },
+    /// Stops the turbo daemon, starts a fresh one, and reports how long each
+    /// phase of its start took
+    StartBench {
+        /// The format to report the timings in
+        #[clap(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Measures the latency of status requests to the turbo daemon
+    Bench {
+        /// The number of requests to send
+        #[clap(long, default_value_t = 100, value_parser = clap::value_parser!(u64).range(1..))]
+        count: u64,
+        /// The format to report latencies in
+        #[clap(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Estimates how much memory the turbo daemon uses per watched file, to
+    /// size machines for larger repositories
+    Capacity {
+        /// Also project the memory the daemon would use to watch this many
+        /// files
+        #[clap(long)]
+        target_files: Option<u64>,
+        /// The format to report the estimate in
+        #[clap(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Has the turbo daemon hash and watch the files below a directory now,
+    /// so that the first run in it is fast. Returns once they are hashed
+    Warm {
+        /// The directory to warm, such as `packages/app`
+        path: Utf8PathBuf,
+    },
+    /// Prints the turbo daemon's debug logs for a while, without changing
+    /// what it writes to its log file
+    DebugLog {
+        /// How long to print logs for, such as `30s` or `2m`
+        #[clap(long, default_value = "30s")]
+        duration: String,
+    },
+    /// Prints the turbo environment variables the running turbo daemon was
+    /// started with, which may differ from the current shell's
+    Env {
+        /// The format to print the variables in
+        #[clap(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
     },
     /// Stops the turbo daemon
     Stop,
+    /// Makes the turbo daemon shut down as if it had timed out from being
+    /// idle, to exercise idle shutdown handling. Held leases still keep it
+    /// running until they are released
+    Expire,
+    /// Keeps the turbo daemon from shutting down when idle until interrupted,
+    /// by holding a lease on it
+    Pin,
+    /// Releases every lease held on the turbo daemon, such as those of clients
+    /// that exited without releasing theirs, so that it can shut down when
+    /// idle again
+    ReleaseLeases,
+    /// Makes the turbo daemon re-read the root and workspace turbo.json files
+    /// without restarting, so that connected clients keep their connections
+    Reload,
+    /// Explains why the turbo daemon hasn't shut down yet, without extending
+    /// its idle timeout
+    WhyAlive,
+    /// Traces a single request to the turbo daemon, printing the spans it
+    /// entered while handling it with their timings
+    Trace {
+        /// The method to trace: status, watches or discover_packages
+        #[clap(long, default_value = "status")]
+        method: String,
+    },
+    /// Replays requests recorded by a daemon started with `--record` against
+    /// the running turbo daemon, and reports their latencies
+    Replay {
+        /// The recording to replay
+        file: Utf8PathBuf,
+        /// Send this many requests per second. By default, each request is
+        /// sent as soon as the previous one completes
+        #[clap(long, value_parser = replay_rate)]
+        rate: Option<f64>,
+        /// The format to report latencies in
+        #[clap(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
     /// Stops the turbo daemon if it is already running, and removes any stale
     /// daemon state
     Clean {
@@ -269,6 +397,51 @@ pub enum DaemonCommand {
     },
     /// Shows the daemon logs
     Logs,
+    /// Clears any errors that have put the turbo daemon in a degraded state
+    ResetErrors,
+    /// Prints, as JSON, how to connect to the turbo daemon for this repository
+    Endpoint,
+    /// Checks that the turbo daemon's socket is only accessible by the
+    /// current user, with mode 0600, and suggests how to fix it if not
+    Audit,
+    /// Generates the autocompletion script for the `turbo daemon`
+    /// subcommands for the specified shell
+    #[serde(skip)]
+    Completions { shell: Shell },
+    /// Lists the globs the turbo daemon is watching, with how many files each
+    /// currently matches
+    Watches {
+        /// Save the watched globs to this file, to compare against later with
+        /// `--diff`
+        #[clap(long, conflicts_with = "diff")]
+        snapshot: Option<Utf8PathBuf>,
+        /// Report the globs added and removed since the snapshot in this file
+        #[clap(long)]
+        diff: Option<Utf8PathBuf>,
+    },
+    /// Inspects the configuration loaded by the turbo daemon
+    Config {
+        #[clap(subcommand)]
+        #[serde(skip)]
+        command: DaemonConfigCommand,
+    },
+}
+
+#[derive(Subcommand, Copy, Clone, Debug, Serialize, PartialEq)]
+#[serde(tag = "command")]
+pub enum DaemonConfigCommand {
+    /// Checks whether the turbo daemon has loaded the turbo.json that is on
+    /// disk
+    Check {
+        /// Reload turbo.json in the daemon if it is using an outdated one
+        #[clap(long)]
+        reload: bool,
+    },
+    /// Prints, as JSON, the configuration the running turbo daemon resolved
+    /// from its flags, environment variables and defaults, along with the
+    /// tasks of the turbo.json files it has loaded, with each workspace's
+    /// merged over the root's
+    Dump,
 }
 
 #[derive(Subcommand, Copy, Clone, Debug, Serialize, PartialEq)]
@@ -447,9 +620,13 @@ pub enum Command {
     },
     /// Runs the Turborepo background daemon
     Daemon {
-        /// Set the idle timeout for turbod
-        #[clap(long, default_value_t = String::from("4h0m0s"))]
-        idle_time: String,
+        /// Run or connect to a daemon that only serves this subdirectory of
+        /// the repository, such as `packages/app`
+        #[clap(long, global = true, value_parser = path_non_empty)]
+        scope: Option<Utf8PathBuf>,
+        #[clap(flatten)]
+        #[serde(flatten)]
+        server_args: DaemonServerArgs,
         #[clap(subcommand)]
         #[serde(flatten)]
         command: Option<DaemonCommand>,
@@ -561,6 +738,91 @@ pub enum Command {
     },
 }
 
+/// Arguments used when running the daemon server itself
+#[derive(Parser, Clone, Debug, Serialize, PartialEq)]
+pub struct DaemonServerArgs {
+    /// Set the idle timeout for turbod
+    #[clap(long, default_value_t = String::from("4h0m0s"))]
+    pub idle_time: String,
+    /// Preferred directory to bind the daemon socket in, such as a fast local
+    /// tmpfs. Falls back to the default location if it is not writable.
+    #[clap(long, value_parser = path_non_empty, env = "TURBO_DAEMON_SOCKET_DIR")]
+    pub socket_dir: Option<Utf8PathBuf>,
+    /// Write `READY` to this file once the daemon is accepting connections.
+    /// Without it, readiness is reported to systemd if `NOTIFY_SOCKET` is set
+    #[clap(long, value_parser = path_non_empty)]
+    pub ready_file: Option<Utf8PathBuf>,
+    /// Checkpoint the daemon's state to disk at this interval, and on
+    /// shutdown, so that a restarted daemon can pick up where it left off.
+    /// Disabled by default
+    #[clap(long, env = "TURBO_DAEMON_CHECKPOINT_INTERVAL")]
+    pub checkpoint_interval: Option<String>,
+    /// The number of worker threads the daemon uses to handle requests.
+    /// Defaults to one per CPU
+    #[clap(long, value_parser = worker_thread_count, env = "TURBO_DAEMON_WORKER_THREADS")]
+    pub worker_threads: Option<usize>,
+    /// Pin the daemon's worker threads to these CPU cores, e.g. `0-3,8`.
+    /// Only supported on Linux
+    #[clap(long, env = "TURBO_DAEMON_CPU_AFFINITY")]
+    pub cpu_affinity: Option<String>,
+    /// Serve a read-only status at `/status` and Prometheus metrics at
+    /// `/metrics` on this localhost port. Disabled by default
+    #[clap(long, env = "TURBO_DAEMON_HTTP_PORT")]
+    pub http_port: Option<u16>,
+    /// Gzip RPC responses of at least this many bytes for clients that
+    /// support it. Disabled by default
+    #[clap(long, env = "TURBO_DAEMON_COMPRESSION_THRESHOLD")]
+    pub compression_threshold: Option<usize>,
+    /// Restart the daemon gracefully once it uses more than this much
+    /// memory, e.g. `1GB`. Connected clients have to reconnect to the
+    /// restarted daemon. Unlimited by default
+    #[clap(long, env = "TURBO_DAEMON_MAX_MEMORY")]
+    pub max_memory: Option<String>,
+    /// Refuse requests that would change the daemon's view of the repo, such
+    /// as recording written outputs, while still serving reads and status.
+    /// Nothing is written to the repo: logs and file watching cookies are
+    /// kept in the daemon's own directory
+    #[clap(long, env = "TURBO_DAEMON_READ_ONLY")]
+    pub read_only: bool,
+    /// Export spans for the daemon's requests and lifetime to the
+    /// OpenTelemetry collector at this URL over OTLP/HTTP, e.g.
+    /// `http://localhost:4318`. Disabled by default
+    #[clap(long, env = "TURBO_DAEMON_OTLP_ENDPOINT")]
+    pub otlp_endpoint: Option<String>,
+    /// Record every request the daemon receives to this file, for
+    /// `turbo daemon replay`. Disabled by default
+    #[clap(long, value_parser = path_non_empty, env = "TURBO_DAEMON_RECORD")]
+    pub record: Option<Utf8PathBuf>,
+    /// Write at most this many lines per second to the daemon log, noting
+    /// how many were suppressed, so that an error storm can't flood it.
+    /// Unlimited by default
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..), env = "TURBO_DAEMON_LOG_RATE_LIMIT")]
+    pub log_rate_limit: Option<u32>,
+    /// When stopped, pass the time left until the daemon would shut down for
+    /// being idle to a daemon started right after, such as by `turbo daemon
+    /// restart`, instead of starting the idle timeout afresh
+    #[clap(long, env = "TURBO_DAEMON_IDLE_HANDOFF")]
+    pub idle_handoff: bool,
+    /// Compact the daemon's checkpoint once it has been idle for this long,
+    /// e.g. `10m`. Needs `--checkpoint-interval`. Disabled by default
+    #[clap(long, env = "TURBO_DAEMON_IDLE_COMPACTION")]
+    pub idle_compaction: Option<String>,
+    /// Only check whether the idle timeout has passed on multiples of this
+    /// duration, e.g. `30s`, so that the daemon wakes up less often at the
+    /// cost of shutting down up to that much later. Exact by default
+    #[clap(long, env = "TURBO_DAEMON_IDLE_GRANULARITY")]
+    pub idle_granularity: Option<String>,
+    /// POST the reason the daemon shut down, and its uptime, as JSON to this
+    /// URL when it exits. Disabled by default
+    #[clap(long, env = "TURBO_DAEMON_SHUTDOWN_WEBHOOK")]
+    pub shutdown_webhook: Option<String>,
+    /// If native file watching fails to start, poll the repository for
+    /// changes this often instead, e.g. `2s`, rather than running without
+    /// file watching. Disabled by default
+    #[clap(long, env = "TURBO_DAEMON_WATCHER_POLL_FALLBACK")]
+    pub watcher_poll_fallback: Option<String>,
+}
+
 #[derive(Parser, Clone, Debug, Default, Serialize, PartialEq)]
 pub struct GenerateWorkspaceArgs {
     /// Name for the new workspace
@@ -644,6 +906,23 @@ fn path_non_empty(s: &str) -> Result<Utf8PathBuf, String> {
     }
 }
 
+fn worker_thread_count(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(0) => Err("the daemon needs at least 1 worker thread".to_string()),
+        Ok(count) => Ok(count),
+        Err(_) => Err(format!("'{s}' is not a valid worker thread count")),
+    }
+}
+
+fn replay_rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(rate) if rate > 0.0 && rate.is_finite() => Ok(rate),
+        _ => Err(format!(
+            "'{s}' is not a valid number of requests per second"
+        )),
+    }
+}
+
 /// Arguments used in run and watch
 #[derive(Parser, Clone, Debug, Default, Serialize, PartialEq)]
 #[command(groups = [
@@ -1122,18 +1401,29 @@ pub async fn run(
             Ok(0)
         }
         #[allow(unused_variables)]
-        Command::Daemon { command, idle_time } => {
+        Command::Daemon {
+            scope,
+            command,
+            server_args,
+        } => {
             CommandEventBuilder::new("daemon")
                 .with_parent(&root_telemetry)
                 .track_call();
+            let repo_root = match scope {
+                Some(scope) => crate::daemon::scoped_root(&repo_root, scope)?,
+                None => repo_root,
+            };
             let base = CommandBase::new(cli_args.clone(), repo_root, version, ui);
 
-            match command {
-                Some(command) => daemon::daemon_client(command, &base).await,
-                None => daemon::daemon_server(&base, idle_time, logger).await,
-            }?;
+            let exit_code = match command {
+                Some(command) => daemon::daemon_client(command, &base).await?,
+                None => {
+                    daemon::daemon_server(&base, server_args, logger).await?;
+                    0
+                }
+            };
 
-            Ok(0)
+            Ok(exit_code)
         }
         Command::Generate {
             tag,
@@ -1370,7 +1660,7 @@ fn warn_flag_removal(flag: &str) {
 mod test {
     use std::assert_matches::assert_matches;
 
-    use camino::Utf8PathBuf;
+    use camino::{Utf8Path, Utf8PathBuf};
     use clap::Parser;
     use itertools::Itertools;
     use pretty_assertions::assert_eq;
@@ -1435,7 +1725,8 @@ mod test {
     use anyhow::Result;
 
     use crate::cli::{
-        Args, Command, DryRunMode, EnvMode, LogOrder, LogPrefix, OutputLogsMode, Verbosity,
+        Args, Command, DaemonCommand, DryRunMode, EnvMode, LogOrder, LogPrefix, OutputLogsMode,
+        Verbosity,
     };
 
     #[test_case::test_case(
@@ -2745,6 +3036,79 @@ mod test {
         assert!(Args::try_parse_from(["turbo", "build", "--cache-dir", ""]).is_err());
     }
 
+    #[test]
+    fn test_daemon_scope() {
+        for args in [
+            ["turbo", "daemon", "start", "--scope", "packages/app"],
+            ["turbo", "daemon", "--scope", "packages/app", "start"],
+        ] {
+            let Some(Command::Daemon { scope, command, .. }) =
+                Args::try_parse_from(args).unwrap().command
+            else {
+                panic!("expected the daemon command");
+            };
+            assert_eq!(scope.as_deref(), Some(Utf8Path::new("packages/app")));
+            assert_eq!(command, Some(DaemonCommand::Start));
+        }
+        assert!(Args::try_parse_from(["turbo", "daemon", "start", "--scope", ""]).is_err());
+    }
+
+    #[test]
+    fn test_daemon_worker_threads() {
+        let Some(Command::Daemon { server_args, .. }) =
+            Args::try_parse_from(["turbo", "daemon", "--worker-threads", "4"])
+                .unwrap()
+                .command
+        else {
+            panic!("expected the daemon command");
+        };
+        assert_eq!(server_args.worker_threads, Some(4));
+
+        for count in ["0", "-1", "many"] {
+            assert!(
+                Args::try_parse_from(["turbo", "daemon", "--worker-threads", count]).is_err(),
+                "{count} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn test_daemon_replay_rate() {
+        let Some(Command::Daemon {
+            command: Some(DaemonCommand::Replay { file, rate, .. }),
+            ..
+        }) = Args::try_parse_from([
+            "turbo",
+            "daemon",
+            "replay",
+            "requests.jsonl",
+            "--rate",
+            "2.5",
+        ])
+        .unwrap()
+        .command
+        else {
+            panic!("expected the daemon replay command");
+        };
+        assert_eq!(file, Utf8PathBuf::from("requests.jsonl"));
+        assert_eq!(rate, Some(2.5));
+
+        for rate in ["0", "-1", "inf", "fast"] {
+            assert!(
+                Args::try_parse_from([
+                    "turbo",
+                    "daemon",
+                    "replay",
+                    "requests.jsonl",
+                    "--rate",
+                    rate
+                ])
+                .is_err(),
+                "{rate} should be rejected"
+            );
+        }
+    }
+
     #[test]
     fn test_preflight() {
         assert!(!Args::try_parse_from(["turbo", "build",]).unwrap().preflight);
@@ -1,35 +1,68 @@
-use std::time::Duration;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::{Duration, Instant},
+};
 
 use camino::Utf8PathBuf;
+use clap::Subcommand;
+use clap_complete::{generate, Shell};
 use futures::FutureExt;
 use pidlock::PidlockError::AlreadyOwned;
 use serde_json::json;
 use time::{format_description, OffsetDateTime};
-use tokio::signal::ctrl_c;
+use tokio::{signal::ctrl_c, sync::oneshot};
 use tracing::{trace, warn};
-use turbopath::AbsoluteSystemPath;
-use turborepo_ui::{color, BOLD_GREEN, BOLD_RED, GREY};
+use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf};
+use turborepo_filewatch::WatchOptions;
+use turborepo_ui::{color, BOLD, BOLD_GREEN, BOLD_RED, GREY, UI, YELLOW};
 use which::which;
 
 use super::CommandBase;
 use crate::{
-    cli::DaemonCommand,
+    cli::{DaemonCommand, DaemonConfigCommand, DaemonServerArgs, HealthThreshold, OutputFormat},
     daemon::{
-        endpoint::SocketOpenError, CloseReason, DaemonConnector, DaemonConnectorError, DaemonError,
-        Paths,
+        config_hash, endpoint::SocketOpenError, proto, replay, CloseReason, CoreSet,
+        DaemonConnector, DaemonConnectorError, DaemonError, MemoryLimit, OsProcessTitle, Paths,
+        ProcessMemory, ReadySignal, RespawnCurrentExe, StartBreakdown,
     },
     tracing::TurboSubscriber,
 };
 
+/// How often `turbo daemon status --watch` updates its countdown.
+const STATUS_WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
 const DAEMON_NOT_RUNNING_MESSAGE: &str =
     "daemon is not running, run `turbo daemon start` to start it";
 
-/// Runs the daemon command.
-pub async fn daemon_client(command: &DaemonCommand, base: &CommandBase) -> Result<(), DaemonError> {
+/// Runs the daemon command, returning the exit code.
+pub async fn daemon_client(
+    command: &DaemonCommand,
+    base: &CommandBase,
+) -> Result<i32, DaemonError> {
     let (can_start_server, can_kill_server) = match command {
-        DaemonCommand::Status { .. } | DaemonCommand::Logs => (false, false),
-        DaemonCommand::Stop => (false, true),
-        DaemonCommand::Restart | DaemonCommand::Start => (true, true),
+        DaemonCommand::Status { .. }
+        | DaemonCommand::Bench { .. }
+        | DaemonCommand::Capacity { .. }
+        | DaemonCommand::Logs
+        | DaemonCommand::ResetErrors
+        | DaemonCommand::Endpoint
+        | DaemonCommand::Audit
+        | DaemonCommand::Completions { .. }
+        | DaemonCommand::Watches { .. }
+        | DaemonCommand::Reload
+        | DaemonCommand::Trace { .. }
+        | DaemonCommand::Replay { .. }
+        | DaemonCommand::WhyAlive
+        | DaemonCommand::Warm { .. }
+        | DaemonCommand::DebugLog { .. }
+        | DaemonCommand::Env { .. }
+        | DaemonCommand::ReleaseLeases
+        | DaemonCommand::Config { .. } => (false, false),
+        DaemonCommand::Pin => (true, false),
+        DaemonCommand::Stop | DaemonCommand::Expire => (false, true),
+        DaemonCommand::Restart | DaemonCommand::Start | DaemonCommand::StartBench { .. } => {
+            (true, true)
+        }
         DaemonCommand::Clean { .. } => (false, true),
     };
 
@@ -45,7 +78,7 @@ pub async fn daemon_client(command: &DaemonCommand, base: &CommandBase) -> Resul
             if let Err(e) = result {
                 tracing::debug!("failed to restart the daemon: {:?}", e);
                 tracing::debug!("falling back to clean");
-                clean(&connector.paths.pid_file, &connector.paths.sock_file)?;
+                clean(&connector.paths, &connector.sock_file())?;
                 tracing::debug!("connecting for second time");
                 let _ = connector.connect().await?;
             }
@@ -63,7 +96,7 @@ pub async fn daemon_client(command: &DaemonCommand, base: &CommandBase) -> Resul
                 Ok(client) => client,
                 Err(DaemonConnectorError::NotRunning) => {
                     println!("{} stopped daemon", color!(base.ui, BOLD_GREEN, "✓"));
-                    return Ok(());
+                    return Ok(0);
                 }
                 Err(e) => {
                     return Err(e.into());
@@ -72,20 +105,197 @@ pub async fn daemon_client(command: &DaemonCommand, base: &CommandBase) -> Resul
             client.stop().await?;
             println!("{} stopped daemon", color!(base.ui, BOLD_GREEN, "✓"));
         }
-        DaemonCommand::Status { json } => {
+        DaemonCommand::Expire => {
             let mut client = match connector.connect().await {
-                Ok(status) => status,
-                Err(DaemonConnectorError::NotRunning) if *json => {
-                    println!("{}", json!({ "error": DAEMON_NOT_RUNNING_MESSAGE }));
-                    return Ok(());
+                Ok(client) => client,
+                Err(DaemonConnectorError::NotRunning) => {
+                    println!("{}", render_not_running(OutputFormat::Table, base.ui));
+                    return Ok(0);
+                }
+                Err(e) => {
+                    return Err(e.into());
+                }
+            };
+            client.expire().await?;
+            println!(
+                "{} expired the daemon's idle timeout",
+                color!(base.ui, BOLD_GREEN, "✓")
+            );
+        }
+        DaemonCommand::Pin => {
+            let mut client = connector.connect().await?;
+            let holder = format!("turbo daemon pin (pid {})", std::process::id());
+            let lease_id = client.acquire_lease(holder).await?;
+            println!(
+                "{} pinned the daemon, press ctrl-c to release it",
+                color!(base.ui, BOLD_GREEN, "✓")
+            );
+            if let Err(e) = ctrl_c().await {
+                tracing::error!("Error with signal handling: {}", e);
+            }
+            client.release_lease(lease_id).await?;
+            println!("{} released the daemon", color!(base.ui, BOLD_GREEN, "✓"));
+        }
+        DaemonCommand::ReleaseLeases => {
+            let mut client = match connector.connect().await {
+                Ok(client) => client,
+                Err(DaemonConnectorError::NotRunning) => {
+                    println!("{}", render_not_running(OutputFormat::Table, base.ui));
+                    return Ok(0);
+                }
+                Err(e) => {
+                    return Err(e.into());
+                }
+            };
+            let released = client.release_leases().await?;
+            println!("{}", render_released_leases(released, base.ui));
+        }
+        DaemonCommand::WhyAlive => {
+            let mut client = match connector.connect().await {
+                Ok(client) => client,
+                Err(DaemonConnectorError::NotRunning) => {
+                    println!("{}", render_not_running(OutputFormat::Table, base.ui));
+                    return Ok(0);
+                }
+                Err(e) => {
+                    return Err(e.into());
                 }
+            };
+            let why_alive = client.why_alive().await?;
+            println!("{}", render_why_alive(&why_alive, base.ui));
+        }
+        DaemonCommand::Replay { file, rate, format } => {
+            let requests = replay::load(&AbsoluteSystemPathBuf::from_cwd(file.clone())?)?;
+            // connecting checks that the daemon is running and compatible,
+            // the requests themselves are sent over a raw channel
+            match connector.clone().connect().await {
+                Ok(_) => {}
                 Err(DaemonConnectorError::NotRunning) => {
+                    println!("{}", render_not_running(*format, base.ui));
+                    return Ok(0);
+                }
+                Err(e) => {
+                    return Err(e.into());
+                }
+            };
+            let replayed = replay::replay(connector.channel().await?, &requests, *rate).await;
+            let latencies = replayed
+                .iter()
+                .map(|request| request.latency)
+                .collect::<Vec<_>>();
+            let Some(summary) = LatencySummary::new(&latencies) else {
+                println!("{} no requests to replay", color!(base.ui, BOLD_GREEN, "✓"));
+                return Ok(0);
+            };
+            println!("{}", summary.render("recorded requests", *format, base.ui)?);
+
+            let failed = replayed.iter().filter(|request| !request.ok).count();
+            if failed > 0 {
+                if *format == OutputFormat::Table {
                     println!(
-                        "{} {}",
+                        "{} {} of them failed",
                         color!(base.ui, BOLD_RED, "x"),
-                        DAEMON_NOT_RUNNING_MESSAGE
+                        failed
                     );
-                    return Ok(());
+                }
+                return Ok(1);
+            }
+        }
+        DaemonCommand::Trace { method } => {
+            let mut client = match connector.connect().await {
+                Ok(client) => client,
+                Err(DaemonConnectorError::NotRunning) => {
+                    println!("{}", render_not_running(OutputFormat::Table, base.ui));
+                    return Ok(0);
+                }
+                Err(e) => {
+                    return Err(e.into());
+                }
+            };
+            let spans = client.trace(method.clone()).await?;
+            println!("{}", render_trace(&spans, base.ui));
+        }
+        DaemonCommand::Reload => {
+            let mut client = match connector.connect().await {
+                Ok(client) => client,
+                Err(DaemonConnectorError::NotRunning) => {
+                    println!("{}", render_not_running(OutputFormat::Table, base.ui));
+                    return Ok(0);
+                }
+                Err(e) => {
+                    return Err(e.into());
+                }
+            };
+            let reload = client.reload().await?;
+            println!("{}", render_reload(&reload, base.ui));
+        }
+        DaemonCommand::Warm { path } => {
+            let path = base
+                .repo_root
+                .anchor(&AbsoluteSystemPathBuf::from_cwd(path.clone())?)?;
+            let mut client = match connector.connect().await {
+                Ok(client) => client,
+                Err(DaemonConnectorError::NotRunning) => {
+                    println!("{}", render_not_running(OutputFormat::Table, base.ui));
+                    return Ok(0);
+                }
+                Err(e) => {
+                    return Err(e.into());
+                }
+            };
+            let file_count = client.warm(&path).await?;
+            println!(
+                "{} warmed {} files below {}",
+                color!(base.ui, BOLD_GREEN, "✓"),
+                file_count,
+                path
+            );
+        }
+        DaemonCommand::DebugLog { duration } => {
+            let window = go_parse_duration::parse_duration(duration)
+                .map_err(|_| DaemonError::InvalidDebugLogDuration(duration.to_owned()))
+                .map(|d| Duration::from_nanos(d as u64))?;
+            let mut client = match connector.connect().await {
+                Ok(client) => client,
+                Err(DaemonConnectorError::NotRunning) => {
+                    println!("{}", render_not_running(OutputFormat::Table, base.ui));
+                    return Ok(0);
+                }
+                Err(e) => {
+                    return Err(e.into());
+                }
+            };
+            // the daemon ends the stream once the window has elapsed
+            let mut lines = client.debug_log(window).await?;
+            while let Some(line) = lines.message().await? {
+                println!("{}", line.line);
+            }
+        }
+        DaemonCommand::Env { format } => {
+            let mut client = match connector.connect().await {
+                Ok(client) => client,
+                Err(DaemonConnectorError::NotRunning) => {
+                    println!("{}", render_not_running(*format, base.ui));
+                    return Ok(0);
+                }
+                Err(e) => {
+                    return Err(e.into());
+                }
+            };
+            println!("{}", render_env(&client.env().await?, *format, base.ui)?);
+        }
+        DaemonCommand::Status {
+            json,
+            format,
+            exit_on,
+            watch,
+        } => {
+            let format = if *json { OutputFormat::Json } else { *format };
+            let mut client = match connector.connect().await {
+                Ok(status) => status,
+                Err(DaemonConnectorError::NotRunning) => {
+                    println!("{}", render_not_running(format, base.ui));
+                    return Ok(status_exit_code(None, *exit_on));
                 }
                 Err(e) => {
                     return Err(e.into());
@@ -99,27 +309,187 @@ pub async fn daemon_client(command: &DaemonCommand, base: &CommandBase) -> Resul
                 log_file: log_file.into(),
                 pid_file: paths.pid_file.to_owned(),
                 sock_file: paths.sock_file.to_owned(),
+                health: status.health().into(),
+                health_message: status.health_message,
+                worker_threads: status.worker_threads,
+                compacted_bytes: status.compacted_bytes,
+                watcher_backend: status.watcher_backend,
             };
 
-            if *json {
-                println!("{}", serde_json::to_string_pretty(&status)?);
+            println!("{}", render_status(&status, format, base.ui)?);
+            if !*watch {
+                return Ok(status_exit_code(Some(status.health), *exit_on));
+            }
+
+            // why-alive doesn't extend the idle timeout, unlike status
+            let mut countdown = IdleCountdown::default();
+            loop {
+                match client.why_alive().await {
+                    Ok(why_alive) => {
+                        println!("{}", countdown.render(why_alive.remaining_ms, base.ui))
+                    }
+                    Err(e) => {
+                        tracing::debug!("stopped watching the daemon: {:?}", e);
+                        println!("{} daemon has stopped", color!(base.ui, BOLD_RED, "x"));
+                        return Ok(0);
+                    }
+                }
+                tokio::time::sleep(STATUS_WATCH_INTERVAL).await;
+            }
+        }
+        DaemonCommand::StartBench { format } => {
+            // connecting with this connector would start a daemon if none is
+            // running, so stop through one that can't
+            let stopper = DaemonConnector::new(false, true, &base.repo_root);
+            match stopper.connect().await {
+                Ok(client) => {
+                    client.stop().await?;
+                }
+                Err(DaemonConnectorError::NotRunning) => {}
+                Err(e) => return Err(e.into()),
+            }
+            let (_client, breakdown) = connector.cold_start().await?;
+            println!("{}", render_start_breakdown(&breakdown, *format, base.ui)?);
+        }
+        DaemonCommand::Bench { count, format } => {
+            let mut client = match connector.connect().await {
+                Ok(client) => client,
+                Err(DaemonConnectorError::NotRunning) => {
+                    println!("{}", render_not_running(*format, base.ui));
+                    return Ok(0);
+                }
+                Err(e) => {
+                    return Err(e.into());
+                }
+            };
+            let mut samples = Vec::with_capacity(*count as usize);
+            for _ in 0..*count {
+                let start = Instant::now();
+                client.status().await?;
+                samples.push(start.elapsed());
+            }
+            let summary = LatencySummary::new(&samples).expect("at least one request is sent");
+            println!("{}", summary.render("status requests", *format, base.ui)?);
+        }
+        DaemonCommand::Capacity {
+            target_files,
+            format,
+        } => {
+            let mut client = match connector.connect().await {
+                Ok(client) => client,
+                Err(DaemonConnectorError::NotRunning) => {
+                    println!("{}", render_not_running(*format, base.ui));
+                    return Ok(0);
+                }
+                Err(e) => {
+                    return Err(e.into());
+                }
+            };
+            let resident_bytes = client.status().await?.resident_bytes;
+            let watched_files = client
+                .watches()
+                .await?
+                .iter()
+                .map(|glob| glob.file_count)
+                .sum();
+            let estimate = CapacityEstimate::new(resident_bytes, watched_files, *target_files);
+            println!("{}", estimate.render(*format, base.ui)?);
+        }
+        DaemonCommand::ResetErrors => {
+            let mut client = match connector.connect().await {
+                Ok(client) => client,
+                Err(DaemonConnectorError::NotRunning) => {
+                    println!(
+                        "{} {}",
+                        color!(base.ui, BOLD_RED, "x"),
+                        DAEMON_NOT_RUNNING_MESSAGE
+                    );
+                    return Ok(0);
+                }
+                Err(e) => {
+                    return Err(e.into());
+                }
+            };
+            if client.reset_errors().await? {
+                println!("{} cleared daemon errors", color!(base.ui, BOLD_GREEN, "✓"));
             } else {
-                println!("{} daemon is running", color!(base.ui, BOLD_GREEN, "✓"));
-                println!("log file: {}", color!(base.ui, GREY, "{}", status.log_file));
-                println!(
-                    "uptime: {}",
-                    color!(
-                        base.ui,
-                        GREY,
-                        "{}s",
-                        humantime::format_duration(Duration::from_millis(status.uptime_ms))
-                    )
-                );
-                println!("pid file: {}", color!(base.ui, GREY, "{}", status.pid_file));
+                println!("{} daemon is healthy", color!(base.ui, BOLD_GREEN, "✓"));
+            }
+        }
+        DaemonCommand::Endpoint => {
+            let endpoint = DaemonEndpoint::new(&connector);
+            println!("{}", serde_json::to_string_pretty(&endpoint)?);
+        }
+        DaemonCommand::Audit => return audit(&connector, base.ui),
+        DaemonCommand::Completions { shell } => {
+            completions(*shell, &mut std::io::stdout());
+        }
+        DaemonCommand::Watches { snapshot, diff } => {
+            let mut client = match connector.connect().await {
+                Ok(client) => client,
+                Err(DaemonConnectorError::NotRunning) => {
+                    println!("{}", render_not_running(OutputFormat::Table, base.ui));
+                    return Ok(0);
+                }
+                Err(e) => {
+                    return Err(e.into());
+                }
+            };
+            let globs = client.watches().await?;
+            if let Some(file) = snapshot {
+                let file = AbsoluteSystemPathBuf::from_cwd(file.clone())?;
+                WatchesSnapshot::new(&globs).save(&file)?;
                 println!(
-                    "socket file: {}",
-                    color!(base.ui, GREY, "{}", status.sock_file)
+                    "{} saved {} watched globs to {}",
+                    color!(base.ui, BOLD_GREEN, "✓"),
+                    globs.len(),
+                    file
                 );
+            } else if let Some(file) = diff {
+                let file = AbsoluteSystemPathBuf::from_cwd(file.clone())?;
+                let diff = WatchesSnapshot::load(&file)?.diff(&globs);
+                println!("{}", diff.render(base.ui));
+            } else {
+                println!("{}", render_watches(&globs, base.ui));
+            }
+        }
+        DaemonCommand::Config {
+            command: DaemonConfigCommand::Dump,
+        } => {
+            let mut client = match connector.connect().await {
+                Ok(client) => client,
+                Err(DaemonConnectorError::NotRunning) => {
+                    println!("{}", render_not_running(OutputFormat::Json, base.ui));
+                    return Ok(0);
+                }
+                Err(e) => {
+                    return Err(e.into());
+                }
+            };
+            println!("{}", render_config(&client.config().await?)?);
+        }
+        DaemonCommand::Config {
+            command: DaemonConfigCommand::Check { reload },
+        } => {
+            let mut client = match connector.connect().await {
+                Ok(client) => client,
+                Err(DaemonConnectorError::NotRunning) => {
+                    println!("{}", render_not_running(OutputFormat::Table, base.ui));
+                    return Ok(0);
+                }
+                Err(e) => {
+                    return Err(e.into());
+                }
+            };
+            let status = client.status().await?;
+            let check = ConfigCheck::new(
+                config_hash(&base.repo_root).as_deref(),
+                status.config_hash.as_deref(),
+            );
+            println!("{}", check.render(base.ui));
+            if check == ConfigCheck::ReloadNeeded && *reload {
+                let reload = client.reload().await?;
+                println!("{}", render_reload(&reload, base.ui));
             }
         }
         DaemonCommand::Logs => {
@@ -142,6 +512,7 @@ pub async fn daemon_client(command: &DaemonCommand, base: &CommandBase) -> Resul
         } => {
             // try to connect and shutdown the daemon
             let paths = connector.paths.clone();
+            let sock_file = connector.sock_file();
             let client = connector.connect().await;
             match client {
                 Ok(client) => match client.stop().await {
@@ -156,7 +527,7 @@ pub async fn daemon_client(command: &DaemonCommand, base: &CommandBase) -> Resul
                     tracing::trace!("unable to connect to the daemon: {:?}", e);
                 }
             }
-            clean(&paths.pid_file, &paths.sock_file)?;
+            clean(&paths, &sock_file)?;
             if *should_clean_logs {
                 clean_logs(&paths.log_folder)?;
             }
@@ -164,7 +535,414 @@ pub async fn daemon_client(command: &DaemonCommand, base: &CommandBase) -> Resul
         }
     };
 
-    Ok(())
+    Ok(0)
+}
+
+/// Writes a completion script for `turbo daemon`, generated from the
+/// `DaemonCommand` definitions so that it covers every subcommand.
+fn completions(shell: Shell, out: &mut impl std::io::Write) {
+    let mut command = clap::Command::new("turbo").subcommand(DaemonCommand::augment_subcommands(
+        clap::Command::new("daemon"),
+    ));
+    generate(shell, &mut command, "turbo", out);
+}
+
+/// Maps the daemon's health, or `None` if it isn't running, to the exit code
+/// of `turbo daemon status`.
+fn status_exit_code(health: Option<DaemonHealth>, exit_on: Option<HealthThreshold>) -> i32 {
+    let failed = match (exit_on, health) {
+        (None, _) | (_, Some(DaemonHealth::Healthy)) => false,
+        (Some(HealthThreshold::Degraded), Some(DaemonHealth::Degraded)) => true,
+        (Some(HealthThreshold::Unhealthy), Some(DaemonHealth::Degraded)) => false,
+        (Some(_), None) => true,
+    };
+    i32::from(failed)
+}
+
+/// Explains what keeps the daemon running: the leases held on it, and its
+/// idle timeout, which every request extends, such as those of the clients
+/// connected to it.
+pub(crate) fn render_why_alive(why_alive: &proto::WhyAliveResponse, ui: UI) -> String {
+    // sub-second precision only adds noise here
+    let format = |ms: u64| humantime::format_duration(Duration::from_secs(ms / 1000)).to_string();
+    let idle_timeout = format(why_alive.idle_timeout_ms);
+    let mut lines = vec![format!(
+        "the daemon shuts down once it has gone {} without a request",
+        color!(ui, BOLD, "{}", idle_timeout)
+    )];
+    if !why_alive.lease_holders.is_empty() {
+        let leases = match why_alive.lease_holders.len() {
+            1 => "1 lease".to_string(),
+            count => format!("{count} leases"),
+        };
+        lines.push(format!(
+            "it is held by {}, which keep it running past its idle timeout:",
+            color!(ui, BOLD, "{}", leases)
+        ));
+        lines.extend(
+            why_alive
+                .lease_holders
+                .iter()
+                .map(|holder| format!("  - {holder}")),
+        );
+        lines.push(
+            "run `turbo daemon release-leases` to release them if their holders have exited"
+                .to_string(),
+        );
+    }
+    match why_alive.connected_clients {
+        0 => {}
+        1 => lines.push("1 other client is connected, and its requests keep it alive".to_string()),
+        count => lines.push(format!(
+            "{count} other clients are connected, and their requests keep it alive"
+        )),
+    }
+    if why_alive.remaining_ms == 0 && why_alive.lease_holders.is_empty() {
+        lines.push("its idle timeout has run out, so it is shutting down".to_string());
+    } else if why_alive.remaining_ms == 0 {
+        lines.push(
+            "its idle timeout has run out, so it shuts down once the leases are released"
+                .to_string(),
+        );
+    } else {
+        lines.push(format!(
+            "the last request, or startup, was {} ago, so it shuts down in {} unless another \
+             request arrives",
+            format(why_alive.idle_for_ms),
+            color!(ui, BOLD, "{}", format(why_alive.remaining_ms))
+        ));
+        lines.push(
+            "every request extends the timeout, including those from `turbo run`, `turbo daemon \
+             status` and editor integrations"
+                .to_string(),
+        );
+    }
+    lines.push(
+        "run `turbo daemon stop` to stop it now, or `turbo daemon expire` to end its idle timeout"
+            .to_string(),
+    );
+    lines.join("\n")
+}
+
+/// The countdown to the daemon's idle shutdown shown by
+/// `turbo daemon status --watch`, which notes when a request has extended the
+/// idle timeout since the previous poll.
+#[derive(Debug, Default)]
+struct IdleCountdown {
+    last_remaining_ms: Option<u64>,
+}
+
+impl IdleCountdown {
+    fn render(&mut self, remaining_ms: u64, ui: UI) -> String {
+        let extended = self
+            .last_remaining_ms
+            .is_some_and(|last| remaining_ms > last);
+        self.last_remaining_ms = Some(remaining_ms);
+        if remaining_ms == 0 {
+            return "idle timeout has run out, the daemon is shutting down".to_string();
+        }
+
+        // round up, so that the countdown never shows 0s while it is running
+        let remaining = Duration::from_secs(remaining_ms.div_ceil(1000));
+        format!(
+            "idle shutdown in {}{}",
+            color!(ui, BOLD, "{}", humantime::format_duration(remaining)),
+            if extended {
+                " (extended by a request)"
+            } else {
+                ""
+            }
+        )
+    }
+}
+
+/// Renders spans as a tree, indenting each span below its parent.
+fn render_trace(spans: &[proto::TraceSpan], ui: UI) -> String {
+    let rows = spans
+        .iter()
+        .map(|span| {
+            (
+                format!("{}{}", "  ".repeat(span.depth as usize), span.name),
+                format!("{:?}", Duration::from_micros(span.duration_us)),
+            )
+        })
+        .collect::<Vec<_>>();
+    let rows = rows
+        .iter()
+        .map(|(name, duration)| (name.as_str(), duration.clone()))
+        .collect::<Vec<_>>();
+    render_table(&rows, ui)
+}
+
+pub(crate) fn render_released_leases(released: u32, ui: UI) -> String {
+    match released {
+        0 => format!("{} no leases were held", color!(ui, BOLD_GREEN, "✓")),
+        1 => format!("{} released 1 lease", color!(ui, BOLD_GREEN, "✓")),
+        released => format!(
+            "{} released {} leases",
+            color!(ui, BOLD_GREEN, "✓"),
+            released
+        ),
+    }
+}
+
+fn render_reload(reload: &proto::ReloadResponse, ui: UI) -> String {
+    let old_config_hash = reload.old_config_hash.as_deref().unwrap_or("none");
+    let new_config_hash = reload.new_config_hash.as_deref().unwrap_or("none");
+    if reload.changed_packages.is_empty() {
+        format!(
+            "{} turbo.json is unchanged ({})",
+            color!(ui, BOLD_GREEN, "✓"),
+            new_config_hash
+        )
+    } else {
+        format!(
+            "{} reloaded turbo.json: {} -> {}, changed tasks of {}",
+            color!(ui, BOLD_GREEN, "✓"),
+            old_config_hash,
+            new_config_hash,
+            reload.changed_packages.join(", ")
+        )
+    }
+}
+
+/// Reports whether the daemon's socket is secured as expected, exiting
+/// with 1 if it isn't.
+#[cfg(unix)]
+fn audit(connector: &DaemonConnector, ui: UI) -> Result<i32, DaemonError> {
+    let socket = connector.sock_file();
+    if !socket.exists() {
+        println!("{}", render_not_running(OutputFormat::Table, ui));
+        return Ok(0);
+    }
+    let deviations =
+        crate::daemon::audit::audit(&socket).map_err(|e| DaemonError::Audit(e, socket.clone()))?;
+    if deviations.is_empty() {
+        println!(
+            "{} {} is only accessible by the current user",
+            color!(ui, BOLD_GREEN, "✓"),
+            socket
+        );
+        return Ok(0);
+    }
+    for deviation in &deviations {
+        println!("{} {} {}", color!(ui, BOLD_RED, "x"), socket, deviation);
+        println!("  fix: {}", deviation.remediation(&socket));
+    }
+    Ok(1)
+}
+
+#[cfg(not(unix))]
+fn audit(_connector: &DaemonConnector, ui: UI) -> Result<i32, DaemonError> {
+    println!(
+        "{} the daemon's socket can only be audited on unix",
+        color!(ui, YELLOW, "!")
+    );
+    Ok(0)
+}
+
+fn render_not_running(format: OutputFormat, ui: UI) -> String {
+    match format {
+        OutputFormat::Json => json!({ "error": DAEMON_NOT_RUNNING_MESSAGE }).to_string(),
+        OutputFormat::Table => format!(
+            "{} {}",
+            color!(ui, BOLD_RED, "x"),
+            DAEMON_NOT_RUNNING_MESSAGE
+        ),
+        OutputFormat::Plain => format!("error={}", DAEMON_NOT_RUNNING_MESSAGE),
+    }
+}
+
+fn render_status(
+    status: &DaemonStatus,
+    format: OutputFormat,
+    ui: UI,
+) -> Result<String, serde_json::Error> {
+    let uptime = humantime::format_duration(Duration::from_millis(status.uptime_ms));
+    let health = match status.health {
+        DaemonHealth::Healthy => "healthy",
+        DaemonHealth::Degraded => "degraded",
+    };
+
+    Ok(match format {
+        OutputFormat::Json => serde_json::to_string_pretty(status)?,
+        OutputFormat::Plain => {
+            let mut lines = vec![
+                format!("health={}", health),
+                format!("log_file={}", status.log_file),
+                format!("uptime_ms={}", status.uptime_ms),
+                format!("pid_file={}", status.pid_file),
+                format!("sock_file={}", status.sock_file),
+                format!("worker_threads={}", status.worker_threads),
+            ];
+            if let Some(message) = &status.health_message {
+                lines.insert(1, format!("health_message={}", message));
+            }
+            if let Some(bytes) = status.compacted_bytes {
+                lines.push(format!("compacted_bytes={}", bytes));
+            }
+            if let Some(backend) = &status.watcher_backend {
+                lines.push(format!("watcher_backend={}", backend));
+            }
+            lines.join("\n")
+        }
+        OutputFormat::Table => {
+            let headline = match &status.health_message {
+                Some(message) if status.health == DaemonHealth::Degraded => format!(
+                    "{} daemon is running, but degraded: {}",
+                    color!(ui, YELLOW, "!"),
+                    message
+                ),
+                _ => format!("{} daemon is running", color!(ui, BOLD_GREEN, "✓")),
+            };
+            let mut rows = vec![
+                ("log file", status.log_file.to_string()),
+                ("uptime", uptime.to_string()),
+                ("pid file", status.pid_file.to_string()),
+                ("socket file", status.sock_file.to_string()),
+                ("worker threads", status.worker_threads.to_string()),
+            ];
+            if let Some(bytes) = status.compacted_bytes {
+                rows.push((
+                    "last compaction",
+                    format!("reclaimed {}", format_bytes(bytes)),
+                ));
+            }
+            if let Some(backend) = &status.watcher_backend {
+                rows.push(("file watching", backend.clone()));
+            }
+            format!("{}\n{}", headline, render_table(&rows, ui))
+        }
+    })
+}
+
+/// Renders the environment variables the daemon was started with, which it
+/// has already redacted.
+pub(crate) fn render_env(
+    env: &BTreeMap<String, String>,
+    format: OutputFormat,
+    ui: UI,
+) -> Result<String, serde_json::Error> {
+    Ok(match format {
+        OutputFormat::Json => serde_json::to_string_pretty(env)?,
+        OutputFormat::Plain => env
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        OutputFormat::Table if env.is_empty() => {
+            "the daemon was started without any turbo environment variables".to_string()
+        }
+        OutputFormat::Table => {
+            let rows = env
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.clone()))
+                .collect::<Vec<_>>();
+            format!(
+                "{} daemon was started with {} turbo environment variables\n{}",
+                color!(ui, BOLD_GREEN, "✓"),
+                env.len(),
+                render_table(&rows, ui)
+            )
+        }
+    })
+}
+
+/// Pretty-prints the configuration reported by the daemon, leaving its
+/// contents as they are.
+fn render_config(config_json: &str) -> Result<String, serde_json::Error> {
+    serde_json::to_string_pretty(&serde_json::from_str::<serde_json::Value>(config_json)?)
+}
+
+pub(crate) fn render_watches(globs: &[proto::WatchedGlob], ui: UI) -> String {
+    if globs.is_empty() {
+        return "the daemon is not watching any globs".to_string();
+    }
+    let rows = globs
+        .iter()
+        .map(|glob| {
+            let files = match glob.file_count {
+                1 => "1 file".to_string(),
+                count => format!("{count} files"),
+            };
+            (glob.glob.as_str(), files)
+        })
+        .collect::<Vec<_>>();
+    render_table(&rows, ui)
+}
+
+/// The globs the daemon was watching, saved by `turbo daemon watches
+/// --snapshot` so that a later `--diff` can show how the set has changed.
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+struct WatchesSnapshot {
+    globs: BTreeSet<String>,
+}
+
+impl WatchesSnapshot {
+    fn new(globs: &[proto::WatchedGlob]) -> Self {
+        Self {
+            globs: globs.iter().map(|glob| glob.glob.clone()).collect(),
+        }
+    }
+
+    fn save(&self, file: &AbsoluteSystemPath) -> Result<(), DaemonError> {
+        file.create_with_contents(serde_json::to_string_pretty(self)?)
+            .map_err(|e| DaemonError::WatchesSnapshot(e, file.to_owned()))
+    }
+
+    fn load(file: &AbsoluteSystemPath) -> Result<Self, DaemonError> {
+        let contents = file
+            .read_to_string()
+            .map_err(|e| DaemonError::WatchesSnapshot(e, file.to_owned()))?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    fn diff(&self, globs: &[proto::WatchedGlob]) -> WatchesDiff {
+        let current = Self::new(globs).globs;
+        WatchesDiff {
+            added: current.difference(&self.globs).cloned().collect(),
+            removed: self.globs.difference(&current).cloned().collect(),
+        }
+    }
+}
+
+/// The globs added to and removed from the daemon's watches since a
+/// [`WatchesSnapshot`], in sorted order.
+#[derive(Debug, PartialEq, Eq)]
+struct WatchesDiff {
+    added: Vec<String>,
+    removed: Vec<String>,
+}
+
+impl WatchesDiff {
+    fn render(&self, ui: UI) -> String {
+        if self.added.is_empty() && self.removed.is_empty() {
+            return "no globs were added or removed since the snapshot".to_string();
+        }
+        let added = self
+            .added
+            .iter()
+            .map(|glob| format!("{} {}", color!(ui, BOLD_GREEN, "+"), glob));
+        let removed = self
+            .removed
+            .iter()
+            .map(|glob| format!("{} {}", color!(ui, BOLD_RED, "-"), glob));
+        let summary = format!("{} added, {} removed", self.added.len(), self.removed.len());
+        added
+            .chain(removed)
+            .chain(std::iter::once(summary))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Renders `(key, value)` rows with the values aligned in a single column.
+fn render_table(rows: &[(&str, String)], ui: UI) -> String {
+    let width = rows.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+    rows.iter()
+        .map(|(key, value)| format!("{key:<width$}  {}", color!(ui, GREY, "{}", value)))
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 async fn get_log_file_from_daemon(connector: DaemonConnector) -> Result<String, DaemonError> {
@@ -196,8 +974,9 @@ async fn get_log_file_from_folder(base: &CommandBase) -> Result<String, DaemonEr
         .to_string())
 }
 
-fn clean(pid_file: &AbsoluteSystemPath, sock_file: &AbsoluteSystemPath) -> Result<(), DaemonError> {
+fn clean(paths: &Paths, sock_file: &AbsoluteSystemPath) -> Result<(), DaemonError> {
     // remove pid and sock files
+    let pid_file = &paths.pid_file;
     let mut success = true;
     trace!("cleaning up daemon files");
     // if the pid_file and sock_file still exist, remove them:
@@ -219,6 +998,8 @@ fn clean(pid_file: &AbsoluteSystemPath, sock_file: &AbsoluteSystemPath) -> Resul
             success = false;
         }
     }
+    // the socket location record is only a hint, so a stale one is harmless
+    paths.sock_path_file.remove_file().ok();
 
     if success {
         Ok(())
@@ -250,26 +1031,103 @@ fn log_filename(base_filename: &str) -> Result<String, time::Error> {
     Ok(format!("{}.{}", base_filename, date))
 }
 
-#[tracing::instrument(skip(base, logging), fields(repo_root = %base.repo_root))]
+#[tracing::instrument(skip(base, server_args, logging), fields(repo_root = %base.repo_root))]
 pub async fn daemon_server(
     base: &CommandBase,
-    idle_time: &String,
+    server_args: &DaemonServerArgs,
     logging: &TurboSubscriber,
 ) -> Result<(), DaemonError> {
-    let paths = Paths::from_repo_root(&base.repo_root);
+    let DaemonServerArgs {
+        idle_time,
+        socket_dir,
+        ready_file,
+        checkpoint_interval,
+        worker_threads,
+        cpu_affinity,
+        http_port,
+        compression_threshold,
+        max_memory,
+        read_only,
+        otlp_endpoint,
+        record,
+        log_rate_limit,
+        idle_handoff,
+        idle_compaction,
+        idle_granularity,
+        shutdown_webhook,
+        watcher_poll_fallback,
+    } = server_args;
+    // a read-only daemon must not write to the repository, not even its logs
+    let log_outside_repo = |paths: Paths| {
+        if *read_only {
+            paths.with_logs_outside_repo()
+        } else {
+            paths
+        }
+    };
+    let paths = log_outside_repo(Paths::from_repo_root(&base.repo_root));
 
     tracing::trace!("logging to file: {:?}", paths.log_file);
-    if let Err(e) = logging.set_daemon_logger(tracing_appender::rolling::daily(
-        &paths.log_folder,
-        &paths.log_file,
-    )) {
+    if let Err(e) = logging.set_daemon_logger(
+        tracing_appender::rolling::daily(&paths.log_folder, &paths.log_file),
+        *log_rate_limit,
+    ) {
         // error here is not fatal, just log it
         tracing::error!("failed to set file logger: {}", e);
     }
 
+    // resolve the socket dir after the file logger is set up, so that a
+    // fallback warning ends up in the daemon log
+    let paths = match socket_dir {
+        Some(socket_dir) => log_outside_repo(Paths::from_repo_root_with_socket_dir(
+            &base.repo_root,
+            &AbsoluteSystemPathBuf::from_cwd(socket_dir.clone())?,
+        )),
+        None => paths,
+    };
+
     let timeout = go_parse_duration::parse_duration(idle_time)
         .map_err(|_| DaemonError::InvalidTimeout(idle_time.to_owned()))
         .map(|d| Duration::from_nanos(d as u64))?;
+    let checkpoint_interval = checkpoint_interval
+        .as_deref()
+        .map(|interval| {
+            go_parse_duration::parse_duration(interval)
+                .map_err(|_| DaemonError::InvalidCheckpointInterval(interval.to_owned()))
+                .map(|d| Duration::from_nanos(d as u64))
+        })
+        .transpose()?;
+    let idle_compaction = idle_compaction
+        .as_deref()
+        .map(|idle_after| {
+            go_parse_duration::parse_duration(idle_after)
+                .map_err(|_| DaemonError::InvalidIdleCompaction(idle_after.to_owned()))
+                .map(|d| Duration::from_nanos(d as u64))
+        })
+        .transpose()?;
+    let idle_granularity = idle_granularity
+        .as_deref()
+        .map(|granularity| {
+            go_parse_duration::parse_duration(granularity)
+                .map_err(|_| DaemonError::InvalidIdleGranularity(granularity.to_owned()))
+                .map(|d| Duration::from_nanos(d as u64))
+        })
+        .transpose()?;
+    let watcher_poll_fallback = watcher_poll_fallback
+        .as_deref()
+        .map(|interval| {
+            go_parse_duration::parse_duration(interval)
+                .map_err(|_| DaemonError::InvalidWatcherPollFallback(interval.to_owned()))
+                .map(|d| Duration::from_nanos(d as u64))
+        })
+        .transpose()?;
+
+    let ready_signal = ReadySignal::new(
+        ready_file
+            .clone()
+            .map(AbsoluteSystemPathBuf::from_cwd)
+            .transpose()?,
+    );
 
     let exit_signal = ctrl_c().map(|result| {
         if let Err(e) = result {
@@ -277,16 +1135,112 @@ pub async fn daemon_server(
         }
         CloseReason::Interrupt
     });
-    let server =
-        crate::daemon::TurboGrpcService::new(base.repo_root.clone(), paths, timeout, exit_signal);
+    let mut server =
+        crate::daemon::TurboGrpcService::new(base.repo_root.clone(), paths, timeout, exit_signal)
+            .with_ready_signal(ready_signal);
+    if let Some(interval) = checkpoint_interval {
+        server = server.with_checkpoint_interval(interval);
+    }
+    if let Some(port) = *http_port {
+        server = server.with_http_port(port);
+    }
+    if let Some(threshold) = *compression_threshold {
+        server = server.with_compression_threshold(threshold);
+    }
+    if let Some(max_memory) = max_memory {
+        let limit = max_memory
+            .parse::<MemoryLimit>()
+            .map_err(DaemonError::InvalidMemoryLimit)?;
+        server = server.with_memory_limit(limit, ProcessMemory::new(), RespawnCurrentExe);
+    }
+    if *read_only {
+        server = server.with_read_only();
+    }
+    if let Some(endpoint) = otlp_endpoint {
+        server = server.with_otlp_endpoint(endpoint.clone());
+    }
+    if let Some(record) = record {
+        server = server.with_record_file(AbsoluteSystemPathBuf::from_cwd(record.clone())?);
+    }
+    if *idle_handoff {
+        server = server.with_idle_handoff();
+    }
+    if let Some(idle_after) = idle_compaction {
+        server = server.with_idle_compaction(idle_after);
+    }
+    if let Some(granularity) = idle_granularity {
+        server = server.with_idle_granularity(granularity);
+    }
+    if let Some(url) = shutdown_webhook {
+        server = server.with_shutdown_webhook(url.clone());
+    }
+    if let Some(interval) = watcher_poll_fallback {
+        server = server.with_watch_options(WatchOptions {
+            poll_fallback: Some(interval),
+            ..Default::default()
+        });
+    }
+    server = server.with_process_title(OsProcessTitle);
+    match logging.enable_debug_log() {
+        Ok(tap) => server = server.with_debug_log(tap),
+        // error here is not fatal, the daemon just can't stream its debug logs
+        Err(e) => tracing::error!("failed to enable debug log: {}", e),
+    }
 
-    let reason = server.serve().await?;
+    let cpu_affinity = cpu_affinity
+        .as_deref()
+        .map(|cores| {
+            cores
+                .parse::<CoreSet>()
+                .map_err(DaemonError::InvalidCpuAffinity)
+        })
+        .transpose()?;
+    #[cfg(not(target_os = "linux"))]
+    let cpu_affinity = cpu_affinity.and_then(|cores| {
+        warn!(
+            "CPU affinity is only supported on Linux, ignoring cores {}",
+            cores
+        );
+        None::<CoreSet>
+    });
+
+    let reason = if worker_threads.is_some() || cpu_affinity.is_some() {
+        // the runtime we are on was started before the flags were parsed, so
+        // the server gets a runtime of its own
+        let mut runtime = tokio::runtime::Builder::new_multi_thread();
+        runtime.enable_all();
+        if let Some(worker_threads) = *worker_threads {
+            runtime.worker_threads(worker_threads);
+            server = server.with_worker_threads(worker_threads);
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(cores) = cpu_affinity {
+            trace!("pinning daemon threads to cores {}", cores);
+            runtime.on_thread_start(move || {
+                if let Err(e) = cores.pin_current_thread() {
+                    warn!("failed to pin daemon thread to cores {}: {}", cores, e);
+                }
+            });
+        }
+        let runtime = runtime.build().map_err(DaemonError::Runtime)?;
+        let (tx, rx) = oneshot::channel();
+        std::thread::spawn(move || {
+            let _ = tx.send(runtime.block_on(server.serve()));
+        });
+        rx.await.map_err(|_| DaemonError::RuntimeExited)??
+    } else {
+        server.serve().await?
+    };
 
     match reason {
         CloseReason::SocketOpenError(SocketOpenError::LockError(AlreadyOwned)) => {
             warn!("daemon already running");
         }
         CloseReason::SocketOpenError(e) => return Err(e.into()),
+        CloseReason::MemoryLimit => {
+            // the server has already started its replacement
+            trace!("restarted daemon over its memory limit");
+        }
         CloseReason::Interrupt
         | CloseReason::ServerClosed
         | CloseReason::WatcherClosed
@@ -308,4 +1262,681 @@ pub struct DaemonStatus {
     pub log_file: Utf8PathBuf,
     pub pid_file: turbopath::AbsoluteSystemPathBuf,
     pub sock_file: turbopath::AbsoluteSystemPathBuf,
+    pub health: DaemonHealth,
+    pub health_message: Option<String>,
+    pub worker_threads: u32,
+    /// The bytes reclaimed by the daemon's last idle compaction, if one ran.
+    pub compacted_bytes: Option<u64>,
+    /// The backend delivering file events, e.g. `polling`, once file watching
+    /// has started.
+    pub watcher_backend: Option<String>,
+}
+
+/// The latencies measured by `turbo daemon bench`, in microseconds.
+#[derive(serde::Serialize, Debug, PartialEq, Eq)]
+struct LatencySummary {
+    count: usize,
+    min_us: u64,
+    p50_us: u64,
+    p95_us: u64,
+    max_us: u64,
+}
+
+impl LatencySummary {
+    fn new(samples: &[Duration]) -> Option<Self> {
+        let mut samples = samples
+            .iter()
+            .map(|sample| sample.as_micros() as u64)
+            .collect::<Vec<_>>();
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_unstable();
+        // nearest-rank percentiles, so that each one is a measured latency
+        let percentile = |p: usize| samples[(samples.len() * p).div_ceil(100).max(1) - 1];
+        Some(Self {
+            count: samples.len(),
+            min_us: samples[0],
+            p50_us: percentile(50),
+            p95_us: percentile(95),
+            max_us: samples[samples.len() - 1],
+        })
+    }
+
+    /// Renders the summary, describing the requests that were sent, such as
+    /// `status requests`, in the table format.
+    fn render(
+        &self,
+        description: &str,
+        format: OutputFormat,
+        ui: UI,
+    ) -> Result<String, serde_json::Error> {
+        let rows = [
+            ("min", self.min_us),
+            ("p50", self.p50_us),
+            ("p95", self.p95_us),
+            ("max", self.max_us),
+        ];
+        Ok(match format {
+            OutputFormat::Json => serde_json::to_string_pretty(self)?,
+            OutputFormat::Plain => std::iter::once(format!("count={}", self.count))
+                .chain(rows.iter().map(|(key, us)| format!("{key}_us={us}")))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            OutputFormat::Table => {
+                let rows = rows.map(|(key, us)| {
+                    (
+                        key,
+                        humantime::format_duration(Duration::from_micros(us)).to_string(),
+                    )
+                });
+                format!(
+                    "{} sent {} {}\n{}",
+                    color!(ui, BOLD_GREEN, "✓"),
+                    self.count,
+                    description,
+                    render_table(&rows, ui)
+                )
+            }
+        })
+    }
+}
+
+fn render_start_breakdown(
+    breakdown: &StartBreakdown,
+    format: OutputFormat,
+    ui: UI,
+) -> Result<String, serde_json::Error> {
+    let rows = [
+        ("spawn", breakdown.spawn_us),
+        ("socket bind", breakdown.socket_bind_us),
+        ("ready", breakdown.ready_us),
+    ];
+    let format_us = |us| humantime::format_duration(Duration::from_micros(us)).to_string();
+    Ok(match format {
+        OutputFormat::Json => serde_json::to_string_pretty(breakdown)?,
+        OutputFormat::Plain => [
+            format!("spawn_us={}", breakdown.spawn_us),
+            format!("socket_bind_us={}", breakdown.socket_bind_us),
+            format!("ready_us={}", breakdown.ready_us),
+            format!("total_us={}", breakdown.total_us),
+        ]
+        .join("\n"),
+        OutputFormat::Table => format!(
+            "{} daemon was ready in {}\n{}",
+            color!(ui, BOLD_GREEN, "✓"),
+            format_us(breakdown.total_us),
+            render_table(&rows.map(|(key, us)| (key, format_us(us))), ui)
+        ),
+    })
+}
+
+/// The memory use per watched file estimated by `turbo daemon capacity`, from
+/// the daemon's current memory use and the files its watched globs match.
+#[derive(serde::Serialize, Debug, PartialEq, Eq)]
+struct CapacityEstimate {
+    resident_bytes: Option<u64>,
+    watched_files: u64,
+    bytes_per_file: Option<u64>,
+    target_files: Option<u64>,
+    projected_bytes: Option<u64>,
+}
+
+impl CapacityEstimate {
+    /// Nothing is derived if the daemon couldn't measure its memory, or isn't
+    /// watching any files to divide it between.
+    fn new(resident_bytes: Option<u64>, watched_files: u64, target_files: Option<u64>) -> Self {
+        let per_file = resident_bytes.filter(|_| watched_files > 0);
+        // scale the total rather than the rounded per-file figure, so that
+        // projections for many files aren't off by the rounding
+        let projected_bytes = per_file.zip(target_files).map(|(resident, target)| {
+            (resident as u128 * target as u128 / watched_files as u128) as u64
+        });
+        Self {
+            resident_bytes,
+            watched_files,
+            bytes_per_file: per_file.map(|resident| resident / watched_files),
+            target_files,
+            projected_bytes,
+        }
+    }
+
+    fn render(&self, format: OutputFormat, ui: UI) -> Result<String, serde_json::Error> {
+        let bytes = |bytes: Option<u64>| bytes.map_or("unknown".to_string(), format_bytes);
+        let mut rows = vec![
+            ("memory", bytes(self.resident_bytes)),
+            ("watched files", self.watched_files.to_string()),
+            ("per watched file", bytes(self.bytes_per_file)),
+        ];
+        let projected = self
+            .target_files
+            .map(|target| format!("for {target} files"));
+        if let Some(projected) = &projected {
+            rows.push((projected.as_str(), bytes(self.projected_bytes)));
+        }
+        let plain = |value: Option<u64>| value.map_or(String::new(), |value| value.to_string());
+        Ok(match format {
+            OutputFormat::Json => serde_json::to_string_pretty(self)?,
+            OutputFormat::Plain => [
+                format!("resident_bytes={}", plain(self.resident_bytes)),
+                format!("watched_files={}", self.watched_files),
+                format!("bytes_per_file={}", plain(self.bytes_per_file)),
+                format!("target_files={}", plain(self.target_files)),
+                format!("projected_bytes={}", plain(self.projected_bytes)),
+            ]
+            .join("\n"),
+            OutputFormat::Table => render_table(&rows, ui),
+        })
+    }
+}
+
+/// Formats a number of bytes with a binary unit, such as `1.5MB`.
+fn format_bytes(bytes: u64) -> String {
+    for (unit, size) in [("GB", 1u64 << 30), ("MB", 1 << 20), ("KB", 1 << 10)] {
+        if bytes >= size {
+            return format!("{:.1}{}", bytes as f64 / size as f64, unit);
+        }
+    }
+    format!("{bytes}B")
+}
+
+/// Whether the daemon is using the turbo.json that is on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConfigCheck {
+    UpToDate,
+    ReloadNeeded,
+}
+
+impl ConfigCheck {
+    fn new(disk_hash: Option<&str>, daemon_hash: Option<&str>) -> Self {
+        if disk_hash == daemon_hash {
+            Self::UpToDate
+        } else {
+            Self::ReloadNeeded
+        }
+    }
+
+    fn render(&self, ui: UI) -> String {
+        match self {
+            Self::UpToDate => format!(
+                "{} daemon has loaded the current turbo.json",
+                color!(ui, BOLD_GREEN, "✓")
+            ),
+            Self::ReloadNeeded => format!(
+                "{} reload needed: turbo.json has changed since the daemon loaded it. Run `turbo \
+                 daemon config check --reload` to reload it",
+                color!(ui, YELLOW, "!")
+            ),
+        }
+    }
+}
+
+/// What a client, such as an editor plugin, needs to connect to the daemon.
+/// This is resolved without contacting the daemon, so it is available even if
+/// the daemon is not running.
+#[derive(serde::Serialize)]
+pub struct DaemonEndpoint {
+    pub transport: &'static str,
+    pub sock_file: turbopath::AbsoluteSystemPathBuf,
+    pub protocol_version: &'static str,
+}
+
+impl DaemonEndpoint {
+    pub fn new(connector: &DaemonConnector) -> Self {
+        // on windows, the daemon uses AF_UNIX sockets as well
+        Self {
+            transport: "unix",
+            sock_file: connector.sock_file(),
+            protocol_version: proto::VERSION,
+        }
+    }
+}
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DaemonHealth {
+    Healthy,
+    Degraded,
+}
+
+impl From<proto::DaemonHealth> for DaemonHealth {
+    fn from(health: proto::DaemonHealth) -> Self {
+        match health {
+            proto::DaemonHealth::Healthy => Self::Healthy,
+            proto::DaemonHealth::Degraded => Self::Degraded,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use clap::Subcommand;
+    use clap_complete::Shell;
+    use serde_json::json;
+    use turbopath::AbsoluteSystemPathBuf;
+    use turborepo_ui::UI;
+
+    use super::{
+        completions, render_config, render_status, render_trace, render_watches, render_why_alive,
+        status_exit_code, CapacityEstimate, ConfigCheck, DaemonEndpoint, DaemonHealth,
+        DaemonStatus, IdleCountdown, LatencySummary, WatchesDiff, WatchesSnapshot,
+    };
+    use crate::{
+        cli::{DaemonCommand, HealthThreshold, OutputFormat},
+        daemon::{config_hash, proto, DaemonConnector, Paths},
+    };
+
+    fn status() -> DaemonStatus {
+        let daemon_dir = AbsoluteSystemPathBuf::cwd()
+            .unwrap()
+            .join_components(&[".turbo", "daemon"]);
+        DaemonStatus {
+            uptime_ms: 90_000,
+            log_file: daemon_dir.join_component("turbod.log").as_path().to_owned(),
+            pid_file: daemon_dir.join_component("turbod.pid"),
+            sock_file: daemon_dir.join_component("turbod.sock"),
+            health: DaemonHealth::Degraded,
+            health_message: Some("file watching stopped".to_string()),
+            worker_threads: 8,
+            compacted_bytes: None,
+            watcher_backend: None,
+        }
+    }
+
+    #[test]
+    fn test_status_formats() {
+        let status = status();
+        let ui = UI::new(true);
+
+        let json = render_status(&status, OutputFormat::Json, ui).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json["uptime_ms"], 90_000);
+        assert_eq!(json["health"], "degraded");
+        assert_eq!(json["health_message"], "file watching stopped");
+        assert_eq!(json["pid_file"], status.pid_file.to_string());
+
+        let plain = render_status(&status, OutputFormat::Plain, ui).unwrap();
+        let lines = plain.lines().collect::<Vec<_>>();
+        assert!(lines.iter().all(|line| line.contains('=')));
+        assert!(lines.contains(&"health=degraded"));
+        assert!(lines.contains(&"uptime_ms=90000"));
+        assert!(lines.contains(&"worker_threads=8"));
+        assert!(lines.contains(&format!("sock_file={}", status.sock_file).as_str()));
+
+        let table = render_status(&status, OutputFormat::Table, ui).unwrap();
+        let mut lines = table.lines();
+        assert_eq!(
+            lines.next(),
+            Some("! daemon is running, but degraded: file watching stopped")
+        );
+        let rows = lines.collect::<Vec<_>>();
+        assert_eq!(rows.len(), 5);
+        let value_columns = rows
+            .iter()
+            .zip([
+                status.log_file.to_string(),
+                "1m 30s".to_string(),
+                status.pid_file.to_string(),
+                status.sock_file.to_string(),
+                "8".to_string(),
+            ])
+            .map(|(row, value)| {
+                assert!(row.ends_with(&value), "{row} should end with {value}");
+                row.len() - value.len()
+            })
+            .collect::<Vec<_>>();
+        assert!(
+            value_columns.windows(2).all(|w| w[0] == w[1]),
+            "values should be aligned: {rows:?}"
+        );
+
+        let compacted = DaemonStatus {
+            compacted_bytes: Some(3072),
+            ..status
+        };
+        let plain = render_status(&compacted, OutputFormat::Plain, ui).unwrap();
+        assert!(plain.lines().any(|line| line == "compacted_bytes=3072"));
+        let table = render_status(&compacted, OutputFormat::Table, ui).unwrap();
+        assert!(table.lines().last().unwrap().ends_with("reclaimed 3.0KB"));
+
+        let polling = DaemonStatus {
+            watcher_backend: Some("polling".to_string()),
+            ..status()
+        };
+        let plain = render_status(&polling, OutputFormat::Plain, ui).unwrap();
+        assert!(plain.lines().any(|line| line == "watcher_backend=polling"));
+    }
+
+    #[test]
+    fn test_status_exit_code() {
+        let healthy = Some(DaemonHealth::Healthy);
+        let degraded = Some(DaemonHealth::Degraded);
+
+        for health in [healthy, degraded, None] {
+            assert_eq!(status_exit_code(health, None), 0, "{health:?}");
+        }
+
+        let exit_on = Some(HealthThreshold::Degraded);
+        assert_eq!(status_exit_code(healthy, exit_on), 0);
+        assert_eq!(status_exit_code(degraded, exit_on), 1);
+        assert_eq!(status_exit_code(None, exit_on), 1);
+
+        let exit_on = Some(HealthThreshold::Unhealthy);
+        assert_eq!(status_exit_code(healthy, exit_on), 0);
+        assert_eq!(status_exit_code(degraded, exit_on), 0);
+        assert_eq!(status_exit_code(None, exit_on), 1);
+    }
+
+    #[test]
+    fn test_endpoint() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let repo_root = AbsoluteSystemPathBuf::try_from(tmp_dir.path()).unwrap();
+        let connector = DaemonConnector::new(false, false, &repo_root);
+
+        let endpoint = serde_json::to_value(DaemonEndpoint::new(&connector)).unwrap();
+        assert_eq!(
+            endpoint["sock_file"],
+            Paths::from_repo_root(&repo_root).sock_file.to_string()
+        );
+        assert_eq!(endpoint["protocol_version"], proto::VERSION);
+        assert_eq!(endpoint["transport"], "unix");
+    }
+
+    #[test]
+    fn test_config_check() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let repo_root = AbsoluteSystemPathBuf::try_from(tmp_dir.path()).unwrap();
+        let turbo_json = repo_root.join_component("turbo.json");
+
+        turbo_json
+            .create_with_contents(r#"{"pipeline": {}}"#)
+            .unwrap();
+        let loaded_hash = config_hash(&repo_root);
+        assert!(loaded_hash.is_some());
+        assert_eq!(
+            ConfigCheck::new(config_hash(&repo_root).as_deref(), loaded_hash.as_deref()),
+            ConfigCheck::UpToDate
+        );
+
+        turbo_json
+            .create_with_contents(r#"{"pipeline": {"build": {}}}"#)
+            .unwrap();
+        let check = ConfigCheck::new(config_hash(&repo_root).as_deref(), loaded_hash.as_deref());
+        assert_eq!(check, ConfigCheck::ReloadNeeded);
+        assert!(check.render(UI::new(true)).contains("reload needed"));
+    }
+
+    #[test]
+    fn test_idle_countdown() {
+        let ui = UI::new(true);
+        let mut countdown = IdleCountdown::default();
+        assert_eq!(countdown.render(10_000, ui), "idle shutdown in 10s");
+        assert_eq!(countdown.render(8_999, ui), "idle shutdown in 9s");
+        assert_eq!(
+            countdown.render(4 * 60 * 60 * 1000, ui),
+            "idle shutdown in 4h (extended by a request)"
+        );
+        assert_eq!(
+            countdown.render(4 * 60 * 60 * 1000 - 1000, ui),
+            "idle shutdown in 3h 59m 59s"
+        );
+        assert!(countdown.render(0, ui).contains("shutting down"));
+    }
+
+    #[test]
+    fn test_why_alive() {
+        let why_alive = proto::WhyAliveResponse {
+            idle_timeout_ms: 4 * 60 * 60 * 1000,
+            idle_for_ms: 90_500,
+            remaining_ms: (4 * 60 * 60 - 90) * 1000,
+            lease_holders: vec![],
+            connected_clients: 0,
+        };
+        let explanation = render_why_alive(&why_alive, UI::new(true));
+        assert!(explanation.contains("gone 4h without a request"));
+        assert!(explanation.contains("was 1m 30s ago, so it shuts down in 3h 58m 30s"));
+        assert!(!explanation.contains("lease"));
+        assert!(!explanation.contains("connected"));
+
+        let busy = proto::WhyAliveResponse {
+            connected_clients: 2,
+            ..why_alive.clone()
+        };
+        assert!(render_why_alive(&busy, UI::new(true))
+            .contains("2 other clients are connected, and their requests keep it alive"));
+
+        let expired = proto::WhyAliveResponse {
+            remaining_ms: 0,
+            ..why_alive.clone()
+        };
+        assert!(render_why_alive(&expired, UI::new(true)).contains("it is shutting down"));
+
+        let leased = proto::WhyAliveResponse {
+            lease_holders: vec!["turbo daemon pin (pid 123)".to_string()],
+            ..expired
+        };
+        let explanation = render_why_alive(&leased, UI::new(true));
+        assert!(explanation.contains("it is held by 1 lease"));
+        assert!(explanation.contains("shuts down once the leases are released"));
+    }
+
+    #[test]
+    fn test_trace() {
+        let span = |name: &str, duration_us, depth| proto::TraceSpan {
+            name: name.to_string(),
+            duration_us,
+            depth,
+        };
+        let spans = [
+            span("status", 1500, 0),
+            span("daemon_status", 250, 1),
+            span("health", 40, 2),
+        ];
+
+        assert_eq!(
+            render_trace(&spans, UI::new(true))
+                .lines()
+                .collect::<Vec<_>>(),
+            [
+                "status           1.5ms",
+                "  daemon_status  250µs",
+                "    health       40µs",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_watches() {
+        let globs = [
+            proto::WatchedGlob {
+                glob: "apps/web/.next/**".to_string(),
+                file_count: 12,
+            },
+            proto::WatchedGlob {
+                glob: "packages/ui/dist/**".to_string(),
+                file_count: 1,
+            },
+        ];
+
+        let rendered = render_watches(&globs, UI::new(true));
+        assert_eq!(
+            rendered.lines().collect::<Vec<_>>(),
+            [
+                "apps/web/.next/**    12 files",
+                "packages/ui/dist/**  1 file",
+            ]
+        );
+
+        assert_eq!(
+            render_watches(&[], UI::new(true)),
+            "the daemon is not watching any globs"
+        );
+    }
+
+    #[test]
+    fn test_render_config() {
+        let config = json!({
+            "repo_root": "/repo",
+            "idle_timeout_ms": 14_400_000,
+            "worker_threads": 4,
+            "checkpoint_interval_ms": null,
+            "read_only": true,
+            "max_memory": "1GB",
+            "config_hash": "abc123",
+        });
+
+        let rendered = render_config(&config.to_string()).unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&rendered).unwrap(),
+            config
+        );
+        assert!(
+            rendered.contains("\n  \"read_only\": true"),
+            "the config is pretty-printed:\n{rendered}"
+        );
+        assert!(render_config("not json").is_err());
+    }
+
+    #[test]
+    fn test_watches_diff() {
+        let glob = |glob: &str| proto::WatchedGlob {
+            glob: glob.to_string(),
+            file_count: 1,
+        };
+        let mut globs = vec![
+            glob("apps/web/.next/**"),
+            glob("packages/ui/dist/**"),
+            glob("packages/utils/dist/**"),
+        ];
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let file = AbsoluteSystemPathBuf::try_from(tempdir.path())
+            .unwrap()
+            .join_component("watches.json");
+        WatchesSnapshot::new(&globs).save(&file).unwrap();
+        let snapshot = WatchesSnapshot::load(&file).unwrap();
+        assert_eq!(snapshot, WatchesSnapshot::new(&globs));
+
+        let unchanged = snapshot.diff(&globs);
+        assert_eq!(
+            unchanged.render(UI::new(true)),
+            "no globs were added or removed since the snapshot"
+        );
+
+        globs.retain(|glob| glob.glob != "packages/ui/dist/**");
+        globs.push(glob("packages/ui/.turbo/**"));
+        globs.push(glob("apps/docs/.next/**"));
+        let diff = snapshot.diff(&globs);
+        assert_eq!(
+            diff,
+            WatchesDiff {
+                added: vec![
+                    "apps/docs/.next/**".to_string(),
+                    "packages/ui/.turbo/**".to_string()
+                ],
+                removed: vec!["packages/ui/dist/**".to_string()],
+            }
+        );
+        assert_eq!(
+            diff.render(UI::new(true)).lines().collect::<Vec<_>>(),
+            [
+                "+ apps/docs/.next/**",
+                "+ packages/ui/.turbo/**",
+                "- packages/ui/dist/**",
+                "2 added, 1 removed",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_latency_summary() {
+        // 1ms through 20ms, out of order
+        let samples = (1..=20)
+            .rev()
+            .map(Duration::from_millis)
+            .collect::<Vec<_>>();
+        let summary = LatencySummary::new(&samples).unwrap();
+        assert_eq!(
+            summary,
+            LatencySummary {
+                count: 20,
+                min_us: 1_000,
+                p50_us: 10_000,
+                p95_us: 19_000,
+                max_us: 20_000,
+            }
+        );
+
+        let json = summary
+            .render("status requests", OutputFormat::Json, UI::new(true))
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json["p95_us"], 19_000);
+
+        let single = LatencySummary::new(&[Duration::from_micros(250)]).unwrap();
+        assert_eq!((single.p50_us, single.p95_us), (250, 250));
+
+        assert_eq!(LatencySummary::new(&[]), None);
+    }
+
+    #[test]
+    fn test_capacity_estimate() {
+        // 100MB over 2048 files
+        let estimate = CapacityEstimate::new(Some(100 << 20), 2048, Some(10_000));
+        assert_eq!(
+            estimate,
+            CapacityEstimate {
+                resident_bytes: Some(100 << 20),
+                watched_files: 2048,
+                bytes_per_file: Some(51_200),
+                target_files: Some(10_000),
+                projected_bytes: Some(512_000_000),
+            }
+        );
+
+        let json = estimate.render(OutputFormat::Json, UI::new(true)).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json["bytes_per_file"], 51_200);
+        assert_eq!(json["projected_bytes"], 512_000_000);
+        assert_eq!(
+            estimate
+                .render(OutputFormat::Table, UI::new(true))
+                .unwrap()
+                .lines()
+                .collect::<Vec<_>>(),
+            [
+                "memory            100.0MB",
+                "watched files     2048",
+                "per watched file  50.0KB",
+                "for 10000 files   488.3MB",
+            ]
+        );
+
+        let unwatched = CapacityEstimate::new(Some(100 << 20), 0, Some(10_000));
+        assert_eq!(
+            (unwatched.bytes_per_file, unwatched.projected_bytes),
+            (None, None)
+        );
+        let unmeasured = CapacityEstimate::new(None, 2048, None);
+        assert_eq!(unmeasured.bytes_per_file, None);
+    }
+
+    #[test]
+    fn test_completions() {
+        let mut script = Vec::new();
+        completions(Shell::Bash, &mut script);
+        let script = String::from_utf8(script).unwrap();
+
+        let daemon = DaemonCommand::augment_subcommands(clap::Command::new("daemon"));
+        let subcommands = daemon
+            .get_subcommands()
+            .map(|subcommand| subcommand.get_name())
+            .collect::<Vec<_>>();
+        assert!(subcommands.contains(&"reset-errors"));
+        for subcommand in subcommands {
+            assert!(script.contains(subcommand), "{subcommand} is not completed");
+        }
+    }
 }
@@ -0,0 +1,99 @@
+//! Pinning the daemon's worker threads to a set of CPU cores, which can
+//! stabilize request latency on large machines. This is only supported on
+//! Linux.
+
+use std::{fmt, str::FromStr};
+
+/// A set of CPU cores, parsed from a comma separated list of cores and
+/// inclusive ranges, such as `0-3,8`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoreSet(Vec<usize>);
+
+impl FromStr for CoreSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_core = |core: &str| {
+            core.trim()
+                .parse::<usize>()
+                .map_err(|_| format!("'{core}' is not a core number"))
+        };
+
+        let mut cores = Vec::new();
+        for part in s.split(',') {
+            match part.split_once('-') {
+                Some((start, end)) => {
+                    let (start, end) = (parse_core(start)?, parse_core(end)?);
+                    if start > end {
+                        return Err(format!("'{part}' is not an ascending range"));
+                    }
+                    cores.extend(start..=end);
+                }
+                None => cores.push(parse_core(part)?),
+            }
+        }
+        cores.sort_unstable();
+        cores.dedup();
+        Ok(Self(cores))
+    }
+}
+
+impl fmt::Display for CoreSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cores = self
+            .0
+            .iter()
+            .map(|core| core.to_string())
+            .collect::<Vec<_>>();
+        f.write_str(&cores.join(","))
+    }
+}
+
+impl CoreSet {
+    /// Restricts the current thread to run on the cores in the set.
+    #[cfg(target_os = "linux")]
+    pub fn pin_current_thread(&self) -> std::io::Result<()> {
+        use nix::{sched::CpuSet, unistd::Pid};
+
+        let mut cpu_set = CpuSet::new();
+        for &core in &self.0 {
+            cpu_set.set(core)?;
+        }
+        // pid 0 is the calling thread
+        nix::sched::sched_setaffinity(Pid::from_raw(0), &cpu_set)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CoreSet;
+
+    #[test]
+    fn test_parse() {
+        let cores = "0-3,8,2".parse::<CoreSet>().unwrap();
+        assert_eq!(cores, CoreSet(vec![0, 1, 2, 3, 8]));
+        assert_eq!(cores.to_string(), "0,1,2,3,8");
+
+        for invalid in ["", "3-1", "a-b", "0,,1", "-1"] {
+            assert!(invalid.parse::<CoreSet>().is_err(), "{invalid}");
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_pin_current_thread() {
+        use nix::{sched::sched_getaffinity, unistd::Pid};
+
+        // pin a thread of its own, so that the test harness isn't affected
+        std::thread::spawn(|| {
+            CoreSet(vec![0]).pin_current_thread().unwrap();
+
+            let cpu_set = sched_getaffinity(Pid::from_raw(0)).unwrap();
+            assert!(cpu_set.is_set(0).unwrap());
+            assert!(!cpu_set.is_set(1).unwrap());
+        })
+        .join()
+        .unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+//! Auditing the daemon's socket, for `turbo daemon audit` on shared machines.
+//!
+//! Anyone who can connect to the socket can make requests to the daemon, so
+//! it should only be readable and writable by its owner, who should be the
+//! user running turbo. Sockets are only audited on unix.
+
+use std::{fmt, os::unix::fs::MetadataExt};
+
+use turbopath::AbsoluteSystemPath;
+
+/// The mode the socket is expected to have.
+pub const EXPECTED_MODE: u32 = 0o600;
+
+/// The parts of the socket's metadata that are audited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SocketStat {
+    /// The permission bits, e.g. `0o600`.
+    pub mode: u32,
+    pub uid: u32,
+}
+
+impl SocketStat {
+    pub fn read(socket: &AbsoluteSystemPath) -> std::io::Result<Self> {
+        let metadata = std::fs::metadata(socket.as_std_path())?;
+        Ok(Self {
+            mode: metadata.mode() & 0o777,
+            uid: metadata.uid(),
+        })
+    }
+}
+
+/// A way in which the socket isn't secured as expected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Deviation {
+    Mode { actual: u32 },
+    Owner { actual: u32, expected: u32 },
+}
+
+impl Deviation {
+    /// How to resolve the deviation for the socket at `socket`.
+    pub fn remediation(&self, socket: &AbsoluteSystemPath) -> String {
+        match self {
+            Deviation::Mode { .. } => format!("chmod {:o} {}", EXPECTED_MODE, socket),
+            // another user's daemon can only be stopped by them, or root
+            Deviation::Owner { .. } => "have its owner stop the daemon, then start it".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Deviation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Deviation::Mode { actual } => {
+                write!(f, "has mode {:04o}, expected {:04o}", actual, EXPECTED_MODE)
+            }
+            Deviation::Owner { actual, expected } => write!(
+                f,
+                "is owned by uid {}, expected {} (the current user)",
+                actual, expected
+            ),
+        }
+    }
+}
+
+/// Compares the socket's metadata against the expected mode and owner.
+pub fn check(stat: SocketStat, expected_uid: u32) -> Vec<Deviation> {
+    let mut deviations = Vec::new();
+    if stat.mode != EXPECTED_MODE {
+        deviations.push(Deviation::Mode { actual: stat.mode });
+    }
+    if stat.uid != expected_uid {
+        deviations.push(Deviation::Owner {
+            actual: stat.uid,
+            expected: expected_uid,
+        });
+    }
+    deviations
+}
+
+/// Audits the socket at `socket` for the current user.
+pub fn audit(socket: &AbsoluteSystemPath) -> std::io::Result<Vec<Deviation>> {
+    let stat = SocketStat::read(socket)?;
+    Ok(check(stat, nix::unistd::Uid::effective().as_raw()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::os::unix::{fs::PermissionsExt, net::UnixListener};
+
+    use turbopath::AbsoluteSystemPathBuf;
+
+    use super::{audit, check, Deviation, SocketStat};
+
+    #[test]
+    fn test_check() {
+        let secured = SocketStat {
+            mode: 0o600,
+            uid: 1000,
+        };
+        assert_eq!(check(secured, 1000), vec![]);
+
+        let shared = SocketStat {
+            mode: 0o755,
+            uid: 1001,
+        };
+        let deviations = check(shared, 1000);
+        assert_eq!(
+            deviations,
+            vec![
+                Deviation::Mode { actual: 0o755 },
+                Deviation::Owner {
+                    actual: 1001,
+                    expected: 1000
+                },
+            ]
+        );
+        assert_eq!(deviations[0].to_string(), "has mode 0755, expected 0600");
+    }
+
+    #[test]
+    fn test_audit_socket() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let socket = AbsoluteSystemPathBuf::try_from(tmp_dir.path())
+            .unwrap()
+            .join_component("turbod.sock");
+        let _listener = UnixListener::bind(socket.as_std_path()).unwrap();
+
+        let set_mode = |mode| {
+            std::fs::set_permissions(socket.as_std_path(), std::fs::Permissions::from_mode(mode))
+                .unwrap()
+        };
+        set_mode(0o600);
+        assert_eq!(audit(&socket).unwrap(), vec![]);
+
+        set_mode(0o666);
+        let deviations = audit(&socket).unwrap();
+        assert_eq!(deviations, vec![Deviation::Mode { actual: 0o666 }]);
+        assert_eq!(
+            deviations[0].remediation(&socket),
+            format!("chmod 600 {}", socket)
+        );
+    }
+}
@@ -3,7 +3,7 @@ use std::{
     time::Duration,
 };
 
-use tokio::time::Instant;
+use tokio::{sync::Notify, time::Instant};
 
 /// A timeout that can be bumped forward in time by calling reset.
 ///
@@ -15,6 +15,12 @@ pub struct BumpTimeout {
     start: Instant,
     increment: Duration,
     deadline: AtomicU64,
+    last_reset: AtomicU64,
+    expired: Notify,
+    /// The granularity `wait` checks the deadline at, or zero to check it
+    /// exactly.
+    granularity: Duration,
+    wakeups: AtomicU64,
 }
 
 impl BumpTimeout {
@@ -25,10 +31,36 @@ impl BumpTimeout {
         Self {
             start,
             deadline: AtomicU64::new(millis as u64),
+            last_reset: AtomicU64::new(0),
             increment,
+            expired: Notify::new(),
+            granularity: Duration::ZERO,
+            wakeups: AtomicU64::new(0),
         }
     }
 
+    /// Makes `wait` only check the deadline on multiples of `granularity`
+    /// since the timeout was created, so that frequent resets cause fewer
+    /// wakeups. In exchange, `wait` can return up to `granularity` after the
+    /// deadline.
+    pub fn with_granularity(mut self, granularity: Duration) -> Self {
+        self.granularity = granularity;
+        self
+    }
+
+    /// Creates a timeout whose first deadline is `remaining` from now rather
+    /// than a full increment, such as one inherited from a daemon that was
+    /// restarted. `remaining` is capped at `increment`, so that the first
+    /// reset never moves the deadline earlier.
+    pub fn with_remaining(increment: Duration, remaining: Duration) -> Self {
+        let timeout = Self::new(increment);
+        timeout.deadline.store(
+            remaining.min(increment).as_millis() as u64,
+            Ordering::Relaxed,
+        );
+        timeout
+    }
+
     pub fn duration(&self) -> Duration {
         Duration::from_millis(self.deadline.load(Ordering::Relaxed))
     }
@@ -43,32 +75,216 @@ impl BumpTimeout {
         self.start.elapsed()
     }
 
+    /// Creates a new timeout with the same deadline and increment as this one.
+    ///
+    /// The fork is fully independent of its parent once created: resetting
+    /// either one does not affect the other.
+    #[cfg(test)]
+    pub fn fork(&self) -> Self {
+        Self {
+            start: self.start,
+            increment: self.increment,
+            deadline: AtomicU64::new(self.deadline.load(Ordering::Relaxed)),
+            last_reset: AtomicU64::new(self.last_reset.load(Ordering::Relaxed)),
+            expired: Notify::new(),
+            granularity: self.granularity,
+            wakeups: AtomicU64::new(0),
+        }
+    }
+
     /// Resets the deadline to the current time plus the given duration.
     pub fn reset(&self) {
-        let duration = self.start.elapsed() + self.increment;
+        let elapsed = self.start.elapsed();
+        self.last_reset
+            .store(elapsed.as_millis() as u64, Ordering::Relaxed);
+        let duration = elapsed + self.increment;
         self.deadline
             .store(duration.as_millis() as u64, Ordering::Relaxed);
     }
 
+    /// The time left until the deadline, or zero if it has passed.
+    pub fn remaining(&self) -> Duration {
+        self.deadline().saturating_duration_since(Instant::now())
+    }
+
+    /// How far each reset moves the deadline past the current time.
+    pub fn increment(&self) -> Duration {
+        self.increment
+    }
+
+    /// The time since the timeout was last reset, or created if it never
+    /// was.
+    pub fn idle_for(&self) -> Duration {
+        self.start.elapsed().saturating_sub(Duration::from_millis(
+            self.last_reset.load(Ordering::Relaxed),
+        ))
+    }
+
+    /// When the timeout was last reset, as the time since it was created, or
+    /// zero if it never was.
+    pub fn last_reset(&self) -> Duration {
+        Duration::from_millis(self.last_reset.load(Ordering::Relaxed))
+    }
+
+    /// Moves the deadline to the current time, so that `wait` returns
+    /// immediately. The pending `wait`, or the next one if none is pending,
+    /// returns even if `reset` is called before it wakes up.
+    pub fn expire(&self) {
+        self.deadline
+            .store(self.start.elapsed().as_millis() as u64, Ordering::Relaxed);
+        self.expired.notify_one();
+    }
+
     #[allow(dead_code)]
     pub fn as_instant(&self) -> Instant {
         self.start + self.duration()
     }
 
+    /// How many times `wait` has woken up to check the deadline.
+    #[cfg(test)]
+    pub fn wakeups(&self) -> u64 {
+        self.wakeups.load(Ordering::Relaxed)
+    }
+
+    /// When `wait` next checks a deadline: the deadline itself, rounded up
+    /// to the granularity.
+    fn wake_at(&self, deadline: Duration) -> Instant {
+        let millis = deadline.as_millis() as u64;
+        let granularity = self.granularity.as_millis() as u64;
+        let rounded = match granularity {
+            0 => millis,
+            granularity => millis.div_ceil(granularity) * granularity,
+        };
+        self.start + Duration::from_millis(rounded)
+    }
+
     /// Waits until the deadline is reached, but if the deadline is
     /// changed while waiting, it will wait until the new deadline is reached.
+    /// Returns early if the timeout is expired.
     #[allow(dead_code)]
     pub async fn wait(&self) {
-        let mut deadline = self.as_instant();
         loop {
-            tokio::time::sleep_until(deadline).await;
-            let new_deadline = self.as_instant();
+            let wake_at = self.wake_at(self.duration());
+            tokio::select! {
+                _ = tokio::time::sleep_until(wake_at) => {}
+                _ = self.expired.notified() => break,
+            }
+            self.wakeups.fetch_add(1, Ordering::Relaxed);
 
-            if new_deadline > deadline {
-                deadline = new_deadline;
-            } else {
+            // resets while sleeping may have moved the deadline past the
+            // wakeup
+            if self.as_instant() <= wake_at {
                 break;
             }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::BumpTimeout;
+
+    #[tokio::test]
+    async fn test_fork_is_independent() {
+        let parent = BumpTimeout::new(Duration::from_millis(100));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let child = parent.fork();
+        assert_eq!(child.deadline(), parent.deadline());
+        assert_eq!(child.duration(), parent.duration());
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let parent_deadline = parent.deadline();
+        child.reset();
+        assert_eq!(parent.deadline(), parent_deadline);
+        assert!(child.deadline() > parent.deadline());
+
+        parent.reset();
+        assert!(parent.deadline() > parent_deadline);
+    }
+
+    #[tokio::test]
+    async fn test_idle_for() {
+        let timeout = BumpTimeout::new(Duration::from_secs(60 * 60));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(timeout.idle_for() >= Duration::from_millis(20));
+
+        timeout.reset();
+        assert!(timeout.idle_for() < Duration::from_millis(20));
+    }
+
+    #[tokio::test]
+    async fn test_with_remaining() {
+        let timeout = BumpTimeout::with_remaining(Duration::from_secs(60), Duration::from_secs(10));
+        let remaining = timeout.remaining();
+        assert!(remaining <= Duration::from_secs(10));
+        assert!(remaining > Duration::from_secs(9));
+
+        timeout.reset();
+        assert!(timeout.remaining() > Duration::from_secs(59));
+        assert!(timeout.idle_for() < Duration::from_millis(20));
+    }
+
+    #[tokio::test]
+    async fn test_with_remaining_is_capped() {
+        let timeout =
+            BumpTimeout::with_remaining(Duration::from_secs(60), Duration::from_secs(10 * 60));
+        assert!(
+            timeout.remaining() <= Duration::from_secs(60),
+            "an inherited deadline is never later than a full increment"
+        );
+    }
+
+    /// Resets the timeout every 5ms for 300ms, then waits for it, returning
+    /// how long after the deadline the wait returned.
+    async fn wait_through_churn(timeout: &BumpTimeout) -> Duration {
+        let churn = async {
+            let start = Instant::now();
+            while start.elapsed() < Duration::from_millis(300) {
+                timeout.reset();
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        };
+        tokio::join!(timeout.wait(), churn);
+        Instant::now().saturating_duration_since(timeout.deadline())
+    }
+
+    #[tokio::test]
+    async fn test_granularity_reduces_wakeups() {
+        let exact = BumpTimeout::new(Duration::from_millis(20));
+        let late = wait_through_churn(&exact).await;
+        assert!(late < Duration::from_millis(50), "{:?}", late);
+
+        let granularity = Duration::from_millis(200);
+        let coarse = BumpTimeout::new(Duration::from_millis(20)).with_granularity(granularity);
+        let late = wait_through_churn(&coarse).await;
+        assert!(
+            late <= granularity + Duration::from_millis(30),
+            "shut down {:?} after the deadline",
+            late
+        );
+
+        assert!(
+            coarse.wakeups() < exact.wakeups(),
+            "{} coarse wakeups, {} exact",
+            coarse.wakeups(),
+            exact.wakeups()
+        );
+        assert!(coarse.wakeups() <= 3, "{}", coarse.wakeups());
+    }
+
+    #[tokio::test]
+    async fn test_expire_ends_wait() {
+        let timeout = BumpTimeout::new(Duration::from_secs(60 * 60));
+        let wait = timeout.wait();
+        timeout.expire();
+        tokio::time::timeout(Duration::from_secs(1), wait)
+            .await
+            .expect("wait returns once the timeout is expired");
+        assert!(timeout.deadline() <= tokio::time::Instant::now());
+    }
+}
@@ -5,11 +5,14 @@
 
 use std::sync::Arc;
 
-use tonic::server::NamedService;
+use tonic::{codegen::http, server::NamedService};
 use tower::{Layer, Service};
 
 use super::bump_timeout::BumpTimeout;
 
+/// Requests that only inspect the timeout, and so shouldn't reset it.
+const EXEMPT_PATHS: &[&str] = &["/turbodprotocol.Turbod/WhyAlive"];
+
 /// A layer that resets a <BumpTimeout> when a request is received.
 pub struct BumpTimeoutLayer(Arc<BumpTimeout>);
 
@@ -37,9 +40,9 @@ pub struct BumpTimeoutService<S> {
     timeout: Arc<BumpTimeout>,
 }
 
-impl<S, Request> Service<Request> for BumpTimeoutService<S>
+impl<S, B> Service<http::Request<B>> for BumpTimeoutService<S>
 where
-    S: Service<Request>,
+    S: Service<http::Request<B>>,
 {
     type Response = S::Response;
     type Error = S::Error;
@@ -52,8 +55,10 @@ where
         self.inner.poll_ready(cx)
     }
 
-    fn call(&mut self, req: Request) -> Self::Future {
-        self.timeout.reset();
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        if !EXEMPT_PATHS.contains(&req.uri().path()) {
+            self.timeout.reset();
+        }
         self.inner.call(req)
     }
 }
@@ -0,0 +1,104 @@
+//! Checkpoints of the daemon's state, so that a restarted daemon doesn't have
+//! to start from scratch.
+//!
+//! A checkpoint is only valid for the config it was taken with: if the
+//! repository's `turbo.json` has changed since, it is discarded.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
+use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// The hash of the `turbo.json` the daemon had loaded when the checkpoint
+    /// was taken.
+    pub config_hash: Option<String>,
+    /// The time saved by each task hash whose outputs the daemon was
+    /// watching.
+    pub times_saved: HashMap<String, u64>,
+}
+
+impl Checkpoint {
+    /// Hashes the checkpointed state, independently of the order it is
+    /// stored in.
+    pub fn state_hash(&self) -> String {
+        let times_saved = self.times_saved.iter().collect::<BTreeMap<_, _>>();
+        let mut hasher = Sha256::new();
+        hasher.update(self.config_hash.as_deref().unwrap_or_default());
+        for (hash, time_saved) in times_saved {
+            hasher.update(hash);
+            hasher.update(time_saved.to_le_bytes());
+        }
+        hex::encode(hasher.finalize())
+    }
+
+    /// Writes the checkpoint, replacing any previous one atomically so that
+    /// a daemon killed mid-write doesn't leave a corrupt checkpoint behind.
+    pub fn write(&self, path: &AbsoluteSystemPath) -> std::io::Result<()> {
+        let contents = serde_json::to_string(self)?;
+        path.ensure_dir()?;
+        let tmp = Self::tmp_file(path);
+        tmp.create_with_contents(contents)?;
+        tmp.rename(path)
+    }
+
+    /// Where a checkpoint at `path` is written before it replaces the
+    /// previous one.
+    pub fn tmp_file(path: &AbsoluteSystemPath) -> AbsoluteSystemPathBuf {
+        path.parent()
+            .expect("checkpoint file has a parent")
+            .join_component(".turbod.checkpoint.tmp")
+    }
+
+    /// Loads the checkpoint at `path` if it was taken with the given config.
+    /// Checkpoints that can't be used are removed.
+    pub fn load(path: &AbsoluteSystemPath, config_hash: Option<&str>) -> Option<Self> {
+        let contents = path.read_to_string().ok()?;
+        let checkpoint = match serde_json::from_str::<Checkpoint>(&contents) {
+            Ok(checkpoint) if checkpoint.config_hash.as_deref() == config_hash => checkpoint,
+            Ok(_) => {
+                debug!("config changed since the checkpoint was taken, discarding it");
+                path.remove_file().ok();
+                return None;
+            }
+            Err(e) => {
+                warn!("discarding invalid checkpoint: {}", e);
+                path.remove_file().ok();
+                return None;
+            }
+        };
+        Some(checkpoint)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use turbopath::AbsoluteSystemPathBuf;
+
+    use super::Checkpoint;
+
+    #[test]
+    fn test_restore_only_with_same_config() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = AbsoluteSystemPathBuf::try_from(tmp_dir.path())
+            .unwrap()
+            .join_component("turbod.checkpoint");
+
+        let checkpoint = Checkpoint {
+            config_hash: Some("abc".to_string()),
+            times_saved: [("task-a".to_string(), 100), ("task-b".to_string(), 200)]
+                .into_iter()
+                .collect(),
+        };
+        checkpoint.write(&path).unwrap();
+
+        let restored = Checkpoint::load(&path, Some("abc")).expect("checkpoint is restored");
+        assert_eq!(restored.state_hash(), checkpoint.state_hash());
+
+        assert_eq!(Checkpoint::load(&path, Some("def")), None);
+        assert!(!path.exists(), "stale checkpoint is removed");
+    }
+}
@@ -1,4 +1,4 @@
-use std::{io, time::Duration};
+use std::{collections::BTreeMap, io, time::Duration};
 
 use globwalk::ValidatedGlob;
 use thiserror::Error;
@@ -10,6 +10,7 @@ use super::{
     connector::{DaemonConnector, DaemonConnectorError},
     endpoint::SocketOpenError,
     proto::{DiscoverPackagesResponse, GetFileHashesResponse},
+    replay::ReplayError,
     Paths,
 };
 use crate::{
@@ -17,6 +18,10 @@ use crate::{
     globwatcher::HashGlobSetupError,
 };
 
+/// How long to wait for the daemon to warm a subtree. Without a timeout of
+/// its own, the request would get the default for non-blocking calls.
+const WARM_TIMEOUT: Duration = Duration::from_secs(30);
+
 #[derive(Debug, Clone)]
 pub struct DaemonClient<T> {
     client: proto::turbod_client::TurbodClient<tonic::transport::Channel>,
@@ -127,6 +132,147 @@ impl<T> DaemonClient<T> {
             .ok_or(DaemonError::MalformedResponse)
     }
 
+    /// Clear any sticky errors on the daemon, returning whether it was
+    /// degraded before the reset.
+    pub async fn reset_errors(&mut self) -> Result<bool, DaemonError> {
+        Ok(self
+            .client
+            .reset_errors(proto::ResetErrorsRequest {})
+            .await?
+            .into_inner()
+            .was_degraded)
+    }
+
+    /// Makes the daemon shut down as if it had been idle for too long.
+    pub async fn expire(&mut self) -> Result<(), DaemonError> {
+        self.client.expire(proto::ExpireRequest {}).await?;
+        Ok(())
+    }
+
+    /// Has the daemon handle a request to `method`, returning the spans it
+    /// entered while doing so.
+    pub async fn trace(&mut self, method: String) -> Result<Vec<proto::TraceSpan>, DaemonError> {
+        Ok(self
+            .client
+            .trace(proto::TraceRequest { method })
+            .await?
+            .into_inner()
+            .spans)
+    }
+
+    /// Asks the daemon how long it will keep running without requests. This
+    /// doesn't extend its idle timeout.
+    pub async fn why_alive(&mut self) -> Result<proto::WhyAliveResponse, DaemonError> {
+        Ok(self
+            .client
+            .why_alive(proto::WhyAliveRequest {})
+            .await?
+            .into_inner())
+    }
+
+    /// Asks the daemon for the configuration it is running with, as a JSON
+    /// object.
+    pub async fn config(&mut self) -> Result<String, DaemonError> {
+        Ok(self
+            .client
+            .config(proto::ConfigRequest {})
+            .await?
+            .into_inner()
+            .config_json)
+    }
+
+    /// Asks the daemon to hash the files below `path` ahead of time,
+    /// returning how many it hashed once it is done.
+    pub async fn warm(&mut self, path: &AnchoredSystemPath) -> Result<u64, DaemonError> {
+        let mut req = proto::WarmRequest {
+            path: path.to_string(),
+        }
+        .into_request();
+        req.set_timeout(WARM_TIMEOUT);
+        Ok(self.client.warm(req).await?.into_inner().file_count)
+    }
+
+    /// Streams the daemon's debug logs for `duration`, after which the
+    /// stream ends.
+    pub async fn debug_log(
+        &mut self,
+        duration: Duration,
+    ) -> Result<tonic::codec::Streaming<proto::DebugLogLine>, DaemonError> {
+        let response = self
+            .client
+            .debug_log(proto::DebugLogRequest {
+                duration_ms: duration.as_millis() as u64,
+            })
+            .await?
+            .into_inner();
+        Ok(response)
+    }
+
+    /// Asks the daemon for the turbo environment variables it was started
+    /// with, by name.
+    pub async fn env(&mut self) -> Result<BTreeMap<String, String>, DaemonError> {
+        Ok(self
+            .client
+            .env(proto::EnvRequest {})
+            .await?
+            .into_inner()
+            .vars
+            .into_iter()
+            .collect())
+    }
+
+    /// Keeps the daemon from shutting down when idle until the returned lease
+    /// is released. `holder` says who holds it.
+    pub async fn acquire_lease(&mut self, holder: String) -> Result<u64, DaemonError> {
+        Ok(self
+            .client
+            .acquire_lease(proto::AcquireLeaseRequest { holder })
+            .await?
+            .into_inner()
+            .lease_id)
+    }
+
+    /// Releases a lease, returning whether it was still held.
+    pub async fn release_lease(&mut self, lease_id: u64) -> Result<bool, DaemonError> {
+        Ok(self
+            .client
+            .release_lease(proto::ReleaseLeaseRequest { lease_id })
+            .await?
+            .into_inner()
+            .released)
+    }
+
+    /// Releases every lease held on the daemon, returning how many there
+    /// were.
+    pub async fn release_leases(&mut self) -> Result<u32, DaemonError> {
+        Ok(self
+            .client
+            .release_leases(proto::ReleaseLeasesRequest {})
+            .await?
+            .into_inner()
+            .released)
+    }
+
+    /// Makes the daemon re-read turbo.json, without dropping connections.
+    pub async fn reload(&mut self) -> Result<proto::ReloadResponse, DaemonError> {
+        Ok(self
+            .client
+            .reload(proto::ReloadRequest {})
+            .await?
+            .into_inner())
+    }
+
+    /// List the globs the daemon is watching, with the number of files each
+    /// currently matches.
+    pub async fn watches(&mut self) -> Result<Vec<proto::WatchedGlob>, DaemonError> {
+        Ok(self
+            .client
+            .watches(proto::WatchesRequest {})
+            .await?
+            .into_inner()
+            .globs)
+    }
+
     pub async fn discover_packages(&mut self) -> Result<DiscoverPackagesResponse, DaemonError> {
         let req = proto::DiscoverPackagesRequest {};
         let mut req = req.into_request();
@@ -205,6 +351,9 @@ pub enum DaemonError {
     /// The server was connected but is now unavailable.
     #[error("server is unavailable: {0}")]
     Unavailable(String),
+    /// The server is read-only and refused a request that would change it.
+    #[error("server is read-only: {0}")]
+    ReadOnly(String),
     #[error("error opening socket: {0}")]
     SocketOpen(#[from] SocketOpenError),
     /// The server is running a different version of turborepo.
@@ -226,10 +375,45 @@ pub enum DaemonError {
     #[error("invalid timeout specified ({0})")]
     #[allow(dead_code)]
     InvalidTimeout(String),
+    /// The checkpoint interval specified was invalid.
+    #[error("invalid checkpoint interval specified ({0})")]
+    InvalidCheckpointInterval(String),
+    /// The idle compaction delay specified was invalid.
+    #[error("invalid idle compaction delay specified ({0})")]
+    InvalidIdleCompaction(String),
+    /// The idle granularity specified was invalid.
+    #[error("invalid idle granularity specified ({0})")]
+    InvalidIdleGranularity(String),
+    /// The watcher poll fallback interval specified was invalid.
+    #[error("invalid watcher poll fallback interval specified ({0})")]
+    InvalidWatcherPollFallback(String),
+    /// The debug log duration specified was invalid.
+    #[error("invalid debug log duration specified ({0})")]
+    InvalidDebugLogDuration(String),
+    /// The CPU affinity specified was invalid.
+    #[error("invalid CPU affinity specified: {0}")]
+    InvalidCpuAffinity(String),
+    /// The memory limit specified was invalid.
+    #[error("invalid memory limit specified: {0}")]
+    InvalidMemoryLimit(String),
+    /// The scope specified was not a directory within the repository.
+    #[error("invalid daemon scope: {0}")]
+    InvalidScope(String),
+    /// The runtime for the configured number of worker threads couldn't be
+    /// started.
+    #[error("unable to start the daemon runtime: {0}")]
+    Runtime(io::Error),
+    /// The runtime for the configured number of worker threads stopped
+    /// without the server reporting why, e.g. because it panicked.
+    #[error("the daemon runtime exited without a close reason")]
+    RuntimeExited,
     /// The server is unable to start file watching.
     #[error("unable to start file watching")]
     SetupFileWatching(#[from] HashGlobSetupError),
 
+    #[error("unable to replay requests: {0}")]
+    Replay(#[from] ReplayError),
+
     #[error("unable to display output: {0}")]
     DisplayError(#[from] serde_json::Error),
 
@@ -239,9 +423,15 @@ pub enum DaemonError {
     #[error("unable to complete daemon clean")]
     CleanFailed,
 
+    #[error("unable to access watches snapshot {1}: {0}")]
+    WatchesSnapshot(io::Error, AbsoluteSystemPathBuf),
+
     #[error("failed to setup cookie dir {1}: {0}")]
     CookieDir(io::Error, AbsoluteSystemPathBuf),
 
+    #[error("unable to audit socket {1}: {0}")]
+    Audit(io::Error, AbsoluteSystemPathBuf),
+
     #[error("failed to determine package manager: {0}")]
     PackageManager(#[from] turborepo_repository::package_manager::Error),
 
@@ -250,6 +440,9 @@ pub enum DaemonError {
 
     #[error("could not find log file")]
     LogFileNotFound,
+
+    #[error("invalid path: {0}")]
+    Path(#[from] turbopath::PathError),
 }
 
 impl From<Status> for DaemonError {
@@ -260,6 +453,7 @@ impl From<Status> for DaemonError {
             }
             Code::Unimplemented => DaemonError::VersionMismatch(None),
             Code::Unavailable => DaemonError::Unavailable(status.message().to_string()),
+            Code::PermissionDenied => DaemonError::ReadOnly(status.message().to_string()),
             c => DaemonError::GrpcFailure(c),
         }
     }
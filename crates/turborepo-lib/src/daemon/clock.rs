@@ -0,0 +1,173 @@
+//! A startup self-check of the monotonic clock. The idle timeout relies on it,
+//! and on some virtualized hosts it misbehaves, so a warning in the log helps
+//! explain a daemon that shuts down too early or not at all.
+
+use std::{
+    fmt,
+    time::{Duration, Instant, SystemTime},
+};
+
+use tracing::{debug, warn};
+
+/// Where the self-check reads the time from.
+pub trait ClockSource: Send + 'static {
+    /// A reading of the monotonic clock.
+    fn monotonic(&mut self) -> Instant;
+    /// A reading of the wall clock, which the monotonic clock is compared to.
+    fn wall(&mut self) -> SystemTime;
+}
+
+/// The clocks of the host.
+pub struct SystemClock;
+
+impl ClockSource for SystemClock {
+    fn monotonic(&mut self) -> Instant {
+        tokio::time::Instant::now().into_std()
+    }
+
+    fn wall(&mut self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A way in which the monotonic clock appears to misbehave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockProblem {
+    /// The second reading was earlier than the first.
+    NonMonotonic { backwards_by: Duration },
+    /// Less than half the wall clock time passed on the monotonic clock.
+    Slow { monotonic: Duration, wall: Duration },
+}
+
+impl fmt::Display for ClockProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClockProblem::NonMonotonic { backwards_by } => {
+                write!(f, "the monotonic clock went backwards by {backwards_by:?}")
+            }
+            ClockProblem::Slow { monotonic, wall } => write!(
+                f,
+                "the monotonic clock advanced {monotonic:?} while the wall clock advanced {wall:?}"
+            ),
+        }
+    }
+}
+
+/// Reads `clock` twice, `pause` apart, and warns if the monotonic clock
+/// appears to misbehave, since the idle timeout will then be unreliable.
+/// Returns the problem that was warned about, if any.
+pub async fn self_check(mut clock: impl ClockSource, pause: Duration) -> Option<ClockProblem> {
+    let (monotonic_start, wall_start) = (clock.monotonic(), clock.wall());
+    tokio::time::sleep(pause).await;
+    let (monotonic_end, wall_end) = (clock.monotonic(), clock.wall());
+
+    let problem = match monotonic_end.checked_duration_since(monotonic_start) {
+        None => Some(ClockProblem::NonMonotonic {
+            backwards_by: monotonic_start - monotonic_end,
+        }),
+        Some(monotonic) => {
+            // a wall clock that was set back tells us nothing about the
+            // monotonic one
+            let wall = wall_end.duration_since(wall_start).unwrap_or_default();
+            (monotonic < wall / 2).then_some(ClockProblem::Slow { monotonic, wall })
+        }
+    };
+
+    match problem {
+        Some(problem) => warn!(
+            "clock self-check failed, idle shutdown may happen too early or too late: {}",
+            problem
+        ),
+        None => debug!("clock self-check passed"),
+    }
+    problem
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::VecDeque,
+        time::{Duration, Instant, SystemTime},
+    };
+
+    use super::{self_check, ClockProblem, ClockSource};
+
+    /// Replays the given readings of each clock.
+    struct FakeClock {
+        monotonic: VecDeque<Instant>,
+        wall: VecDeque<SystemTime>,
+    }
+
+    impl FakeClock {
+        fn new(monotonic: [Duration; 2], wall: [Duration; 2]) -> Self {
+            let (base, wall_base) = (Instant::now(), SystemTime::UNIX_EPOCH);
+            Self {
+                monotonic: monotonic.iter().map(|offset| base + *offset).collect(),
+                wall: wall.iter().map(|offset| wall_base + *offset).collect(),
+            }
+        }
+    }
+
+    impl ClockSource for FakeClock {
+        fn monotonic(&mut self) -> Instant {
+            self.monotonic.pop_front().expect("no more readings")
+        }
+
+        fn wall(&mut self) -> SystemTime {
+            self.wall.pop_front().expect("no more readings")
+        }
+    }
+
+    const PAUSE: Duration = Duration::from_millis(1);
+
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn test_non_monotonic() {
+        let clock = FakeClock::new(
+            [Duration::from_secs(10), Duration::from_secs(7)],
+            [Duration::ZERO, Duration::from_secs(1)],
+        );
+        assert_eq!(
+            self_check(clock, PAUSE).await,
+            Some(ClockProblem::NonMonotonic {
+                backwards_by: Duration::from_secs(3)
+            })
+        );
+        assert!(logs_contain(
+            "clock self-check failed, idle shutdown may happen too early or too late: the \
+             monotonic clock went backwards by 3s"
+        ));
+    }
+
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn test_slow() {
+        let clock = FakeClock::new(
+            [Duration::ZERO, Duration::from_millis(100)],
+            [Duration::ZERO, Duration::from_secs(1)],
+        );
+        assert_eq!(
+            self_check(clock, PAUSE).await,
+            Some(ClockProblem::Slow {
+                monotonic: Duration::from_millis(100),
+                wall: Duration::from_secs(1)
+            })
+        );
+        assert!(logs_contain("clock self-check failed"));
+    }
+
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn test_healthy() {
+        let clock = FakeClock::new(
+            [Duration::ZERO, Duration::from_secs(1)],
+            [Duration::from_secs(5), Duration::ZERO],
+        );
+        assert_eq!(
+            self_check(clock, PAUSE).await,
+            None,
+            "a wall clock set back is ignored"
+        );
+        assert!(!logs_contain("clock self-check failed"));
+    }
+}
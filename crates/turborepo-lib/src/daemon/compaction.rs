@@ -0,0 +1,213 @@
+//! Compacting the daemon's on-disk cache while it is idle, so that it
+//! doesn't fragment over a long-running daemon's lifetime without getting in
+//! the way of requests.
+//!
+//! Idleness comes from the daemon's [`BumpTimeout`], which every request
+//! resets. A cache is compacted at most once per idle period, and compaction
+//! runs on the blocking pool rather than on a worker thread.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tracing::{info, warn};
+use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf};
+
+use super::{bump_timeout::BumpTimeout, checkpoint::Checkpoint};
+
+/// The outcome of compacting a cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compaction {
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+impl Compaction {
+    pub fn reclaimed_bytes(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
+}
+
+/// A cache that can be compacted.
+pub trait Compact: Send + Sync + 'static {
+    fn compact(&self) -> std::io::Result<Compaction>;
+}
+
+/// Compacts the checkpoint by taking a fresh one, which replaces the
+/// previous file whole and clears away a temporary file left behind by an
+/// interrupted write.
+pub struct CheckpointCompaction<F> {
+    file: AbsoluteSystemPathBuf,
+    take_checkpoint: F,
+}
+
+impl<F> CheckpointCompaction<F> {
+    pub fn new(file: AbsoluteSystemPathBuf, take_checkpoint: F) -> Self {
+        Self {
+            file,
+            take_checkpoint,
+        }
+    }
+
+    fn size(&self) -> u64 {
+        let file_size = |path: &AbsoluteSystemPath| {
+            std::fs::metadata(path.as_std_path()).map_or(0, |metadata| metadata.len())
+        };
+        file_size(&self.file) + file_size(&Checkpoint::tmp_file(&self.file))
+    }
+}
+
+impl<F: Fn() + Send + Sync + 'static> Compact for CheckpointCompaction<F> {
+    fn compact(&self) -> std::io::Result<Compaction> {
+        let bytes_before = self.size();
+        (self.take_checkpoint)();
+        Ok(Compaction {
+            bytes_before,
+            bytes_after: self.size(),
+        })
+    }
+}
+
+/// Compacts a cache once the daemon has been idle for long enough.
+pub struct IdleCompactor {
+    cache: Arc<dyn Compact>,
+    timeout: Arc<BumpTimeout>,
+    idle_after: Duration,
+    /// When the idle period that was last compacted began, as reported by
+    /// [`BumpTimeout::last_reset`].
+    compacted_period: Mutex<Option<Duration>>,
+    last: Arc<Mutex<Option<Compaction>>>,
+}
+
+impl IdleCompactor {
+    pub fn new(cache: impl Compact, timeout: Arc<BumpTimeout>, idle_after: Duration) -> Self {
+        Self {
+            cache: Arc::new(cache),
+            timeout,
+            idle_after,
+            compacted_period: Mutex::new(None),
+            last: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// The most recent successful compaction, for reporting in the status.
+    pub fn last(&self) -> Arc<Mutex<Option<Compaction>>> {
+        self.last.clone()
+    }
+
+    /// Compacts the cache if the daemon has been idle for `idle_after` and
+    /// the cache hasn't already been compacted since it was last active.
+    /// Returns the compaction, if one ran and succeeded.
+    pub async fn compact_if_idle(&self) -> Option<Compaction> {
+        if self.timeout.idle_for() < self.idle_after {
+            return None;
+        }
+        {
+            let period = self.timeout.last_reset();
+            let mut compacted_period = self
+                .compacted_period
+                .lock()
+                .expect("compacted period lock poisoned");
+            if *compacted_period == Some(period) {
+                return None;
+            }
+            *compacted_period = Some(period);
+        }
+
+        let cache = self.cache.clone();
+        match tokio::task::spawn_blocking(move || cache.compact()).await {
+            Ok(Ok(compaction)) => {
+                info!(
+                    "compacted the cache while idle, reclaiming {} bytes ({} -> {})",
+                    compaction.reclaimed_bytes(),
+                    compaction.bytes_before,
+                    compaction.bytes_after
+                );
+                *self.last.lock().expect("last compaction lock poisoned") = Some(compaction);
+                Some(compaction)
+            }
+            Ok(Err(e)) => {
+                warn!("failed to compact the cache: {}", e);
+                None
+            }
+            Err(e) => {
+                warn!("compacting the cache panicked: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Checks whether to compact every `poll_interval`, until the task is
+    /// aborted.
+    pub async fn run(self, poll_interval: Duration) {
+        let mut polls = tokio::time::interval(poll_interval);
+        polls.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            polls.tick().await;
+            self.compact_if_idle().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use super::{Compact, Compaction, IdleCompactor};
+    use crate::daemon::bump_timeout::BumpTimeout;
+
+    struct MockCache(Arc<AtomicUsize>);
+
+    impl Compact for MockCache {
+        fn compact(&self) -> std::io::Result<Compaction> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(Compaction {
+                bytes_before: 4096,
+                bytes_after: 1024,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compact_only_when_idle() {
+        let compactions = Arc::new(AtomicUsize::new(0));
+        let timeout = Arc::new(BumpTimeout::new(Duration::from_secs(60 * 60)));
+        let compactor = IdleCompactor::new(
+            MockCache(compactions.clone()),
+            timeout.clone(),
+            Duration::from_millis(50),
+        );
+
+        timeout.reset();
+        assert_eq!(
+            compactor.compact_if_idle().await,
+            None,
+            "the daemon is active"
+        );
+        assert_eq!(compactions.load(Ordering::SeqCst), 0);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let compaction = compactor.compact_if_idle().await.unwrap();
+        assert_eq!(compaction.reclaimed_bytes(), 3072);
+        assert_eq!(*compactor.last().lock().unwrap(), Some(compaction));
+        assert_eq!(
+            compactor.compact_if_idle().await,
+            None,
+            "already compacted during this idle period"
+        );
+        assert_eq!(compactions.load(Ordering::SeqCst), 1);
+
+        timeout.reset();
+        assert_eq!(compactor.compact_if_idle().await, None);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(compactor.compact_if_idle().await.is_some());
+        assert_eq!(compactions.load(Ordering::SeqCst), 2);
+    }
+}
@@ -11,11 +11,16 @@ use pidlock::PidFileError;
 use sysinfo::{Pid, ProcessExt, ProcessRefreshKind, RefreshKind, SystemExt};
 use thiserror::Error;
 use tokio::{sync::mpsc, time::timeout};
-use tonic::transport::Endpoint;
+use tonic::{codec::CompressionEncoding, transport::Endpoint};
 use tracing::debug;
 use turbopath::AbsoluteSystemPath;
 
-use super::{proto::turbod_client::TurbodClient, DaemonClient, Paths};
+use super::{
+    clock::SystemClock,
+    proto::turbod_client::TurbodClient,
+    start_bench::{StartBreakdown, StartTimer},
+    DaemonClient, Paths,
+};
 use crate::daemon::DaemonError;
 
 #[derive(Error, Debug)]
@@ -57,6 +62,18 @@ pub enum ForkError {
     Spawn(#[from] std::io::Error),
 }
 
+/// A failed attempt to connect to the daemon, as reported to the observer
+/// passed to [`DaemonConnector::connect_with_observer`].
+#[derive(Debug)]
+pub struct ConnectAttempt<'a> {
+    /// Which attempt failed, starting from 1.
+    pub attempt: usize,
+    /// Why the attempt failed.
+    pub error: &'a DaemonConnectorError,
+    /// How long the connector waits before the next attempt.
+    pub delay: Duration,
+}
+
 #[derive(Debug, Clone)]
 pub struct DaemonConnector {
     /// Whether the connector is allowed to start a daemon if it is not already
@@ -99,19 +116,36 @@ impl DaemonConnector {
     /// 1. the versions do not match
     /// 2. the server is not running
     /// 3. the server is unresponsive
-    #[tracing::instrument(skip(self))]
     pub async fn connect(self) -> Result<DaemonClient<DaemonConnector>, DaemonConnectorError> {
+        self.connect_with_observer(|_| {}).await
+    }
+
+    /// Like [`DaemonConnector::connect`], but calls `observer` with every
+    /// failed attempt that is going to be retried, which is useful when
+    /// debugging a daemon that is slow to start.
+    #[tracing::instrument(skip(self, observer))]
+    pub async fn connect_with_observer(
+        mut self,
+        mut observer: impl FnMut(ConnectAttempt<'_>),
+    ) -> Result<DaemonClient<DaemonConnector>, DaemonConnectorError> {
         let time = Instant::now();
-        for _ in 0..Self::CONNECT_RETRY_MAX {
+        for attempt in 1..=Self::CONNECT_RETRY_MAX {
             let pid = self.get_or_start_daemon().await?;
             debug!("got daemon with pid: {}", pid);
 
-            let conn = match self.get_connection(self.paths.sock_file.clone()).await {
-                Err(DaemonConnectorError::Watcher(_)) => continue,
-                Err(DaemonConnectorError::Socket(e)) => {
+            let conn = match self.get_connection().await {
+                Err(
+                    error @ (DaemonConnectorError::Watcher(_) | DaemonConnectorError::Socket(_)),
+                ) => {
                     // assume the server is not yet ready
-                    debug!("socket error: {}", e);
-                    tokio::time::sleep(DaemonConnector::SOCKET_ERROR_WAIT).await;
+                    debug!("unable to connect: {}", error);
+                    let delay = Self::retry_delay(attempt);
+                    observer(ConnectAttempt {
+                        attempt,
+                        error: &error,
+                        delay,
+                    });
+                    tokio::time::sleep(delay).await;
                     continue;
                 }
                 rest => rest?,
@@ -123,13 +157,26 @@ impl DaemonConnector {
                 Ok(_) => {
                     return {
                         debug!("connected in {}µs", time.elapsed().as_micros());
+                        self.paths.sock_file = self.sock_file();
                         Ok(client.with_connect_settings(self))
                     }
                 }
-                Err(DaemonError::VersionMismatch(_)) if self.can_kill_server => {
+                Err(error @ DaemonError::VersionMismatch(_)) if self.can_kill_server => {
+                    observer(ConnectAttempt {
+                        attempt,
+                        error: &DaemonConnectorError::Handshake(Box::new(error)),
+                        delay: Duration::ZERO,
+                    });
                     self.kill_live_server(client, pid).await?
                 }
-                Err(DaemonError::Unavailable(_)) => self.kill_dead_server(pid).await?,
+                Err(error @ DaemonError::Unavailable(_)) => {
+                    observer(ConnectAttempt {
+                        attempt,
+                        error: &DaemonConnectorError::Handshake(Box::new(error)),
+                        delay: Duration::ZERO,
+                    });
+                    self.kill_dead_server(pid).await?
+                }
                 Err(e) => return Err(DaemonConnectorError::Handshake(Box::new(e))),
             };
         }
@@ -139,6 +186,42 @@ impl DaemonConnector {
         ))
     }
 
+    /// Starts a fresh daemon once any running one has finished shutting
+    /// down, and connects to it, timing each phase of the start. See
+    /// [`start_bench`](super::start_bench).
+    pub async fn cold_start(
+        self,
+    ) -> Result<(DaemonClient<DaemonConnector>, StartBreakdown), DaemonConnectorError> {
+        // otherwise, we would time connecting to a daemon that is stopping
+        timeout(
+            Self::SHUTDOWN_TIMEOUT,
+            wait_for_file(&self.paths.pid_file, WaitAction::Deleted),
+        )
+        .await??;
+        // a socket left behind would look like it was bound right away
+        self.sock_file().remove_file().ok();
+
+        let mut timer = StartTimer::start(SystemClock);
+        self.start_daemon().await?;
+        let spawn = timer.lap();
+        // windows doesn't treat sockets as files, so binding is timed as part
+        // of getting ready
+        #[cfg(not(target_os = "windows"))]
+        self.wait_for_socket().await?;
+        let socket_bind = timer.lap();
+        let client = self.connect().await?;
+        let ready = timer.lap();
+
+        Ok((client, StartBreakdown::new(spawn, socket_bind, ready)))
+    }
+
+    /// How long to wait before retrying after a failed attempt to reach the
+    /// socket. This doubles with each attempt, to give a slow daemon more
+    /// time to start listening.
+    fn retry_delay(attempt: usize) -> Duration {
+        Self::SOCKET_ERROR_WAIT * 2u32.saturating_pow(attempt.saturating_sub(1) as u32)
+    }
+
     /// Gets the PID of the daemon process.
     ///
     /// If a daemon is not running, it starts one.
@@ -154,20 +237,24 @@ impl DaemonConnector {
             }
             None if self.can_start_server => {
                 debug!("no pid found, starting daemon");
-                Self::start_daemon().await
+                self.start_daemon().await
             }
             None => Err(DaemonConnectorError::NotRunning),
         }
     }
 
     /// Starts the daemon process, returning its PID.
-    async fn start_daemon() -> Result<sysinfo::Pid, DaemonConnectorError> {
+    async fn start_daemon(&self) -> Result<sysinfo::Pid, DaemonConnectorError> {
         let binary_path =
             std::env::current_exe().map_err(|e| DaemonConnectorError::Fork(e.into()))?;
         // this creates a new process group for the given command
         // in a cross platform way, directing all output to /dev/null
         let mut group = tokio::process::Command::new(binary_path)
             .arg("--skip-infer")
+            // the daemon serves the root it was started for, which is not
+            // necessarily the directory we were run from
+            .arg("--cwd")
+            .arg(self.paths.repo_root.as_str())
             .arg("daemon")
             .stderr(Stdio::null())
             .stdout(Stdio::null())
@@ -183,19 +270,43 @@ impl DaemonConnector {
             .ok_or(DaemonConnectorError::Fork(ForkError::Exited))
     }
 
-    /// Gets a connection to given path
+    /// Gets the path of the socket that the running daemon is listening on.
     ///
-    /// On Windows the socket file cannot be interacted with via any filesystem
-    /// apis, due to this we need to just naively attempt to connect on that
-    /// platform and retry in case of error.
+    /// The daemon records this next to its pid file, since the socket may have
+    /// been relocated with `--socket-dir`. If there is no record, for example
+    /// because the daemon predates it, we assume the default location.
+    pub fn sock_file(&self) -> turbopath::AbsoluteSystemPathBuf {
+        self.paths
+            .sock_path_file
+            .read_to_string()
+            .ok()
+            .and_then(|path| turbopath::AbsoluteSystemPathBuf::new(path.trim()).ok())
+            .unwrap_or_else(|| self.paths.sock_file.clone())
+    }
+
+    /// Gets a connection to the daemon's socket
     #[tracing::instrument(skip(self))]
     async fn get_connection(
         &self,
-        path: turbopath::AbsoluteSystemPathBuf,
     ) -> Result<TurbodClient<tonic::transport::Channel>, DaemonConnectorError> {
+        self.channel()
+            .await
+            // servers that don't compress ignore this, and reply uncompressed
+            .map(|channel| TurbodClient::new(channel).accept_compressed(CompressionEncoding::Gzip))
+    }
+
+    /// Opens a channel to the daemon's socket, without checking that the
+    /// daemon is compatible with us, for sending raw requests.
+    ///
+    /// On Windows the socket file cannot be interacted with via any filesystem
+    /// apis, due to this we need to just naively attempt to connect on that
+    /// platform and retry in case of error.
+    pub async fn channel(&self) -> Result<tonic::transport::Channel, DaemonConnectorError> {
         // windows doesn't treat sockets as files, so don't attempt to wait
         #[cfg(not(target_os = "windows"))]
-        self.wait_for_socket().await?;
+        let path = self.wait_for_socket().await?;
+        #[cfg(target_os = "windows")]
+        let path = self.sock_file();
 
         debug!("connecting to socket: {}", path);
         let path = Arc::new(path);
@@ -220,7 +331,6 @@ impl DaemonConnector {
             .timeout(Self::CONNECT_TIMEOUT)
             .connect_with_connector(tower::service_fn(make_service))
             .await
-            .map(TurbodClient::new)
             .map_err(DaemonConnectorError::Socket)
     }
 
@@ -277,8 +387,11 @@ impl DaemonConnector {
         }
     }
 
+    /// Waits for the daemon's socket to exist, returning its path.
     #[tracing::instrument(skip(self))]
-    async fn wait_for_socket(&self) -> Result<(), DaemonConnectorError> {
+    async fn wait_for_socket(
+        &self,
+    ) -> Result<turbopath::AbsoluteSystemPathBuf, DaemonConnectorError> {
         // Note that we don't care if this is our daemon
         // or not. We started a process, but someone else could beat
         // use to listening. That's fine, we'll check the version
@@ -289,12 +402,14 @@ impl DaemonConnector {
             wait_for_file(&self.paths.pid_file, WaitAction::Exists),
         )
         .await??;
+        // the pid file exists, so the socket location has been decided
+        let sock_file = self.sock_file();
         timeout(
             Self::SOCKET_TIMEOUT,
-            wait_for_file(&self.paths.sock_file, WaitAction::Exists),
+            wait_for_file(&sock_file, WaitAction::Exists),
         )
-        .await?
-        .map_err(Into::into)
+        .await??;
+        Ok(sock_file)
     }
 
     fn pid_lock(&self) -> pidlock::Pidlock {
@@ -409,22 +524,34 @@ enum WaitAction {
 
 #[cfg(test)]
 mod test {
-    use std::assert_matches::assert_matches;
+    use std::{assert_matches::assert_matches, io::Write};
 
     use tokio::{
         select,
-        sync::{oneshot::Sender, Mutex},
+        sync::{
+            oneshot::{Receiver, Sender},
+            Mutex,
+        },
     };
     use tokio_stream::wrappers::ReceiverStream;
     use tonic::{Request, Response, Status};
     use tower::ServiceBuilder;
     use tracing::info;
-    use turbopath::AbsoluteSystemPathBuf;
+    use tracing_subscriber::fmt::MakeWriter;
+    use turbopath::{AbsoluteSystemPathBuf, AnchoredSystemPathBuf};
 
     use super::*;
-    use crate::daemon::{
-        default_timeout_layer::DefaultTimeoutLayer,
-        proto::{self, PackageChangesRequest},
+    use crate::{
+        cli::OutputFormat,
+        commands::daemon::{render_env, render_released_leases, render_watches, render_why_alive},
+        daemon::{
+            debug_log,
+            default_timeout_layer::DefaultTimeoutLayer,
+            env,
+            lease::Leases,
+            proto::{self, PackageChangesRequest},
+        },
+        tracing::DebugLogTap,
     };
 
     #[cfg(not(target_os = "windows"))]
@@ -576,6 +703,9 @@ mod test {
 
     struct DummyServer {
         shutdown: Mutex<Option<Sender<bool>>>,
+        warmed: Mutex<Vec<String>>,
+        debug_log: DebugLogTap,
+        leases: Leases,
     }
 
     #[tonic::async_trait]
@@ -600,6 +730,9 @@ mod test {
             request: tonic::Request<proto::HelloRequest>,
         ) -> tonic::Result<tonic::Response<proto::HelloResponse>> {
             let client_version = request.into_inner().version;
+            if client_version == proto::VERSION {
+                return Ok(tonic::Response::new(proto::HelloResponse {}));
+            }
             Err(tonic::Status::failed_precondition(format!(
                 "version mismatch. Client {} Server test-version",
                 client_version
@@ -655,11 +788,198 @@ mod test {
         ) -> Result<tonic::Response<proto::GetFileHashesResponse>, tonic::Status> {
             unimplemented!()
         }
+
+        async fn reset_errors(
+            &self,
+            _req: tonic::Request<proto::ResetErrorsRequest>,
+        ) -> tonic::Result<tonic::Response<proto::ResetErrorsResponse>> {
+            unimplemented!()
+        }
+
+        async fn expire(
+            &self,
+            _req: tonic::Request<proto::ExpireRequest>,
+        ) -> tonic::Result<tonic::Response<proto::ExpireResponse>> {
+            unimplemented!()
+        }
+
+        async fn watches(
+            &self,
+            _req: tonic::Request<proto::WatchesRequest>,
+        ) -> tonic::Result<tonic::Response<proto::WatchesResponse>> {
+            Ok(tonic::Response::new(proto::WatchesResponse {
+                globs: vec![
+                    proto::WatchedGlob {
+                        glob: "apps/web/.next/**".to_string(),
+                        file_count: 12,
+                    },
+                    proto::WatchedGlob {
+                        glob: "packages/ui/dist/**".to_string(),
+                        file_count: 1,
+                    },
+                ],
+            }))
+        }
+
+        async fn reload(
+            &self,
+            _req: tonic::Request<proto::ReloadRequest>,
+        ) -> tonic::Result<tonic::Response<proto::ReloadResponse>> {
+            unimplemented!()
+        }
+
+        async fn trace(
+            &self,
+            _req: tonic::Request<proto::TraceRequest>,
+        ) -> tonic::Result<tonic::Response<proto::TraceResponse>> {
+            unimplemented!()
+        }
+
+        async fn why_alive(
+            &self,
+            _req: tonic::Request<proto::WhyAliveRequest>,
+        ) -> tonic::Result<tonic::Response<proto::WhyAliveResponse>> {
+            Ok(tonic::Response::new(proto::WhyAliveResponse {
+                idle_timeout_ms: 4 * 60 * 60 * 1000,
+                idle_for_ms: 30_000,
+                remaining_ms: (4 * 60 * 60 - 30) * 1000,
+                lease_holders: self.leases.holders(),
+                connected_clients: 1,
+            }))
+        }
+
+        async fn config(
+            &self,
+            _req: tonic::Request<proto::ConfigRequest>,
+        ) -> tonic::Result<tonic::Response<proto::ConfigResponse>> {
+            unimplemented!()
+        }
+
+        async fn warm(
+            &self,
+            req: tonic::Request<proto::WarmRequest>,
+        ) -> tonic::Result<tonic::Response<proto::WarmResponse>> {
+            self.warmed.lock().await.push(req.into_inner().path);
+            Ok(tonic::Response::new(proto::WarmResponse { file_count: 3 }))
+        }
+
+        type DebugLogStream = debug_log::DebugLogStream;
+        async fn debug_log(
+            &self,
+            req: tonic::Request<proto::DebugLogRequest>,
+        ) -> tonic::Result<tonic::Response<Self::DebugLogStream>> {
+            let window = Duration::from_millis(req.into_inner().duration_ms);
+            Ok(tonic::Response::new(debug_log::stream(
+                self.debug_log.subscribe(),
+                window,
+            )))
+        }
+
+        async fn env(
+            &self,
+            _req: tonic::Request<proto::EnvRequest>,
+        ) -> tonic::Result<tonic::Response<proto::EnvResponse>> {
+            let captured = env::capture(
+                [
+                    ("TURBO_DAEMON_LOG_DIR", "/tmp/turbo-logs"),
+                    ("TURBO_TOKEN", "secret"),
+                    ("HOME", "/home/turbo"),
+                ]
+                .map(|(key, value)| (key.to_string(), value.to_string())),
+            );
+            Ok(tonic::Response::new(proto::EnvResponse {
+                vars: captured.into_iter().collect(),
+            }))
+        }
+
+        async fn acquire_lease(
+            &self,
+            req: tonic::Request<proto::AcquireLeaseRequest>,
+        ) -> tonic::Result<tonic::Response<proto::AcquireLeaseResponse>> {
+            let lease_id = self.leases.acquire(req.into_inner().holder);
+            Ok(tonic::Response::new(proto::AcquireLeaseResponse {
+                lease_id,
+            }))
+        }
+
+        async fn release_lease(
+            &self,
+            req: tonic::Request<proto::ReleaseLeaseRequest>,
+        ) -> tonic::Result<tonic::Response<proto::ReleaseLeaseResponse>> {
+            let released = self.leases.release(req.into_inner().lease_id);
+            Ok(tonic::Response::new(proto::ReleaseLeaseResponse {
+                released,
+            }))
+        }
+
+        async fn release_leases(
+            &self,
+            _req: tonic::Request<proto::ReleaseLeasesRequest>,
+        ) -> tonic::Result<tonic::Response<proto::ReleaseLeasesResponse>> {
+            Ok(tonic::Response::new(proto::ReleaseLeasesResponse {
+                released: self.leases.release_all() as u32,
+            }))
+        }
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[tokio::test]
+    async fn reports_failed_attempts() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let repo_root = AbsoluteSystemPathBuf::try_from(tmp_dir.path()).unwrap();
+        let connector = DaemonConnector::new(false, false, &repo_root);
+
+        // a running 'daemon' whose socket nobody is listening on yet
+        connector.paths.pid_file.ensure_dir().unwrap();
+        connector
+            .paths
+            .pid_file
+            .create_with_contents(std::process::id().to_string())
+            .unwrap();
+        connector.paths.sock_file.ensure_dir().unwrap();
+        connector.paths.sock_file.create_with_contents("").unwrap();
+
+        let sock_file = connector.paths.sock_file.clone();
+        let mut server = Some(DummyServer::new().0);
+        let mut failures = vec![];
+
+        let client = connector
+            .connect_with_observer(|attempt| {
+                assert_matches!(attempt.error, DaemonConnectorError::Socket(_));
+                failures.push((attempt.attempt, attempt.delay));
+
+                // start listening after the second failure so that the third
+                // attempt succeeds
+                if failures.len() == 2 {
+                    sock_file.remove_file().unwrap();
+                    let listener = tokio::net::UnixListener::bind(sock_file.as_path()).unwrap();
+                    tokio::spawn(
+                        tonic::transport::Server::builder()
+                            .add_service(proto::turbod_server::TurbodServer::new(
+                                server.take().unwrap(),
+                            ))
+                            .serve_with_incoming(tokio_stream::wrappers::UnixListenerStream::new(
+                                listener,
+                            )),
+                    );
+                }
+            })
+            .await;
+
+        assert_matches!(client, Ok(_));
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures[0].0, 1);
+        assert_eq!(failures[1].0, 2);
+        assert!(
+            failures[0].1 < failures[1].1,
+            "delays should increase: {:?}",
+            failures
+        );
     }
 
     #[tokio::test]
     async fn handles_kill_live_server() {
-        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+        let (server, shutdown_rx) = DummyServer::new();
 
         let (tx, mut rx) = tokio::sync::mpsc::channel(1);
 
@@ -670,11 +990,9 @@ mod test {
             }
         };
 
-        let service = ServiceBuilder::new().layer(DefaultTimeoutLayer).service(
-            proto::turbod_server::TurbodServer::new(DummyServer {
-                shutdown: Mutex::new(Some(shutdown_tx)),
-            }),
-        );
+        let service = ServiceBuilder::new()
+            .layer(DefaultTimeoutLayer)
+            .service(proto::turbod_server::TurbodServer::new(server));
 
         let server_fut = tonic::transport::Server::builder()
             .add_service(service)
@@ -729,4 +1047,169 @@ mod test {
             "shutdown should have been received"
         )
     }
+
+    impl DummyServer {
+        /// A server with nothing warmed, logged or leased, along with the
+        /// receiver its shutdown is signalled on.
+        fn new() -> (Self, Receiver<bool>) {
+            let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+            let server = DummyServer {
+                shutdown: Mutex::new(Some(shutdown_tx)),
+                warmed: Mutex::default(),
+                debug_log: DebugLogTap::new(),
+                leases: Leases::default(),
+            };
+            (server, shutdown_rx)
+        }
+    }
+
+    /// Serves `server` over an in-memory connection.
+    async fn connect_dummy(server: Arc<DummyServer>) -> DaemonClient<()> {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let stream = async_stream::stream! {
+            while let Some(item) = rx.recv().await {
+                yield item;
+            }
+        };
+        let service = ServiceBuilder::new()
+            .layer(DefaultTimeoutLayer)
+            .service(proto::turbod_server::TurbodServer::from_arc(server.clone()));
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(service)
+                .serve_with_incoming(stream),
+        );
+
+        let client = Endpoint::try_from("http://[::]:50051")
+            .expect("this is a valid uri")
+            .connect_with_connector(tower::service_fn(move |_| {
+                let tx = tx.clone();
+                async move {
+                    let (client, server) = tokio::io::duplex(1024);
+                    let server: Result<_, anyhow::Error> = Ok(server);
+                    let client: Result<_, anyhow::Error> = Ok(client);
+                    tx.send(server).await.unwrap();
+                    client
+                }
+            }))
+            .await
+            .map(TurbodClient::new)
+            .unwrap();
+        DaemonClient::new(client)
+    }
+
+    #[tokio::test]
+    async fn warm_reports_completion() {
+        let server = Arc::new(DummyServer::new().0);
+        let mut client = connect_dummy(server.clone()).await;
+
+        let path = AnchoredSystemPathBuf::from_raw("packages/app").unwrap();
+        assert_eq!(client.warm(&path).await.unwrap(), 3);
+        assert_eq!(*server.warmed.lock().await, vec![path.to_string()]);
+    }
+
+    #[tokio::test]
+    async fn debug_log_stops_after_window() {
+        let server = Arc::new(DummyServer::new().0);
+        let mut client = connect_dummy(server.clone()).await;
+
+        let mut lines = client.debug_log(Duration::from_millis(300)).await.unwrap();
+        assert!(server.debug_log.is_subscribed());
+        server
+            .debug_log
+            .make_writer()
+            .write_all(b"DEBUG turborepo_lib: hashing packages\n")
+            .unwrap();
+        assert_eq!(
+            lines.message().await.unwrap().unwrap().line,
+            "DEBUG turborepo_lib: hashing packages"
+        );
+
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        server
+            .debug_log
+            .make_writer()
+            .write_all(b"logged after the window\n")
+            .unwrap();
+        assert!(lines.message().await.unwrap().is_none());
+        assert!(
+            !server.debug_log.is_subscribed(),
+            "the server stopped streaming"
+        );
+    }
+
+    #[tokio::test]
+    async fn env_is_rendered_redacted() {
+        let server = Arc::new(DummyServer::new().0);
+        let mut client = connect_dummy(server).await;
+
+        let captured = client.env().await.unwrap();
+        let plain =
+            render_env(&captured, OutputFormat::Plain, turborepo_ui::UI::new(true)).unwrap();
+        assert_eq!(
+            plain.lines().collect::<Vec<_>>(),
+            vec![
+                "TURBO_DAEMON_LOG_DIR=/tmp/turbo-logs",
+                "TURBO_TOKEN=<redacted>"
+            ]
+        );
+        assert!(!plain.contains("secret"));
+    }
+
+    #[tokio::test]
+    async fn watches_are_rendered() {
+        let server = Arc::new(DummyServer::new().0);
+        let mut client = connect_dummy(server).await;
+
+        let globs = client.watches().await.unwrap();
+        assert_eq!(
+            render_watches(&globs, turborepo_ui::UI::new(true))
+                .lines()
+                .collect::<Vec<_>>(),
+            [
+                "apps/web/.next/**    12 files",
+                "packages/ui/dist/**  1 file",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn leases_are_released() {
+        let server = Arc::new(DummyServer::new().0);
+        server.leases.acquire("crashed".to_string());
+        server
+            .leases
+            .acquire("turbo daemon pin (pid 123)".to_string());
+        let mut client = connect_dummy(server.clone()).await;
+
+        let released = client.release_leases().await.unwrap();
+        assert_eq!(
+            render_released_leases(released, turborepo_ui::UI::new(true)),
+            "✓ released 2 leases"
+        );
+        assert!(server.leases.holders().is_empty());
+        assert_eq!(
+            render_released_leases(
+                client.release_leases().await.unwrap(),
+                turborepo_ui::UI::new(true)
+            ),
+            "✓ no leases were held"
+        );
+    }
+
+    #[tokio::test]
+    async fn why_alive_names_leases() {
+        let server = Arc::new(DummyServer::new().0);
+        server
+            .leases
+            .acquire("turbo daemon pin (pid 123)".to_string());
+        let mut client = connect_dummy(server).await;
+
+        let why_alive = client.why_alive().await.unwrap();
+        let explanation = render_why_alive(&why_alive, turborepo_ui::UI::new(true));
+        assert!(explanation.contains("it is held by 1 lease"));
+        assert!(explanation.contains("  - turbo daemon pin (pid 123)"));
+        assert!(explanation.contains("1 other client is connected"));
+        assert!(explanation.contains("shuts down in 3h 59m 30s"));
+    }
 }
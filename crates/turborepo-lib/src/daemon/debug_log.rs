@@ -0,0 +1,77 @@
+//! Streaming the daemon's debug logs to a client for a limited time, for
+//! `turbo daemon debug-log`.
+//!
+//! Lines come from the [`DebugLogTap`](crate::tracing::DebugLogTap), which
+//! only formats them while a stream is subscribed. Once the window elapses,
+//! the stream ends and drops its subscription, so the daemon goes back to
+//! logging only to its log file.
+
+use std::time::Duration;
+
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+
+use super::proto;
+use crate::tracing::DebugLogReceiver;
+
+pub type DebugLogStream = ReceiverStream<Result<proto::DebugLogLine, tonic::Status>>;
+
+/// Forwards `lines` to a stream until `window` has elapsed or the client
+/// goes away.
+pub fn stream(mut lines: DebugLogReceiver, window: Duration) -> DebugLogStream {
+    let (tx, rx) = mpsc::channel(16);
+    let deadline = tokio::time::Instant::now() + window;
+    tokio::spawn(async move {
+        loop {
+            let line = tokio::select! {
+                biased;
+                _ = tokio::time::sleep_until(deadline) => break,
+                _ = tx.closed() => break,
+                line = lines.recv() => match line {
+                    Ok(line) => line,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        format!("missed {} lines that were logged too quickly", missed)
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+            };
+            if tx.send(Ok(proto::DebugLogLine { line })).await.is_err() {
+                break;
+            }
+        }
+    });
+    ReceiverStream::new(rx)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio_stream::StreamExt;
+    use tracing::debug;
+    use tracing_subscriber::{prelude::*, Registry};
+
+    use super::stream;
+    use crate::tracing::DebugLogTap;
+
+    #[tokio::test]
+    async fn test_stream_ends_after_window() {
+        let tap = DebugLogTap::new();
+        let _subscriber = tracing::subscriber::set_default(
+            Registry::default().with(tap.layer().with_filter(tap.filter())),
+        );
+
+        debug!("logged before the stream");
+        let mut lines = stream(tap.subscribe(), Duration::from_millis(200));
+        debug!("hashing packages");
+
+        let line = lines.next().await.unwrap().unwrap().line;
+        assert!(line.contains("DEBUG"), "{}", line);
+        assert!(line.ends_with("hashing packages"), "{}", line);
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        debug!("logged after the window");
+        assert!(lines.next().await.is_none(), "the window has elapsed");
+        assert!(!tap.is_subscribed());
+    }
+}
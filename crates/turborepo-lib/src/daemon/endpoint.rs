@@ -1,11 +1,18 @@
-use std::sync::{atomic::AtomicBool, Arc};
 #[cfg(windows)]
-use std::{io::ErrorKind, sync::atomic::Ordering, time::Duration};
-
-use futures::Stream;
+use std::{io::ErrorKind, time::Duration};
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use futures::{Stream, StreamExt};
 use tokio::io::{AsyncRead, AsyncWrite};
 use tonic::transport::server::Connected;
-use tracing::{debug, trace};
+use tracing::{debug, trace, warn};
 use turbopath::AbsoluteSystemPath;
 
 #[derive(thiserror::Error, Debug)]
@@ -26,12 +33,21 @@ const WINDOWS_POLL_DURATION: Duration = Duration::from_millis(1);
 /// On windows, this will use the `uds_windows` crate, and
 /// poll the result in another thread.
 ///
+/// Once the pid lock is held, and before the socket is bound, the socket's
+/// location is recorded in `sock_path_file` so that clients can find it even
+/// if it has been relocated. A daemon that fails to take the lock leaves the
+/// record of the one that holds it alone.
+///
+/// On unix, the socket is made readable and writable only by its owner, as
+/// `turbo daemon audit` expects.
+///
 /// note: the running param is used by the windows
 ///       code path to shut down the non-blocking polling
 #[tracing::instrument]
 pub async fn listen_socket(
     pid_path: &AbsoluteSystemPath,
     sock_path: &AbsoluteSystemPath,
+    sock_path_file: &AbsoluteSystemPath,
     #[allow(unused)] running: Arc<AtomicBool>,
 ) -> Result<
     (
@@ -48,16 +64,28 @@ pub async fn listen_socket(
     lock.acquire()?;
     sock_path.remove_file().ok();
 
+    let recorded = sock_path_file
+        .ensure_dir()
+        .and_then(|_| sock_path_file.create_with_contents(sock_path.as_str()));
+    if let Err(e) = recorded {
+        warn!("failed to record socket path: {}", e);
+    }
+
     debug!("pidlock acquired at {}", pid_path);
     debug!("listening on socket at {}", sock_path);
 
     #[cfg(unix)]
     {
+        use std::os::unix::fs::PermissionsExt;
+
+        let listener = tokio::net::UnixListener::bind(sock_path)?;
+        std::fs::set_permissions(
+            sock_path.as_std_path(),
+            std::fs::Permissions::from_mode(super::audit::EXPECTED_MODE),
+        )?;
         Ok((
             lock,
-            tokio_stream::wrappers::UnixListenerStream::new(tokio::net::UnixListener::bind(
-                sock_path,
-            )?),
+            tokio_stream::wrappers::UnixListenerStream::new(listener),
         ))
     }
 
@@ -173,24 +201,121 @@ impl<T> Connected for UdsWindowsStream<T> {
     fn connect_info(&self) -> Self::ConnectInfo {}
 }
 
+/// Counts the connections from `incoming` that are still open in `open`, so
+/// that the daemon can report how many clients are connected.
+pub fn count_connections<T>(
+    incoming: impl Stream<Item = Result<T, std::io::Error>>,
+    open: Arc<AtomicUsize>,
+) -> impl Stream<Item = Result<CountedConnection<T>, std::io::Error>> {
+    incoming.map(move |connection| {
+        connection.map(|connection| CountedConnection::new(connection, open.clone()))
+    })
+}
+
+/// A connection that counts towards the open connections until it is
+/// dropped. The connection is boxed so that it can be polled without
+/// projecting the pin onto it.
+pub struct CountedConnection<T> {
+    connection: Pin<Box<T>>,
+    open: Arc<AtomicUsize>,
+}
+
+impl<T> CountedConnection<T> {
+    fn new(connection: T, open: Arc<AtomicUsize>) -> Self {
+        open.fetch_add(1, Ordering::Relaxed);
+        Self {
+            connection: Box::pin(connection),
+            open,
+        }
+    }
+}
+
+impl<T> Drop for CountedConnection<T> {
+    fn drop(&mut self) {
+        self.open.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl<T: AsyncRead> AsyncRead for CountedConnection<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        self.connection.as_mut().poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncWrite> AsyncWrite for CountedConnection<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, std::io::Error>> {
+        self.connection.as_mut().poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), std::io::Error>> {
+        self.connection.as_mut().poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), std::io::Error>> {
+        self.connection.as_mut().poll_shutdown(cx)
+    }
+}
+
+impl<T: Connected> Connected for CountedConnection<T> {
+    type ConnectInfo = T::ConnectInfo;
+    fn connect_info(&self) -> Self::ConnectInfo {
+        self.connection.connect_info()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::{
         assert_matches::assert_matches,
         process::Command,
-        sync::{atomic::AtomicBool, Arc},
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc,
+        },
     };
 
+    use futures::StreamExt;
     use pidlock::PidlockError;
     use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf};
 
-    use super::listen_socket;
+    use super::{count_connections, listen_socket};
     use crate::daemon::{endpoint::SocketOpenError, Paths};
 
     fn pid_path(daemon_root: &AbsoluteSystemPath) -> AbsoluteSystemPathBuf {
         daemon_root.join_component("turbod.pid")
     }
 
+    #[tokio::test]
+    async fn test_count_connections() {
+        let open = Arc::new(AtomicUsize::new(0));
+        let incoming =
+            futures::stream::iter([Ok(tokio::io::duplex(1).0), Ok(tokio::io::duplex(1).0)]);
+        let mut connections = Box::pin(count_connections(incoming, open.clone()));
+
+        let first = connections.next().await.unwrap().unwrap();
+        let second = connections.next().await.unwrap().unwrap();
+        assert_eq!(open.load(Ordering::Relaxed), 2);
+
+        drop(first);
+        assert_eq!(open.load(Ordering::Relaxed), 1);
+        drop(second);
+        assert_eq!(open.load(Ordering::Relaxed), 0);
+    }
+
     #[tokio::test]
     async fn test_stale_pid() {
         let tmp_dir = tempfile::tempdir().unwrap();
@@ -201,12 +326,22 @@ mod test {
         paths.pid_file.create_with_contents("100000").unwrap();
 
         let running = Arc::new(AtomicBool::new(true));
-        let result = listen_socket(&paths.pid_file, &paths.sock_file, running).await;
+        let result = listen_socket(
+            &paths.pid_file,
+            &paths.sock_file,
+            &paths.sock_path_file,
+            running,
+        )
+        .await;
 
         assert!(
             result.is_ok(),
             "expected to clear stale pid file and connect"
         );
+        assert_eq!(
+            paths.sock_path_file.read_to_string().unwrap(),
+            paths.sock_file.as_str()
+        );
     }
 
     #[tokio::test]
@@ -228,7 +363,17 @@ mod test {
             .unwrap();
 
         let running = Arc::new(AtomicBool::new(true));
-        let result = listen_socket(&paths.pid_file, &paths.sock_file, running).await;
+        let result = listen_socket(
+            &paths.pid_file,
+            &paths.sock_file,
+            &paths.sock_path_file,
+            running,
+        )
+        .await;
+        assert!(
+            !paths.sock_path_file.exists(),
+            "only the daemon holding the pid lock records its socket"
+        );
 
         // Note: PidLock doesn't implement Debug, so we can't unwrap_err()
 
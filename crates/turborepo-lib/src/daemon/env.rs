@@ -0,0 +1,71 @@
+//! The environment the daemon was started with, for `turbo daemon env`.
+//!
+//! A running daemon may have been started from a different shell than the
+//! one a client runs in, so it captures the turbo variables it saw at startup.
+//! The values of sensitive variables are redacted as they are captured, so
+//! that they never leave the daemon.
+
+use std::collections::BTreeMap;
+
+/// Only variables whose name starts with this are captured.
+const CAPTURED_PREFIX: &str = "TURBO_";
+
+/// The values of variables whose name starts with any of these are redacted.
+pub const SENSITIVE_PREFIXES: &[&str] = &["TURBO_TOKEN", "TURBO_REMOTE_CACHE_SIGNATURE_KEY"];
+
+/// What the values of sensitive variables are replaced with.
+pub const REDACTED: &str = "<redacted>";
+
+/// Captures the turbo variables in `vars`, e.g. from [`std::env::vars`].
+pub fn capture(vars: impl IntoIterator<Item = (String, String)>) -> BTreeMap<String, String> {
+    vars.into_iter()
+        .filter(|(key, _)| key.starts_with(CAPTURED_PREFIX))
+        .map(|(key, value)| {
+            let value = if is_sensitive(&key) {
+                REDACTED.to_string()
+            } else {
+                value
+            };
+            (key, value)
+        })
+        .collect()
+}
+
+fn is_sensitive(key: &str) -> bool {
+    SENSITIVE_PREFIXES
+        .iter()
+        .any(|prefix| key.starts_with(prefix))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::{capture, REDACTED};
+
+    #[test]
+    fn test_capture() {
+        let vars = [
+            ("TURBO_DAEMON", "0"),
+            ("TURBO_DAEMON_LOG_DIR", "/tmp/turbo-logs"),
+            ("TURBO_TOKEN", "secret"),
+            ("TURBO_REMOTE_CACHE_SIGNATURE_KEY_V2", "secret"),
+            ("PATH", "/usr/bin"),
+            ("NOT_TURBO_TOKEN", "unrelated"),
+        ]
+        .map(|(key, value)| (key.to_string(), value.to_string()));
+
+        assert_eq!(
+            capture(vars),
+            BTreeMap::from(
+                [
+                    ("TURBO_DAEMON", "0"),
+                    ("TURBO_DAEMON_LOG_DIR", "/tmp/turbo-logs"),
+                    ("TURBO_REMOTE_CACHE_SIGNATURE_KEY_V2", REDACTED),
+                    ("TURBO_TOKEN", REDACTED),
+                ]
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+            )
+        );
+    }
+}
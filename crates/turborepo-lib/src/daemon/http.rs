@@ -0,0 +1,145 @@
+//! A read-only view of the daemon's status over HTTP, for dashboards and
+//! scrapers that can't speak the daemon's gRPC protocol. This is separate from
+//! the RPC socket, and only listens on the loopback interface.
+
+use std::{
+    fmt::Write,
+    io,
+    net::{Ipv4Addr, SocketAddr, TcpListener},
+    sync::Arc,
+};
+
+use axum::{extract::State, http::header, response::IntoResponse, routing::get, Json, Router};
+use serde_json::json;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use super::proto;
+
+/// Takes a snapshot of the daemon's current status.
+pub type StatusSource = Arc<dyn Fn() -> proto::DaemonStatus + Send + Sync>;
+
+/// Serves `/status` as JSON and `/metrics` in the Prometheus text format on
+/// the given localhost port, or an ephemeral one if it is 0. Returns the
+/// address the server is listening on.
+pub fn serve(port: u16, status: StatusSource) -> io::Result<(SocketAddr, JoinHandle<()>)> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    let addr = listener.local_addr()?;
+
+    let app = Router::new()
+        .route("/status", get(status_json))
+        .route("/metrics", get(metrics))
+        .with_state(status);
+    let server = axum::Server::from_tcp(listener)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+        .serve(app.into_make_service());
+
+    let handle = tokio::task::spawn(async move {
+        if let Err(e) = server.await {
+            warn!("status http server exited: {}", e);
+        }
+    });
+    Ok((addr, handle))
+}
+
+fn health_name(status: &proto::DaemonStatus) -> &'static str {
+    match status.health() {
+        proto::DaemonHealth::Healthy => "healthy",
+        proto::DaemonHealth::Degraded => "degraded",
+    }
+}
+
+async fn status_json(State(status): State<StatusSource>) -> impl IntoResponse {
+    let status = status();
+    Json(json!({
+        "uptime_ms": status.uptime_msec,
+        "log_file": status.log_file,
+        "health": health_name(&status),
+        "health_message": status.health_message,
+        "config_hash": status.config_hash,
+        "worker_threads": status.worker_threads,
+    }))
+}
+
+async fn metrics(State(status): State<StatusSource>) -> impl IntoResponse {
+    let status = status();
+    let healthy = u8::from(status.health() == proto::DaemonHealth::Healthy);
+    let uptime_seconds = status.uptime_msec as f64 / 1000.0;
+
+    let mut body = String::new();
+    for (name, help, value) in [
+        (
+            "turbod_uptime_seconds",
+            "Time since the daemon started.",
+            uptime_seconds.to_string(),
+        ),
+        (
+            "turbod_healthy",
+            "Whether the daemon is healthy (1) or degraded (0).",
+            healthy.to_string(),
+        ),
+        (
+            "turbod_worker_threads",
+            "The number of worker threads handling requests.",
+            status.worker_threads.to_string(),
+        ),
+    ] {
+        // writing to a String can't fail
+        let _ = writeln!(body, "# HELP {name} {help}");
+        let _ = writeln!(body, "# TYPE {name} gauge");
+        let _ = writeln!(body, "{name} {value}");
+    }
+
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::serve;
+    use crate::daemon::proto::{DaemonHealth, DaemonStatus};
+
+    #[tokio::test]
+    async fn test_status_and_metrics() {
+        let (addr, handle) = serve(
+            0,
+            Arc::new(|| DaemonStatus {
+                uptime_msec: 1500,
+                log_file: "turbod.log".to_string(),
+                health: DaemonHealth::Degraded.into(),
+                health_message: Some("file watching stopped".to_string()),
+                config_hash: None,
+                worker_threads: 4,
+                resident_bytes: None,
+                compacted_bytes: None,
+                watcher_backend: None,
+            }),
+        )
+        .unwrap();
+        assert!(addr.ip().is_loopback());
+
+        let status = reqwest::get(format!("http://{addr}/status"))
+            .await
+            .unwrap()
+            .json::<serde_json::Value>()
+            .await
+            .unwrap();
+        assert_eq!(status["health"], "degraded");
+        assert_eq!(status["health_message"], "file watching stopped");
+        assert_eq!(status["uptime_ms"], 1500);
+        assert_eq!(status["worker_threads"], 4);
+
+        let metrics = reqwest::get(format!("http://{addr}/metrics"))
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert!(metrics.contains("turbod_uptime_seconds 1.5\n"));
+        assert!(metrics.contains("turbod_healthy 0\n"));
+        assert!(metrics.contains("turbod_worker_threads 4\n"));
+
+        handle.abort();
+    }
+}
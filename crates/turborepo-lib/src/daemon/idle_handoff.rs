@@ -0,0 +1,124 @@
+//! Handing the idle deadline of a daemon that is stopping to the daemon that
+//! replaces it, so that a quick restart doesn't change when the daemon shuts
+//! down for being idle.
+//!
+//! The deadline is recorded in wall clock time next to the pid file, since
+//! the monotonic clock of one process means nothing to another.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+use turbopath::AbsoluteSystemPath;
+
+/// How long after a daemon stopped its deadline is still handed off. A daemon
+/// started any later is a fresh start rather than a restart.
+const MAX_HANDOFF_AGE: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdleHandoff {
+    /// When the stopping daemon wrote the handoff, in milliseconds since the
+    /// Unix epoch.
+    pub written_at_ms: u64,
+    /// When the stopping daemon would have shut down for being idle, in
+    /// milliseconds since the Unix epoch.
+    pub deadline_ms: u64,
+}
+
+impl IdleHandoff {
+    /// A handoff for a daemon that, as of `now`, would shut down for being
+    /// idle after `remaining`.
+    pub fn new(remaining: Duration, now: SystemTime) -> Self {
+        let now = unix_millis(now);
+        Self {
+            written_at_ms: now,
+            deadline_ms: now.saturating_add(remaining.as_millis() as u64),
+        }
+    }
+
+    pub fn write(&self, path: &AbsoluteSystemPath) -> std::io::Result<()> {
+        let contents = serde_json::to_string(self)?;
+        path.ensure_dir()?;
+        path.create_with_contents(contents)
+    }
+
+    /// Takes the handoff at `path`, removing it so that only one daemon
+    /// inherits the deadline, and returns how long is left of it as of `now`.
+    /// Returns `None` if there is no handoff, or it is too old to be from a
+    /// restart, or its deadline has already passed.
+    pub fn take(path: &AbsoluteSystemPath, now: SystemTime) -> Option<Duration> {
+        let contents = path.read_to_string().ok()?;
+        path.remove_file().ok();
+        let handoff = match serde_json::from_str::<IdleHandoff>(&contents) {
+            Ok(handoff) => handoff,
+            Err(e) => {
+                warn!("discarding invalid idle handoff: {}", e);
+                return None;
+            }
+        };
+
+        let now = unix_millis(now);
+        let age = Duration::from_millis(now.saturating_sub(handoff.written_at_ms));
+        if age > MAX_HANDOFF_AGE {
+            debug!(
+                "idle handoff is {:?} old, starting the idle timeout afresh",
+                age
+            );
+            return None;
+        }
+        let remaining = handoff.deadline_ms.checked_sub(now)?;
+        Some(Duration::from_millis(remaining))
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use turbopath::AbsoluteSystemPathBuf;
+
+    use super::IdleHandoff;
+
+    #[test]
+    fn test_take_handoff() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let path = AbsoluteSystemPathBuf::try_from(tmp_dir.path())
+            .unwrap()
+            .join_component("turbod.idle");
+        let stopped_at = SystemTime::now();
+
+        IdleHandoff::new(Duration::from_secs(600), stopped_at)
+            .write(&path)
+            .unwrap();
+        assert_eq!(
+            IdleHandoff::take(&path, stopped_at + Duration::from_secs(2)),
+            Some(Duration::from_secs(598))
+        );
+        assert!(!path.exists(), "the handoff is only taken once");
+        assert_eq!(IdleHandoff::take(&path, stopped_at), None);
+
+        IdleHandoff::new(Duration::from_secs(600), stopped_at)
+            .write(&path)
+            .unwrap();
+        assert_eq!(
+            IdleHandoff::take(&path, stopped_at + Duration::from_secs(60)),
+            None,
+            "too old to be from a restart"
+        );
+
+        IdleHandoff::new(Duration::from_secs(1), stopped_at)
+            .write(&path)
+            .unwrap();
+        assert_eq!(
+            IdleHandoff::take(&path, stopped_at + Duration::from_secs(5)),
+            None,
+            "the deadline has passed"
+        );
+    }
+}
@@ -0,0 +1,124 @@
+//! Idle leases, which keep the daemon from shutting down when idle for as
+//! long as they are held, such as by `turbo daemon pin`.
+//!
+//! A client that exits without releasing its lease leaks it, which would keep
+//! the daemon alive forever, so `turbo daemon release-leases` releases every
+//! held lease at once.
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use tokio::sync::Notify;
+
+use super::bump_timeout::BumpTimeout;
+
+#[derive(Debug, Default)]
+pub struct Leases {
+    next_id: AtomicU64,
+    /// The holder of each held lease, by lease id.
+    held: Mutex<BTreeMap<u64, String>>,
+    /// Notified when the last held lease is released.
+    released: Notify,
+}
+
+impl Leases {
+    /// Acquires a lease on behalf of `holder`, returning its id.
+    pub fn acquire(&self, holder: String) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.held
+            .lock()
+            .expect("leases lock poisoned")
+            .insert(id, holder);
+        id
+    }
+
+    /// Releases the lease with the given id, returning whether it was held.
+    pub fn release(&self, id: u64) -> bool {
+        let mut held = self.held.lock().expect("leases lock poisoned");
+        let released = held.remove(&id).is_some();
+        if released && held.is_empty() {
+            self.released.notify_one();
+        }
+        released
+    }
+
+    /// Releases every held lease, returning how many there were.
+    pub fn release_all(&self) -> usize {
+        let released = std::mem::take(&mut *self.held.lock().expect("leases lock poisoned")).len();
+        if released > 0 {
+            self.released.notify_one();
+        }
+        released
+    }
+
+    /// The holders of the held leases, in the order they were acquired.
+    pub fn holders(&self) -> Vec<String> {
+        self.held
+            .lock()
+            .expect("leases lock poisoned")
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// Waits for `timeout`, and then for as long as any lease is held. Once
+    /// the last lease is released the timeout starts over, so that the daemon
+    /// doesn't shut down the moment a long-lived client lets go of it.
+    pub async fn wait_idle(&self, timeout: &BumpTimeout) {
+        loop {
+            timeout.wait().await;
+            if self.held.lock().expect("leases lock poisoned").is_empty() {
+                return;
+            }
+            self.released.notified().await;
+            timeout.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::Leases;
+    use crate::daemon::bump_timeout::BumpTimeout;
+
+    #[test]
+    fn test_release() {
+        let leases = Leases::default();
+        let first = leases.acquire("first".to_string());
+        let second = leases.acquire("second".to_string());
+        assert_eq!(leases.holders(), vec!["first", "second"]);
+
+        assert!(leases.release(first));
+        assert!(!leases.release(first), "a lease is only released once");
+        assert_eq!(leases.holders(), vec!["second"]);
+
+        leases.acquire("third".to_string());
+        assert_eq!(leases.release_all(), 2);
+        assert_eq!(leases.release_all(), 0);
+        assert!(!leases.release(second));
+        assert!(leases.holders().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_wait_idle_waits_for_leases() {
+        let leases = Leases::default();
+        let timeout = BumpTimeout::new(Duration::from_millis(10));
+        let id = leases.acquire("holder".to_string());
+
+        // the timeout has passed, but the lease is still held
+        let waited = tokio::time::timeout(Duration::from_millis(100), leases.wait_idle(&timeout));
+        assert!(waited.await.is_err());
+
+        leases.release(id);
+        tokio::time::timeout(Duration::from_secs(10), leases.wait_idle(&timeout))
+            .await
+            .expect("idle timeout fires once the lease is released");
+    }
+}
@@ -0,0 +1,170 @@
+//! Limiting the daemon's memory use, so that it doesn't grow without bound on
+//! very large repositories. A daemon over its limit shuts down gracefully and
+//! starts a replacement, which binds a new socket at the same path. Clients
+//! lose their connection to the old daemon and have to reconnect; if the
+//! replacement fails to start, a client that may start a daemon starts one
+//! when it reconnects.
+
+use std::{
+    fmt, io,
+    process::{Command, Stdio},
+    str::FromStr,
+    time::Duration,
+};
+
+use sysinfo::{Pid, ProcessExt, System, SystemExt};
+
+/// A number of bytes, parsed from an integer with an optional binary unit,
+/// such as `1GB` or `512MB`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryLimit(pub u64);
+
+impl FromStr for MemoryLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (amount, unit) = s.split_at(split);
+        let amount = amount
+            .parse::<u64>()
+            .map_err(|_| format!("'{s}' does not start with a number of bytes"))?;
+        let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+            "" | "B" => 1,
+            "KB" => 1 << 10,
+            "MB" => 1 << 20,
+            "GB" => 1 << 30,
+            _ => return Err(format!("'{unit}' is not one of B, KB, MB or GB")),
+        };
+        amount
+            .checked_mul(multiplier)
+            .map(Self)
+            .ok_or_else(|| format!("'{s}' is too large"))
+    }
+}
+
+impl fmt::Display for MemoryLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (unit, size) in [("GB", 1 << 30), ("MB", 1 << 20), ("KB", 1 << 10)] {
+            if self.0 >= size && self.0 % size == 0 {
+                return write!(f, "{}{}", self.0 / size, unit);
+            }
+        }
+        write!(f, "{}B", self.0)
+    }
+}
+
+/// Reports how much memory the daemon is using.
+pub trait MemoryProbe: Send + 'static {
+    /// The resident set size of the daemon in bytes, if it can be measured.
+    fn resident_bytes(&mut self) -> Option<u64>;
+}
+
+/// Measures the memory of the current process.
+pub struct ProcessMemory {
+    system: System,
+    pid: Option<Pid>,
+}
+
+impl ProcessMemory {
+    pub fn new() -> Self {
+        Self {
+            system: System::new(),
+            pid: sysinfo::get_current_pid().ok(),
+        }
+    }
+}
+
+impl Default for ProcessMemory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryProbe for ProcessMemory {
+    fn resident_bytes(&mut self) -> Option<u64> {
+        let pid = self.pid?;
+        self.system.refresh_process(pid);
+        self.system.process(pid).map(|process| process.memory())
+    }
+}
+
+/// Starts the replacement for a daemon that shut down over its memory limit.
+pub trait Respawn: Send + 'static {
+    fn respawn(&mut self) -> io::Result<()>;
+}
+
+/// Starts a daemon with the same executable and arguments as the current
+/// process.
+pub struct RespawnCurrentExe;
+
+impl Respawn for RespawnCurrentExe {
+    fn respawn(&mut self) -> io::Result<()> {
+        Command::new(std::env::current_exe()?)
+            .args(std::env::args_os().skip(1))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        Ok(())
+    }
+}
+
+/// Resolves with the daemon's memory use once it exceeds `limit`, checking
+/// every `interval`. Measurements that fail are skipped.
+pub async fn exceeded(
+    limit: MemoryLimit,
+    interval: Duration,
+    mut probe: Box<dyn MemoryProbe>,
+) -> u64 {
+    let mut checks = tokio::time::interval(interval);
+    loop {
+        checks.tick().await;
+        match probe.resident_bytes() {
+            Some(resident_bytes) if resident_bytes > limit.0 => return resident_bytes,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{exceeded, MemoryLimit, MemoryProbe};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("1GB".parse(), Ok(MemoryLimit(1 << 30)));
+        assert_eq!("512 mb".parse(), Ok(MemoryLimit(512 << 20)));
+        assert_eq!("4096".parse(), Ok(MemoryLimit(4096)));
+        assert_eq!(MemoryLimit(1 << 30).to_string(), "1GB");
+        assert_eq!(MemoryLimit(1536 << 10).to_string(), "1536KB");
+
+        for invalid in ["", "GB", "1.5GB", "1TB", "-1", "99999999999GB"] {
+            assert!(invalid.parse::<MemoryLimit>().is_err(), "{invalid}");
+        }
+    }
+
+    struct Growing {
+        resident_bytes: u64,
+    }
+
+    impl MemoryProbe for Growing {
+        fn resident_bytes(&mut self) -> Option<u64> {
+            self.resident_bytes += 100;
+            Some(self.resident_bytes)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_exceeded() {
+        let resident_bytes = exceeded(
+            MemoryLimit(300),
+            Duration::from_millis(1),
+            Box::new(Growing { resident_bytes: 0 }),
+        )
+        .await;
+        assert_eq!(resident_bytes, 400, "the limit itself is not a breach");
+    }
+}
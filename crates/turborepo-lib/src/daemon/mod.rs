@@ -20,26 +20,66 @@
 //! `_tx`/`_rx` suffixes indicate that this variable is respectively a `Sender`
 //! or `Receiver`.
 
+mod affinity;
+#[cfg(unix)]
+pub(crate) mod audit;
 mod bump_timeout;
 mod bump_timeout_layer;
+mod checkpoint;
 mod client;
+mod clock;
+mod compaction;
 mod connector;
+mod debug_log;
 mod default_timeout_layer;
 pub(crate) mod endpoint;
+mod env;
+mod http;
+mod idle_handoff;
+mod lease;
+mod memory;
+mod otlp;
+mod ready;
+pub(crate) mod replay;
 mod server;
+mod start_bench;
+mod title;
+mod trace;
+mod turbo_config;
 
+pub use affinity::CoreSet;
+use camino::Utf8Path;
 pub use client::{DaemonClient, DaemonError};
-pub use connector::{DaemonConnector, DaemonConnectorError};
+pub use connector::{ConnectAttempt, DaemonConnector, DaemonConnectorError};
+pub use memory::{MemoryLimit, MemoryProbe, ProcessMemory, Respawn, RespawnCurrentExe};
+pub use ready::ReadySignal;
 pub use server::{CloseReason, TurboGrpcService};
 use sha2::{Digest, Sha256};
+pub use start_bench::StartBreakdown;
+pub use title::{OsProcessTitle, ProcessTitle};
+use tracing::warn;
 use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf};
 
 #[derive(Clone, Debug)]
 pub struct Paths {
+    /// The root the daemon serves. For a daemon started with `--scope`, this
+    /// is a subdirectory of the repository.
+    pub repo_root: AbsoluteSystemPathBuf,
     pub pid_file: AbsoluteSystemPathBuf,
     pub lock_file: AbsoluteSystemPathBuf,
     pub sock_file: AbsoluteSystemPathBuf,
+    /// Records where the running daemon bound its socket, so that clients
+    /// can find it even if it was relocated with `--socket-dir`.
+    pub sock_path_file: AbsoluteSystemPathBuf,
+    /// Where the daemon checkpoints its state, if checkpointing is enabled.
+    pub checkpoint_file: AbsoluteSystemPathBuf,
+    /// Where a stopping daemon records its idle deadline for its replacement,
+    /// if idle handoff is enabled.
+    pub idle_handoff_file: AbsoluteSystemPathBuf,
     pub lsp_pid_file: AbsoluteSystemPathBuf,
+    /// Where a read-only daemon writes its filewatching cookies, rather than
+    /// in the repository.
+    pub cookie_dir: AbsoluteSystemPathBuf,
     pub log_file: AbsoluteSystemPathBuf,
     pub log_folder: AbsoluteSystemPathBuf,
 }
@@ -73,14 +113,95 @@ impl Paths {
         let daemon_root = daemon_file_root(&repo_hash);
         let (log_file, log_folder) = daemon_log_file_and_folder(repo_root, &repo_hash);
         Self {
+            repo_root: repo_root.to_owned(),
             pid_file: daemon_root.join_component("turbod.pid"),
             lock_file: daemon_root.join_component("turbod.lock"),
             sock_file: daemon_root.join_component("turbod.sock"),
+            sock_path_file: daemon_root.join_component("turbod.sockpath"),
+            checkpoint_file: daemon_root.join_component("turbod.checkpoint"),
+            idle_handoff_file: daemon_root.join_component("turbod.idle"),
             lsp_pid_file: daemon_root.join_component("lsp.pid"),
+            cookie_dir: daemon_root.join_component("cookies"),
             log_file,
             log_folder,
         }
     }
+
+    /// Like `from_repo_root`, but binds the socket in `socket_dir` instead of
+    /// the default daemon file root. If `socket_dir` cannot be used, we log
+    /// a warning and fall back to the default location.
+    pub fn from_repo_root_with_socket_dir(
+        repo_root: &AbsoluteSystemPath,
+        socket_dir: &AbsoluteSystemPath,
+    ) -> Self {
+        let repo_hash = repo_hash(repo_root);
+        let mut paths = Self::from_repo_root(repo_root);
+        match check_socket_dir(socket_dir, &repo_hash) {
+            Ok(()) => {
+                paths.sock_file =
+                    socket_dir.join_component(format!("turbod-{}.sock", repo_hash).as_str());
+            }
+            Err(e) => {
+                warn!(
+                    "unable to use {} for the daemon socket, falling back to {}: {}",
+                    socket_dir, paths.sock_file, e
+                );
+            }
+        }
+        paths
+    }
+
+    /// Moves the logs into the daemon file root, so that a read-only daemon
+    /// doesn't write to the repository.
+    pub fn with_logs_outside_repo(mut self) -> Self {
+        let repo_hash = repo_hash(&self.repo_root);
+        self.log_folder = daemon_file_root(&repo_hash).join_component("logs");
+        self.log_file = self
+            .log_folder
+            .join_component(format!("{}-turbo.log", repo_hash).as_str());
+        self
+    }
+}
+
+/// Hashes the repository's `turbo.json`, so that we can tell whether the
+/// daemon was started with the config that is currently on disk. Returns
+/// `None` if there is no `turbo.json` to read.
+pub fn config_hash(repo_root: &AbsoluteSystemPath) -> Option<String> {
+    let config = repo_root.join_component("turbo.json").read().ok()?;
+    let mut hasher = Sha256::new();
+    hasher.update(&config);
+    Some(hex::encode(hasher.finalize()))
+}
+
+/// Resolves the `--scope` of a daemon, relative to the repository root, to the
+/// root that the daemon serves. Files are hashed from that root, so a scoped
+/// daemon has its own pid file and socket.
+pub fn scoped_root(
+    repo_root: &AbsoluteSystemPath,
+    scope: &Utf8Path,
+) -> Result<AbsoluteSystemPathBuf, DaemonError> {
+    let root = AbsoluteSystemPathBuf::from_unknown(repo_root, scope);
+    if !root.as_path().starts_with(repo_root.as_path()) {
+        return Err(DaemonError::InvalidScope(format!(
+            "{} is outside of the repository",
+            scope
+        )));
+    }
+    if !root.as_std_path().is_dir() {
+        return Err(DaemonError::InvalidScope(format!(
+            "{} is not a directory",
+            scope
+        )));
+    }
+    Ok(root)
+}
+
+/// Checks that we are able to create files in the given socket directory.
+fn check_socket_dir(socket_dir: &AbsoluteSystemPath, repo_hash: &str) -> std::io::Result<()> {
+    socket_dir.create_dir_all()?;
+    let probe = socket_dir.join_component(format!(".turbod-{}.probe", repo_hash).as_str());
+    probe.create_with_contents("")?;
+    probe.remove_file()
 }
 
 pub(crate) mod proto {
@@ -100,7 +221,7 @@ pub(crate) mod proto {
     /// - Bump the minor version if adding new features, such that clients can
     ///   mandate at least some set of features on the target server.
     /// - Bump the patch version if making backwards compatible bug fixes.
-    pub const VERSION: &str = "1.11.0";
+    pub const VERSION: &str = "1.12.0";
 
     impl From<PackageManager> for turborepo_repository::package_manager::PackageManager {
         fn from(pm: PackageManager) -> Self {
@@ -133,9 +254,11 @@ pub(crate) mod proto {
 
 #[cfg(test)]
 mod test {
+    use camino::Utf8Path;
     use turbopath::AbsoluteSystemPathBuf;
 
-    use super::repo_hash;
+    use super::{repo_hash, scoped_root, Paths};
+    use crate::daemon::DaemonError;
 
     #[test]
     fn test_repo_hash() {
@@ -149,4 +272,48 @@ mod test {
         assert_eq!(hash, expected_hash);
         assert_eq!(hash.len(), 16);
     }
+
+    #[test]
+    fn test_socket_dir_fallback() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let tmp = AbsoluteSystemPathBuf::try_from(tmp_dir.path()).unwrap();
+        let repo_root = tmp.join_component("repo");
+        let default_paths = Paths::from_repo_root(&repo_root);
+
+        let socket_dir = tmp.join_component("sockets");
+        let paths = Paths::from_repo_root_with_socket_dir(&repo_root, &socket_dir);
+        assert_eq!(paths.sock_file.parent(), Some(&*socket_dir));
+        assert_eq!(paths.pid_file, default_paths.pid_file);
+
+        // a directory nested under a file can never be created
+        let not_a_dir = tmp.join_component("file");
+        not_a_dir.create_with_contents("").unwrap();
+        let paths =
+            Paths::from_repo_root_with_socket_dir(&repo_root, &not_a_dir.join_component("sockets"));
+        assert_eq!(paths.sock_file, default_paths.sock_file);
+    }
+
+    #[test]
+    fn test_scoped_root() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let repo_root = AbsoluteSystemPathBuf::try_from(tmp_dir.path()).unwrap();
+        let app = repo_root.join_components(&["packages", "app"]);
+        app.create_dir_all().unwrap();
+
+        let scoped = scoped_root(&repo_root, Utf8Path::new("packages/app")).unwrap();
+        assert_eq!(scoped, app);
+        let paths = Paths::from_repo_root(&scoped);
+        assert_eq!(paths.repo_root, app);
+        assert_ne!(paths.sock_file, Paths::from_repo_root(&repo_root).sock_file);
+
+        for scope in ["packages/missing", "..", "/"] {
+            assert!(
+                matches!(
+                    scoped_root(&repo_root, Utf8Path::new(scope)),
+                    Err(DaemonError::InvalidScope(_))
+                ),
+                "{scope} should be rejected"
+            );
+        }
+    }
 }
@@ -0,0 +1,226 @@
+//! Exporting the daemon's spans to an OpenTelemetry collector, so that it can
+//! be observed alongside other services. Spans are sent over OTLP/HTTP with
+//! the JSON encoding, which any collector accepts at `/v1/traces`.
+//!
+//! Each RPC the server handles is exported as a span named after the method,
+//! and the daemon's lifetime is exported as a `turbod` span once it exits.
+
+use std::{
+    task::{Context, Poll},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+use tokio::{sync::mpsc, task::JoinHandle};
+use tonic::{codegen::http, server::NamedService};
+use tower::{Layer, Service};
+use tracing::{debug, warn};
+
+/// The most spans sent to the collector in one request.
+const MAX_BATCH: usize = 512;
+
+/// A finished span, waiting to be exported.
+#[derive(Debug, Clone)]
+pub struct ExportedSpan {
+    pub name: String,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    pub attributes: Vec<(&'static str, String)>,
+}
+
+/// Sends spans to a collector in the background. Spans are batched while a
+/// previous batch is being sent, and the background task exits once every
+/// exporter has been dropped and the remaining spans were sent.
+#[derive(Clone)]
+pub struct OtlpExporter {
+    spans: mpsc::UnboundedSender<ExportedSpan>,
+}
+
+impl OtlpExporter {
+    /// Starts exporting to the collector at `endpoint`, such as
+    /// `http://localhost:4318`.
+    pub fn new(endpoint: &str) -> (Self, JoinHandle<()>) {
+        let url = format!("{}/v1/traces", endpoint.trim_end_matches('/'));
+        let (spans, rx) = mpsc::unbounded_channel();
+        let handle = tokio::task::spawn(send_batches(url, rx));
+        (Self { spans }, handle)
+    }
+
+    pub fn export(&self, span: ExportedSpan) {
+        // the background task only exits once every exporter is dropped
+        let _ = self.spans.send(span);
+    }
+}
+
+async fn send_batches(url: String, mut rx: mpsc::UnboundedReceiver<ExportedSpan>) {
+    let client = reqwest::Client::new();
+    while let Some(span) = rx.recv().await {
+        let mut batch = vec![span];
+        while batch.len() < MAX_BATCH {
+            match rx.try_recv() {
+                Ok(span) => batch.push(span),
+                Err(_) => break,
+            }
+        }
+
+        let result = client
+            .post(&url)
+            .json(&encode(&batch))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match result {
+            Ok(_) => debug!("exported {} spans to {}", batch.len(), url),
+            Err(e) => warn!("failed to export {} spans to {}: {}", batch.len(), url, e),
+        }
+    }
+}
+
+/// Encodes spans as an OTLP `ExportTraceServiceRequest`. Each span is the root
+/// of a trace of its own.
+fn encode(spans: &[ExportedSpan]) -> Value {
+    let unix_nanos = |time: SystemTime| {
+        time.duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos()
+            .to_string()
+    };
+    let spans = spans
+        .iter()
+        .map(|span| {
+            let attributes = span
+                .attributes
+                .iter()
+                .map(|(key, value)| json!({ "key": key, "value": { "stringValue": value } }))
+                .collect::<Vec<_>>();
+            json!({
+                "traceId": hex::encode(rand::random::<[u8; 16]>()),
+                "spanId": hex::encode(rand::random::<[u8; 8]>()),
+                "name": span.name,
+                // SPAN_KIND_SERVER
+                "kind": 2,
+                "startTimeUnixNano": unix_nanos(span.start),
+                "endTimeUnixNano": unix_nanos(span.end),
+                "attributes": attributes,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [
+                    { "key": "service.name", "value": { "stringValue": "turbod" } },
+                ],
+            },
+            "scopeSpans": [{
+                "scope": { "name": "turbod" },
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+/// A layer that exports a span for every request, if it has an exporter.
+pub struct OtlpLayer(Option<OtlpExporter>);
+
+impl OtlpLayer {
+    pub fn new(exporter: Option<OtlpExporter>) -> Self {
+        Self(exporter)
+    }
+}
+
+impl<S> Layer<S> for OtlpLayer {
+    type Service = OtlpService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        OtlpService {
+            inner,
+            exporter: self.0.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct OtlpService<S> {
+    inner: S,
+    exporter: Option<OtlpExporter>,
+}
+
+impl<S, B> Service<http::Request<B>> for OtlpService<S>
+where
+    S: Service<http::Request<B>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<S::Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let Some(exporter) = self.exporter.clone() else {
+            return Box::pin(self.inner.call(req));
+        };
+
+        // gRPC paths are `/<service>/<method>`
+        let path = req.uri().path().trim_start_matches('/').to_string();
+        let start = SystemTime::now();
+        let response = self.inner.call(req);
+        Box::pin(async move {
+            let result = response.await;
+            let (service, method) = path.split_once('/').unwrap_or(("", &path));
+            let attributes = vec![
+                ("rpc.system", "grpc".to_string()),
+                ("rpc.service", service.to_string()),
+                ("rpc.method", method.to_string()),
+            ];
+            exporter.export(ExportedSpan {
+                name: path,
+                start,
+                end: SystemTime::now(),
+                attributes,
+            });
+            result
+        })
+    }
+}
+
+impl<T: NamedService> NamedService for OtlpService<T> {
+    const NAME: &'static str = T::NAME;
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{encode, ExportedSpan};
+
+    #[test]
+    fn test_encode() {
+        let span = ExportedSpan {
+            name: "turbodprotocol.Turbod/Status".to_string(),
+            start: UNIX_EPOCH + Duration::from_secs(1),
+            end: UNIX_EPOCH + Duration::from_millis(1500),
+            attributes: vec![("rpc.method", "Status".to_string())],
+        };
+        let request = encode(&[span]);
+
+        let resource_spans = &request["resourceSpans"][0];
+        assert_eq!(
+            resource_spans["resource"]["attributes"][0]["value"]["stringValue"],
+            "turbod"
+        );
+        let span = &resource_spans["scopeSpans"][0]["spans"][0];
+        assert_eq!(span["name"], "turbodprotocol.Turbod/Status");
+        assert_eq!(span["startTimeUnixNano"], "1000000000");
+        assert_eq!(span["endTimeUnixNano"], "1500000000");
+        assert_eq!(span["traceId"].as_str().unwrap().len(), 32);
+        assert_eq!(span["spanId"].as_str().unwrap().len(), 16);
+        assert_eq!(span["attributes"][0]["key"], "rpc.method");
+        assert_eq!(span["attributes"][0]["value"]["stringValue"], "Status");
+    }
+}
@@ -0,0 +1,99 @@
+//! Readiness notifications, so that the daemon can be supervised by an init
+//! system that waits for services to report that they have started.
+
+use std::{fs::OpenOptions, io, io::Write};
+
+use turbopath::AbsoluteSystemPathBuf;
+
+/// Where to announce that the daemon is accepting connections.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ReadySignal {
+    /// Readiness is not announced.
+    #[default]
+    None,
+    /// Send `READY=1` to the given systemd notification socket, as expected
+    /// of `Type=notify` services. This is a no-op on platforms other than
+    /// Linux.
+    Systemd(String),
+    /// Write `READY` to the given file, so that a supervisor can wait for it
+    /// to appear. The file is removed when the daemon exits.
+    File(AbsoluteSystemPathBuf),
+}
+
+impl ReadySignal {
+    /// Announces readiness to an explicitly requested ready file, falling
+    /// back to systemd if it passed us a notification socket.
+    pub fn new(ready_file: Option<AbsoluteSystemPathBuf>) -> Self {
+        match ready_file {
+            Some(path) => Self::File(path),
+            None => match std::env::var("NOTIFY_SOCKET") {
+                Ok(socket) if !socket.is_empty() => Self::Systemd(socket),
+                _ => Self::None,
+            },
+        }
+    }
+
+    pub fn notify(&self) -> io::Result<()> {
+        match self {
+            Self::None => Ok(()),
+            Self::Systemd(socket) => notify_systemd(socket),
+            Self::File(path) => {
+                path.ensure_dir()?;
+                let mut file = OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(path.as_std_path())?;
+                file.write_all(b"READY\n")
+            }
+        }
+    }
+
+    /// Withdraws the announcement, if it is one that persists.
+    pub fn clear(&self) {
+        if let Self::File(path) = self {
+            path.remove_file().ok();
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn notify_systemd(socket: &str) -> io::Result<()> {
+    use std::os::{
+        linux::net::SocketAddrExt,
+        unix::net::{SocketAddr, UnixDatagram},
+    };
+
+    // a leading '@' denotes a socket in the abstract namespace
+    let addr = match socket.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name)?,
+        None => SocketAddr::from_pathname(socket)?,
+    };
+    UnixDatagram::unbound()?.send_to_addr(b"READY=1", &addr)?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn notify_systemd(_socket: &str) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod test {
+    use super::ReadySignal;
+
+    #[test]
+    fn test_notify_systemd() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let socket_path = tmp_dir.path().join("notify.sock");
+        let socket = std::os::unix::net::UnixDatagram::bind(&socket_path).unwrap();
+
+        ReadySignal::Systemd(socket_path.to_str().unwrap().to_string())
+            .notify()
+            .unwrap();
+
+        let mut buf = [0; 16];
+        let len = socket.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1");
+    }
+}
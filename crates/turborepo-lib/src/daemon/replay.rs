@@ -0,0 +1,281 @@
+//! Recording the requests the daemon receives, so that they can be replayed
+//! against a running daemon to reproduce performance problems.
+//!
+//! Requests are recorded as they arrive on the wire, one JSON object per line
+//! holding the gRPC method and the hex-encoded request body, so any RPC can be
+//! replayed without knowing its message type.
+
+use std::{
+    future::poll_fn,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use tokio::{io::AsyncWriteExt, sync::mpsc, task::JoinHandle};
+use tonic::{
+    codegen::{http, Body as _},
+    server::NamedService,
+    transport::{Body, Channel},
+};
+use tower::{Layer, Service};
+use tracing::{debug, warn};
+use turbopath::AbsoluteSystemPath;
+
+/// A request, as it was received by the daemon.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    /// The path of the gRPC method, such as `/turbodprotocol.Turbod/Status`.
+    pub method: String,
+    /// The hex-encoded request body, including gRPC message framing.
+    pub body: String,
+}
+
+/// Appends requests to a recording in the background. The background task
+/// exits once every recorder has been dropped and the remaining requests
+/// were written.
+#[derive(Clone)]
+pub struct Recorder {
+    requests: mpsc::UnboundedSender<RecordedRequest>,
+}
+
+impl Recorder {
+    /// Starts recording to `file`, replacing any previous recording there.
+    pub async fn new(file: &AbsoluteSystemPath) -> std::io::Result<(Self, JoinHandle<()>)> {
+        let mut writer = tokio::fs::File::create(file.as_std_path()).await?;
+        let (requests, mut rx) = mpsc::unbounded_channel::<RecordedRequest>();
+        let file = file.to_owned();
+        let handle = tokio::task::spawn(async move {
+            while let Some(request) = rx.recv().await {
+                let mut line = serde_json::to_vec(&request).expect("requests serialize to JSON");
+                line.push(b'\n');
+                // flush every request, so that a recording can be replayed
+                // while the daemon is still running
+                let result = async {
+                    writer.write_all(&line).await?;
+                    writer.flush().await
+                }
+                .await;
+                if let Err(e) = result {
+                    warn!("failed to record request to {}: {}", file, e);
+                }
+            }
+        });
+        Ok((Self { requests }, handle))
+    }
+
+    pub fn record(&self, method: &str, body: &[u8]) {
+        // the background task only exits once every recorder is dropped
+        let _ = self.requests.send(RecordedRequest {
+            method: method.to_string(),
+            body: hex::encode(body),
+        });
+    }
+}
+
+/// Reads a recording written by a [`Recorder`].
+pub fn load(file: &AbsoluteSystemPath) -> Result<Vec<RecordedRequest>, ReplayError> {
+    file.read_to_string()?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Ok(serde_json::from_str(line)?))
+        .collect()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReplayError {
+    #[error("unable to read recording: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("malformed recording: {0}")]
+    Malformed(#[from] serde_json::Error),
+}
+
+/// A recorded request that was sent again.
+#[derive(Debug, Clone)]
+pub struct ReplayedRequest {
+    pub method: String,
+    pub latency: Duration,
+    /// Whether the daemon responded with an OK status.
+    pub ok: bool,
+}
+
+/// Sends the recorded requests to the daemon behind `channel`, one at a time.
+/// With a `rate`, requests are spaced out to send that many per second;
+/// otherwise each one is sent as soon as the previous one completes.
+pub async fn replay(
+    mut channel: Channel,
+    requests: &[RecordedRequest],
+    rate: Option<f64>,
+) -> Vec<ReplayedRequest> {
+    let mut interval = rate.map(|rate| {
+        let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / rate));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        interval
+    });
+
+    let mut replayed = Vec::with_capacity(requests.len());
+    for request in requests {
+        if let Some(interval) = &mut interval {
+            interval.tick().await;
+        }
+        let Ok(body) = hex::decode(&request.body) else {
+            warn!("skipping {}, its body is not valid hex", request.method);
+            continue;
+        };
+
+        let start = Instant::now();
+        let ok = match send(&mut channel, &request.method, body).await {
+            Ok(ok) => ok,
+            Err(e) => {
+                debug!("replaying {} failed: {}", request.method, e);
+                false
+            }
+        };
+        replayed.push(ReplayedRequest {
+            method: request.method.clone(),
+            latency: start.elapsed(),
+            ok,
+        });
+    }
+    replayed
+}
+
+/// Sends a raw gRPC request and reads the response to the end, returning
+/// whether it had an OK status.
+async fn send(
+    channel: &mut Channel,
+    method: &str,
+    body: Vec<u8>,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let request = http::Request::post(format!("http://[::]:50051{method}"))
+        .header("content-type", "application/grpc")
+        .header("te", "trailers")
+        .body(tonic::body::boxed(Body::from(body)))?;
+
+    poll_fn(|cx| channel.poll_ready(cx)).await?;
+    let response = channel.call(request).await?;
+
+    // a gRPC error without a body is reported in the headers instead of the
+    // trailers
+    let (parts, mut body) = response.into_parts();
+    let mut status = parts.headers.get("grpc-status").cloned();
+    while let Some(chunk) = body.data().await {
+        chunk?;
+    }
+    if let Some(trailers) = body.trailers().await? {
+        status = trailers.get("grpc-status").cloned().or(status);
+    }
+    Ok(status.is_some_and(|status| status == "0"))
+}
+
+/// A layer that records every request, if it has a recorder.
+pub struct RecordLayer(Option<Recorder>);
+
+impl RecordLayer {
+    pub fn new(recorder: Option<Recorder>) -> Self {
+        Self(recorder)
+    }
+}
+
+impl<S> Layer<S> for RecordLayer {
+    type Service = RecordService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RecordService {
+            inner,
+            recorder: self.0.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RecordService<S> {
+    inner: S,
+    recorder: Option<Recorder>,
+}
+
+impl<S> Service<http::Request<Body>> for RecordService<S>
+where
+    S: Service<http::Request<Body>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<S::Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<Body>) -> Self::Future {
+        let Some(recorder) = self.recorder.clone() else {
+            return Box::pin(self.inner.call(req));
+        };
+
+        // the body has to be read before the request is handled, so call the
+        // service that was made ready in `poll_ready` rather than a clone
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let (parts, mut body) = req.into_parts();
+            let mut bytes = Vec::new();
+            while let Some(chunk) = body.data().await {
+                match chunk {
+                    Ok(chunk) => bytes.extend_from_slice(&chunk),
+                    Err(e) => {
+                        // pass on what was read, the service will reject it
+                        warn!("unable to record request to {}: {}", parts.uri.path(), e);
+                        break;
+                    }
+                }
+            }
+            recorder.record(parts.uri.path(), &bytes);
+            inner
+                .call(http::Request::from_parts(parts, Body::from(bytes)))
+                .await
+        })
+    }
+}
+
+impl<T: NamedService> NamedService for RecordService<T> {
+    const NAME: &'static str = T::NAME;
+}
+
+#[cfg(test)]
+mod test {
+    use turbopath::AbsoluteSystemPathBuf;
+
+    use super::{load, RecordedRequest, Recorder, ReplayError};
+
+    #[tokio::test]
+    async fn test_record_and_load() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let file = AbsoluteSystemPathBuf::try_from(tmp_dir.path())
+            .unwrap()
+            .join_component("requests.jsonl");
+
+        let (recorder, handle) = Recorder::new(&file).await.unwrap();
+        recorder.record("/turbodprotocol.Turbod/Status", &[0, 0, 0, 0, 0]);
+        recorder.record("/turbodprotocol.Turbod/Hello", &[0, 0, 0, 0, 2, 10, 0]);
+        drop(recorder);
+        handle.await.unwrap();
+
+        assert_eq!(
+            load(&file).unwrap(),
+            vec![
+                RecordedRequest {
+                    method: "/turbodprotocol.Turbod/Status".to_string(),
+                    body: "0000000000".to_string(),
+                },
+                RecordedRequest {
+                    method: "/turbodprotocol.Turbod/Hello".to_string(),
+                    body: "00000000020a00".to_string(),
+                },
+            ]
+        );
+
+        file.create_with_contents("not json\n").unwrap();
+        assert!(matches!(load(&file), Err(ReplayError::Malformed(_))));
+    }
+}
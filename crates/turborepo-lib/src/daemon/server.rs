@@ -4,17 +4,20 @@
 //! [daemon module](std::daemon).
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
+    str::FromStr,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
         Arc, Mutex,
     },
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
 
 use futures::Future;
+use globwalk::{ValidatedGlob, WalkType};
 use prost::DecodeError;
 use semver::Version;
+use serde_json::json;
 use thiserror::Error;
 use tokio::{
     select,
@@ -22,27 +25,48 @@ use tokio::{
     task::JoinHandle,
 };
 use tokio_stream::wrappers::ReceiverStream;
-use tonic::{server::NamedService, transport::Server};
+use tonic::{codec::CompressionEncoding, server::NamedService, transport::Server};
 use tower::ServiceBuilder;
-use tracing::{error, info, trace, warn};
+use tracing::{debug, error, info, trace, warn, Instrument};
 use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf, AnchoredSystemPathBuf, PathError};
 use turborepo_filewatch::{
     cookies::CookieWriter,
-    globwatcher::{Error as GlobWatcherError, GlobError, GlobSet, GlobWatcher},
+    globwatcher::{Error as GlobWatcherError, GlobError, GlobSet, GlobWatcher, WatchedGlob},
     hash_watcher::{Error as HashWatcherError, HashSpec, HashWatcher},
     package_watcher::{PackageWatchError, PackageWatcher},
-    FileSystemWatcher, WatchError,
+    FileSystemWatcher, WatchError, WatchOptions,
 };
 use turborepo_repository::package_manager;
 use turborepo_scm::SCM;
 
-use super::{bump_timeout::BumpTimeout, endpoint::SocketOpenError, proto};
+use super::{
+    bump_timeout::BumpTimeout,
+    checkpoint::Checkpoint,
+    clock::{self, SystemClock},
+    compaction::{CheckpointCompaction, Compaction, IdleCompactor},
+    debug_log,
+    endpoint::SocketOpenError,
+    env, http,
+    idle_handoff::IdleHandoff,
+    lease::Leases,
+    memory::{self, MemoryLimit, MemoryProbe, ProcessMemory, Respawn},
+    otlp::{ExportedSpan, OtlpExporter, OtlpLayer},
+    proto,
+    replay::{RecordLayer, Recorder},
+    title::{self, ProcessTitle},
+    trace,
+    turbo_config::TurboConfig,
+    ReadySignal,
+};
 use crate::{
     daemon::{
-        bump_timeout_layer::BumpTimeoutLayer, default_timeout_layer::DefaultTimeoutLayer,
-        endpoint::listen_socket, Paths,
+        bump_timeout_layer::BumpTimeoutLayer,
+        default_timeout_layer::DefaultTimeoutLayer,
+        endpoint::{count_connections, listen_socket},
+        Paths,
     },
     package_changes_watcher::{PackageChangeEvent, PackageChangesWatcher},
+    tracing::DebugLogTap,
 };
 
 #[derive(Debug)]
@@ -53,9 +77,70 @@ pub enum CloseReason {
     WatcherClosed,
     ServerClosed,
     Interrupt,
+    MemoryLimit,
     SocketOpenError(SocketOpenError),
 }
 
+impl CloseReason {
+    fn as_str(&self) -> &'static str {
+        match self {
+            CloseReason::Timeout => "idle timeout",
+            CloseReason::Shutdown => "explicit stop",
+            CloseReason::WatcherClosed => "file watcher closed",
+            CloseReason::ServerClosed => "server closed",
+            CloseReason::Interrupt => "signal",
+            CloseReason::MemoryLimit => "memory limit",
+            CloseReason::SocketOpenError(_) => "error",
+        }
+    }
+}
+
+/// Logs why the daemon is exiting. This is always the last line the server
+/// logs, so that the log file explains where a daemon went.
+fn log_close_reason(reason: &CloseReason) {
+    match reason {
+        CloseReason::SocketOpenError(e) => {
+            error!(
+                reason = reason.as_str(),
+                "daemon exiting: {}: {}",
+                reason.as_str(),
+                e
+            )
+        }
+        _ => info!(
+            reason = reason.as_str(),
+            "daemon exiting: {}",
+            reason.as_str()
+        ),
+    }
+}
+
+/// Tells the webhook at `url` that the daemon is exiting, and why, so that
+/// whatever is orchestrating it can react. Failures are only logged.
+async fn notify_shutdown_webhook(
+    url: &str,
+    repo_root: &AbsoluteSystemPath,
+    reason: &CloseReason,
+    uptime: Duration,
+) {
+    let payload = json!({
+        "repo_root": repo_root.as_str(),
+        "reason": reason.as_str(),
+        "uptime_ms": uptime.as_millis() as u64,
+    });
+    let result = reqwest::Client::new()
+        .post(url)
+        .timeout(SHUTDOWN_WEBHOOK_TIMEOUT)
+        .json(&payload)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    match result {
+        Ok(_) => debug!("notified {} of shutdown", url),
+        Err(e) => warn!("failed to notify {} of shutdown: {}", url, e),
+    }
+}
+
 /// We may need to pass out references to a subset of these, so
 /// we'll make them public Arcs. Eventually we can stabilize on
 /// a general API and close this up.
@@ -68,6 +153,36 @@ pub struct FileWatching {
     pub hash_watcher: Arc<HashWatcher>,
 }
 
+/// Tracks errors that put the daemon in a degraded state. These are sticky,
+/// and are only cleared by an explicit reset.
+#[derive(Debug, Default)]
+struct Health {
+    degraded_reason: Mutex<Option<String>>,
+}
+
+impl Health {
+    fn mark_degraded(&self, reason: String) {
+        warn!("daemon is degraded: {}", reason);
+        *self.degraded_reason.lock().expect("health lock poisoned") = Some(reason);
+    }
+
+    /// Returns whether the daemon was degraded before the reset.
+    fn reset(&self) -> bool {
+        self.degraded_reason
+            .lock()
+            .expect("health lock poisoned")
+            .take()
+            .is_some()
+    }
+
+    fn status(&self) -> (proto::DaemonHealth, Option<String>) {
+        match &*self.degraded_reason.lock().expect("health lock poisoned") {
+            Some(reason) => (proto::DaemonHealth::Degraded, Some(reason.clone())),
+            None => (proto::DaemonHealth::Healthy, None),
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 enum RpcError {
     #[error("deadline exceeded")]
@@ -82,6 +197,16 @@ enum RpcError {
     NoFileWatching,
     #[error("file hashing failed: {0}")]
     FileHashing(#[from] HashWatcherError),
+    #[error("invalid watched glob: {0}")]
+    InvalidWatchedGlob(#[from] globwalk::GlobError),
+    #[error("unable to count watched files: {0}")]
+    CountWatchedFiles(#[from] globwalk::WalkError),
+    #[error("package discovery failed: {0}")]
+    PackageDiscovery(#[from] PackageWatchError),
+    #[error("unable to load turbo.json: {0}")]
+    LoadTurboConfig(#[from] crate::config::Error),
+    #[error("blocking task failed: {0}")]
+    BlockingTask(#[from] tokio::task::JoinError),
 }
 
 impl From<RpcError> for tonic::Status {
@@ -99,6 +224,14 @@ impl From<RpcError> for tonic::Status {
             e @ RpcError::InvalidAnchoredPath(_, _) => {
                 tonic::Status::invalid_argument(e.to_string())
             }
+            e @ (RpcError::InvalidWatchedGlob(_)
+            | RpcError::CountWatchedFiles(_)
+            | RpcError::BlockingTask(_)) => tonic::Status::internal(e.to_string()),
+            RpcError::PackageDiscovery(PackageWatchError::Unavailable) => {
+                tonic::Status::unavailable("package discovery unavailable")
+            }
+            e @ (RpcError::PackageDiscovery(PackageWatchError::InvalidState(_))
+            | RpcError::LoadTurboConfig(_)) => tonic::Status::failed_precondition(e.to_string()),
         }
     }
 }
@@ -110,8 +243,22 @@ impl FileWatching {
     /// waiting for the filewatcher to be ready. Using `OptionalWatch`,
     /// dependent services can wait for resources they need to become
     /// available, and the server can start up without waiting for them.
-    pub fn new(repo_root: AbsoluteSystemPathBuf) -> Result<FileWatching, WatchError> {
-        let watcher = Arc::new(FileSystemWatcher::new_with_default_cookie_dir(&repo_root)?);
+    ///
+    /// Cookies are written to `cookie_dir` if one is given, rather than to
+    /// the repository's `.turbo/cookies`.
+    pub fn new(
+        repo_root: AbsoluteSystemPathBuf,
+        cookie_dir: Option<AbsoluteSystemPathBuf>,
+        options: WatchOptions,
+    ) -> Result<FileWatching, WatchError> {
+        let watcher = Arc::new(match cookie_dir {
+            Some(cookie_dir) => {
+                FileSystemWatcher::new_with_options(&repo_root, cookie_dir, options)?
+            }
+            None => {
+                FileSystemWatcher::new_with_default_cookie_dir_and_options(&repo_root, options)?
+            }
+        });
         let recv = watcher.watch();
 
         let cookie_writer = CookieWriter::new(
@@ -151,11 +298,48 @@ impl FileWatching {
 /// Timeout for every RPC the server handles
 const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
 
+/// How far apart the clock is read in the startup self-check
+const CLOCK_CHECK_PAUSE: Duration = Duration::from_millis(100);
+
+/// How long the server waits for its spans to be exported when it exits
+const OTLP_FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long the server waits for its shutdown webhook to respond when it
+/// exits
+const SHUTDOWN_WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the server waits for recorded requests to be written when it
+/// exits
+const RECORD_FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How often the server's memory use is checked against its limit
+const MEMORY_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often an idle server checks whether to compact its cache
+const COMPACTION_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
 pub struct TurboGrpcService<S> {
     repo_root: AbsoluteSystemPathBuf,
     paths: Paths,
     timeout: Duration,
     external_shutdown: S,
+    ready_signal: ReadySignal,
+    checkpoint_interval: Option<Duration>,
+    worker_threads: usize,
+    http_port: Option<u16>,
+    compression_threshold: Option<usize>,
+    memory_limit: Option<(MemoryLimit, Box<dyn MemoryProbe>)>,
+    respawn: Option<Box<dyn Respawn>>,
+    read_only: bool,
+    process_title: Option<Box<dyn ProcessTitle>>,
+    otlp_endpoint: Option<String>,
+    record_file: Option<AbsoluteSystemPathBuf>,
+    idle_handoff: bool,
+    idle_compaction: Option<Duration>,
+    idle_granularity: Option<Duration>,
+    shutdown_webhook: Option<String>,
+    debug_log: Option<DebugLogTap>,
+    watch_options: WatchOptions,
 }
 
 impl<S> TurboGrpcService<S>
@@ -182,35 +366,400 @@ where
             paths,
             timeout,
             external_shutdown,
+            ready_signal: ReadySignal::None,
+            checkpoint_interval: None,
+            worker_threads: default_worker_threads(),
+            http_port: None,
+            compression_threshold: None,
+            memory_limit: None,
+            respawn: None,
+            read_only: false,
+            process_title: None,
+            otlp_endpoint: None,
+            record_file: None,
+            idle_handoff: false,
+            idle_compaction: None,
+            idle_granularity: None,
+            shutdown_webhook: None,
+            debug_log: None,
+            watch_options: WatchOptions::default(),
         }
     }
 
+    /// Announce readiness through the given signal once the server is
+    /// accepting connections.
+    pub fn with_ready_signal(mut self, ready_signal: ReadySignal) -> Self {
+        self.ready_signal = ready_signal;
+        self
+    }
+
+    /// Checkpoint the server's state every `interval`, as well as when it
+    /// shuts down, and restore it on startup if the repository's config
+    /// hasn't changed in between.
+    pub fn with_checkpoint_interval(mut self, interval: Duration) -> Self {
+        self.checkpoint_interval = Some(interval);
+        self
+    }
+
+    /// Report `count` worker threads in the server's status. The server
+    /// doesn't start a runtime of its own, so this should match the runtime
+    /// that `serve` is run on.
+    pub fn with_worker_threads(mut self, count: usize) -> Self {
+        self.worker_threads = count;
+        self
+    }
+
+    /// Also serve a read-only view of the server's status over HTTP on the
+    /// given localhost port. See [`http::serve`].
+    pub fn with_http_port(mut self, port: u16) -> Self {
+        self.http_port = Some(port);
+        self
+    }
+
+    /// Gzip responses of at least `bytes` bytes. Compression is negotiated
+    /// per request, so clients that don't support it still get uncompressed
+    /// responses.
+    pub fn with_compression_threshold(mut self, bytes: usize) -> Self {
+        self.compression_threshold = Some(bytes);
+        self
+    }
+
+    /// Shut down gracefully, with [`CloseReason::MemoryLimit`], once `probe`
+    /// reports that the server is using more than `limit`, and start a
+    /// replacement with `respawn` once the pid lock and socket are released.
+    pub fn with_memory_limit(
+        mut self,
+        limit: MemoryLimit,
+        probe: impl MemoryProbe,
+        respawn: impl Respawn,
+    ) -> Self {
+        self.memory_limit = Some((limit, Box::new(probe)));
+        self.respawn = Some(Box::new(respawn));
+        self
+    }
+
+    /// Refuse requests that would change what the server knows about the
+    /// repository, such as `NotifyOutputsWritten` and `Reload`, while still
+    /// serving reads and status. Refused requests are logged. File watching
+    /// writes its cookies outside the repository, so that nothing is written
+    /// to it.
+    pub fn with_read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    /// Set the process title to name the repository the server is for, such
+    /// as `turbod [1a2b3c]`, so that it can be found in `ps`. See
+    /// [`title::daemon_title`].
+    pub fn with_process_title(mut self, process_title: impl ProcessTitle) -> Self {
+        self.process_title = Some(Box::new(process_title));
+        self
+    }
+
+    /// Export a span for every request, and for the server's lifetime when it
+    /// exits, to the OpenTelemetry collector at `endpoint`, such as
+    /// `http://localhost:4318`. See [`otlp`](super::otlp).
+    pub fn with_otlp_endpoint(mut self, endpoint: String) -> Self {
+        self.otlp_endpoint = Some(endpoint);
+        self
+    }
+
+    /// Record every request to `file`, replacing any previous recording, so
+    /// that it can be replayed with `turbo daemon replay`. See
+    /// [`replay`](super::replay).
+    pub fn with_record_file(mut self, file: AbsoluteSystemPathBuf) -> Self {
+        self.record_file = Some(file);
+        self
+    }
+
+    /// When the server stops, record when it would have shut down for being
+    /// idle, and on startup continue the idle timeout of a server that was
+    /// stopped moments earlier, so that a restart doesn't change when the
+    /// daemon goes away. See [`idle_handoff`](super::idle_handoff).
+    pub fn with_idle_handoff(mut self) -> Self {
+        self.idle_handoff = true;
+        self
+    }
+
+    /// Compact the checkpoint once the server has been idle for
+    /// `idle_after`, at most once per idle period. This needs checkpointing
+    /// to be enabled, as the checkpoint is the only cache the server keeps on
+    /// disk. See [`compaction`](super::compaction).
+    pub fn with_idle_compaction(mut self, idle_after: Duration) -> Self {
+        self.idle_compaction = Some(idle_after);
+        self
+    }
+
+    /// Only check whether the idle timeout has passed on multiples of
+    /// `granularity`, trading up to that much idle shutdown precision for
+    /// fewer wakeups while requests keep resetting it.
+    pub fn with_idle_granularity(mut self, granularity: Duration) -> Self {
+        self.idle_granularity = Some(granularity);
+        self
+    }
+
+    /// POST the reason the server stopped, and its uptime, as JSON to `url`
+    /// once it has shut down.
+    pub fn with_shutdown_webhook(mut self, url: String) -> Self {
+        self.shutdown_webhook = Some(url);
+        self
+    }
+
+    /// Serve `turbo daemon debug-log` from `tap`. Without one, requests to
+    /// stream the debug log are rejected. See [`debug_log`](super::debug_log).
+    pub fn with_debug_log(mut self, tap: DebugLogTap) -> Self {
+        self.debug_log = Some(tap);
+        self
+    }
+
+    /// Set up file watching with `options`, e.g. to fall back to polling if
+    /// the native watcher can't start.
+    pub fn with_watch_options(mut self, options: WatchOptions) -> Self {
+        self.watch_options = options;
+        self
+    }
+
     pub async fn serve(self) -> Result<CloseReason, package_manager::Error> {
         let Self {
             external_shutdown,
             paths,
             repo_root,
             timeout,
+            ready_signal,
+            checkpoint_interval,
+            worker_threads,
+            http_port,
+            compression_threshold,
+            memory_limit,
+            respawn,
+            read_only,
+            process_title,
+            otlp_endpoint,
+            record_file,
+            idle_handoff,
+            idle_compaction,
+            idle_granularity,
+            shutdown_webhook,
+            debug_log,
+            watch_options,
         } = self;
+        let started_at = SystemTime::now();
+
+        if let Some(mut process_title) = process_title {
+            let title = title::daemon_title(&repo_root);
+            match process_title.set(&title) {
+                Ok(()) => debug!("set process title to {}", title),
+                Err(e) => debug!("unable to set process title: {}", e),
+            }
+        }
+
+        // the idle timeout relies on the monotonic clock, so warn early if it
+        // misbehaves. This only logs, so it doesn't hold up startup
+        tokio::task::spawn(clock::self_check(SystemClock, CLOCK_CHECK_PAUSE));
 
         // A channel to trigger the shutdown of the gRPC server. This is handed out
         // to components internal to the server process such as root watching, as
         // well as available to the gRPC server itself to handle the shutdown RPC.
         let (trigger_shutdown, mut shutdown_signal) = mpsc::channel::<()>(1);
 
-        let (service, exit_root_watch, watch_root_handle) =
-            TurboGrpcServiceInner::new(repo_root.clone(), trigger_shutdown, paths.log_file);
+        // everything the server was configured with, for the config RPC. The
+        // turbo config can change while the server runs, so it is added per
+        // request
+        let resolved_config = json!({
+            "repo_root": repo_root.as_str(),
+            "idle_timeout_ms": timeout.as_millis() as u64,
+            "worker_threads": worker_threads,
+            "checkpoint_interval_ms": checkpoint_interval.map(|i| i.as_millis() as u64),
+            "http_port": http_port,
+            "compression_threshold": compression_threshold,
+            "max_memory": memory_limit.as_ref().map(|(limit, _)| limit.to_string()),
+            "read_only": read_only,
+            "otlp_endpoint": otlp_endpoint.as_deref(),
+            "record_file": record_file.as_ref().map(|file| file.as_str()),
+            "idle_handoff": idle_handoff,
+            "idle_compaction_ms": idle_compaction.map(|i| i.as_millis() as u64),
+            "idle_granularity_ms": idle_granularity.map(|g| g.as_millis() as u64),
+            "shutdown_webhook": shutdown_webhook.as_deref(),
+            "watcher_poll_fallback_ms": watch_options.poll_fallback.map(|i| i.as_millis() as u64),
+            "pid_file": paths.pid_file.as_str(),
+            "sock_file": paths.sock_file.as_str(),
+            "log_file": paths.log_file.as_str(),
+        });
 
-        let running = Arc::new(AtomicBool::new(true));
-        let (_pid_lock, stream) =
-            match listen_socket(&paths.pid_file, &paths.sock_file, running.clone()).await {
-                Ok((pid_lock, stream)) => (pid_lock, stream),
-                Err(e) => return Ok(CloseReason::SocketOpenError(e)),
+        let (mut service, exit_root_watch, watch_root_handle) = TurboGrpcServiceInner::new(
+            repo_root.clone(),
+            trigger_shutdown,
+            paths.log_file,
+            // a read-only server must not write its cookies to the repository
+            read_only.then(|| paths.cookie_dir.clone()),
+            watch_options,
+        );
+        service.resolved_config = resolved_config;
+        service.worker_threads = worker_threads;
+        service.compression_threshold = compression_threshold;
+        service.read_only = read_only;
+        service.debug_log = debug_log;
+
+        let times_saved = service.times_saved.clone();
+        let turbo_config = service.turbo_config.clone();
+        let checkpoint_file = paths.checkpoint_file.clone();
+        // checkpoints are taken on an interval and by idle compaction, so
+        // only write one at a time
+        let checkpoint_lock = Arc::new(Mutex::new(()));
+        let take_checkpoint = move || {
+            let _guard = checkpoint_lock.lock().expect("checkpoint lock poisoned");
+            let checkpoint = Checkpoint {
+                config_hash: turbo_config
+                    .lock()
+                    .expect("turbo config lock poisoned")
+                    .hash
+                    .clone(),
+                times_saved: times_saved
+                    .lock()
+                    .expect("times saved lock poisoned")
+                    .clone(),
             };
+            if let Err(e) = checkpoint.write(&checkpoint_file) {
+                warn!("failed to checkpoint daemon state: {}", e);
+            }
+        };
+        if checkpoint_interval.is_some() {
+            if let Some(checkpoint) =
+                Checkpoint::load(&paths.checkpoint_file, service.config_hash().as_deref())
+            {
+                info!("restored checkpoint {}", checkpoint.state_hash());
+                *service
+                    .times_saved
+                    .lock()
+                    .expect("times saved lock poisoned") = checkpoint.times_saved;
+            }
+        }
+
+        // this also lets clients know where to find the socket, in case it has
+        // been relocated
+        let running = Arc::new(AtomicBool::new(true));
+        let (pid_lock, stream) = match listen_socket(
+            &paths.pid_file,
+            &paths.sock_file,
+            &paths.sock_path_file,
+            running.clone(),
+        )
+        .await
+        {
+            Ok((pid_lock, stream)) => (pid_lock, stream),
+            Err(e) => {
+                let reason = CloseReason::SocketOpenError(e);
+                log_close_reason(&reason);
+                return Ok(reason);
+            }
+        };
         trace!("acquired connection stream for socket");
+        let stream = count_connections(stream, service.open_connections.clone());
+
+        let checkpoint_handle = checkpoint_interval.map(|interval| {
+            let take_checkpoint = take_checkpoint.clone();
+            tokio::task::spawn(async move {
+                let mut checkpoints = tokio::time::interval(interval);
+                // the first tick completes immediately, and there is nothing to save yet
+                checkpoints.tick().await;
+                loop {
+                    checkpoints.tick().await;
+                    take_checkpoint();
+                }
+            })
+        });
+
+        // workspaces are only known once package discovery is ready, so their
+        // turbo.json files are loaded in the background
+        let load_handle = {
+            let repo_root = repo_root.clone();
+            let package_watcher = service.package_watcher.clone();
+            let turbo_config = service.turbo_config.clone();
+            tokio::task::spawn(async move {
+                if let Err(e) = load_turbo_config(&repo_root, &package_watcher, &turbo_config).await
+                {
+                    warn!("unable to load workspace turbo.json files: {}", e);
+                }
+            })
+        };
+
+        // the socket is bound, so connections will be queued until the server
+        // starts handling them below
+        if let Err(e) = ready_signal.notify() {
+            warn!("failed to signal readiness: {}", e);
+        }
+
+        // only a server that holds the pid lock takes the handoff, so that one
+        // which fails to start doesn't throw it away
+        let handed_off = idle_handoff
+            .then(|| IdleHandoff::take(&paths.idle_handoff_file, SystemTime::now()))
+            .flatten();
+        let bump_timeout = match handed_off {
+            Some(remaining) => {
+                info!(
+                    "continuing the previous daemon's idle timeout, {:?} left",
+                    remaining
+                );
+                BumpTimeout::with_remaining(timeout, remaining)
+            }
+            None => BumpTimeout::new(timeout),
+        };
+        let bump_timeout = Arc::new(match idle_granularity {
+            Some(granularity) => bump_timeout.with_granularity(granularity),
+            None => bump_timeout,
+        });
+        let timeout_fut = {
+            let bump_timeout = bump_timeout.clone();
+            let leases = service.leases.clone();
+            async move { leases.wait_idle(&bump_timeout).await }
+        };
+        service.idle_timeout = Some(bump_timeout.clone());
+
+        let compaction_handle = match (idle_compaction, checkpoint_interval) {
+            (Some(idle_after), Some(_)) => {
+                let cache = CheckpointCompaction::new(
+                    paths.checkpoint_file.clone(),
+                    take_checkpoint.clone(),
+                );
+                let compactor = IdleCompactor::new(cache, bump_timeout.clone(), idle_after);
+                service.last_compaction = compactor.last();
+                Some(tokio::task::spawn(compactor.run(COMPACTION_CHECK_INTERVAL)))
+            }
+            (Some(_), None) => {
+                warn!("idle compaction needs checkpointing to be enabled, not compacting");
+                None
+            }
+            (None, _) => None,
+        };
+        let service = Arc::new(service);
+
+        let http_handle = http_port.and_then(|port| {
+            let status = service.clone();
+            match http::serve(port, Arc::new(move || status.daemon_status())) {
+                Ok((addr, handle)) => {
+                    info!("serving status over http at {}", addr);
+                    Some(handle)
+                }
+                Err(e) => {
+                    warn!("failed to serve status over http: {}", e);
+                    None
+                }
+            }
+        });
 
-        let bump_timeout = Arc::new(BumpTimeout::new(timeout));
-        let timeout_fut = bump_timeout.wait();
+        let memory_fut = async move {
+            match memory_limit {
+                Some((limit, probe)) => {
+                    let resident_bytes =
+                        memory::exceeded(limit, MEMORY_CHECK_INTERVAL, probe).await;
+                    warn!(
+                        "daemon is using {} bytes of memory, over its limit of {}",
+                        resident_bytes, limit
+                    );
+                }
+                None => std::future::pending().await,
+            }
+        };
 
         // when one of these futures complete, let the server gracefully shutdown
         let (grpc_shutdown_tx, shutdown_reason) = oneshot::channel();
@@ -218,17 +767,47 @@ where
             select! {
                 _ = shutdown_signal.recv() => grpc_shutdown_tx.send(CloseReason::Shutdown).ok(),
                 _ = timeout_fut => grpc_shutdown_tx.send(CloseReason::Timeout).ok(),
+                _ = memory_fut => grpc_shutdown_tx.send(CloseReason::MemoryLimit).ok(),
                 reason = external_shutdown => grpc_shutdown_tx.send(reason).ok(),
             };
         };
 
+        let (otlp_exporter, otlp_handle) = match otlp_endpoint {
+            Some(endpoint) => {
+                info!("exporting spans to {}", endpoint);
+                let (exporter, handle) = OtlpExporter::new(&endpoint);
+                (Some(exporter), Some(handle))
+            }
+            None => (None, None),
+        };
+
+        let (recorder, recorder_handle) = match &record_file {
+            Some(file) => match Recorder::new(file).await {
+                Ok((recorder, handle)) => {
+                    info!("recording requests to {}", file);
+                    (Some(recorder), Some(handle))
+                }
+                Err(e) => {
+                    warn!("unable to record requests to {}: {}", file, e);
+                    (None, None)
+                }
+            },
+            None => (None, None),
+        };
+
         let server_fut = {
+            let mut turbod = crate::daemon::proto::turbod_server::TurbodServer::from_arc(service);
+            if compression_threshold.is_some() {
+                turbod = turbod
+                    .send_compressed(CompressionEncoding::Gzip)
+                    .accept_compressed(CompressionEncoding::Gzip);
+            }
             let service = ServiceBuilder::new()
+                .layer(OtlpLayer::new(otlp_exporter.clone()))
+                .layer(RecordLayer::new(recorder))
                 .layer(BumpTimeoutLayer::new(bump_timeout.clone()))
                 .layer(DefaultTimeoutLayer)
-                .service(crate::daemon::proto::turbod_server::TurbodServer::new(
-                    service,
-                ));
+                .service(turbod);
 
             Server::builder()
                 // we respect the timeout specified by the client if it is set, but
@@ -247,8 +826,26 @@ where
         tracing::debug!("server exited");
         // Ensure our timer will exit
         running.store(false, Ordering::SeqCst);
+        if let Some(handle) = http_handle {
+            handle.abort();
+        }
+        if let Some(handle) = compaction_handle {
+            handle.abort();
+        }
+        load_handle.abort();
+        if let Some(handle) = checkpoint_handle {
+            handle.abort();
+            take_checkpoint();
+        }
         // We expect to have a signal from the grpc server on what triggered the exit
         let close_reason = shutdown_reason.await.unwrap_or(CloseReason::ServerClosed);
+        // a server that timed out has no idle budget left to hand off
+        if idle_handoff && !matches!(close_reason, CloseReason::Timeout) {
+            let handoff = IdleHandoff::new(bump_timeout.remaining(), SystemTime::now());
+            if let Err(e) = handoff.write(&paths.idle_handoff_file) {
+                warn!("failed to hand off the idle timeout: {}", e);
+            }
+        }
         // Now that the server has exited, the TurboGrpcService instance should be
         // dropped. The root watcher still has a reference to a receiver, keeping
         // the filewatcher alive. Trigger the root watcher to exit. We don't care
@@ -256,6 +853,44 @@ where
         let _ = exit_root_watch.send(());
         let _ = watch_root_handle.await;
         trace!("root watching exited");
+        paths.sock_path_file.remove_file().ok();
+        ready_signal.clear();
+        if let Some(handle) = recorder_handle {
+            if tokio::time::timeout(RECORD_FLUSH_TIMEOUT, handle)
+                .await
+                .is_err()
+            {
+                warn!("timed out recording the remaining requests");
+            }
+        }
+        if let (Some(exporter), Some(handle)) = (otlp_exporter, otlp_handle) {
+            exporter.export(ExportedSpan {
+                name: "turbod".to_string(),
+                start: started_at,
+                end: SystemTime::now(),
+                attributes: vec![("turbod.close_reason", close_reason.as_str().to_string())],
+            });
+            // the server has exited, so this was the last exporter
+            drop(exporter);
+            if tokio::time::timeout(OTLP_FLUSH_TIMEOUT, handle)
+                .await
+                .is_err()
+            {
+                warn!("timed out exporting the remaining spans");
+            }
+        }
+        if let Some(url) = &shutdown_webhook {
+            let uptime = started_at.elapsed().unwrap_or_default();
+            notify_shutdown_webhook(url, &repo_root, &close_reason, uptime).await;
+        }
+        if let (CloseReason::MemoryLimit, Some(mut respawn)) = (&close_reason, respawn) {
+            // the replacement takes over the pid lock and the socket path
+            drop(pid_lock);
+            if let Err(e) = respawn.respawn() {
+                error!("failed to start a replacement daemon: {}", e);
+            }
+        }
+        log_close_reason(&close_reason);
         Ok(close_reason)
     }
 }
@@ -267,6 +902,19 @@ struct TurboGrpcServiceInner {
     start_time: Instant,
     log_file: AbsoluteSystemPathBuf,
     package_watcher: Arc<PackageWatcher>,
+    health: Health,
+    turbo_config: Arc<Mutex<TurboConfig>>,
+    repo_root: AbsoluteSystemPathBuf,
+    worker_threads: usize,
+    idle_timeout: Option<Arc<BumpTimeout>>,
+    leases: Arc<Leases>,
+    open_connections: Arc<AtomicUsize>,
+    compression_threshold: Option<usize>,
+    read_only: bool,
+    resolved_config: serde_json::Value,
+    last_compaction: Arc<Mutex<Option<Compaction>>>,
+    debug_log: Option<DebugLogTap>,
+    env: BTreeMap<String, String>,
 }
 
 // we have a grpc service that uses watching package discovery, and where the
@@ -277,12 +925,23 @@ impl TurboGrpcServiceInner {
         repo_root: AbsoluteSystemPathBuf,
         trigger_shutdown: mpsc::Sender<()>,
         log_file: AbsoluteSystemPathBuf,
+        cookie_dir: Option<AbsoluteSystemPathBuf>,
+        watch_options: WatchOptions,
     ) -> (
         Self,
         oneshot::Sender<()>,
         JoinHandle<Result<(), WatchError>>,
     ) {
-        let file_watching = FileWatching::new(repo_root.clone()).unwrap();
+        let file_watching =
+            FileWatching::new(repo_root.clone(), cookie_dir, watch_options).unwrap();
+        // until package discovery is ready, only the root turbo.json is loaded
+        let turbo_config = TurboConfig::load(&repo_root, &[]).unwrap_or_else(|e| {
+            warn!("unable to load turbo.json: {}", e);
+            TurboConfig {
+                hash: super::config_hash(&repo_root),
+                ..Default::default()
+            }
+        });
 
         tracing::debug!("initing package discovery");
         // Note that we're cloning the Arc, not the package watcher itself
@@ -307,17 +966,100 @@ impl TurboGrpcServiceInner {
                 times_saved: Arc::new(Mutex::new(HashMap::new())),
                 start_time: Instant::now(),
                 log_file,
+                health: Health::default(),
+                turbo_config: Arc::new(Mutex::new(turbo_config)),
+                repo_root,
+                worker_threads: default_worker_threads(),
+                idle_timeout: None,
+                leases: Arc::default(),
+                open_connections: Arc::default(),
+                compression_threshold: None,
+                read_only: false,
+                resolved_config: serde_json::Value::Null,
+                last_compaction: Arc::new(Mutex::new(None)),
+                debug_log: None,
+                env: env::capture(std::env::vars()),
             },
             exit_root_watch,
             watch_root_handle,
         )
     }
 
+    #[tracing::instrument(skip_all)]
+    fn daemon_status(&self) -> proto::DaemonStatus {
+        let (health, health_message) = self.health.status();
+        proto::DaemonStatus {
+            uptime_msec: self.start_time.elapsed().as_millis() as u64,
+            log_file: self.log_file.to_string(),
+            health: health.into(),
+            health_message,
+            config_hash: self.config_hash(),
+            worker_threads: self.worker_threads as u32,
+            resident_bytes: ProcessMemory::new().resident_bytes(),
+            compacted_bytes: self
+                .last_compaction
+                .lock()
+                .expect("last compaction lock poisoned")
+                .map(|compaction| compaction.reclaimed_bytes()),
+            watcher_backend: self
+                .file_watching
+                .watcher
+                .backend()
+                .map(|backend| backend.to_string()),
+        }
+    }
+
+    fn config_hash(&self) -> Option<String> {
+        self.turbo_config
+            .lock()
+            .expect("turbo config lock poisoned")
+            .hash
+            .clone()
+    }
+
+    /// Wraps a response, opting it out of compression if it is below the
+    /// compression threshold.
+    fn response<T: prost::Message>(&self, message: T) -> tonic::Response<T> {
+        let compress = self
+            .compression_threshold
+            .is_some_and(|threshold| message.encoded_len() >= threshold);
+        let mut response = tonic::Response::new(message);
+        if !compress {
+            response.disable_compression();
+        }
+        response
+    }
+
+    /// Refuses `method` if the server is read-only.
+    fn check_writable(&self, method: &str) -> Result<(), tonic::Status> {
+        if !self.read_only {
+            return Ok(());
+        }
+        warn!("refusing {} request, the daemon is read-only", method);
+        Err(tonic::Status::permission_denied(format!(
+            "{method} is not allowed, the daemon was started with --read-only"
+        )))
+    }
+
     async fn trigger_shutdown(&self) {
         info!("triggering shutdown");
         let _ = self.shutdown.send(()).await;
     }
 
+    /// Errors from file watching having gone away mean that the daemon can
+    /// no longer give reliable answers, so we record them to surface in the
+    /// status. Transient errors, such as timeouts, are not recorded.
+    fn record_error(&self, error: RpcError) -> RpcError {
+        if matches!(
+            error,
+            RpcError::GlobWatching(GlobWatcherError::Closed | GlobWatcherError::SendError(_))
+                | RpcError::NoFileWatching
+        ) {
+            self.health.mark_degraded(error.to_string());
+        }
+        error
+    }
+
     async fn watch_globs(
         &self,
         hash: String,
@@ -432,6 +1174,50 @@ async fn watch_root(
     Ok(())
 }
 
+/// The number of worker threads a tokio runtime starts with by default.
+fn default_worker_threads() -> usize {
+    std::env::var("TOKIO_WORKER_THREADS")
+        .ok()
+        .and_then(|count| count.parse().ok())
+        .filter(|&count| count > 0)
+        .unwrap_or_else(num_cpus::get)
+}
+
+/// Loads the root `turbo.json` and those of the workspaces package discovery
+/// finds, in place of the config in `turbo_config`, which is returned. The
+/// files are read on a blocking thread, and the lock is only taken to swap
+/// the loaded config in.
+async fn load_turbo_config(
+    repo_root: &AbsoluteSystemPath,
+    package_watcher: &PackageWatcher,
+    turbo_config: &Mutex<TurboConfig>,
+) -> Result<TurboConfig, RpcError> {
+    let discovery = package_watcher.discover_packages_blocking().await?;
+    let repo_root = repo_root.to_owned();
+    let loaded =
+        tokio::task::spawn_blocking(move || TurboConfig::load(&repo_root, &discovery.workspaces))
+            .await??;
+    let mut turbo_config = turbo_config.lock().expect("turbo config lock poisoned");
+    Ok(std::mem::replace(&mut *turbo_config, loaded))
+}
+
+/// Counts the files in the repository that match a watched glob, leaving out
+/// those it excludes. This walks the repository, so it blocks.
+#[tracing::instrument(skip(repo_root))]
+fn count_matching_files(
+    repo_root: &AbsoluteSystemPath,
+    glob: &WatchedGlob,
+) -> Result<u64, RpcError> {
+    let include = ValidatedGlob::from_str(&glob.glob)?;
+    let exclude = glob
+        .exclude
+        .iter()
+        .map(|exclude| ValidatedGlob::from_str(exclude))
+        .collect::<Result<Vec<_>, _>>()?;
+    let files = globwalk::globwalk(repo_root, &[include], &exclude, WalkType::Files)?;
+    Ok(files.len() as u64)
+}
+
 #[tonic::async_trait]
 impl proto::turbod_server::Turbod for TurboGrpcServiceInner {
     async fn hello(
@@ -454,7 +1240,7 @@ impl proto::turbod_server::Turbod for TurboGrpcServiceInner {
         };
 
         if passes_version_check {
-            Ok(tonic::Response::new(proto::HelloResponse {}))
+            Ok(self.response(proto::HelloResponse {}))
         } else {
             Err(tonic::Status::failed_precondition(format!(
                 "version mismatch. Client {} Server {}",
@@ -472,25 +1258,276 @@ impl proto::turbod_server::Turbod for TurboGrpcServiceInner {
         // if Some(Ok), then the server is shutting down now
         // if Some(Err), then the server is already shutting down
         // if None, then someone has already called shutdown
-        Ok(tonic::Response::new(proto::ShutdownResponse {}))
+        Ok(self.response(proto::ShutdownResponse {}))
     }
 
     async fn status(
         &self,
         _request: tonic::Request<proto::StatusRequest>,
     ) -> Result<tonic::Response<proto::StatusResponse>, tonic::Status> {
-        Ok(tonic::Response::new(proto::StatusResponse {
-            daemon_status: Some(proto::DaemonStatus {
-                uptime_msec: self.start_time.elapsed().as_millis() as u64,
-                log_file: self.log_file.to_string(),
-            }),
+        Ok(self.response(proto::StatusResponse {
+            daemon_status: Some(self.daemon_status()),
+        }))
+    }
+
+    async fn reset_errors(
+        &self,
+        _request: tonic::Request<proto::ResetErrorsRequest>,
+    ) -> Result<tonic::Response<proto::ResetErrorsResponse>, tonic::Status> {
+        let was_degraded = self.health.reset();
+        if was_degraded {
+            info!("daemon errors reset");
+        }
+        Ok(self.response(proto::ResetErrorsResponse { was_degraded }))
+    }
+
+    async fn expire(
+        &self,
+        _request: tonic::Request<proto::ExpireRequest>,
+    ) -> Result<tonic::Response<proto::ExpireResponse>, tonic::Status> {
+        let Some(idle_timeout) = &self.idle_timeout else {
+            return Err(tonic::Status::unavailable(
+                "the idle timeout is not running",
+            ));
+        };
+        info!("expiring idle timeout");
+        idle_timeout.expire();
+        Ok(self.response(proto::ExpireResponse {}))
+    }
+
+    async fn config(
+        &self,
+        _request: tonic::Request<proto::ConfigRequest>,
+    ) -> Result<tonic::Response<proto::ConfigResponse>, tonic::Status> {
+        let mut config = self.resolved_config.clone();
+        if let Some(config) = config.as_object_mut() {
+            let turbo_config = self
+                .turbo_config
+                .lock()
+                .expect("turbo config lock poisoned");
+            config.insert("config_hash".to_string(), json!(turbo_config.hash));
+            config.insert("turbo".to_string(), json!(turbo_config.tasks));
+        }
+        Ok(self.response(proto::ConfigResponse {
+            config_json: config.to_string(),
+        }))
+    }
+
+    async fn env(
+        &self,
+        _request: tonic::Request<proto::EnvRequest>,
+    ) -> Result<tonic::Response<proto::EnvResponse>, tonic::Status> {
+        Ok(self.response(proto::EnvResponse {
+            vars: self.env.clone().into_iter().collect(),
+        }))
+    }
+
+    async fn acquire_lease(
+        &self,
+        request: tonic::Request<proto::AcquireLeaseRequest>,
+    ) -> Result<tonic::Response<proto::AcquireLeaseResponse>, tonic::Status> {
+        let holder = request.into_inner().holder;
+        let lease_id = self.leases.acquire(holder.clone());
+        info!("acquired lease {} for {}", lease_id, holder);
+        Ok(self.response(proto::AcquireLeaseResponse { lease_id }))
+    }
+
+    async fn release_lease(
+        &self,
+        request: tonic::Request<proto::ReleaseLeaseRequest>,
+    ) -> Result<tonic::Response<proto::ReleaseLeaseResponse>, tonic::Status> {
+        let lease_id = request.into_inner().lease_id;
+        let released = self.leases.release(lease_id);
+        if released {
+            info!("released lease {}", lease_id);
+        }
+        Ok(self.response(proto::ReleaseLeaseResponse { released }))
+    }
+
+    async fn release_leases(
+        &self,
+        _request: tonic::Request<proto::ReleaseLeasesRequest>,
+    ) -> Result<tonic::Response<proto::ReleaseLeasesResponse>, tonic::Status> {
+        let released = self.leases.release_all();
+        info!("released all {} leases", released);
+        Ok(self.response(proto::ReleaseLeasesResponse {
+            released: released as u32,
+        }))
+    }
+
+    async fn warm(
+        &self,
+        request: tonic::Request<proto::WarmRequest>,
+    ) -> Result<tonic::Response<proto::WarmResponse>, tonic::Status> {
+        let path = request.into_inner().path;
+        let start = Instant::now();
+        // hashing every file below the path is what a run needs first, and
+        // leaves the hash watcher watching them
+        let file_hashes = self
+            .get_file_hashes(path.clone(), vec![])
+            .await
+            .map_err(|e| self.record_error(e))?;
+        info!(
+            "warmed {} files below {} in {:?}",
+            file_hashes.len(),
+            path,
+            start.elapsed()
+        );
+        Ok(self.response(proto::WarmResponse {
+            file_count: file_hashes.len() as u64,
         }))
     }
 
+    type DebugLogStream = debug_log::DebugLogStream;
+
+    async fn debug_log(
+        &self,
+        request: tonic::Request<proto::DebugLogRequest>,
+    ) -> Result<tonic::Response<Self::DebugLogStream>, tonic::Status> {
+        let Some(tap) = &self.debug_log else {
+            return Err(tonic::Status::unavailable(
+                "the daemon was started without a debug log",
+            ));
+        };
+        let window = Duration::from_millis(request.into_inner().duration_ms);
+        info!("streaming the debug log for {:?}", window);
+        Ok(tonic::Response::new(debug_log::stream(
+            tap.subscribe(),
+            window,
+        )))
+    }
+
+    async fn why_alive(
+        &self,
+        _request: tonic::Request<proto::WhyAliveRequest>,
+    ) -> Result<tonic::Response<proto::WhyAliveResponse>, tonic::Status> {
+        let Some(idle_timeout) = &self.idle_timeout else {
+            return Err(tonic::Status::unavailable(
+                "the idle timeout is not running",
+            ));
+        };
+        Ok(self.response(proto::WhyAliveResponse {
+            idle_timeout_ms: idle_timeout.increment().as_millis() as u64,
+            idle_for_ms: idle_timeout.idle_for().as_millis() as u64,
+            remaining_ms: idle_timeout.remaining().as_millis() as u64,
+            lease_holders: self.leases.holders(),
+            // the client asking is connected too
+            connected_clients: self
+                .open_connections
+                .load(Ordering::Relaxed)
+                .saturating_sub(1) as u32,
+        }))
+    }
+
+    async fn reload(
+        &self,
+        _request: tonic::Request<proto::ReloadRequest>,
+    ) -> Result<tonic::Response<proto::ReloadResponse>, tonic::Status> {
+        self.check_writable("Reload")?;
+        let old_config =
+            load_turbo_config(&self.repo_root, &self.package_watcher, &self.turbo_config)
+                .await
+                .map_err(|e| self.record_error(e))?;
+        let (new_config_hash, changed_packages) = {
+            let new_config = self
+                .turbo_config
+                .lock()
+                .expect("turbo config lock poisoned");
+            (
+                new_config.hash.clone(),
+                new_config.changed_packages(&old_config),
+            )
+        };
+        if !changed_packages.is_empty() {
+            info!(
+                "reloaded turbo.json: {} -> {}, changed tasks of {}",
+                old_config.hash.as_deref().unwrap_or("none"),
+                new_config_hash.as_deref().unwrap_or("none"),
+                changed_packages.join(", ")
+            );
+        }
+        Ok(self.response(proto::ReloadResponse {
+            old_config_hash: old_config.hash,
+            new_config_hash,
+            changed_packages,
+        }))
+    }
+
+    async fn trace(
+        &self,
+        request: tonic::Request<proto::TraceRequest>,
+    ) -> Result<tonic::Response<proto::TraceResponse>, tonic::Status> {
+        let method = request.into_inner().method;
+        let (result, spans) = match method.as_str() {
+            "status" => {
+                let (result, spans) = trace::capture(|| {
+                    self.status(tonic::Request::new(proto::StatusRequest {}))
+                        .instrument(tracing::info_span!("status"))
+                })
+                .await;
+                (result.map(|_| ()), spans)
+            }
+            "watches" => {
+                let (result, spans) = trace::capture(|| {
+                    self.watches(tonic::Request::new(proto::WatchesRequest {}))
+                        .instrument(tracing::info_span!("watches"))
+                })
+                .await;
+                (result.map(|_| ()), spans)
+            }
+            "discover_packages" => {
+                let (result, spans) = trace::capture(|| {
+                    self.discover_packages(tonic::Request::new(proto::DiscoverPackagesRequest {}))
+                        .instrument(tracing::info_span!("discover_packages"))
+                })
+                .await;
+                (result.map(|_| ()), spans)
+            }
+            _ => {
+                return Err(tonic::Status::invalid_argument(format!(
+                    "cannot trace method {method}, expected one of status, watches or \
+                     discover_packages"
+                )))
+            }
+        };
+        result?;
+        Ok(self.response(proto::TraceResponse { spans }))
+    }
+
+    async fn watches(
+        &self,
+        _request: tonic::Request<proto::WatchesRequest>,
+    ) -> Result<tonic::Response<proto::WatchesResponse>, tonic::Status> {
+        let globs = self
+            .file_watching
+            .glob_watcher
+            .watched_globs(REQUEST_TIMEOUT)
+            .await
+            .map_err(|e| self.record_error(e.into()))?;
+        // each glob walks the repository, which can take a while in a large
+        // monorepo
+        let repo_root = self.repo_root.clone();
+        let globs = tokio::task::spawn_blocking(move || {
+            globs
+                .into_iter()
+                .map(|glob| {
+                    Ok(proto::WatchedGlob {
+                        file_count: count_matching_files(&repo_root, &glob)?,
+                        glob: glob.glob,
+                    })
+                })
+                .collect::<Result<Vec<_>, RpcError>>()
+        })
+        .await
+        .map_err(|e| tonic::Status::internal(e.to_string()))??;
+        Ok(self.response(proto::WatchesResponse { globs }))
+    }
+
     async fn notify_outputs_written(
         &self,
         request: tonic::Request<proto::NotifyOutputsWrittenRequest>,
     ) -> Result<tonic::Response<proto::NotifyOutputsWrittenResponse>, tonic::Status> {
+        self.check_writable("NotifyOutputsWritten")?;
         let inner = request.into_inner();
 
         self.watch_globs(
@@ -499,8 +1536,9 @@ impl proto::turbod_server::Turbod for TurboGrpcServiceInner {
             inner.output_exclusion_globs,
             inner.time_saved,
         )
-        .await?;
-        Ok(tonic::Response::new(proto::NotifyOutputsWrittenResponse {}))
+        .await
+        .map_err(|e| self.record_error(e))?;
+        Ok(self.response(proto::NotifyOutputsWrittenResponse {}))
     }
 
     async fn get_changed_outputs(
@@ -510,8 +1548,9 @@ impl proto::turbod_server::Turbod for TurboGrpcServiceInner {
         let inner = request.into_inner();
         let (changed, time_saved) = self
             .get_changed_outputs(inner.hash, HashSet::from_iter(inner.output_globs))
-            .await?;
-        Ok(tonic::Response::new(proto::GetChangedOutputsResponse {
+            .await
+            .map_err(|e| self.record_error(e))?;
+        Ok(self.response(proto::GetChangedOutputsResponse {
             changed_output_globs: changed.into_iter().collect(),
             time_saved,
         }))
@@ -527,10 +1566,9 @@ impl proto::turbod_server::Turbod for TurboGrpcServiceInner {
         let inner = request.into_inner();
         let file_hashes = self
             .get_file_hashes(inner.package_path, inner.input_globs)
-            .await?;
-        Ok(tonic::Response::new(proto::GetFileHashesResponse {
-            file_hashes,
-        }))
+            .await
+            .map_err(|e| self.record_error(e))?;
+        Ok(self.response(proto::GetFileHashesResponse { file_hashes }))
     }
 
     async fn discover_packages(
@@ -538,7 +1576,7 @@ impl proto::turbod_server::Turbod for TurboGrpcServiceInner {
         _request: tonic::Request<proto::DiscoverPackagesRequest>,
     ) -> Result<tonic::Response<proto::DiscoverPackagesResponse>, tonic::Status> {
         match self.package_watcher.discover_packages().await {
-            Some(Ok(packages)) => Ok(tonic::Response::new(proto::DiscoverPackagesResponse {
+            Some(Ok(packages)) => Ok(self.response(proto::DiscoverPackagesResponse {
                 package_files: packages
                     .workspaces
                     .into_iter()
@@ -563,7 +1601,7 @@ impl proto::turbod_server::Turbod for TurboGrpcServiceInner {
         _request: tonic::Request<proto::DiscoverPackagesRequest>,
     ) -> Result<tonic::Response<proto::DiscoverPackagesResponse>, tonic::Status> {
         match self.package_watcher.discover_packages_blocking().await {
-            Ok(packages) => Ok(tonic::Response::new(proto::DiscoverPackagesResponse {
+            Ok(packages) => Ok(self.response(proto::DiscoverPackagesResponse {
                 package_files: packages
                     .workspaces
                     .into_iter()
@@ -670,21 +1708,42 @@ impl NamedService for TurboGrpcServiceInner {
 mod test {
     use std::{
         assert_matches::{self, assert_matches},
-        time::{Duration, Instant},
+        collections::BTreeSet,
+        path::PathBuf,
+        str::FromStr,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant, SystemTime},
     };
 
-    use futures::FutureExt;
+    use camino::Utf8Path;
+    use futures::{Future, FutureExt};
+    use globwalk::ValidatedGlob;
     use semver::Version;
+    use serde_json::json;
     use test_case::test_case;
-    use tokio::sync::oneshot;
-    use turbopath::AbsoluteSystemPathBuf;
+    use tokio::{
+        sync::{mpsc, oneshot},
+        task::JoinHandle,
+    };
+    use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf, AnchoredSystemPath};
+    use turborepo_filewatch::WatchOptions;
     use turborepo_repository::{
         discovery::{DiscoveryResponse, PackageDiscovery},
         package_manager::PackageManager,
     };
 
-    use super::compare_versions;
-    use crate::daemon::{proto::VersionRange, CloseReason, Paths, TurboGrpcService};
+    use super::{compare_versions, log_close_reason, RpcError, TurboGrpcServiceInner};
+    use crate::daemon::{
+        config_hash,
+        endpoint::SocketOpenError,
+        idle_handoff::IdleHandoff,
+        proto::{
+            turbod_server::Turbod, DaemonHealth, ResetErrorsRequest, StatusRequest, VersionRange,
+        },
+        replay, scoped_root, CloseReason, DaemonClient, DaemonConnector, DaemonConnectorError,
+        DaemonError, MemoryLimit, MemoryProbe, Paths, ProcessTitle, ReadySignal, Respawn,
+        TurboGrpcService,
+    };
 
     #[test_case("1.2.3", "1.2.3", VersionRange::Exact, true ; "exact match")]
     #[test_case("1.2.3", "1.2.3", VersionRange::Patch, true ; "patch match")]
@@ -737,26 +1796,107 @@ mod test {
         }
     }
 
-    // the windows runner starts a new thread to accept uds requests,
-    // so we need a multi-threaded runtime
-    #[tokio::test(flavor = "multi_thread")]
-    #[tracing_test::traced_test]
-    async fn lifecycle() {
+    /// Creates an npm workspace in a temporary directory, which must be kept
+    /// alive for as long as the repository is used.
+    fn test_repo() -> (tempfile::TempDir, AbsoluteSystemPathBuf, Paths) {
         let tempdir = tempfile::tempdir().unwrap();
-        let path = AbsoluteSystemPathBuf::try_from(tempdir.path())
+        let repo_root = AbsoluteSystemPathBuf::try_from(tempdir.path())
             .unwrap()
             .to_realpath()
+            .unwrap()
+            .join_component("repo");
+        repo_root.create_dir_all().unwrap();
+        repo_root
+            .join_component("package.json")
+            .create_with_contents(r#"{"workspaces": ["packages/*"]}"#)
+            .unwrap();
+        repo_root
+            .join_component("package-lock.json")
+            .create_with_contents("")
             .unwrap();
-
-        let repo_root = path.join_component("repo");
         let paths = Paths::from_repo_root(&repo_root);
-        tracing::info!("start");
+        (tempdir, repo_root, paths)
+    }
 
+    /// A signal that interrupts a server once the sender is used.
+    fn exit_signal() -> (
+        oneshot::Sender<CloseReason>,
+        impl Future<Output = CloseReason> + Send + 'static,
+    ) {
         let (tx, rx) = oneshot::channel::<CloseReason>();
-        let exit_signal = rx.map(|_result| CloseReason::Interrupt);
+        (tx, rx.map(|_result| CloseReason::Interrupt))
+    }
 
-        let service = TurboGrpcService::new(
-            repo_root.clone(),
+    /// Starts `server` in the background, returning once it has taken the
+    /// pid lock and bound its socket, or has already exited.
+    async fn serve_until_ready<S>(
+        server: TurboGrpcService<S>,
+    ) -> JoinHandle<Result<CloseReason, package_manager::Error>>
+    where
+        S: Future<Output = CloseReason> + Send + 'static,
+    {
+        let ready_dir = tempfile::tempdir().unwrap();
+        let ready_file = AbsoluteSystemPathBuf::try_from(ready_dir.path())
+            .unwrap()
+            .join_component("ready");
+        let handle = tokio::task::spawn(
+            server
+                .with_ready_signal(ReadySignal::File(ready_file.clone()))
+                .serve(),
+        );
+
+        let start = Instant::now();
+        while !ready_file.exists() && !handle.is_finished() {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "server never became ready"
+            );
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        handle
+    }
+
+    /// Polls the config RPC until the daemon has loaded the turbo.json of
+    /// `package`, which it does once package discovery is ready.
+    async fn loaded_config(
+        client: &mut DaemonClient<DaemonConnector>,
+        package: &str,
+    ) -> serde_json::Value {
+        let start = Instant::now();
+        loop {
+            let config: serde_json::Value =
+                serde_json::from_str(&client.config().await.unwrap()).unwrap();
+            if !config["turbo"][package].is_null() {
+                return config;
+            }
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "the turbo.json of {package} was never loaded"
+            );
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    // the windows runner starts a new thread to accept uds requests,
+    // so we need a multi-threaded runtime
+    #[tokio::test(flavor = "multi_thread")]
+    #[tracing_test::traced_test]
+    async fn lifecycle() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = AbsoluteSystemPathBuf::try_from(tempdir.path())
+            .unwrap()
+            .to_realpath()
+            .unwrap();
+
+        let repo_root = path.join_component("repo");
+        let paths = Paths::from_repo_root(&repo_root);
+        tracing::info!("start");
+
+        let (tx, rx) = oneshot::channel::<CloseReason>();
+        let exit_signal = rx.map(|_result| CloseReason::Interrupt);
+
+        let service = TurboGrpcService::new(
+            repo_root.clone(),
             paths.clone(),
             Duration::from_secs(60 * 60),
             exit_signal,
@@ -889,4 +2029,1043 @@ mod test {
             .expect("server exited");
         assert_matches!(close_reason, Ok(CloseReason::Shutdown));
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[tracing_test::traced_test]
+    async fn socket_dir_fallback() {
+        let (_tempdir, repo_root, _) = test_repo();
+
+        // a directory nested under a file can never be created, so the server
+        // must fall back to the default socket location
+        let not_a_dir = repo_root.parent().unwrap().join_component("file");
+        not_a_dir.create_with_contents("").unwrap();
+        let paths =
+            Paths::from_repo_root_with_socket_dir(&repo_root, &not_a_dir.join_component("sockets"));
+        assert_eq!(paths.sock_file, Paths::from_repo_root(&repo_root).sock_file);
+
+        let (tx, exit_signal) = exit_signal();
+
+        let server = TurboGrpcService::new(
+            repo_root.clone(),
+            paths.clone(),
+            Duration::from_secs(60 * 60),
+            exit_signal,
+        );
+        let handle = serve_until_ready(server).await;
+
+        let mut client = DaemonConnector::new(false, false, &repo_root)
+            .connect()
+            .await
+            .expect("connector finds the fallback socket");
+        assert_eq!(client.paths().sock_file, paths.sock_file);
+        client.status().await.unwrap();
+
+        tx.send(CloseReason::Interrupt).unwrap();
+        handle.await.unwrap().unwrap();
+        assert!(
+            !paths.sock_path_file.exists(),
+            "socket record must be deleted"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn expire() {
+        let (_tempdir, repo_root, paths) = test_repo();
+
+        let (_tx, exit_signal) = exit_signal();
+
+        let server = TurboGrpcService::new(
+            repo_root.clone(),
+            paths.clone(),
+            Duration::from_secs(60 * 60),
+            exit_signal,
+        );
+        let handle = serve_until_ready(server).await;
+
+        let mut client = DaemonConnector::new(false, false, &repo_root)
+            .connect()
+            .await
+            .unwrap();
+        client.expire().await.unwrap();
+
+        let close_reason = tokio::time::timeout(Duration::from_secs(5), handle)
+            .await
+            .expect("no timeout")
+            .expect("server exited");
+        assert_matches!(
+            close_reason,
+            Ok(CloseReason::Timeout),
+            "expiring takes the idle path, not the shutdown path"
+        );
+        assert!(!paths.pid_file.exists(), "pid file must be deleted");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn leases() {
+        let (_tempdir, repo_root, paths) = test_repo();
+
+        let (_tx, exit_signal) = exit_signal();
+
+        let server = TurboGrpcService::new(
+            repo_root.clone(),
+            paths.clone(),
+            Duration::from_secs(1),
+            exit_signal,
+        );
+        let mut handle = serve_until_ready(server).await;
+
+        let mut client = DaemonConnector::new(false, false, &repo_root)
+            .connect()
+            .await
+            .unwrap();
+        let first = client.acquire_lease("first".to_string()).await.unwrap();
+        client.acquire_lease("second".to_string()).await.unwrap();
+        assert!(client.release_lease(first).await.unwrap());
+        assert!(!client.release_lease(first).await.unwrap());
+        client.acquire_lease("third".to_string()).await.unwrap();
+
+        // the idle timeout has passed, but the leases keep the daemon alive
+        client.expire().await.unwrap();
+        assert!(
+            tokio::time::timeout(Duration::from_millis(500), &mut handle)
+                .await
+                .is_err(),
+            "held leases must keep the daemon alive"
+        );
+
+        assert_eq!(client.release_leases().await.unwrap(), 2);
+        let close_reason = tokio::time::timeout(Duration::from_secs(10), handle)
+            .await
+            .expect("idle timeout fires once the leases are released")
+            .expect("server exited");
+        assert_matches!(close_reason, Ok(CloseReason::Timeout));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn why_alive() {
+        let (_tempdir, repo_root, paths) = test_repo();
+
+        let (tx, exit_signal) = exit_signal();
+
+        let server = TurboGrpcService::new(
+            repo_root.clone(),
+            paths.clone(),
+            Duration::from_secs(60 * 60),
+            exit_signal,
+        );
+        let handle = serve_until_ready(server).await;
+
+        let mut client = DaemonConnector::new(false, false, &repo_root)
+            .connect()
+            .await
+            .unwrap();
+        let why_alive = client.why_alive().await.unwrap();
+        assert_eq!(why_alive.connected_clients, 0);
+        assert!(why_alive.lease_holders.is_empty());
+
+        let mut other = DaemonConnector::new(false, false, &repo_root)
+            .connect()
+            .await
+            .unwrap();
+        other.acquire_lease("other".to_string()).await.unwrap();
+        let why_alive = client.why_alive().await.unwrap();
+        assert_eq!(why_alive.connected_clients, 1);
+        assert_eq!(why_alive.lease_holders, vec!["other".to_string()]);
+
+        tx.send(CloseReason::Interrupt).unwrap();
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn idle_handoff() {
+        let (_tempdir, repo_root, paths) = test_repo();
+
+        // a daemon restarted with a second left of its idle timeout
+        IdleHandoff::new(Duration::from_secs(1), SystemTime::now())
+            .write(&paths.idle_handoff_file)
+            .unwrap();
+
+        let (_tx, exit_signal) = exit_signal();
+
+        let server = TurboGrpcService::new(
+            repo_root.clone(),
+            paths.clone(),
+            Duration::from_secs(60 * 60),
+            exit_signal,
+        )
+        .with_idle_handoff();
+        let handle = serve_until_ready(server).await;
+
+        let close_reason = tokio::time::timeout(Duration::from_secs(10), handle)
+            .await
+            .expect("the previous daemon's idle budget is kept")
+            .expect("server exited");
+        assert_matches!(close_reason, Ok(CloseReason::Timeout));
+        assert!(
+            !paths.idle_handoff_file.exists(),
+            "the handoff is taken on startup, and not written on an idle shutdown"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn idle_handoff_on_stop() {
+        let (_tempdir, repo_root, paths) = test_repo();
+
+        let (tx, exit_signal) = exit_signal();
+
+        let server = TurboGrpcService::new(
+            repo_root.clone(),
+            paths.clone(),
+            Duration::from_secs(60),
+            exit_signal,
+        )
+        .with_idle_handoff();
+        let handle = serve_until_ready(server).await;
+
+        tx.send(CloseReason::Interrupt).unwrap();
+        handle.await.unwrap().unwrap();
+
+        let remaining = IdleHandoff::take(&paths.idle_handoff_file, SystemTime::now())
+            .expect("the stopped server hands off its idle timeout");
+        assert!(remaining > Duration::from_secs(50) && remaining <= Duration::from_secs(60));
+    }
+
+    struct FixedMemory(u64);
+
+    impl MemoryProbe for FixedMemory {
+        fn resident_bytes(&mut self) -> Option<u64> {
+            Some(self.0)
+        }
+    }
+
+    /// Records whether the pid lock was released each time a replacement was
+    /// started.
+    struct RecordedRespawn {
+        pid_file: AbsoluteSystemPathBuf,
+        respawns: Arc<Mutex<Vec<bool>>>,
+    }
+
+    impl Respawn for RecordedRespawn {
+        fn respawn(&mut self) -> std::io::Result<()> {
+            self.respawns.lock().unwrap().push(!self.pid_file.exists());
+            Ok(())
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn memory_limit() {
+        let (_tempdir, repo_root, paths) = test_repo();
+
+        let (_tx, exit_signal) = exit_signal();
+
+        let respawns = Arc::new(Mutex::new(Vec::new()));
+        let server = TurboGrpcService::new(
+            repo_root.clone(),
+            paths.clone(),
+            Duration::from_secs(60 * 60),
+            exit_signal,
+        )
+        .with_memory_limit(
+            MemoryLimit(1 << 20),
+            FixedMemory(2 << 20),
+            RecordedRespawn {
+                pid_file: paths.pid_file.clone(),
+                respawns: respawns.clone(),
+            },
+        );
+        let handle = tokio::task::spawn(server.serve());
+
+        let close_reason = tokio::time::timeout(Duration::from_secs(10), handle)
+            .await
+            .expect("no timeout")
+            .expect("server exited");
+        assert_matches!(
+            close_reason,
+            Ok(CloseReason::MemoryLimit),
+            "crossing the limit takes the graceful shutdown path"
+        );
+        assert!(!paths.pid_file.exists(), "pid file must be deleted");
+        assert_eq!(
+            *respawns.lock().unwrap(),
+            [true],
+            "a replacement is started once, after the pid lock is released"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn otlp_export() {
+        use axum::{extract::State, routing::post, Json, Router};
+
+        let received = Arc::new(Mutex::new(Vec::<serde_json::Value>::new()));
+        let collector = {
+            async fn receive(
+                State(received): State<Arc<Mutex<Vec<serde_json::Value>>>>,
+                Json(request): Json<serde_json::Value>,
+            ) {
+                received.lock().unwrap().push(request);
+            }
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            let app = Router::new()
+                .route("/v1/traces", post(receive))
+                .with_state(received.clone());
+            tokio::task::spawn(
+                axum::Server::from_tcp(listener)
+                    .unwrap()
+                    .serve(app.into_make_service()),
+            );
+            format!("http://{addr}")
+        };
+
+        let (_tempdir, repo_root, paths) = test_repo();
+
+        let (tx, exit_signal) = exit_signal();
+
+        let server = TurboGrpcService::new(
+            repo_root.clone(),
+            paths.clone(),
+            Duration::from_secs(60 * 60),
+            exit_signal,
+        )
+        .with_otlp_endpoint(collector);
+        let handle = serve_until_ready(server).await;
+
+        let mut client = DaemonConnector::new(false, false, &repo_root)
+            .connect()
+            .await
+            .unwrap();
+        client.status().await.unwrap();
+
+        tx.send(CloseReason::Interrupt).unwrap();
+        handle.await.unwrap().unwrap();
+
+        // the server flushes its spans before exiting
+        let spans = received
+            .lock()
+            .unwrap()
+            .iter()
+            .flat_map(|request| {
+                request["resourceSpans"][0]["scopeSpans"][0]["spans"]
+                    .as_array()
+                    .cloned()
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>();
+        let status = spans
+            .iter()
+            .find(|span| span["name"] == "turbodprotocol.Turbod/Status")
+            .expect("the status request is exported");
+        assert_eq!(status["attributes"][2]["key"], "rpc.method");
+        assert_eq!(status["attributes"][2]["value"]["stringValue"], "Status");
+        let lifetime = spans
+            .iter()
+            .find(|span| span["name"] == "turbod")
+            .expect("the daemon's lifetime is exported");
+        assert_eq!(
+            lifetime["attributes"][0]["value"]["stringValue"],
+            CloseReason::Interrupt.as_str()
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn shutdown_webhook() {
+        use axum::{extract::State, routing::post, Json, Router};
+
+        let received = Arc::new(Mutex::new(Vec::<serde_json::Value>::new()));
+        let webhook = {
+            async fn receive(
+                State(received): State<Arc<Mutex<Vec<serde_json::Value>>>>,
+                Json(payload): Json<serde_json::Value>,
+            ) {
+                received.lock().unwrap().push(payload);
+            }
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            let app = Router::new()
+                .route("/daemon-exited", post(receive))
+                .with_state(received.clone());
+            tokio::task::spawn(
+                axum::Server::from_tcp(listener)
+                    .unwrap()
+                    .serve(app.into_make_service()),
+            );
+            format!("http://{addr}/daemon-exited")
+        };
+
+        let (_tempdir, repo_root, paths) = test_repo();
+
+        let (_tx, exit_signal) = exit_signal();
+
+        let close_reason = TurboGrpcService::new(
+            repo_root.clone(),
+            paths,
+            Duration::from_millis(10),
+            exit_signal,
+        )
+        .with_shutdown_webhook(webhook)
+        .serve()
+        .await;
+        assert_matches!(close_reason, Ok(CloseReason::Timeout));
+
+        // the server waits for the webhook before exiting
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0]["reason"], CloseReason::Timeout.as_str());
+        assert_eq!(received[0]["repo_root"], repo_root.as_str());
+        assert!(received[0]["uptime_ms"].is_u64());
+    }
+
+    struct RecordedTitle(Arc<Mutex<Option<String>>>);
+
+    impl ProcessTitle for RecordedTitle {
+        fn set(&mut self, title: &str) -> std::io::Result<()> {
+            *self.0.lock().unwrap() = Some(title.to_string());
+            Ok(())
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn process_title() {
+        let (_tempdir, repo_root, paths) = test_repo();
+
+        let (tx, exit_signal) = exit_signal();
+
+        let title = Arc::new(Mutex::new(None));
+        let server = TurboGrpcService::new(
+            repo_root.clone(),
+            paths.clone(),
+            Duration::from_secs(60 * 60),
+            exit_signal,
+        )
+        .with_process_title(RecordedTitle(title.clone()));
+        let handle = serve_until_ready(server).await;
+        tx.send(CloseReason::Interrupt).unwrap();
+        handle.await.unwrap().unwrap();
+
+        let repo_hash = crate::daemon::repo_hash(&repo_root);
+        let title = title
+            .lock()
+            .unwrap()
+            .clone()
+            .expect("title is set at startup");
+        assert!(title.contains(&repo_hash[..6]), "{title} names the repo");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn worker_threads() {
+        let (_tempdir, repo_root, paths) = test_repo();
+
+        let (tx, exit_signal) = exit_signal();
+
+        let server = TurboGrpcService::new(
+            repo_root.clone(),
+            paths.clone(),
+            Duration::from_secs(60 * 60),
+            exit_signal,
+        )
+        .with_worker_threads(3);
+        let handle = serve_until_ready(server).await;
+
+        let mut client = DaemonConnector::new(false, false, &repo_root)
+            .connect()
+            .await
+            .unwrap();
+        assert_eq!(client.status().await.unwrap().worker_threads, 3);
+
+        tx.send(CloseReason::Interrupt).unwrap();
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reload() {
+        let (_tempdir, repo_root, paths) = test_repo();
+        let turbo_json = repo_root.join_component("turbo.json");
+        turbo_json
+            .create_with_contents(r#"{"pipeline": {}}"#)
+            .unwrap();
+        let app = repo_root.join_components(&["packages", "app"]);
+        app.create_dir_all().unwrap();
+        app.join_component("package.json")
+            .create_with_contents(r#"{"name": "app"}"#)
+            .unwrap();
+        let app_turbo_json = app.join_component("turbo.json");
+        app_turbo_json
+            .create_with_contents(r#"{"extends": ["//"], "pipeline": {"build": {}}}"#)
+            .unwrap();
+
+        let (tx, exit_signal) = exit_signal();
+
+        let server = TurboGrpcService::new(
+            repo_root.clone(),
+            paths.clone(),
+            Duration::from_secs(60 * 60),
+            exit_signal,
+        );
+        let handle = serve_until_ready(server).await;
+
+        let mut client = DaemonConnector::new(false, false, &repo_root)
+            .connect()
+            .await
+            .unwrap();
+        let started_with = client.status().await.unwrap().config_hash;
+        assert_eq!(started_with, crate::daemon::config_hash(&repo_root));
+        let config = loaded_config(&mut client, "app").await;
+        assert_eq!(config["turbo"]["app"], json!({"build": {}}));
+
+        // a workspace's tasks are reloaded without the root turbo.json changing
+        app_turbo_json
+            .create_with_contents(
+                r#"{"extends": ["//"], "pipeline": {"build": {"outputs": ["dist/**"]}}}"#,
+            )
+            .unwrap();
+        let reload = client.reload().await.unwrap();
+        assert_eq!(reload.old_config_hash, started_with);
+        assert_eq!(reload.new_config_hash, started_with);
+        assert_eq!(reload.changed_packages, vec!["app".to_string()]);
+        let config = loaded_config(&mut client, "app").await;
+        assert_eq!(
+            config["turbo"]["app"],
+            json!({"build": {"outputs": ["dist/**"]}})
+        );
+
+        // root tasks are merged into the workspace's, so both change
+        turbo_json
+            .create_with_contents(r#"{"pipeline": {"build": {"dependsOn": ["^build"]}}}"#)
+            .unwrap();
+        let reload = client.reload().await.unwrap();
+        assert_eq!(reload.old_config_hash, started_with);
+        assert_eq!(
+            reload.new_config_hash,
+            crate::daemon::config_hash(&repo_root)
+        );
+        assert_ne!(reload.old_config_hash, reload.new_config_hash);
+        assert_eq!(
+            reload.changed_packages,
+            vec!["//".to_string(), "app".to_string()]
+        );
+
+        // the same connection sees the new config
+        assert_eq!(
+            client.status().await.unwrap().config_hash,
+            reload.new_config_hash
+        );
+        let config = loaded_config(&mut client, "app").await;
+        assert_eq!(
+            config["turbo"]["app"],
+            json!({"build": {"dependsOn": ["^build"], "outputs": ["dist/**"]}})
+        );
+
+        // a turbo.json that can't be loaded leaves the loaded config in place
+        app_turbo_json.create_with_contents("{").unwrap();
+        assert!(client.reload().await.is_err());
+        assert_eq!(loaded_config(&mut client, "app").await, config);
+
+        tx.send(CloseReason::Interrupt).unwrap();
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn read_only() {
+        let (_tempdir, repo_root, paths) = test_repo();
+
+        let (tx, exit_signal) = exit_signal();
+
+        let server = TurboGrpcService::new(
+            repo_root.clone(),
+            paths.clone(),
+            Duration::from_secs(60 * 60),
+            exit_signal,
+        )
+        .with_read_only();
+        let handle = serve_until_ready(server).await;
+
+        let mut client = DaemonConnector::new(false, false, &repo_root)
+            .connect()
+            .await
+            .unwrap();
+        let glob = ValidatedGlob::from_str("dist/**").unwrap();
+        let result = client
+            .notify_outputs_written("some-hash".to_string(), &[glob], &[], 100)
+            .await;
+        assert_matches!(result, Err(DaemonError::ReadOnly(message)) if message.contains("--read-only"));
+        assert_matches!(client.reload().await, Err(DaemonError::ReadOnly(_)));
+
+        // reads are still served
+        client.status().await.unwrap();
+
+        tx.send(CloseReason::Interrupt).unwrap();
+        handle.await.unwrap().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn socket_passes_audit() {
+        let (_tempdir, repo_root, paths) = test_repo();
+
+        let (tx, exit_signal) = exit_signal();
+
+        let server = TurboGrpcService::new(
+            repo_root.clone(),
+            paths.clone(),
+            Duration::from_secs(60 * 60),
+            exit_signal,
+        );
+        let handle = serve_until_ready(server).await;
+
+        assert_eq!(
+            crate::daemon::audit::audit(&paths.sock_file).unwrap(),
+            vec![]
+        );
+
+        tx.send(CloseReason::Interrupt).unwrap();
+        handle.await.unwrap().unwrap();
+    }
+
+    /// Every file and directory under `root`.
+    fn repo_entries(root: &AbsoluteSystemPath) -> BTreeSet<PathBuf> {
+        let mut entries = BTreeSet::new();
+        let mut dirs = vec![root.as_std_path().to_owned()];
+        while let Some(dir) = dirs.pop() {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    dirs.push(path.clone());
+                }
+                entries.insert(path);
+            }
+        }
+        entries
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn read_only_writes_nothing_to_repo() {
+        let (_tempdir, repo_root, paths) = test_repo();
+        let app = repo_root.join_components(&["packages", "app"]);
+        app.create_dir_all().unwrap();
+        app.join_component("package.json")
+            .create_with_contents(r#"{"name": "app"}"#)
+            .unwrap();
+        let before = repo_entries(&repo_root);
+
+        let (tx, exit_signal) = exit_signal();
+
+        let server = TurboGrpcService::new(
+            repo_root.clone(),
+            paths.clone(),
+            Duration::from_secs(60 * 60),
+            exit_signal,
+        )
+        .with_read_only();
+        let handle = serve_until_ready(server).await;
+
+        let mut client = DaemonConnector::new(false, false, &repo_root)
+            .connect()
+            .await
+            .unwrap();
+        // each of these waits on a filewatching cookie
+        client.discover_packages_blocking().await.unwrap();
+        let glob = ValidatedGlob::from_str("dist/**").unwrap();
+        client
+            .get_changed_outputs("some-hash".to_string(), &[glob])
+            .await
+            .unwrap();
+        client.watches().await.unwrap();
+        let package_path = AnchoredSystemPath::new("packages/app").unwrap();
+        client.get_file_hashes(package_path, &[]).await.unwrap();
+        client.warm(package_path).await.unwrap();
+
+        tx.send(CloseReason::Interrupt).unwrap();
+        handle.await.unwrap().unwrap();
+
+        assert_eq!(repo_entries(&repo_root), before);
+        assert!(
+            paths.cookie_dir.exists(),
+            "cookies are written outside the repo"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn config() {
+        let (_tempdir, repo_root, paths) = test_repo();
+        repo_root
+            .join_component("turbo.json")
+            .create_with_contents(
+                r#"{"pipeline": {"build": {"dependsOn": ["^build"], "outputs": ["dist/**"]}}}"#,
+            )
+            .unwrap();
+        let app = repo_root.join_components(&["packages", "app"]);
+        app.create_dir_all().unwrap();
+        app.join_component("package.json")
+            .create_with_contents(r#"{"name": "app"}"#)
+            .unwrap();
+        app.join_component("turbo.json")
+            .create_with_contents(
+                r#"{"extends": ["//"], "pipeline": {"build": {"outputs": [".next/**"]}}}"#,
+            )
+            .unwrap();
+
+        let (tx, exit_signal) = exit_signal();
+
+        let server = TurboGrpcService::new(
+            repo_root.clone(),
+            paths.clone(),
+            Duration::from_secs(60 * 60),
+            exit_signal,
+        )
+        .with_worker_threads(3)
+        .with_compression_threshold(1024)
+        .with_read_only();
+        let handle = serve_until_ready(server).await;
+
+        let mut client = DaemonConnector::new(false, false, &repo_root)
+            .connect()
+            .await
+            .unwrap();
+        let config = loaded_config(&mut client, "app").await;
+        assert_eq!(config["repo_root"], repo_root.as_str());
+        assert_eq!(config["idle_timeout_ms"], 60 * 60 * 1000);
+        assert_eq!(config["worker_threads"], 3);
+        assert_eq!(config["compression_threshold"], 1024);
+        assert_eq!(config["read_only"], true);
+        assert_eq!(config["http_port"], serde_json::Value::Null);
+        assert_eq!(config["sock_file"], paths.sock_file.as_str());
+        assert_eq!(
+            config["config_hash"],
+            crate::daemon::config_hash(&repo_root).unwrap()
+        );
+        assert_eq!(
+            config["turbo"],
+            json!({
+                "//": {"build": {"dependsOn": ["^build"], "outputs": ["dist/**"]}},
+                "app": {"build": {"dependsOn": ["^build"], "outputs": [".next/**"]}},
+            })
+        );
+
+        tx.send(CloseReason::Interrupt).unwrap();
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn record_and_replay() {
+        let (_tempdir, repo_root, paths) = test_repo();
+        let record_file = repo_root.parent().unwrap().join_component("requests.jsonl");
+
+        let (tx, exit_signal) = exit_signal();
+
+        let server = TurboGrpcService::new(
+            repo_root.clone(),
+            paths.clone(),
+            Duration::from_secs(60 * 60),
+            exit_signal,
+        )
+        .with_record_file(record_file.clone());
+        let handle = serve_until_ready(server).await;
+
+        let connector = DaemonConnector::new(false, false, &repo_root);
+        let mut client = connector.clone().connect().await.unwrap();
+        client.status().await.unwrap();
+        client.reset_errors().await.unwrap();
+
+        async fn wait_for_recording(
+            file: &AbsoluteSystemPath,
+            count: usize,
+        ) -> Vec<replay::RecordedRequest> {
+            loop {
+                // the last line may not have been written completely yet
+                let recorded = replay::load(file).unwrap_or_default();
+                if recorded.len() >= count {
+                    return recorded;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }
+        let recorded = wait_for_recording(&record_file, 3).await;
+        let methods = recorded
+            .iter()
+            .map(|request| request.method.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            methods,
+            [
+                "/turbodprotocol.Turbod/Hello",
+                "/turbodprotocol.Turbod/Status",
+                "/turbodprotocol.Turbod/ResetErrors",
+            ]
+        );
+
+        let replayed = replay::replay(connector.channel().await.unwrap(), &recorded, None).await;
+        assert_eq!(
+            replayed
+                .iter()
+                .map(|request| request.method.as_str())
+                .collect::<Vec<_>>(),
+            methods
+        );
+        assert!(replayed.iter().all(|request| request.ok), "{replayed:?}");
+        assert!(replayed
+            .iter()
+            .all(|request| request.latency > Duration::ZERO));
+
+        // the daemon records the replayed requests as well
+        let rerecorded = wait_for_recording(&record_file, 6).await;
+        assert_eq!(rerecorded[3..], recorded[..]);
+
+        tx.send(CloseReason::Interrupt).unwrap();
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn scoped_daemon() {
+        let (_tempdir, repo_root, _) = test_repo();
+        repo_root
+            .join_component("turbo.json")
+            .create_with_contents(r#"{"pipeline": {}}"#)
+            .unwrap();
+        let app = repo_root.join_components(&["packages", "app"]);
+        app.create_dir_all().unwrap();
+        app.join_component("package.json")
+            .create_with_contents(r#"{"name": "app"}"#)
+            .unwrap();
+        app.join_component("turbo.json")
+            .create_with_contents(r#"{"extends": ["//"], "pipeline": {}}"#)
+            .unwrap();
+
+        let scoped = scoped_root(&repo_root, Utf8Path::new("packages/app")).unwrap();
+        let paths = Paths::from_repo_root(&scoped);
+        assert_ne!(paths.pid_file, Paths::from_repo_root(&repo_root).pid_file);
+
+        let (tx, exit_signal) = exit_signal();
+
+        let server = TurboGrpcService::new(
+            scoped.clone(),
+            paths.clone(),
+            Duration::from_secs(60 * 60),
+            exit_signal,
+        );
+        let handle = serve_until_ready(server).await;
+
+        let mut client = DaemonConnector::new(false, false, &scoped)
+            .connect()
+            .await
+            .unwrap();
+        let status = client.status().await.unwrap();
+        assert_eq!(status.config_hash, config_hash(&scoped));
+        assert_ne!(status.config_hash, config_hash(&repo_root));
+
+        // the daemon for the whole repository is a different one
+        assert_matches!(
+            DaemonConnector::new(false, false, &repo_root)
+                .connect()
+                .await,
+            Err(DaemonConnectorError::NotRunning)
+        );
+
+        tx.send(CloseReason::Interrupt).unwrap();
+        handle.await.unwrap().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn compression() {
+        use tonic::codec::CompressionEncoding;
+
+        use crate::daemon::proto::turbod_client::TurbodClient;
+
+        let (_tempdir, repo_root, paths) = test_repo();
+
+        let (tx, exit_signal) = exit_signal();
+
+        let server = TurboGrpcService::new(
+            repo_root.clone(),
+            paths.clone(),
+            Duration::from_secs(60 * 60),
+            exit_signal,
+        )
+        .with_compression_threshold(1);
+        let handle = serve_until_ready(server).await;
+
+        let sock_file = paths.sock_file.clone();
+        let channel = tonic::transport::Endpoint::try_from("http://[::]:50051")
+            .unwrap()
+            .connect_with_connector(tower::service_fn(move |_| {
+                let sock_file = sock_file.clone();
+                async move { tokio::net::UnixStream::connect(sock_file.as_std_path()).await }
+            }))
+            .await
+            .unwrap();
+
+        let mut gzip_client =
+            TurbodClient::new(channel.clone()).accept_compressed(CompressionEncoding::Gzip);
+        let status = gzip_client.status(StatusRequest {}).await.unwrap();
+        assert_eq!(status.metadata().get("grpc-encoding").unwrap(), "gzip");
+        assert!(status.into_inner().daemon_status.is_some());
+        // an empty response is below the threshold, and is sent uncompressed
+        let reset = gzip_client
+            .reset_errors(ResetErrorsRequest {})
+            .await
+            .unwrap();
+        assert!(!reset.into_inner().was_degraded);
+
+        // clients that don't support compression still get a response
+        let mut plain_client = TurbodClient::new(channel);
+        let status = plain_client.status(StatusRequest {}).await.unwrap();
+        assert!(status.metadata().get("grpc-encoding").is_none());
+        assert!(status.into_inner().daemon_status.is_some());
+
+        tx.send(CloseReason::Interrupt).unwrap();
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn ready_signal() {
+        let (_tempdir, repo_root, paths) = test_repo();
+        let ready_file = repo_root.parent().unwrap().join_component("ready");
+
+        let (tx, exit_signal) = exit_signal();
+
+        let server = TurboGrpcService::new(
+            repo_root.clone(),
+            paths.clone(),
+            Duration::from_secs(60 * 60),
+            exit_signal,
+        )
+        .with_ready_signal(ReadySignal::File(ready_file.clone()));
+        let handle = tokio::task::spawn(server.serve());
+
+        let start = Instant::now();
+        while !ready_file.exists() {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "server never signalled readiness"
+            );
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(ready_file.read_to_string().unwrap(), "READY\n");
+        assert!(
+            paths.sock_file.exists(),
+            "socket must be bound before signalling readiness"
+        );
+
+        tx.send(CloseReason::Interrupt).unwrap();
+        handle.await.unwrap().unwrap();
+        assert!(!ready_file.exists(), "ready file must be deleted");
+    }
+
+    #[test]
+    #[tracing_test::traced_test]
+    fn close_reason_log_line() {
+        let reasons = [
+            (CloseReason::Timeout, "daemon exiting: idle timeout"),
+            (CloseReason::Shutdown, "daemon exiting: explicit stop"),
+            (
+                CloseReason::WatcherClosed,
+                "daemon exiting: file watcher closed",
+            ),
+            (CloseReason::ServerClosed, "daemon exiting: server closed"),
+            (CloseReason::Interrupt, "daemon exiting: signal"),
+            (CloseReason::MemoryLimit, "daemon exiting: memory limit"),
+            (
+                CloseReason::SocketOpenError(SocketOpenError::Io(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    "address in use",
+                ))),
+                "daemon exiting: error: IO error: address in use",
+            ),
+        ];
+
+        for (reason, line) in reasons {
+            assert!(!logs_contain(line));
+            log_close_reason(&reason);
+            assert!(logs_contain(line), "missing log line: {line}");
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reset_errors() {
+        let (_tempdir, repo_root, paths) = test_repo();
+
+        let (trigger_shutdown, _shutdown_signal) = mpsc::channel(1);
+        let (service, exit_root_watch, watch_root_handle) = TurboGrpcServiceInner::new(
+            repo_root,
+            trigger_shutdown,
+            paths.log_file,
+            None,
+            WatchOptions::default(),
+        );
+
+        service.record_error(RpcError::NoFileWatching);
+
+        let status = service
+            .status(tonic::Request::new(StatusRequest {}))
+            .await
+            .unwrap()
+            .into_inner()
+            .daemon_status
+            .unwrap();
+        assert_eq!(status.health(), DaemonHealth::Degraded);
+        assert_eq!(
+            status.health_message.as_deref(),
+            Some("filewatching unavailable")
+        );
+
+        let reset = service
+            .reset_errors(tonic::Request::new(ResetErrorsRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(
+            reset.was_degraded,
+            "the daemon was degraded before the reset"
+        );
+
+        let status = service
+            .status(tonic::Request::new(StatusRequest {}))
+            .await
+            .unwrap()
+            .into_inner()
+            .daemon_status
+            .unwrap();
+        assert_eq!(status.health(), DaemonHealth::Healthy);
+        assert_eq!(status.health_message, None);
+
+        let _ = exit_root_watch.send(());
+        let _ = watch_root_handle.await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn polling_fallback() {
+        let (_tempdir, repo_root, paths) = test_repo();
+
+        // fail the native watcher, as on a mount it doesn't support
+        let options = WatchOptions {
+            poll_fallback: Some(Duration::from_millis(50)),
+            disable_native: true,
+        };
+        let (trigger_shutdown, _shutdown_signal) = mpsc::channel(1);
+        let (service, exit_root_watch, watch_root_handle) =
+            TurboGrpcServiceInner::new(repo_root, trigger_shutdown, paths.log_file, None, options);
+
+        // file watching is only available once the polling watcher has
+        // seen its cookie
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            service.file_watching.watcher.subscribe(),
+        )
+        .await
+        .expect("file watching did not start")
+        .unwrap();
+
+        let status = service
+            .status(tonic::Request::new(StatusRequest {}))
+            .await
+            .unwrap()
+            .into_inner()
+            .daemon_status
+            .unwrap();
+        assert_eq!(status.watcher_backend.as_deref(), Some("polling"));
+        assert_eq!(status.health(), DaemonHealth::Healthy);
+
+        let _ = exit_root_watch.send(());
+        let _ = watch_root_handle.await;
+    }
 }
@@ -0,0 +1,108 @@
+//! Timing a cold start of the daemon, for `turbo daemon start-bench`.
+//!
+//! A start is broken down into the phases a client can observe: spawning the
+//! daemon's process, the daemon binding its socket, and the daemon answering
+//! the handshake once it is ready to serve requests.
+
+use std::time::{Duration, Instant};
+
+use super::clock::ClockSource;
+
+/// How long each phase of a cold start took, in microseconds.
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StartBreakdown {
+    pub spawn_us: u64,
+    pub socket_bind_us: u64,
+    pub ready_us: u64,
+    /// The time from starting the process until it was ready, which is the
+    /// sum of the phases.
+    pub total_us: u64,
+}
+
+impl StartBreakdown {
+    pub fn new(spawn: Duration, socket_bind: Duration, ready: Duration) -> Self {
+        let (spawn_us, socket_bind_us, ready_us) = (
+            spawn.as_micros() as u64,
+            socket_bind.as_micros() as u64,
+            ready.as_micros() as u64,
+        );
+        Self {
+            spawn_us,
+            socket_bind_us,
+            ready_us,
+            total_us: spawn_us + socket_bind_us + ready_us,
+        }
+    }
+}
+
+/// Measures consecutive phases on the monotonic clock of a [`ClockSource`].
+pub struct StartTimer<C> {
+    clock: C,
+    last: Instant,
+}
+
+impl<C: ClockSource> StartTimer<C> {
+    pub fn start(mut clock: C) -> Self {
+        let last = clock.monotonic();
+        Self { clock, last }
+    }
+
+    /// The time since the previous lap ended, or since the timer started.
+    pub fn lap(&mut self) -> Duration {
+        let now = self.clock.monotonic();
+        let lap = now.saturating_duration_since(self.last);
+        self.last = now;
+        lap
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::VecDeque,
+        time::{Duration, Instant, SystemTime},
+    };
+
+    use super::{StartBreakdown, StartTimer};
+    use crate::daemon::clock::ClockSource;
+
+    /// Replays the given offsets from a base instant.
+    struct FakeClock(VecDeque<Instant>);
+
+    impl FakeClock {
+        fn new(offsets_ms: &[u64]) -> Self {
+            let base = Instant::now();
+            Self(
+                offsets_ms
+                    .iter()
+                    .map(|offset| base + Duration::from_millis(*offset))
+                    .collect(),
+            )
+        }
+    }
+
+    impl ClockSource for FakeClock {
+        fn monotonic(&mut self) -> Instant {
+            self.0.pop_front().expect("no more readings")
+        }
+
+        fn wall(&mut self) -> SystemTime {
+            unimplemented!("only the monotonic clock is used")
+        }
+    }
+
+    #[test]
+    fn test_breakdown() {
+        let mut timer = StartTimer::start(FakeClock::new(&[0, 12, 180, 230]));
+        let breakdown = StartBreakdown::new(timer.lap(), timer.lap(), timer.lap());
+        assert_eq!(
+            breakdown,
+            StartBreakdown {
+                spawn_us: 12_000,
+                socket_bind_us: 168_000,
+                ready_us: 50_000,
+                total_us: 230_000,
+            }
+        );
+    }
+}
@@ -0,0 +1,59 @@
+//! Naming the daemon process after the repository it serves, so that several
+//! daemons on one machine can be told apart in `ps` and `top`.
+
+use std::io;
+
+use turbopath::AbsoluteSystemPath;
+
+/// Sets the title the process is listed under.
+pub trait ProcessTitle: Send + 'static {
+    fn set(&mut self, title: &str) -> io::Result<()>;
+}
+
+/// Sets the title of the current process. On Linux this is the process name
+/// shown by `ps -e` and `top`, which is truncated to 15 bytes. It is not
+/// supported on other platforms.
+pub struct OsProcessTitle;
+
+impl ProcessTitle for OsProcessTitle {
+    #[cfg(target_os = "linux")]
+    fn set(&mut self, title: &str) -> io::Result<()> {
+        // /proc/self is the main thread, which is the one `ps` lists, whichever
+        // thread this runs on
+        std::fs::write("/proc/self/comm", title)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn set(&mut self, _title: &str) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "process titles are only supported on Linux",
+        ))
+    }
+}
+
+/// The title of the daemon for the given repository, such as `turbod
+/// [1a2b3c]`. The hash is shortened so that the title fits in a Linux
+/// process name.
+pub fn daemon_title(repo_root: &AbsoluteSystemPath) -> String {
+    format!("turbod [{}]", &super::repo_hash(repo_root)[..6])
+}
+
+#[cfg(test)]
+mod test {
+    use turbopath::AbsoluteSystemPathBuf;
+
+    use super::daemon_title;
+
+    #[test]
+    fn test_daemon_title() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let repo_root = AbsoluteSystemPathBuf::try_from(tempdir.path()).unwrap();
+        let title = daemon_title(&repo_root);
+
+        assert!(title.starts_with("turbod ["));
+        assert!(title.len() <= 15, "{title} is too long for a process name");
+        let other = repo_root.join_component("other");
+        assert_ne!(title, daemon_title(&other));
+    }
+}
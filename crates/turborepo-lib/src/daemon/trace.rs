@@ -0,0 +1,152 @@
+//! Capturing the spans of a single request, so that `turbo daemon trace` can
+//! show where the server spends its time without enabling tracing for every
+//! request.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use futures::Future;
+use tracing::{instrument::WithSubscriber, span, Subscriber};
+use tracing_subscriber::{layer::Context, prelude::*, registry::LookupSpan, Layer};
+
+use super::proto;
+
+/// Runs the future made by `make_fut`, recording every span created while it
+/// runs. The future is made once recording has started, so that spans it is
+/// instrumented with are recorded as well. Returns its output along with the
+/// spans in depth-first order.
+pub async fn capture<F: Future>(
+    make_fut: impl FnOnce() -> F,
+) -> (F::Output, Vec<proto::TraceSpan>) {
+    let recorder = Recorder::default();
+    let spans = recorder.spans.clone();
+    let output = async move { make_fut().await }
+        .with_subscriber(tracing_subscriber::registry().with(recorder))
+        .await;
+
+    let spans = std::mem::take(&mut *spans.lock().expect("trace lock poisoned"));
+    (output, flatten(&spans))
+}
+
+#[derive(Debug)]
+struct RecordedSpan {
+    name: &'static str,
+    parent: Option<usize>,
+    start: Instant,
+    duration: Option<Duration>,
+}
+
+/// The position of a span in the recorder, stored in the span's extensions.
+struct SpanIndex(usize);
+
+#[derive(Default)]
+struct Recorder {
+    spans: Arc<Mutex<Vec<RecordedSpan>>>,
+}
+
+impl<S> Layer<S> for Recorder
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let parent = span
+            .parent()
+            .and_then(|parent| parent.extensions().get::<SpanIndex>().map(|index| index.0));
+
+        let mut spans = self.spans.lock().expect("trace lock poisoned");
+        span.extensions_mut().insert(SpanIndex(spans.len()));
+        spans.push(RecordedSpan {
+            name: attrs.metadata().name(),
+            parent,
+            start: Instant::now(),
+            duration: None,
+        });
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let Some(index) = ctx
+            .span(&id)
+            .and_then(|span| span.extensions().get::<SpanIndex>().map(|index| index.0))
+        else {
+            return;
+        };
+        let mut spans = self.spans.lock().expect("trace lock poisoned");
+        let span = &mut spans[index];
+        span.duration = Some(span.start.elapsed());
+    }
+}
+
+/// Orders the spans depth first, so that each span is followed by its
+/// children. Spans that never closed are reported as far as they got.
+fn flatten(spans: &[RecordedSpan]) -> Vec<proto::TraceSpan> {
+    fn visit(
+        spans: &[RecordedSpan],
+        index: usize,
+        depth: u32,
+        flattened: &mut Vec<proto::TraceSpan>,
+    ) {
+        let span = &spans[index];
+        flattened.push(proto::TraceSpan {
+            name: span.name.to_string(),
+            duration_us: span
+                .duration
+                .unwrap_or_else(|| span.start.elapsed())
+                .as_micros() as u64,
+            depth,
+        });
+        for (child, _) in spans
+            .iter()
+            .enumerate()
+            .filter(|(_, child)| child.parent == Some(index))
+        {
+            visit(spans, child, depth + 1, flattened);
+        }
+    }
+
+    let mut flattened = Vec::with_capacity(spans.len());
+    for (root, _) in spans
+        .iter()
+        .enumerate()
+        .filter(|(_, span)| span.parent.is_none())
+    {
+        visit(spans, root, 0, &mut flattened);
+    }
+    flattened
+}
+
+#[cfg(test)]
+mod test {
+    use tracing::{info_span, Instrument};
+
+    use super::capture;
+
+    #[tokio::test]
+    async fn test_capture() {
+        let ((), spans) = capture(|| {
+            async {
+                async {
+                    let _inner = info_span!("inner").entered();
+                }
+                .instrument(info_span!("middle"))
+                .await;
+                let _sibling = info_span!("sibling").entered();
+            }
+            .instrument(info_span!("outer"))
+        })
+        .await;
+
+        let tree = spans
+            .iter()
+            .map(|span| (span.name.as_str(), span.depth))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tree,
+            [("outer", 0), ("middle", 1), ("inner", 2), ("sibling", 1)]
+        );
+    }
+}
@@ -0,0 +1,193 @@
+//! The turbo configuration the daemon has loaded, for `turbo daemon config`
+//! and `turbo daemon reload`.
+//!
+//! The tasks of a workspace with a `turbo.json` of its own are resolved the
+//! way a run resolves them: the workspace's definition of a task is merged
+//! over the root's.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Serialize;
+use turbopath::{AbsoluteSystemPath, AnchoredSystemPath};
+use turborepo_repository::{
+    discovery::WorkspaceData, package_graph::ROOT_PKG_NAME, package_json::PackageJson,
+};
+
+use crate::{
+    config::Error,
+    run::task_id::TaskId,
+    turbo_json::{validate_extends, validate_no_package_task_syntax, RawTaskDefinition, TurboJson},
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TurboConfig {
+    /// The hash of the root `turbo.json`, see [`super::config_hash`].
+    pub hash: Option<String>,
+    /// The tasks of each package, by package name. The root's tasks are under
+    /// `//` as they are written; every other package's are merged over them.
+    pub tasks: BTreeMap<String, BTreeMap<String, RawTaskDefinition>>,
+}
+
+impl TurboConfig {
+    /// Loads the root `turbo.json` and those of `workspaces`. A repository
+    /// without a root `turbo.json` loads with no tasks.
+    pub fn load(
+        repo_root: &AbsoluteSystemPath,
+        workspaces: &[WorkspaceData],
+    ) -> Result<Self, Error> {
+        let hash = super::config_hash(repo_root);
+        let root = match TurboJson::read(repo_root, AnchoredSystemPath::new("turbo.json")?) {
+            Ok(root) => root,
+            Err(Error::Io(_)) => TurboJson::default(),
+            Err(e) => return Err(e),
+        };
+
+        let mut tasks = BTreeMap::new();
+        tasks.insert(
+            ROOT_PKG_NAME.to_string(),
+            root.pipeline
+                .iter()
+                .map(|(task_name, definition)| (task_name.to_string(), definition.value.clone()))
+                .collect(),
+        );
+
+        for workspace in workspaces {
+            let Some(turbo_json_path) = &workspace.turbo_json else {
+                continue;
+            };
+            // packages without a name can't be run, so there is nothing to
+            // resolve for them
+            let Some(name) = PackageJson::load(&workspace.package_json)?.name else {
+                continue;
+            };
+            let turbo_json = TurboJson::read(repo_root, &repo_root.anchor(turbo_json_path)?)?;
+            if let Some(error) = turbo_json
+                .validate(&[validate_no_package_task_syntax, validate_extends])
+                .into_iter()
+                .next()
+            {
+                return Err(error);
+            }
+
+            let package_tasks = turbo_json
+                .pipeline
+                .iter()
+                .map(|(task_name, definition)| {
+                    let task_id = TaskId::new(&name, task_name.task());
+                    let mut merged = root.task(&task_id, task_name).unwrap_or_default();
+                    merged.merge(definition.value.clone());
+                    (task_name.to_string(), merged)
+                })
+                .collect();
+            tasks.insert(name, package_tasks);
+        }
+
+        Ok(Self { hash, tasks })
+    }
+
+    /// The packages whose tasks differ from those in `other`. Tasks are
+    /// compared by what they define, not by where in the file they are.
+    pub fn changed_packages(&self, other: &TurboConfig) -> Vec<String> {
+        let tasks_json = |config: &TurboConfig, package: &str| {
+            config
+                .tasks
+                .get(package)
+                .map(|tasks| serde_json::to_value(tasks).expect("tasks serialize to json"))
+        };
+        self.tasks
+            .keys()
+            .chain(other.tasks.keys())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .filter(|package| {
+                tasks_json(self, package.as_str()) != tasks_json(other, package.as_str())
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+    use turbopath::AbsoluteSystemPathBuf;
+    use turborepo_repository::discovery::WorkspaceData;
+
+    use super::TurboConfig;
+
+    #[test]
+    fn test_load_merges_workspace_tasks() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let repo_root = AbsoluteSystemPathBuf::try_from(tmp_dir.path()).unwrap();
+        repo_root
+            .join_component("turbo.json")
+            .create_with_contents(
+                r#"{"pipeline": {
+                    "build": {"dependsOn": ["^build"], "outputs": ["dist/**"]},
+                    "lint": {}
+                }}"#,
+            )
+            .unwrap();
+
+        let app = repo_root.join_components(&["packages", "app"]);
+        app.create_dir_all().unwrap();
+        let package_json = app.join_component("package.json");
+        package_json
+            .create_with_contents(r#"{"name": "app"}"#)
+            .unwrap();
+        let turbo_json = app.join_component("turbo.json");
+        turbo_json
+            .create_with_contents(
+                r#"{"extends": ["//"], "pipeline": {"build": {"outputs": [".next/**"]}}}"#,
+            )
+            .unwrap();
+
+        let config = TurboConfig::load(
+            &repo_root,
+            &[WorkspaceData {
+                package_json,
+                turbo_json: Some(turbo_json),
+            }],
+        )
+        .unwrap();
+
+        assert_eq!(config.hash, crate::daemon::config_hash(&repo_root));
+        assert_eq!(
+            serde_json::to_value(&config.tasks).unwrap(),
+            json!({
+                "//": {
+                    "build": {"dependsOn": ["^build"], "outputs": ["dist/**"]},
+                    "lint": {},
+                },
+                "app": {
+                    "build": {"dependsOn": ["^build"], "outputs": [".next/**"]},
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_load_rejects_workspace_without_extends() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let repo_root = AbsoluteSystemPathBuf::try_from(tmp_dir.path()).unwrap();
+        let app = repo_root.join_components(&["packages", "app"]);
+        app.create_dir_all().unwrap();
+        let package_json = app.join_component("package.json");
+        package_json
+            .create_with_contents(r#"{"name": "app"}"#)
+            .unwrap();
+        let turbo_json = app.join_component("turbo.json");
+        turbo_json
+            .create_with_contents(r#"{"pipeline": {"build": {}}}"#)
+            .unwrap();
+
+        let config = TurboConfig::load(
+            &repo_root,
+            &[WorkspaceData {
+                package_json,
+                turbo_json: Some(turbo_json),
+            }],
+        );
+        assert!(config.is_err());
+    }
+}
@@ -1,11 +1,24 @@
-use std::{io::Stderr, marker::PhantomData, path::Path, sync::Mutex};
+use std::{
+    io::{self, Stderr, Write},
+    marker::PhantomData,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
 
 use chrono::Local;
 use owo_colors::{
     colors::{Black, Default, Red, Yellow},
     Color, OwoColorize,
 };
-use tracing::{field::Visit, metadata::LevelFilter, trace, Event, Level, Subscriber};
+use tokio::sync::broadcast;
+use tracing::{
+    field::Visit, metadata::LevelFilter, subscriber::Interest, trace, Event, Level, Metadata,
+    Subscriber,
+};
 use tracing_appender::{non_blocking::NonBlocking, rolling::RollingFileAppender};
 use tracing_chrome::ChromeLayer;
 pub use tracing_subscriber::reload::Error;
@@ -14,6 +27,7 @@ use tracing_subscriber::{
     fmt::{
         self,
         format::{DefaultFields, Writer},
+        writer::EitherWriter,
         FmtContext, FormatEvent, FormatFields, MakeWriter,
     },
     layer,
@@ -50,7 +64,12 @@ type StdErrLogLayered = layer::Layered<StdErrLogFiltered, Registry>;
 
 /// A logger that spits lines into a file, using the standard formatter.
 /// It is applied on top of the `StdErrLogLayered` layer.
-type DaemonLog = fmt::Layer<StdErrLogLayered, DefaultFields, fmt::format::Format, NonBlocking>;
+type DaemonLog = fmt::Layer<
+    StdErrLogLayered,
+    DefaultFields,
+    fmt::format::Format,
+    RateLimitedWriter<NonBlocking>,
+>;
 /// This layer can be reloaded. `None` means the layer is disabled.
 type DaemonReload = reload::Layer<Option<DaemonLog>, StdErrLogLayered>;
 /// We filter this using a custom filter that only logs events
@@ -70,6 +89,15 @@ type ChromeReload = reload::Layer<Option<ChromeLog>, DaemonLogLayered>;
 /// `ChromeLogLayered`, which forms the base for the next layer.
 type ChromeLogLayered = layer::Layered<ChromeReload, DaemonLogLayered>;
 
+/// A logger that feeds the `DebugLogTap`, using the standard formatter. It is
+/// applied on top of the `ChromeLogLayered` layer.
+type DebugLog = DebugLogLayer<ChromeLogLayered>;
+/// This layer can be reloaded. `None` means the layer is disabled.
+type DebugLogReload = reload::Layer<Option<DebugLog>, ChromeLogLayered>;
+/// We filter this so that lines are only formatted while the tap is
+/// subscribed to.
+type DebugLogFiltered = Filtered<DebugLogReload, DebugLogFilter, ChromeLogLayered>;
+
 pub struct TurboSubscriber {
     daemon_update: Handle<Option<DaemonLog>, StdErrLogLayered>,
 
@@ -80,6 +108,9 @@ pub struct TurboSubscriber {
     chrome_update: Handle<Option<ChromeLog>, DaemonLogLayered>,
     chrome_guard: Mutex<Option<tracing_chrome::FlushGuard>>,
 
+    debug_log_update: Handle<Option<DebugLog>, ChromeLogLayered>,
+    debug_log: DebugLogTap,
+
     #[cfg(feature = "pprof")]
     pprof_guard: pprof::ProfilerGuard<'static>,
 }
@@ -102,6 +133,8 @@ impl TurboSubscriber {
     ///  formatter.
     /// - `enable_chrome_tracing` enables logging to a file, using the chrome
     ///  tracing formatter.
+    /// - `enable_debug_log` feeds a `DebugLogTap`, which formats debug logs
+    ///   only while something is subscribed to it.
     pub fn new_with_verbosity(verbosity: usize, ui: &UI) -> Self {
         let level_override = match verbosity {
             0 => None,
@@ -137,10 +170,15 @@ impl TurboSubscriber {
 
         let (chrome, chrome_update) = reload::Layer::new(Option::<ChromeLog>::None);
 
+        let debug_log = DebugLogTap::new();
+        let (debug_log_layer, debug_log_update) = reload::Layer::new(Option::<DebugLog>::None);
+        let debug_log_layer: DebugLogFiltered = debug_log_layer.with_filter(debug_log.filter());
+
         let registry = Registry::default()
             .with(stderr)
             .with(logrotate)
-            .with(chrome);
+            .with(chrome)
+            .with(debug_log_layer);
 
         #[cfg(feature = "pprof")]
         let pprof_guard = pprof::ProfilerGuardBuilder::default()
@@ -156,21 +194,36 @@ impl TurboSubscriber {
             daemon_guard: Mutex::new(None),
             chrome_update,
             chrome_guard: Mutex::new(None),
+            debug_log_update,
+            debug_log,
             #[cfg(feature = "pprof")]
             pprof_guard,
         }
     }
 
-    /// Enables daemon logging with the specified rotation settings.
+    /// Enables the tap that `turbo daemon debug-log` streams from, returning
+    /// it. Until then, nothing pays for it.
+    #[tracing::instrument(skip(self))]
+    pub fn enable_debug_log(&self) -> Result<DebugLogTap, Error> {
+        self.debug_log_update.reload(Some(self.debug_log.layer()))?;
+        Ok(self.debug_log.clone())
+    }
+
+    /// Enables daemon logging with the specified rotation settings, writing at
+    /// most `rate_limit` lines per second if it is set.
     ///
     /// Daemon logging uses the standard tracing formatter.
     #[tracing::instrument(skip(self, appender))]
-    pub fn set_daemon_logger(&self, appender: RollingFileAppender) -> Result<(), Error> {
+    pub fn set_daemon_logger(
+        &self,
+        appender: RollingFileAppender,
+        rate_limit: Option<u32>,
+    ) -> Result<(), Error> {
         let (file_writer, guard) = tracing_appender::non_blocking(appender);
         trace!("created non-blocking file writer");
 
         let layer: DaemonLog = tracing_subscriber::fmt::layer()
-            .with_writer(file_writer)
+            .with_writer(RateLimitedWriter::new(file_writer, rate_limit))
             .with_ansi(false);
 
         self.daemon_update.reload(Some(layer))?;
@@ -208,6 +261,14 @@ impl TurboSubscriber {
 
 impl Drop for TurboSubscriber {
     fn drop(&mut self) {
+        // note the end of a storm in the daemon log while its writer still
+        // runs, since no later line will
+        let _ = self.daemon_update.with_current(|layer| {
+            if let Some(layer) = layer {
+                layer.writer().flush_suppressed();
+            }
+        });
+
         // drop the guard so that the non-blocking file writer stops
         #[cfg(feature = "pprof")]
         if let Ok(report) = self.pprof_guard.report().build() {
@@ -234,6 +295,252 @@ impl Drop for TurboSubscriber {
     }
 }
 
+/// Limits how many lines per second are written to a log, so that an error
+/// storm can't flood it. Lines over the limit are dropped, and how many were
+/// dropped is noted before the next line that is written, or by
+/// [`RateLimitedWriter::flush_suppressed`] if no line follows.
+pub struct RateLimitedWriter<W> {
+    inner: W,
+    /// The most lines written per second, or `None` for no limit.
+    limit: Option<u32>,
+    window: Mutex<RateWindow>,
+}
+
+/// The lines seen in the current one-second window.
+struct RateWindow {
+    start: Instant,
+    written: u32,
+    suppressed: u64,
+}
+
+impl<W> RateLimitedWriter<W> {
+    pub fn new(inner: W, limit: Option<u32>) -> Self {
+        Self {
+            inner,
+            limit,
+            window: Mutex::new(RateWindow {
+                start: Instant::now(),
+                written: 0,
+                suppressed: 0,
+            }),
+        }
+    }
+}
+
+impl<W: for<'a> MakeWriter<'a>> RateLimitedWriter<W> {
+    /// Notes how many lines were dropped since the last one that was written,
+    /// if any were.
+    pub fn flush_suppressed(&self) {
+        let Some(limit) = self.limit else {
+            return;
+        };
+        let mut window = self.window.lock().expect("not poisoned");
+        if window.suppressed > 0 {
+            window.note_suppressed(&mut self.inner.make_writer(), limit);
+        }
+    }
+}
+
+impl RateWindow {
+    fn note_suppressed(&mut self, writer: &mut impl io::Write, limit: u32) {
+        let _ = writeln!(
+            writer,
+            "suppressed {} messages over the limit of {} per second",
+            self.suppressed, limit
+        );
+        self.suppressed = 0;
+    }
+}
+
+impl<'a, W: MakeWriter<'a>> MakeWriter<'a> for RateLimitedWriter<W> {
+    type Writer = EitherWriter<W::Writer, io::Sink>;
+
+    // the fmt layer makes a writer for every line it writes
+    fn make_writer(&'a self) -> Self::Writer {
+        let Some(limit) = self.limit else {
+            return EitherWriter::A(self.inner.make_writer());
+        };
+
+        let mut window = self.window.lock().expect("not poisoned");
+        let now = Instant::now();
+        if now.duration_since(window.start) >= Duration::from_secs(1) {
+            window.start = now;
+            window.written = 0;
+        }
+        if window.written >= limit {
+            window.suppressed += 1;
+            return EitherWriter::B(io::sink());
+        }
+
+        window.written += 1;
+        let mut writer = self.inner.make_writer();
+        if window.suppressed > 0 {
+            window.note_suppressed(&mut writer, limit);
+        }
+        EitherWriter::A(writer)
+    }
+}
+
+/// How many formatted lines a slow debug log subscriber can fall behind by
+/// before it misses some.
+const DEBUG_LOG_CAPACITY: usize = 1024;
+
+/// Targets left out of the debug log. Streaming the log produces events in
+/// these, which would otherwise feed back into the stream.
+const DEBUG_LOG_QUIET_TARGETS: &[&str] = &["h2", "hyper", "tonic", "tower"];
+
+/// Broadcasts debug logs, formatted with the standard formatter, to whoever
+/// is subscribed. Nothing is formatted while there are no subscribers, and
+/// the tap is independent of the other loggers, so subscribing doesn't
+/// change what they log.
+#[derive(Clone)]
+pub struct DebugLogTap {
+    lines: broadcast::Sender<String>,
+    /// How many `DebugLogReceiver`s are alive. Kept apart from the channel's
+    /// own count, which takes a lock to read, since it is read for every
+    /// event.
+    subscribers: Arc<AtomicUsize>,
+    /// Whether a layer feeds this tap. Until one does, debug logs are left
+    /// disabled for the other layers' sake.
+    installed: Arc<AtomicBool>,
+}
+
+/// A debug log layer, without the [`DebugLogFilter`] it needs.
+pub type DebugLogLayer<S> = fmt::Layer<S, DefaultFields, fmt::format::Format, DebugLogTap>;
+
+impl DebugLogTap {
+    pub fn new() -> Self {
+        let (lines, _) = broadcast::channel(DEBUG_LOG_CAPACITY);
+        Self {
+            lines,
+            subscribers: Arc::default(),
+            installed: Arc::default(),
+        }
+    }
+
+    pub fn subscribe(&self) -> DebugLogReceiver {
+        let lines = self.lines.subscribe();
+        self.subscribers.fetch_add(1, Ordering::Relaxed);
+        DebugLogReceiver {
+            lines,
+            subscribers: self.subscribers.clone(),
+        }
+    }
+
+    pub fn is_subscribed(&self) -> bool {
+        self.subscribers.load(Ordering::Relaxed) > 0
+    }
+
+    /// A layer that feeds this tap. It must be filtered with
+    /// [`DebugLogTap::filter`].
+    pub fn layer<S>(&self) -> DebugLogLayer<S>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        self.installed.store(true, Ordering::Relaxed);
+        fmt::layer().with_writer(self.clone()).with_ansi(false)
+    }
+
+    /// The filter for the layer that feeds this tap.
+    pub fn filter(&self) -> DebugLogFilter {
+        DebugLogFilter { tap: self.clone() }
+    }
+}
+
+/// A subscription to a [`DebugLogTap`]. The tap formats lines for as long as
+/// any of its subscriptions are alive.
+pub struct DebugLogReceiver {
+    lines: broadcast::Receiver<String>,
+    subscribers: Arc<AtomicUsize>,
+}
+
+impl DebugLogReceiver {
+    pub async fn recv(&mut self) -> Result<String, broadcast::error::RecvError> {
+        self.lines.recv().await
+    }
+}
+
+impl Drop for DebugLogReceiver {
+    fn drop(&mut self) {
+        self.subscribers.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Enables debug logs outside of [`DEBUG_LOG_QUIET_TARGETS`] for a
+/// [`DebugLogTap`] while it is subscribed to. Which callsites qualify is
+/// decided once per callsite, so each event only checks the level and the
+/// subscription.
+pub struct DebugLogFilter {
+    tap: DebugLogTap,
+}
+
+impl DebugLogFilter {
+    fn is_quiet(metadata: &Metadata<'_>) -> bool {
+        DEBUG_LOG_QUIET_TARGETS.iter().any(|quiet| {
+            metadata.target() == *quiet
+                || metadata
+                    .target()
+                    .strip_prefix(quiet)
+                    .is_some_and(|rest| rest.starts_with("::"))
+        })
+    }
+}
+
+impl<S> layer::Filter<S> for DebugLogFilter {
+    fn enabled(&self, metadata: &Metadata<'_>, _cx: &layer::Context<'_, S>) -> bool {
+        *metadata.level() <= Level::DEBUG && self.tap.is_subscribed() && !Self::is_quiet(metadata)
+    }
+
+    fn callsite_enabled(&self, metadata: &'static Metadata<'static>) -> Interest {
+        if *metadata.level() > Level::DEBUG || Self::is_quiet(metadata) {
+            Interest::never()
+        } else {
+            // the subscription comes and goes, so check it for each event
+            Interest::sometimes()
+        }
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        if self.tap.installed.load(Ordering::Relaxed) {
+            Some(LevelFilter::DEBUG)
+        } else {
+            Some(LevelFilter::OFF)
+        }
+    }
+}
+
+impl Default for DebugLogTap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> MakeWriter<'a> for DebugLogTap {
+    type Writer = DebugLogWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        DebugLogWriter(self.lines.clone())
+    }
+}
+
+/// Sends each line the fmt layer writes to the subscribers of a
+/// [`DebugLogTap`].
+pub struct DebugLogWriter(broadcast::Sender<String>);
+
+impl Write for DebugLogWriter {
+    // the fmt layer writes each line with a single call
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let line = String::from_utf8_lossy(buf);
+        // there being no subscribers left is not an error for the logger
+        let _ = self.0.send(line.trim_end().to_string());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 /// The formatter for TURBOREPO
 ///
 /// This is a port of the go formatter, which follows a few main rules:
@@ -351,3 +658,140 @@ fn write_message<FG: Color, BG: Color>(
     event.record(&mut visitor);
     writeln!(writer)
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io,
+        io::Write,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use tracing::metadata::LevelFilter;
+    use tracing_subscriber::{fmt::MakeWriter, layer::Filter, prelude::*, Registry};
+
+    use super::{DebugLogTap, RateLimitedWriter};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Buffer {
+        type Writer = Buffer;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn test_rate_limited_writer() {
+        let buffer = Buffer::default();
+        let subscriber = Registry::default().with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(RateLimitedWriter::new(buffer.clone(), Some(5)))
+                .with_ansi(false),
+        );
+
+        tracing::subscriber::with_default(subscriber, || {
+            for i in 0..100 {
+                tracing::warn!("storm {}", i);
+            }
+            std::thread::sleep(Duration::from_millis(1100));
+            tracing::warn!("after the storm");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 7, "{output}");
+        for (i, line) in lines[..5].iter().enumerate() {
+            assert!(line.ends_with(&format!("storm {i}")), "{line}");
+        }
+        assert_eq!(
+            lines[5],
+            "suppressed 95 messages over the limit of 5 per second"
+        );
+        assert!(lines[6].ends_with("after the storm"), "{}", lines[6]);
+    }
+
+    #[test]
+    fn test_flush_suppressed() {
+        let buffer = Buffer::default();
+        let writer = RateLimitedWriter::new(buffer.clone(), Some(5));
+        for i in 0..20 {
+            let _ = writeln!(writer.make_writer(), "storm {i}");
+        }
+
+        writer.flush_suppressed();
+        // only noted once
+        writer.flush_suppressed();
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                "storm 0",
+                "storm 1",
+                "storm 2",
+                "storm 3",
+                "storm 4",
+                "suppressed 15 messages over the limit of 5 per second"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unlimited_writer() {
+        let buffer = Buffer::default();
+        let subscriber = Registry::default().with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(RateLimitedWriter::new(buffer.clone(), None))
+                .with_ansi(false),
+        );
+
+        tracing::subscriber::with_default(subscriber, || {
+            for i in 0..100 {
+                tracing::warn!("storm {}", i);
+            }
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(output.lines().count(), 100);
+    }
+
+    #[test]
+    fn test_debug_log_tap_only_enables_once_installed() {
+        let tap = DebugLogTap::new();
+        let filter = tap.filter();
+        assert_eq!(
+            Filter::<Registry>::max_level_hint(&filter),
+            Some(LevelFilter::OFF),
+            "a tap nothing feeds doesn't enable debug logs"
+        );
+
+        let _layer = tap.layer::<Registry>();
+        assert_eq!(
+            Filter::<Registry>::max_level_hint(&filter),
+            Some(LevelFilter::DEBUG)
+        );
+
+        assert!(!tap.is_subscribed());
+        let first = tap.subscribe();
+        let second = tap.subscribe();
+        drop(first);
+        assert!(tap.is_subscribed());
+        drop(second);
+        assert!(!tap.is_subscribed());
+    }
+}